@@ -1,4 +1,4 @@
-use chitchat::{ChitchatId, ClusterStateSnapshot};
+use chitchat::{ChitchatId, ClusterStateSnapshot, ClusterStateSnapshotRef};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -9,6 +9,15 @@ pub struct ApiResponse {
     pub dead_nodes: Vec<ChitchatId>,
 }
 
+/// Borrowed counterpart of [`ApiResponse`], serialized while holding the chitchat lock.
+#[derive(Debug, Serialize)]
+pub struct ApiResponseRef<'a> {
+    pub cluster_id: &'a str,
+    pub cluster_state: ClusterStateSnapshotRef<'a>,
+    pub live_nodes: Vec<&'a ChitchatId>,
+    pub dead_nodes: Vec<&'a ChitchatId>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SetKeyValueResponse {
     pub status: bool,
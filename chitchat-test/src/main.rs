@@ -4,7 +4,7 @@ use std::time::{Duration, SystemTime};
 
 use chitchat::transport::UdpTransport;
 use chitchat::{spawn_chitchat, Chitchat, ChitchatConfig, ChitchatId, FailureDetectorConfig};
-use chitchat_test::{ApiResponse, SetKeyValueResponse};
+use chitchat_test::{ApiResponseRef, SetKeyValueResponse};
 use cool_id_generator::Size;
 use poem::listener::TcpListener;
 use poem::{Route, Server};
@@ -24,11 +24,11 @@ impl Api {
     #[oai(path = "/", method = "get")]
     async fn index(&self) -> PlainText<String> {
         let chitchat_guard = self.chitchat.lock().await;
-        let response = ApiResponse {
-            cluster_id: chitchat_guard.cluster_id().to_string(),
-            cluster_state: chitchat_guard.state_snapshot(),
-            live_nodes: chitchat_guard.live_nodes().cloned().collect::<Vec<_>>(),
-            dead_nodes: chitchat_guard.dead_nodes().cloned().collect::<Vec<_>>(),
+        let response = ApiResponseRef {
+            cluster_id: chitchat_guard.cluster_id(),
+            cluster_state: chitchat_guard.state_snapshot_ref(),
+            live_nodes: chitchat_guard.live_nodes().collect::<Vec<_>>(),
+            dead_nodes: chitchat_guard.dead_nodes().collect::<Vec<_>>(),
         };
         PlainText(serde_json::to_string_pretty(&response).unwrap())
     }
@@ -99,20 +99,18 @@ async fn main() -> anyhow::Result<()> {
         .unwrap()
         .as_secs();
     let chitchat_id = ChitchatId::new(node_id, generation, public_addr);
-    let config = ChitchatConfig {
-        cluster_id: "testing".to_string(),
-        chitchat_id,
-        gossip_interval: Duration::from_millis(opt.interval),
-        listen_addr: opt.listen_addr,
-        seed_nodes: opt.seeds.clone(),
-        failure_detector_config: FailureDetectorConfig {
-            dead_node_grace_period: Duration::from_secs(10),
+    let config = ChitchatConfig::builder()
+        .chitchat_id(chitchat_id)
+        .cluster_id("testing")
+        .listen_addr(opt.listen_addr)
+        .gossip_interval(Duration::from_millis(opt.interval))
+        .seed_nodes(opt.seeds.clone())
+        .failure_detector_config(FailureDetectorConfig {
+            dead_node_grace_period: Some(Duration::from_secs(10)),
             ..FailureDetectorConfig::default()
-        },
-        marked_for_deletion_grace_period: Duration::from_secs(60),
-        catchup_callback: None,
-        extra_liveness_predicate: None,
-    };
+        })
+        .marked_for_deletion_grace_period(Duration::from_secs(60))
+        .build()?;
     let chitchat_handler = spawn_chitchat(config, Vec::new(), &UdpTransport).await?;
     let chitchat = chitchat_handler.chitchat();
     let api = Api { chitchat };
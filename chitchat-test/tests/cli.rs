@@ -80,7 +80,7 @@ fn test_multiple_nodes() {
     // indeed set to be "some_value"
     let node_state = info.cluster_state.node_states.get(1).unwrap();
     let versioned_value = node_state.get_versioned("some_key").unwrap();
-    assert_eq!(versioned_value.value, "some_value");
+    assert_eq!(&*versioned_value.value, "some_value");
 }
 
 #[test]
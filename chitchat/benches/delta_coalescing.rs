@@ -0,0 +1,108 @@
+//! Measures the cost of answering the SYN-ACK messages of a gossip round with a fanout of 5, when
+//! the 5 peers are at the same versions, with and without delta coalescing.
+//!
+//! Run with `cargo bench -p chitchat --features testsuite`.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use chitchat::{Chitchat, ChitchatConfig, ChitchatId, ChitchatMessage};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tokio::sync::watch;
+
+const FANOUT: u16 = 5;
+const NUM_KEYS: usize = 200;
+
+fn new_node(port: u16) -> Chitchat {
+    let empty_seeds = watch::channel(Default::default()).1;
+    let chitchat_id = ChitchatId::for_local_test(port);
+    let config = ChitchatConfig::builder()
+        .listen_addr(chitchat_id.gossip_advertise_addr)
+        .chitchat_id(chitchat_id)
+        .cluster_id("default-cluster")
+        .build()
+        .unwrap();
+    Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new())
+}
+
+fn gossip_addr(node: &Chitchat) -> SocketAddr {
+    node.self_chitchat_id().gossip_advertise_addr
+}
+
+fn run_handshake(initiating_node: &mut Chitchat, peer_node: &mut Chitchat) {
+    let syn_message = initiating_node.create_syn_message_for_test();
+    let syn_ack_message = peer_node
+        .process_message_for_test(gossip_addr(initiating_node), syn_message)
+        .unwrap();
+    let ack_message = initiating_node
+        .process_message_for_test(gossip_addr(peer_node), syn_ack_message)
+        .unwrap();
+    peer_node.process_message_for_test(gossip_addr(initiating_node), ack_message);
+}
+
+/// Returns a node and `FANOUT` peers that know the same nodes at the same versions, and lag
+/// behind the node by `NUM_KEYS` key-values.
+fn setup() -> (Chitchat, Vec<Chitchat>) {
+    let mut node = new_node(10_000);
+    let mut peers: Vec<Chitchat> = (1..=FANOUT).map(|i| new_node(10_000 + i)).collect();
+    for _ in 0..2 {
+        for peer in &mut peers {
+            run_handshake(&mut node, peer);
+        }
+    }
+    for i in 0..NUM_KEYS {
+        node.self_set(format!("key-{i:04}"), format!("value-{i:04}"));
+    }
+    (node, peers)
+}
+
+/// Sends a SYN to every peer and returns their SYN-ACK, as received at the end of a gossip round.
+fn collect_syn_acks(node: &Chitchat, peers: &mut [Chitchat]) -> Vec<(SocketAddr, ChitchatMessage)> {
+    peers
+        .iter_mut()
+        .map(|peer| {
+            let syn_ack_message = peer
+                .process_message_for_test(gossip_addr(node), node.create_syn_message_for_test())
+                .unwrap();
+            (gossip_addr(peer), syn_ack_message)
+        })
+        .collect()
+}
+
+fn answer_syn_acks(
+    node: &mut Chitchat,
+    peers: &mut [Chitchat],
+    iters: u64,
+    coalesce: bool,
+) -> Duration {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..iters {
+        let syn_acks = collect_syn_acks(node, peers);
+        let start = Instant::now();
+        node.record_gossip_round_for_test();
+        for (peer_addr, syn_ack_message) in syn_acks {
+            if !coalesce {
+                // Starting a new round drops the deltas computed so far.
+                node.record_gossip_round_for_test();
+            }
+            black_box(node.process_message_for_test(peer_addr, syn_ack_message));
+        }
+        elapsed += start.elapsed();
+    }
+    elapsed
+}
+
+fn bench_delta_coalescing(c: &mut Criterion) {
+    let (mut node, mut peers) = setup();
+    let mut group = c.benchmark_group("fanout_5_identical_peers");
+    group.bench_function("without_coalescing", |b| {
+        b.iter_custom(|iters| answer_syn_acks(&mut node, &mut peers, iters, false))
+    });
+    group.bench_function("with_coalescing", |b| {
+        b.iter_custom(|iters| answer_syn_acks(&mut node, &mut peers, iters, true))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_delta_coalescing);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+//! Measures the cost of answering a SYN message from a peer that lags behind by a few key-values,
+//! when the node holds many key-values. Stale key-values are found through an index of the keys
+//! by version rather than by scanning all the key-values.
+//!
+//! Run with `cargo bench -p chitchat --features testsuite --bench stale_key_values`.
+
+use std::net::SocketAddr;
+
+use chitchat::{Chitchat, ChitchatConfig, ChitchatId, ChitchatMessage};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use tokio::sync::watch;
+
+const NUM_KEYS: usize = 5_000;
+const NUM_STALE_KEYS: usize = 10;
+
+fn new_node(port: u16) -> Chitchat {
+    let empty_seeds = watch::channel(Default::default()).1;
+    let chitchat_id = ChitchatId::for_local_test(port);
+    let config = ChitchatConfig::builder()
+        .listen_addr(chitchat_id.gossip_advertise_addr)
+        .chitchat_id(chitchat_id)
+        .cluster_id("default-cluster")
+        .build()
+        .unwrap();
+    Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new())
+}
+
+fn gossip_addr(node: &Chitchat) -> SocketAddr {
+    node.self_chitchat_id().gossip_advertise_addr
+}
+
+fn run_handshake(initiating_node: &mut Chitchat, peer_node: &mut Chitchat) {
+    let syn_message = initiating_node.create_syn_message_for_test();
+    let syn_ack_message = peer_node
+        .process_message_for_test(gossip_addr(initiating_node), syn_message)
+        .unwrap();
+    let ack_message = initiating_node
+        .process_message_for_test(gossip_addr(peer_node), syn_ack_message)
+        .unwrap();
+    peer_node.process_message_for_test(gossip_addr(initiating_node), ack_message);
+}
+
+/// Returns a node holding `NUM_KEYS` key-values, and a peer lagging behind it by
+/// `NUM_STALE_KEYS` key-values.
+fn setup() -> (Chitchat, Chitchat) {
+    let mut node = new_node(10_000);
+    let mut peer = new_node(10_001);
+    for i in 0..NUM_KEYS {
+        node.self_node_state()
+            .set(format!("key-{i:05}"), format!("value-{i:05}"));
+    }
+    // The whole state of the node does not fit in a single delta.
+    for _ in 0..100 {
+        run_handshake(&mut peer, &mut node);
+    }
+    for i in 0..NUM_STALE_KEYS {
+        node.self_node_state()
+            .set(format!("key-{i:05}"), format!("new-value-{i:05}"));
+    }
+    (node, peer)
+}
+
+fn answer_syn(node: &mut Chitchat, syn_message: ChitchatMessage, peer_addr: SocketAddr) {
+    // Starting a new gossip round drops the deltas computed so far.
+    node.record_gossip_round_for_test();
+    let syn_ack_message = node.process_message_for_test(peer_addr, syn_message);
+    black_box(syn_ack_message);
+}
+
+fn bench_stale_key_values(c: &mut Criterion) {
+    let (mut node, peer) = setup();
+    let peer_addr = gossip_addr(&peer);
+    c.bench_function("answer_syn_5000_keys_10_stale", |b| {
+        b.iter_batched(
+            || peer.create_syn_message_for_test(),
+            |syn_message| answer_syn(&mut node, syn_message, peer_addr),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_stale_key_values);
+criterion_main!(benches);
@@ -0,0 +1,167 @@
+//! Measures the number of SYN messages a chitchat server answers per second over UDP, when a
+//! single client or several concurrent clients flood it with SYN messages.
+//!
+//! Run with `cargo bench -p chitchat --bench syn_flood`.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use chitchat::transport::{Socket, UdpSocket, UdpTransport};
+use chitchat::{
+    spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId, ChitchatMessage, Digest,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+/// Number of SYN messages sent before waiting for the SYN-ACK messages, small enough for the
+/// socket buffers not to overflow.
+const WINDOW_SIZE: usize = 64;
+const NUM_WINDOWS: usize = 16;
+const NUM_KEYS: usize = 10;
+/// Number of clients flooding the server concurrently in the fan-in scenario. Each of them sends
+/// `NUM_WINDOWS` windows of `WINDOW_SIZE / NUM_CLIENTS` SYN messages, so that both scenarios
+/// send the same number of SYNs, with the same number of SYNs in flight.
+const NUM_CLIENTS: usize = 8;
+/// Number of tasks repeatedly taking the chitchat lock in the contended scenario, as the read
+/// path of an application embedding chitchat would.
+const NUM_LOCK_READERS: usize = 4;
+
+async fn spawn_server(server_addr: SocketAddr) -> ChitchatHandle {
+    let chitchat_id = ChitchatId::new("server".to_string(), 0, server_addr);
+    let config = ChitchatConfig::builder()
+        .chitchat_id(chitchat_id)
+        .cluster_id("default-cluster")
+        .listen_addr(server_addr)
+        .gossip_interval(Duration::from_secs(3_600))
+        .build()
+        .unwrap();
+    let key_values = (0..NUM_KEYS)
+        .map(|i| (format!("key-{i:02}"), format!("value-{i:02}")))
+        .collect();
+    spawn_chitchat(config, key_values, &UdpTransport)
+        .await
+        .unwrap()
+}
+
+/// Floods the server with `NUM_WINDOWS` windows of `window_size` SYN messages, and returns the
+/// number of SYN-ACK messages received.
+async fn flood(
+    client_socket: &mut UdpSocket,
+    server_addr: SocketAddr,
+    window_size: usize,
+) -> usize {
+    let syn = || ChitchatMessage::Syn {
+        cluster_id: "default-cluster".to_string(),
+        digest: Digest::default(),
+    };
+    let mut num_syn_acks = 0;
+    for _ in 0..NUM_WINDOWS {
+        for _ in 0..window_size {
+            client_socket.send(server_addr, syn()).await.unwrap();
+        }
+        for _ in 0..window_size {
+            // Datagrams may be dropped: we do not wait forever for the missing SYN-ACK messages.
+            let Ok(received) =
+                tokio::time::timeout(Duration::from_millis(100), client_socket.recv()).await
+            else {
+                break;
+            };
+            if let (_, ChitchatMessage::SynAck { .. }) = received.unwrap() {
+                num_syn_acks += 1;
+            }
+        }
+    }
+    num_syn_acks
+}
+
+/// Floods the server from all the client sockets concurrently, and returns the number of SYN-ACK
+/// messages received.
+async fn flood_fan_in(client_sockets: &mut Vec<UdpSocket>, server_addr: SocketAddr) -> usize {
+    let flood_tasks: Vec<_> = client_sockets
+        .drain(..)
+        .map(|mut client_socket| {
+            tokio::spawn(async move {
+                let num_syn_acks =
+                    flood(&mut client_socket, server_addr, WINDOW_SIZE / NUM_CLIENTS).await;
+                (client_socket, num_syn_acks)
+            })
+        })
+        .collect();
+    let mut total_num_syn_acks = 0;
+    for flood_task in flood_tasks {
+        let (client_socket, num_syn_acks) = flood_task.await.unwrap();
+        client_sockets.push(client_socket);
+        total_num_syn_acks += num_syn_acks;
+    }
+    total_num_syn_acks
+}
+
+fn bench_syn_flood(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let server_addr: SocketAddr = ([127, 0, 0, 1], 17_280).into();
+    let client_addr: SocketAddr = ([127, 0, 0, 1], 17_281).into();
+    let (server_handle, mut client_socket, mut fan_in_client_sockets) = runtime.block_on(async {
+        let server_handle = spawn_server(server_addr).await;
+        let client_socket = UdpSocket::open(client_addr).await.unwrap();
+        let mut fan_in_client_sockets = Vec::with_capacity(NUM_CLIENTS);
+        for client_id in 0..NUM_CLIENTS {
+            let fan_in_client_addr: SocketAddr = ([127, 0, 0, 1], 17_290 + client_id as u16).into();
+            fan_in_client_sockets.push(UdpSocket::open(fan_in_client_addr).await.unwrap());
+        }
+        (server_handle, client_socket, fan_in_client_sockets)
+    });
+
+    let mut group = c.benchmark_group("syn_flood");
+    group.throughput(Throughput::Elements((WINDOW_SIZE * NUM_WINDOWS) as u64));
+    group.bench_function("udp", |b| {
+        b.iter_custom(|num_iters| {
+            let start = Instant::now();
+            for _ in 0..num_iters {
+                let num_syn_acks =
+                    runtime.block_on(flood(&mut client_socket, server_addr, WINDOW_SIZE));
+                assert!(num_syn_acks > 0);
+            }
+            start.elapsed()
+        })
+    });
+    group.bench_function("udp_fan_in", |b| {
+        b.iter_custom(|num_iters| {
+            let start = Instant::now();
+            for _ in 0..num_iters {
+                let num_syn_acks =
+                    runtime.block_on(flood_fan_in(&mut fan_in_client_sockets, server_addr));
+                assert!(num_syn_acks > 0);
+            }
+            start.elapsed()
+        })
+    });
+    let lock_reader_tasks: Vec<_> = (0..NUM_LOCK_READERS)
+        .map(|_| {
+            let chitchat = server_handle.chitchat();
+            runtime.spawn(async move {
+                loop {
+                    black_box(chitchat.lock().await.num_live_nodes());
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    group.bench_function("udp_fan_in_contended", |b| {
+        b.iter_custom(|num_iters| {
+            let start = Instant::now();
+            for _ in 0..num_iters {
+                let num_syn_acks =
+                    runtime.block_on(flood_fan_in(&mut fan_in_client_sockets, server_addr));
+                assert!(num_syn_acks > 0);
+            }
+            start.elapsed()
+        })
+    });
+    for lock_reader_task in lock_reader_tasks {
+        lock_reader_task.abort();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_syn_flood);
+criterion_main!(benches);
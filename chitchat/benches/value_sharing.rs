@@ -0,0 +1,42 @@
+//! Measures the cost of taking a snapshot of the cluster state, when the self node holds
+//! `NUM_KEYS` values of `VALUE_LEN` bytes each. Values are shared with the snapshot rather than
+//! copied.
+//!
+//! Run with `cargo bench -p chitchat --bench value_sharing`.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use chitchat::{Chitchat, ChitchatConfig, ChitchatId};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tokio::sync::watch;
+
+const NUM_KEYS: usize = 1_000;
+const VALUE_LEN: usize = 1_024;
+
+fn new_node() -> Chitchat {
+    let empty_seeds = watch::channel(HashSet::<SocketAddr>::new()).1;
+    let listen_addr: SocketAddr = ([127, 0, 0, 1], 10_000).into();
+    let config = ChitchatConfig::builder()
+        .chitchat_id(ChitchatId::new("node".to_string(), 0, listen_addr))
+        .cluster_id("default-cluster")
+        .listen_addr(listen_addr)
+        .build()
+        .unwrap();
+    let mut node = Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new());
+    let value = "v".repeat(VALUE_LEN);
+    for i in 0..NUM_KEYS {
+        node.self_node_state().set(format!("key-{i:04}"), &value);
+    }
+    node
+}
+
+fn bench_value_sharing(c: &mut Criterion) {
+    let node = new_node();
+    c.bench_function("state_snapshot_1000_values_of_1kib", |b| {
+        b.iter(|| black_box(node.state_snapshot()))
+    });
+}
+
+criterion_group!(benches, bench_value_sharing);
+criterion_main!(benches);
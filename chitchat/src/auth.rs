@@ -0,0 +1,102 @@
+use anyhow::{anyhow, bail, Context};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::serialize::{Deserializable, Serializable};
+use crate::ChitchatMessage;
+
+/// Length of an HMAC-SHA256 message authentication code.
+pub(crate) const MAC_LEN: usize = 32;
+
+/// Number of extra bytes added by the [`ChitchatMessage::Authenticated`] envelope: the envelope
+/// header and the message authentication code.
+pub(crate) const AUTHENTICATED_MESSAGE_OVERHEAD: usize = 2 + 1 + 1 + MAC_LEN;
+
+/// Seals and opens [`ChitchatMessage::Authenticated`] envelopes using HMAC-SHA256 keyed with a
+/// secret shared by all the nodes of the cluster.
+///
+/// This authenticates the origin and the integrity of gossip messages. It does not encrypt them.
+#[derive(Clone)]
+pub(crate) struct MessageAuthenticator {
+    hmac: Hmac<Sha256>,
+}
+
+impl MessageAuthenticator {
+    pub fn new(secret: &[u8]) -> Self {
+        let hmac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        Self { hmac }
+    }
+
+    /// Wraps `message` into an authenticated envelope.
+    pub fn seal(&self, message: &ChitchatMessage) -> ChitchatMessage {
+        let mut payload = Vec::with_capacity(message.serialized_len());
+        message.serialize(&mut payload);
+        let mut hmac = self.hmac.clone();
+        hmac.update(&payload);
+        let mac: [u8; MAC_LEN] = hmac.finalize().into_bytes().into();
+        ChitchatMessage::Authenticated { mac, payload }
+    }
+
+    /// Verifies the authentication code of an envelope, and deserializes the message it contains.
+    ///
+    /// The payload is only deserialized if the authentication code is valid.
+    pub fn open(&self, message: ChitchatMessage) -> anyhow::Result<ChitchatMessage> {
+        let ChitchatMessage::Authenticated { mac, payload } = message else {
+            bail!("message is not authenticated");
+        };
+        let mut hmac = self.hmac.clone();
+        hmac.update(&payload);
+        hmac.verify_slice(&mac)
+            .map_err(|_| anyhow!("invalid message authentication code"))?;
+        let message = ChitchatMessage::deserialize(&mut &payload[..])
+            .context("failed to deserialize authenticated payload")?;
+        if matches!(message, ChitchatMessage::Authenticated { .. }) {
+            bail!("authenticated envelopes cannot be nested");
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Digest;
+
+    fn sample_syn_msg() -> ChitchatMessage {
+        ChitchatMessage::Syn {
+            cluster_id: "cluster-a".to_string(),
+            digest: Digest::default(),
+        }
+    }
+
+    #[test]
+    fn test_message_authenticator_seal_open() {
+        let authenticator = MessageAuthenticator::new(b"secret");
+        let sealed_message = authenticator.seal(&sample_syn_msg());
+        assert_eq!(
+            sealed_message.serialized_len(),
+            sample_syn_msg().serialized_len() + AUTHENTICATED_MESSAGE_OVERHEAD
+        );
+        let opened_message = authenticator.open(sealed_message).unwrap();
+        assert_eq!(opened_message, sample_syn_msg());
+    }
+
+    #[test]
+    fn test_message_authenticator_rejects_invalid_messages() {
+        let authenticator = MessageAuthenticator::new(b"secret");
+        assert!(authenticator.open(sample_syn_msg()).is_err());
+
+        let other_authenticator = MessageAuthenticator::new(b"other-secret");
+        let sealed_message = other_authenticator.seal(&sample_syn_msg());
+        assert!(authenticator.open(sealed_message).is_err());
+
+        let ChitchatMessage::Authenticated { mac, mut payload } =
+            authenticator.seal(&sample_syn_msg())
+        else {
+            panic!("expected an authenticated message");
+        };
+        *payload.last_mut().unwrap() ^= 1;
+        let tampered_message = ChitchatMessage::Authenticated { mac, payload };
+        assert!(authenticator.open(tampered_message).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use tokio::time::Instant;
+
+/// A source of the current instant, used to timestamp tombstones and to decide when they can be
+/// garbage collected.
+///
+/// Implementing it makes it possible to control the passing of time outside of a tokio runtime,
+/// for instance in deterministic simulations.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+}
+
+/// The default clock, which reads the tokio clock. The tokio clock can be paused and advanced
+/// manually in tests.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Handle to the clock shared by the cluster state and its node states.
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self(clock)
+    }
+
+    pub fn now(&self) -> Instant {
+        self.0.now()
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self(Arc::new(TokioClock))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// A clock that only moves forward when told to.
+    pub(crate) struct ManualClock {
+        now: Mutex<Instant>,
+    }
+
+    impl ManualClock {
+        pub fn new() -> Self {
+            Self {
+                now: Mutex::new(Instant::now()),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let manual_clock = Arc::new(ManualClock::new());
+        let shared_clock = SharedClock::new(manual_clock.clone());
+        let start = shared_clock.now();
+        assert_eq!(shared_clock.now(), start);
+        manual_clock.advance(Duration::from_secs(10));
+        assert_eq!(shared_clock.now(), start + Duration::from_secs(10));
+    }
+}
@@ -1,18 +1,116 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::{ChitchatId, FailureDetectorConfig, NodeState};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BadClusterQuarantineConfig, ChitchatId, Clock, Delta, FailureDetectorConfig, NodeState,
+};
 
 /// An optional user-defined callback executed when the self node is lagging behind.
 pub type CatchupCallback = Box<dyn Fn() + Send>;
 
+/// An optional user-defined callback executed when another process claims the identity of the
+/// self node. It is called with the conflicting identity.
+pub type SelfSupersededCallback = Box<dyn Fn(&ChitchatId) + Send>;
+
+/// An optional user-defined callback executed after a delta received from a peer was applied. It
+/// is called with the address the delta was received from and the delta itself.
+pub type DeltaAppliedCallback = Box<dyn Fn(SocketAddr, &Delta) + Send>;
+
 /// An optional user-defined predicate liveness predication applied on top of the output of the
 /// failure detector.
 pub type ExtraLivenessPredicate = Box<dyn Fn(&NodeState) -> bool + Send>;
 
+/// An optional user-defined predicate applied to every key before it is stored, whether it is set
+/// locally or received from a peer. Keys for which it returns `false` are dropped.
+pub type KeyValidator = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// An optional user-defined function applied to the `(key, value)` of every key-value before it
+/// is stored, whether it is set locally or received from a peer. It returns the value to store,
+/// possibly rewritten, or `None` to drop the key-value.
+pub type ValueTransform = Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>;
+
+/// Greatest accepted [`ChitchatConfig::key_value_history_depth`].
+pub const MAX_KEY_VALUE_HISTORY_DEPTH: usize = 64;
+
+/// Greatest accepted [`ChitchatConfig::recent_deltas_capacity`].
+pub const MAX_RECENT_DELTAS_CAPACITY: usize = 16_384;
+
+const DEFAULT_KEY_VALUE_HISTORY_DEPTH: usize = 4;
+
+const DEFAULT_RECENT_DELTAS_CAPACITY: usize = 128;
+
+/// Configuration of the digest-only gossip mode.
+///
+/// In this mode, gossip rounds exchange digests only, which is enough to keep the failure detector
+/// fed, and deltas are only sent once a peer is lagging far enough behind. This trades
+/// propagation latency for bandwidth in very large clusters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestOnlyGossipConfig {
+    /// Minimum number of versions, summed over all the nodes, a peer must be missing for us to
+    /// send it a delta.
+    pub divergence_threshold: u64,
+    /// At least one response out of `full_reconciliation_period` carries a delta regardless of
+    /// the divergence, so that small divergences are eventually repaired. 0 means never.
+    pub full_reconciliation_period: u32,
+}
+
+impl Default for DigestOnlyGossipConfig {
+    fn default() -> Self {
+        Self {
+            divergence_threshold: 100,
+            full_reconciliation_period: 10,
+        }
+    }
+}
+
+/// Handling of live nodes sharing a node ID but advertising different gossip addresses.
+///
+/// Since the gossip advertise address is part of a node's identity, such nodes are considered
+/// distinct and would otherwise all be reported as live. Note that a node restarting on a new
+/// address is briefly reported as a duplicate, until its previous identity is declared dead.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DuplicateNodeIdPolicy {
+    /// Duplicate node IDs are not looked for.
+    #[default]
+    Ignore,
+    /// A warning is logged when a duplicate node ID is detected.
+    Warn,
+    /// A warning is logged when a duplicate node ID is detected, and only the greatest identity
+    /// sharing the node ID, i.e. the one with the most recent generation, is reported in the live
+    /// nodes watcher. Every node of the cluster picks the same identity.
+    Reject,
+}
+
+/// Configuration of the timestamps attached to gossip messages.
+///
+/// Outgoing messages carry the wall-clock time at which they were sent, and incoming messages
+/// whose timestamp is too far from our own clock are dropped. Combined with message
+/// authentication, this protects against replayed messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageTimestampConfig {
+    /// Maximum difference, in either direction, between the timestamp of an incoming message and
+    /// our own clock. It must account for the clock skew between nodes.
+    pub max_message_age: Duration,
+}
+
+impl Default for MessageTimestampConfig {
+    fn default() -> Self {
+        Self {
+            max_message_age: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
 /// A struct for configuring a Chitchat instance.
+///
+/// See [`ChitchatConfig::builder`] for a builder that fills in the default values and validates
+/// the config.
 pub struct ChitchatConfig {
     pub chitchat_id: ChitchatId,
     pub cluster_id: String,
@@ -20,6 +118,10 @@ pub struct ChitchatConfig {
     pub listen_addr: SocketAddr,
     pub seed_nodes: Vec<String>,
     pub failure_detector_config: FailureDetectorConfig,
+    /// Period following startup during which the failure detector does not mark as dead the
+    /// nodes it has not received enough heartbeats from yet. Defaults to two gossip intervals if
+    /// `None`.
+    pub failure_detector_startup_grace_period: Option<Duration>,
     // Marked for deletion grace period expressed as a number of hearbeats.
     // Chitchat ensures a key marked for deletion is eventually deleted by three mechanisms:
     // - Garbage collection: each heartbeat, marked for deletion keys with `deletion now > instant
@@ -32,10 +134,110 @@ pub struct ChitchatConfig {
     pub marked_for_deletion_grace_period: Duration,
     /// An optional callback executed when the self node is lagging behind.
     pub catchup_callback: Option<CatchupCallback>,
+    /// An optional callback executed when another process claims the identity of the self node:
+    /// either the same node ID with an equal or higher generation, or the exact same identity
+    /// with a state more recent than ours. This typically reveals a misconfiguration.
+    pub self_superseded_callback: Option<SelfSupersededCallback>,
+    /// An optional callback executed after every delta received from a peer was applied, with the
+    /// address of the peer. Node deltas that are stale are ignored rather than applied, but they
+    /// are still part of the delta passed to the callback.
+    pub delta_applied_callback: Option<DeltaAppliedCallback>,
     // Extra lifeness predicate that can be used to define what a node being "live" means.
     // It can be used for instance, to only surface the nodes that are both alive according
     // to the failure detector, but also have a given set of required keys.
+    // The predicate is applied to the live nodes watcher, so nodes flipping between ready and
+    // not ready are observed as leaving and joining the set of live nodes.
+    // Readiness that depends on an asynchronous probe can be pushed with `Chitchat::set_ready`
+    // and checked with `NodeState::is_ready`.
     pub extra_liveness_predicate: Option<ExtraLivenessPredicate>,
+    /// What to do when live nodes share a node ID but advertise different gossip addresses,
+    /// typically because two hosts were deployed with the same node ID.
+    pub duplicate_node_id_policy: DuplicateNodeIdPolicy,
+    /// If set, we stop answering `BadCluster` to addresses that keep sending us messages
+    /// addressed to a different cluster.
+    pub bad_cluster_quarantine_config: Option<BadClusterQuarantineConfig>,
+    /// Whether SYN messages addressed to a different cluster are answered with `BadCluster`.
+    /// When `false`, they are silently dropped, so that probes from other clusters cannot tell
+    /// that a node is listening.
+    pub respond_to_bad_cluster: bool,
+    /// Multiplier applied to the probability of gossiping with a dead node in a given round,
+    /// which is `dead_nodes_count / (live_nodes_count + 1)` by default. Lowering it saves
+    /// gossip budget in clusters where many nodes are legitimately down.
+    pub dead_node_gossip_probability_multiplier: f64,
+    /// Minimum number of distinct seed nodes to gossip with in a given round, counting the seed
+    /// nodes selected among the live nodes. With the default of one, the self node gossips with a
+    /// seed node with some probability only (see CASSANDRA-150). Greater values guarantee that
+    /// every round reaches that many distinct seed nodes, or all of them if there are fewer, which
+    /// makes clusters with seeds spread across availability zones more resistant to partitions.
+    pub min_seed_nodes_per_round: usize,
+    /// Addresses of peers the self node gossips with every round, on top of the randomly selected
+    /// nodes, whether they are live or dead. This keeps a small set of nodes with a known fixed
+    /// membership, e.g. a control plane, tightly coupled. Unlike seed nodes, they are not
+    /// resolved through DNS.
+    pub static_peers: Vec<SocketAddr>,
+    /// If set, the number of bytes sent per second is capped, trading convergence speed for
+    /// bandwidth. Once the budget of the current second is spent, the SYN messages initiating
+    /// gossip are skipped until the next second. Responses to peers are always sent and count
+    /// toward the budget, but the deltas they carry are truncated to what remains of it. The
+    /// budget must be large enough for at least one SYN message, whose size
+    /// grows with the number of nodes in the cluster.
+    pub max_gossip_bytes_per_sec: Option<u64>,
+    /// If true, the self node is an observer: it gossips to learn the state of the cluster, but
+    /// never advertises its own state. See
+    /// [`Chitchat::is_observer`](crate::Chitchat::is_observer).
+    pub observer: bool,
+    /// If set, gossip messages are authenticated with an HMAC-SHA256 keyed with this secret, and
+    /// messages that are not properly authenticated are dropped. All the nodes of the cluster
+    /// must share the same secret. Messages are not encrypted.
+    pub message_auth_secret: Option<Vec<u8>>,
+    /// If set, key-values whose key is rejected by this predicate are dropped, both when they
+    /// are set locally and when they are received from a peer. This prevents a buggy peer from
+    /// polluting the state of the whole cluster.
+    pub key_validator: Option<KeyValidator>,
+    /// If set, the values are passed through this function before they are stored, both when
+    /// they are set locally and when they are received from a peer, so that it can redact them,
+    /// e.g. to keep secrets out of the gossiped state, or drop them altogether. Deletions are not
+    /// affected.
+    pub value_transform: Option<ValueTransform>,
+    /// Enables the diagnostic buffers sized by `key_value_history_depth` and
+    /// `recent_deltas_capacity`. The buffers are only allocated if this is true, in which case
+    /// their sizes are clamped to [`MAX_KEY_VALUE_HISTORY_DEPTH`] and
+    /// [`MAX_RECENT_DELTAS_CAPACITY`]. Otherwise, the sizes are ignored. Defaults to false.
+    pub debug_buffers_enabled: bool,
+    /// Number of previous values kept for every key, so that [`NodeState::get_at_version`] can
+    /// tell what a node's state looked like at a given version. Only used if
+    /// `debug_buffers_enabled` is set, and at most [`MAX_KEY_VALUE_HISTORY_DEPTH`].
+    ///
+    /// History is kept for every key of every node: it costs up to `depth * (value length + 40
+    /// bytes)` per key, e.g. about 100MB for 100 nodes with 1,000 keys of 200 bytes and a depth
+    /// of 4.
+    pub key_value_history_depth: usize,
+    /// Number of recently applied node deltas kept, and returned by
+    /// [`crate::Chitchat::recent_deltas`]. Only used if `debug_buffers_enabled` is set, and at
+    /// most [`MAX_RECENT_DELTAS_CAPACITY`].
+    ///
+    /// Only a summary of each delta is kept: about 100 bytes plus the length of the node ID,
+    /// whatever the size of the cluster or of the delta.
+    pub recent_deltas_capacity: usize,
+    /// If set, gossip responses only carry a delta when the peer lags far enough behind. By
+    /// default, every response carries a delta (full reconciliation).
+    pub digest_only_gossip_config: Option<DigestOnlyGossipConfig>,
+    /// The clock used to timestamp tombstones and to garbage collect them. Defaults to the tokio
+    /// clock.
+    pub clock: Option<Arc<dyn Clock>>,
+    /// If true, the versions of the self node that have not been gossiped yet are renumbered
+    /// before every gossip round, so that a key written several times between two rounds only
+    /// consumes a single version. Note that the versions observed locally may then go down.
+    pub compact_self_versions: bool,
+    /// The address peers should use to send RPCs to the self node, if it differs from the gossip
+    /// advertise address, e.g. in NAT or multi-interface setups. It is gossiped under the key
+    /// [`RPC_ADVERTISE_ADDR_KEY`](crate::RPC_ADVERTISE_ADDR_KEY). See
+    /// [`NodeState::rpc_advertise_addr`](crate::NodeState::rpc_advertise_addr).
+    pub rpc_advertise_addr: Option<SocketAddr>,
+    /// If set, gossip messages are timestamped, and messages that are not timestamped or whose
+    /// timestamp is out of the configured window are dropped. All the nodes of the cluster must
+    /// agree on this setting.
+    pub message_timestamp_config: Option<MessageTimestampConfig>,
 }
 
 impl ChitchatConfig {
@@ -50,9 +252,31 @@ impl ChitchatConfig {
             listen_addr,
             seed_nodes: Vec::new(),
             failure_detector_config: Default::default(),
+            failure_detector_startup_grace_period: None,
             marked_for_deletion_grace_period: Duration::from_secs(10_000),
             catchup_callback: None,
+            self_superseded_callback: None,
+            delta_applied_callback: None,
             extra_liveness_predicate: None,
+            duplicate_node_id_policy: DuplicateNodeIdPolicy::Ignore,
+            bad_cluster_quarantine_config: None,
+            respond_to_bad_cluster: true,
+            dead_node_gossip_probability_multiplier: 1.0,
+            min_seed_nodes_per_round: 1,
+            static_peers: Vec::new(),
+            max_gossip_bytes_per_sec: None,
+            observer: false,
+            message_auth_secret: None,
+            key_validator: None,
+            value_transform: None,
+            debug_buffers_enabled: false,
+            key_value_history_depth: DEFAULT_KEY_VALUE_HISTORY_DEPTH,
+            recent_deltas_capacity: DEFAULT_RECENT_DELTAS_CAPACITY,
+            digest_only_gossip_config: None,
+            clock: None,
+            compact_self_versions: false,
+            rpc_advertise_addr: None,
+            message_timestamp_config: None,
         }
     }
 }
@@ -62,16 +286,549 @@ impl Default for ChitchatConfig {
     fn default() -> Self {
         let chitchat_id = ChitchatId::for_local_test(10_000);
         let listen_addr = chitchat_id.gossip_advertise_addr;
+        Self::with_defaults(chitchat_id, "default-cluster".to_string(), listen_addr)
+    }
+}
+
+impl ChitchatConfig {
+    /// Returns a config for the given node identity with all the other settings at their
+    /// defaults, which are listed in the documentation of [`ChitchatConfigBuilder`].
+    fn with_defaults(chitchat_id: ChitchatId, cluster_id: String, listen_addr: SocketAddr) -> Self {
         Self {
             chitchat_id,
-            cluster_id: "default-cluster".to_string(),
+            cluster_id,
             gossip_interval: Duration::from_millis(1_000),
             listen_addr,
             seed_nodes: Vec::new(),
             failure_detector_config: Default::default(),
+            failure_detector_startup_grace_period: None,
             marked_for_deletion_grace_period: Duration::from_secs(3_600 * 2), // 2h
             catchup_callback: None,
+            self_superseded_callback: None,
+            delta_applied_callback: None,
             extra_liveness_predicate: None,
+            duplicate_node_id_policy: DuplicateNodeIdPolicy::Ignore,
+            bad_cluster_quarantine_config: None,
+            respond_to_bad_cluster: true,
+            dead_node_gossip_probability_multiplier: 1.0,
+            min_seed_nodes_per_round: 1,
+            static_peers: Vec::new(),
+            max_gossip_bytes_per_sec: None,
+            observer: false,
+            message_auth_secret: None,
+            key_validator: None,
+            value_transform: None,
+            debug_buffers_enabled: false,
+            key_value_history_depth: DEFAULT_KEY_VALUE_HISTORY_DEPTH,
+            recent_deltas_capacity: DEFAULT_RECENT_DELTAS_CAPACITY,
+            digest_only_gossip_config: None,
+            clock: None,
+            compact_self_versions: false,
+            rpc_advertise_addr: None,
+            message_timestamp_config: None,
         }
     }
 }
+
+/// A builder for [`ChitchatConfig`].
+///
+/// The chitchat ID, the cluster ID, and the listen address are required. The other fields
+/// default to:
+/// - `gossip_interval`: 1s.
+/// - `failure_detector_startup_grace_period`: two gossip intervals.
+/// - `marked_for_deletion_grace_period`: 2h.
+/// - `respond_to_bad_cluster`: true.
+/// - `dead_node_gossip_probability_multiplier`: 1.0.
+/// - `min_seed_nodes_per_round`: 1.
+/// - all the optional settings are disabled.
+pub struct ChitchatConfigBuilder {
+    chitchat_id: Option<ChitchatId>,
+    cluster_id: String,
+    listen_addr: Option<SocketAddr>,
+    config: ChitchatConfig,
+}
+
+impl ChitchatConfig {
+    /// Returns the startup grace period of the failure detector, which defaults to two gossip
+    /// intervals.
+    pub(crate) fn effective_failure_detector_startup_grace_period(&self) -> Duration {
+        self.failure_detector_startup_grace_period
+            .unwrap_or(self.gossip_interval * 2)
+    }
+
+    /// Returns the number of previous values to keep for every key, taking
+    /// `debug_buffers_enabled` into account.
+    pub(crate) fn effective_key_value_history_depth(&self) -> usize {
+        if self.debug_buffers_enabled {
+            self.key_value_history_depth
+                .min(MAX_KEY_VALUE_HISTORY_DEPTH)
+        } else {
+            0
+        }
+    }
+
+    /// Returns the number of recently applied node deltas to keep, taking
+    /// `debug_buffers_enabled` into account.
+    pub(crate) fn effective_recent_deltas_capacity(&self) -> usize {
+        if self.debug_buffers_enabled {
+            self.recent_deltas_capacity.min(MAX_RECENT_DELTAS_CAPACITY)
+        } else {
+            0
+        }
+    }
+
+    /// Returns true if a diagnostic buffer size was changed from its default while
+    /// `debug_buffers_enabled` is not set, which most likely means the flag was forgotten.
+    pub(crate) fn debug_buffer_sizes_ignored(&self) -> bool {
+        let is_customized = |size: usize, default_size: usize| size != 0 && size != default_size;
+        !self.debug_buffers_enabled
+            && (is_customized(
+                self.key_value_history_depth,
+                DEFAULT_KEY_VALUE_HISTORY_DEPTH,
+            ) || is_customized(self.recent_deltas_capacity, DEFAULT_RECENT_DELTAS_CAPACITY))
+    }
+
+    /// Returns a builder for a [`ChitchatConfig`].
+    pub fn builder() -> ChitchatConfigBuilder {
+        // The identity of this placeholder is replaced by the validated one in `build`.
+        let unspecified_addr: SocketAddr = ([0, 0, 0, 0], 0).into();
+        let config = ChitchatConfig::with_defaults(
+            ChitchatId::from_addr(unspecified_addr),
+            String::new(),
+            unspecified_addr,
+        );
+        ChitchatConfigBuilder {
+            chitchat_id: None,
+            cluster_id: String::new(),
+            listen_addr: None,
+            config,
+        }
+    }
+}
+
+impl ChitchatConfigBuilder {
+    pub fn chitchat_id(mut self, chitchat_id: ChitchatId) -> Self {
+        self.chitchat_id = Some(chitchat_id);
+        self
+    }
+
+    pub fn cluster_id(mut self, cluster_id: impl ToString) -> Self {
+        self.cluster_id = cluster_id.to_string();
+        self
+    }
+
+    pub fn listen_addr(mut self, listen_addr: SocketAddr) -> Self {
+        self.listen_addr = Some(listen_addr);
+        self
+    }
+
+    pub fn gossip_interval(mut self, gossip_interval: Duration) -> Self {
+        self.config.gossip_interval = gossip_interval;
+        self
+    }
+
+    pub fn seed_nodes(mut self, seed_nodes: Vec<String>) -> Self {
+        self.config.seed_nodes = seed_nodes;
+        self
+    }
+
+    pub fn failure_detector_config(
+        mut self,
+        failure_detector_config: FailureDetectorConfig,
+    ) -> Self {
+        self.config.failure_detector_config = failure_detector_config;
+        self
+    }
+
+    pub fn failure_detector_startup_grace_period(mut self, grace_period: Duration) -> Self {
+        self.config.failure_detector_startup_grace_period = Some(grace_period);
+        self
+    }
+
+    pub fn marked_for_deletion_grace_period(mut self, grace_period: Duration) -> Self {
+        self.config.marked_for_deletion_grace_period = grace_period;
+        self
+    }
+
+    pub fn catchup_callback(mut self, callback: impl Fn() + Send + 'static) -> Self {
+        self.config.catchup_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn self_superseded_callback(
+        mut self,
+        callback: impl Fn(&ChitchatId) + Send + 'static,
+    ) -> Self {
+        self.config.self_superseded_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn delta_applied_callback(
+        mut self,
+        callback: impl Fn(SocketAddr, &Delta) + Send + 'static,
+    ) -> Self {
+        self.config.delta_applied_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn extra_liveness_predicate(
+        mut self,
+        predicate: impl Fn(&NodeState) -> bool + Send + 'static,
+    ) -> Self {
+        self.config.extra_liveness_predicate = Some(Box::new(predicate));
+        self
+    }
+
+    pub fn duplicate_node_id_policy(
+        mut self,
+        duplicate_node_id_policy: DuplicateNodeIdPolicy,
+    ) -> Self {
+        self.config.duplicate_node_id_policy = duplicate_node_id_policy;
+        self
+    }
+
+    pub fn bad_cluster_quarantine_config(
+        mut self,
+        quarantine_config: BadClusterQuarantineConfig,
+    ) -> Self {
+        self.config.bad_cluster_quarantine_config = Some(quarantine_config);
+        self
+    }
+
+    pub fn respond_to_bad_cluster(mut self, respond_to_bad_cluster: bool) -> Self {
+        self.config.respond_to_bad_cluster = respond_to_bad_cluster;
+        self
+    }
+
+    pub fn dead_node_gossip_probability_multiplier(mut self, multiplier: f64) -> Self {
+        self.config.dead_node_gossip_probability_multiplier = multiplier;
+        self
+    }
+
+    pub fn min_seed_nodes_per_round(mut self, min_seed_nodes_per_round: usize) -> Self {
+        self.config.min_seed_nodes_per_round = min_seed_nodes_per_round;
+        self
+    }
+
+    pub fn static_peers(mut self, static_peers: Vec<SocketAddr>) -> Self {
+        self.config.static_peers = static_peers;
+        self
+    }
+
+    pub fn max_gossip_bytes_per_sec(mut self, max_gossip_bytes_per_sec: u64) -> Self {
+        self.config.max_gossip_bytes_per_sec = Some(max_gossip_bytes_per_sec);
+        self
+    }
+
+    pub fn observer(mut self, observer: bool) -> Self {
+        self.config.observer = observer;
+        self
+    }
+
+    pub fn message_auth_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.config.message_auth_secret = Some(secret.into());
+        self
+    }
+
+    pub fn key_validator(
+        mut self,
+        key_validator: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.key_validator = Some(Arc::new(key_validator));
+        self
+    }
+
+    pub fn value_transform(
+        mut self,
+        value_transform: impl Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.value_transform = Some(Arc::new(value_transform));
+        self
+    }
+
+    pub fn debug_buffers_enabled(mut self, debug_buffers_enabled: bool) -> Self {
+        self.config.debug_buffers_enabled = debug_buffers_enabled;
+        self
+    }
+
+    pub fn key_value_history_depth(mut self, key_value_history_depth: usize) -> Self {
+        self.config.key_value_history_depth = key_value_history_depth;
+        self
+    }
+
+    pub fn recent_deltas_capacity(mut self, recent_deltas_capacity: usize) -> Self {
+        self.config.recent_deltas_capacity = recent_deltas_capacity;
+        self
+    }
+
+    pub fn digest_only_gossip_config(
+        mut self,
+        digest_only_gossip_config: DigestOnlyGossipConfig,
+    ) -> Self {
+        self.config.digest_only_gossip_config = Some(digest_only_gossip_config);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = Some(clock);
+        self
+    }
+
+    pub fn compact_self_versions(mut self, compact_self_versions: bool) -> Self {
+        self.config.compact_self_versions = compact_self_versions;
+        self
+    }
+
+    pub fn rpc_advertise_addr(mut self, rpc_advertise_addr: SocketAddr) -> Self {
+        self.config.rpc_advertise_addr = Some(rpc_advertise_addr);
+        self
+    }
+
+    pub fn message_timestamp_config(
+        mut self,
+        message_timestamp_config: MessageTimestampConfig,
+    ) -> Self {
+        self.config.message_timestamp_config = Some(message_timestamp_config);
+        self
+    }
+
+    /// Validates the settings and builds the config.
+    pub fn build(self) -> anyhow::Result<ChitchatConfig> {
+        let Some(chitchat_id) = self.chitchat_id else {
+            bail!("chitchat ID is not set");
+        };
+        if self.cluster_id.is_empty() {
+            bail!("cluster ID is empty");
+        }
+        let Some(listen_addr) = self.listen_addr else {
+            bail!("listen address is not set");
+        };
+        let mut config = self.config;
+        if config.gossip_interval.is_zero() {
+            bail!("gossip interval must be positive");
+        }
+        let multiplier = config.dead_node_gossip_probability_multiplier;
+        if multiplier.is_nan() || multiplier < 0.0 {
+            bail!(
+                "dead node gossip probability multiplier must be non-negative, got `{multiplier}`"
+            );
+        }
+        if config
+            .message_auth_secret
+            .as_ref()
+            .is_some_and(|secret| secret.is_empty())
+        {
+            bail!("message authentication secret is empty");
+        }
+        if config.max_gossip_bytes_per_sec == Some(0) {
+            bail!("max gossip bytes per second must be positive");
+        }
+        if config.key_value_history_depth > MAX_KEY_VALUE_HISTORY_DEPTH {
+            bail!(
+                "key-value history depth must be at most {MAX_KEY_VALUE_HISTORY_DEPTH}, got `{}`",
+                config.key_value_history_depth
+            );
+        }
+        if config.recent_deltas_capacity > MAX_RECENT_DELTAS_CAPACITY {
+            bail!(
+                "recent deltas capacity must be at most {MAX_RECENT_DELTAS_CAPACITY}, got `{}`",
+                config.recent_deltas_capacity
+            );
+        }
+        config.chitchat_id = chitchat_id;
+        config.cluster_id = self.cluster_id;
+        config.listen_addr = listen_addr;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chitchat_config_builder() {
+        let chitchat_id = ChitchatId::for_local_test(10_001);
+        let config = ChitchatConfig::builder()
+            .chitchat_id(chitchat_id.clone())
+            .cluster_id("test-cluster")
+            .listen_addr("0.0.0.0:10001".parse().unwrap())
+            .seed_nodes(vec!["127.0.0.1:10002".to_string()])
+            .min_seed_nodes_per_round(2)
+            .extra_liveness_predicate(|node_state| node_state.get("ready").is_some())
+            .build()
+            .unwrap();
+        assert_eq!(config.chitchat_id, chitchat_id);
+        assert_eq!(config.cluster_id, "test-cluster");
+        assert_eq!(config.listen_addr, "0.0.0.0:10001".parse().unwrap());
+        assert_eq!(config.seed_nodes, ["127.0.0.1:10002"]);
+        assert_eq!(config.min_seed_nodes_per_round, 2);
+        assert!(config.extra_liveness_predicate.is_some());
+        // Defaults
+        assert_eq!(config.gossip_interval, Duration::from_secs(1));
+        assert_eq!(
+            config.marked_for_deletion_grace_period,
+            Duration::from_secs(3_600 * 2)
+        );
+        assert!(config.respond_to_bad_cluster);
+        assert!(!config.observer);
+        assert!(!config.debug_buffers_enabled);
+        assert_eq!(config.effective_key_value_history_depth(), 0);
+        assert_eq!(config.effective_recent_deltas_capacity(), 0);
+        assert_eq!(config.dead_node_gossip_probability_multiplier, 1.0);
+        assert!(config.message_auth_secret.is_none());
+        assert!(config.catchup_callback.is_none());
+    }
+
+    #[test]
+    fn test_failure_detector_startup_grace_period_defaults_to_two_gossip_intervals() {
+        let builder = || {
+            ChitchatConfig::builder()
+                .chitchat_id(ChitchatId::for_local_test(10_001))
+                .cluster_id("test-cluster")
+                .listen_addr("0.0.0.0:10001".parse().unwrap())
+                .gossip_interval(Duration::from_millis(300))
+        };
+        let config = builder().build().unwrap();
+        assert_eq!(
+            config.effective_failure_detector_startup_grace_period(),
+            Duration::from_millis(600)
+        );
+        let config = builder()
+            .failure_detector_startup_grace_period(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.effective_failure_detector_startup_grace_period(),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_chitchat_config_builder_validation() {
+        let builder = || {
+            ChitchatConfig::builder()
+                .chitchat_id(ChitchatId::for_local_test(10_001))
+                .cluster_id("test-cluster")
+                .listen_addr("0.0.0.0:10001".parse().unwrap())
+        };
+        builder().build().unwrap();
+
+        let error = ChitchatConfig::builder()
+            .cluster_id("test-cluster")
+            .listen_addr("0.0.0.0:10001".parse().unwrap())
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "chitchat ID is not set");
+
+        let error = builder().cluster_id("").build().err().unwrap();
+        assert_eq!(error.to_string(), "cluster ID is empty");
+
+        let error = ChitchatConfig::builder()
+            .chitchat_id(ChitchatId::for_local_test(10_001))
+            .cluster_id("test-cluster")
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "listen address is not set");
+
+        let error = builder()
+            .gossip_interval(Duration::ZERO)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "gossip interval must be positive");
+
+        builder()
+            .dead_node_gossip_probability_multiplier(f64::NAN)
+            .build()
+            .err()
+            .unwrap();
+        builder()
+            .dead_node_gossip_probability_multiplier(-1.0)
+            .build()
+            .err()
+            .unwrap();
+
+        let error = builder().message_auth_secret("").build().err().unwrap();
+        assert_eq!(error.to_string(), "message authentication secret is empty");
+
+        let error = builder().max_gossip_bytes_per_sec(0).build().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "max gossip bytes per second must be positive"
+        );
+
+        let error = builder()
+            .key_value_history_depth(MAX_KEY_VALUE_HISTORY_DEPTH + 1)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "key-value history depth must be at most 64, got `65`"
+        );
+
+        let error = builder()
+            .recent_deltas_capacity(MAX_RECENT_DELTAS_CAPACITY + 1)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "recent deltas capacity must be at most 16384, got `16385`"
+        );
+    }
+
+    #[test]
+    fn test_chitchat_config_debug_buffers() {
+        let builder = || {
+            ChitchatConfig::builder()
+                .chitchat_id(ChitchatId::for_local_test(10_001))
+                .cluster_id("test-cluster")
+                .listen_addr("0.0.0.0:10001".parse().unwrap())
+        };
+        let config = builder().debug_buffers_enabled(true).build().unwrap();
+        assert_eq!(config.effective_key_value_history_depth(), 4);
+        assert_eq!(config.effective_recent_deltas_capacity(), 128);
+
+        let config = builder()
+            .debug_buffers_enabled(true)
+            .key_value_history_depth(0)
+            .recent_deltas_capacity(MAX_RECENT_DELTAS_CAPACITY)
+            .build()
+            .unwrap();
+        assert_eq!(config.effective_key_value_history_depth(), 0);
+        assert_eq!(
+            config.effective_recent_deltas_capacity(),
+            MAX_RECENT_DELTAS_CAPACITY
+        );
+
+        let config = builder()
+            .key_value_history_depth(8)
+            .recent_deltas_capacity(8)
+            .build()
+            .unwrap();
+        assert_eq!(config.effective_key_value_history_depth(), 0);
+        assert_eq!(config.effective_recent_deltas_capacity(), 0);
+        assert!(config.debug_buffer_sizes_ignored());
+
+        let config = builder().build().unwrap();
+        assert!(!config.debug_buffer_sizes_ignored());
+
+        // The public fields bypass the validation of the builder.
+        let mut config = ChitchatConfig::for_test(10_001);
+        config.debug_buffers_enabled = true;
+        config.key_value_history_depth = usize::MAX;
+        config.recent_deltas_capacity = usize::MAX;
+        assert_eq!(
+            config.effective_key_value_history_depth(),
+            MAX_KEY_VALUE_HISTORY_DEPTH
+        );
+        assert_eq!(
+            config.effective_recent_deltas_capacity(),
+            MAX_RECENT_DELTAS_CAPACITY
+        );
+    }
+}
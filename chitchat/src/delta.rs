@@ -1,4 +1,7 @@
 use std::collections::HashSet;
+use std::ops::AddAssign;
+
+use anyhow::Context;
 
 use crate::serialize::*;
 use crate::types::{DeletionStatusMutation, KeyValueMutation, KeyValueMutationRef};
@@ -8,7 +11,7 @@ use crate::{ChitchatId, Version, VersionedValue};
 ///
 /// Its serialization is done by transforming it into a sequence of operations,
 /// encoded one after the other in a compressed stream.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Delta {
     pub(crate) node_deltas: Vec<NodeDelta>,
     serialized_len: usize,
@@ -23,7 +26,38 @@ impl Default for Delta {
     }
 }
 
+/// Size of one or several deltas received from peers.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DeltaStats {
+    /// The number of node deltas.
+    pub num_node_deltas: usize,
+    /// The number of key-values, including tombstones.
+    pub num_key_values: usize,
+    /// The serialized length of the deltas, in bytes.
+    pub num_bytes: usize,
+}
+
+impl AddAssign for DeltaStats {
+    fn add_assign(&mut self, other: DeltaStats) {
+        self.num_node_deltas += other.num_node_deltas;
+        self.num_key_values += other.num_key_values;
+        self.num_bytes += other.num_bytes;
+    }
+}
+
 impl Delta {
+    pub(crate) fn stats(&self) -> DeltaStats {
+        DeltaStats {
+            num_node_deltas: self.node_deltas.len(),
+            num_key_values: self
+                .node_deltas
+                .iter()
+                .map(|node_delta| node_delta.key_values.len())
+                .sum(),
+            num_bytes: self.serialized_len,
+        }
+    }
+
     fn get_operations(&self) -> impl Iterator<Item = DeltaOpRef<'_>> {
         self.node_deltas.iter().flat_map(|node_delta| {
             std::iter::once(DeltaOpRef::Node {
@@ -100,13 +134,17 @@ impl From<DeltaOpTag> for u8 {
 
 impl Deserializable for DeltaOp {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
-        let tag_bytes: [u8; 1] = Deserializable::deserialize(buf)?;
+        let tag_bytes: [u8; 1] =
+            Deserializable::deserialize(buf).context("failed to deserialize delta op tag")?;
         let tag = DeltaOpTag::try_from(tag_bytes[0])?;
         match tag {
             DeltaOpTag::Node => {
-                let chitchat_id = ChitchatId::deserialize(buf)?;
-                let last_gc_version = Version::deserialize(buf)?;
-                let from_version_excluded = u64::deserialize(buf)?;
+                let chitchat_id = ChitchatId::deserialize(buf)
+                    .context("failed to deserialize node op chitchat id")?;
+                let last_gc_version = Version::deserialize(buf)
+                    .context("failed to deserialize node op last gc version")?;
+                let from_version_excluded = u64::deserialize(buf)
+                    .context("failed to deserialize node op from version excluded")?;
                 Ok(DeltaOp::Node {
                     chitchat_id,
                     last_gc_version,
@@ -114,19 +152,24 @@ impl Deserializable for DeltaOp {
                 })
             }
             DeltaOpTag::KeyValue => {
-                let key = String::deserialize(buf)?;
-                let value = String::deserialize(buf)?;
-                let version = u64::deserialize(buf)?;
-                let deleted = DeletionStatusMutation::deserialize(buf)?;
+                let key =
+                    String::deserialize(buf).context("failed to deserialize key-value op key")?;
+                let value = String::deserialize(buf)
+                    .with_context(|| format!("failed to deserialize value of key `{key}`"))?;
+                let version = u64::deserialize(buf)
+                    .with_context(|| format!("failed to deserialize version of key `{key}`"))?;
+                let deleted = DeletionStatusMutation::deserialize(buf)
+                    .with_context(|| format!("failed to deserialize status of key `{key}`"))?;
                 Ok(DeltaOp::KeyValue(KeyValueMutation {
                     key,
-                    value,
+                    value: value.into(),
                     version,
                     status: deleted,
                 }))
             }
             DeltaOpTag::SetMaxVersion => {
-                let max_version = Version::deserialize(buf)?;
+                let max_version = Version::deserialize(buf)
+                    .context("failed to deserialize set-max-version op max version")?;
                 Ok(DeltaOp::SetMaxVersion { max_version })
             }
         }
@@ -134,7 +177,7 @@ impl Deserializable for DeltaOp {
 }
 
 impl DeltaOp {
-    fn as_ref(&self) -> DeltaOpRef {
+    fn as_ref(&self) -> DeltaOpRef<'_> {
         match self {
             DeltaOp::Node {
                 chitchat_id,
@@ -225,11 +268,14 @@ impl Serializable for Delta {
 impl Deserializable for Delta {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
         let original_len = buf.len();
-        let ops: Vec<DeltaOp> = crate::serialize::deserialize_stream(buf)?;
+        let ops: Vec<DeltaOp> =
+            crate::serialize::deserialize_stream(buf).context("failed to deserialize delta ops")?;
         let consumed_len = original_len - buf.len();
         let mut delta_builder = DeltaBuilder::default();
-        for op in ops {
-            delta_builder.apply_op(op)?;
+        for (op_idx, op) in ops.into_iter().enumerate() {
+            delta_builder
+                .apply_op(op)
+                .with_context(|| format!("invalid delta op #{op_idx}"))?;
         }
         Ok(delta_builder.finish(consumed_len))
     }
@@ -278,7 +324,7 @@ impl Delta {
             .unwrap();
         node_delta.key_values.push(KeyValueMutation {
             key: key.to_string(),
-            value: value.to_string(),
+            value: value.into(),
             version,
             status: if deleted {
                 DeletionStatusMutation::Delete
@@ -297,9 +343,26 @@ impl Delta {
             .iter()
             .find(|node_delta| &node_delta.chitchat_id == chitchat_id)
     }
+
+    /// Returns the nodes updated by the delta.
+    pub fn nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.node_deltas
+            .iter()
+            .map(|node_delta| &node_delta.chitchat_id)
+    }
+
+    /// Returns the key-value updates carried by the delta, along with the node they belong to.
+    pub fn key_values(&self) -> impl Iterator<Item = (&ChitchatId, &KeyValueMutation)> {
+        self.node_deltas.iter().flat_map(|node_delta| {
+            node_delta
+                .key_values
+                .iter()
+                .map(move |key_value_mutation| (&node_delta.chitchat_id, key_value_mutation))
+        })
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, serde::Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
 pub(crate) struct NodeDelta {
     pub chitchat_id: ChitchatId,
     // `from_version_excluded` and `last_gc_version` are here to express on which states
@@ -320,9 +383,30 @@ pub(crate) struct NodeDelta {
     pub from_version_excluded: Version,
     pub last_gc_version: Version,
     pub key_values: Vec<KeyValueMutation>,
+    // Set instead of key-values when the sender has no key-value to share in
+    // (`from_version_excluded`, `max_version`], typically because all the key-values of the node
+    // in that range were tombstones that have since been garbage collected. The two are never
+    // set together: deltas mixing them are rejected when deserialized.
+    //
+    // The max version is only applied when the delta resets the node state. Otherwise, the
+    // receiver keeps its max version, see `NodeState::apply_delta`.
     pub max_version: Option<Version>,
 }
 
+impl NodeDelta {
+    /// Returns the greatest version mentioned by the node delta.
+    pub(crate) fn greatest_version(&self) -> Version {
+        self.key_values
+            .iter()
+            .map(|key_value_mutation| key_value_mutation.version)
+            .chain(self.max_version)
+            .fold(
+                self.from_version_excluded.max(self.last_gc_version),
+                Version::max,
+            )
+    }
+}
+
 #[cfg(test)]
 impl NodeDelta {
     pub fn num_tuples(&self) -> usize {
@@ -330,7 +414,7 @@ impl NodeDelta {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct DeltaBuilder {
     existing_nodes: HashSet<ChitchatId>,
     delta: Delta,
@@ -366,10 +450,17 @@ impl DeltaBuilder {
                 let Some(current_node_delta) = self.current_node_delta.as_mut() else {
                     anyhow::bail!("received a key-value op without a node op before.");
                 };
+                anyhow::ensure!(
+                    current_node_delta.max_version.is_none(),
+                    "received a key-value op after a set-max-version op"
+                );
                 if let Some(previous_key_value_mutation) = current_node_delta.key_values.last() {
+                    // Key-values set as a batch share a version. This is only valid since
+                    // protocol version 1: nodes running protocol version 0, which required strictly
+                    // increasing versions, drop our messages before reaching this point.
                     anyhow::ensure!(
-                        previous_key_value_mutation.version < key_value_mutation.version,
-                        "kv version should be increasing"
+                        previous_key_value_mutation.version <= key_value_mutation.version,
+                        "kv version should be non-decreasing"
                     );
                 }
                 current_node_delta.key_values.push(key_value_mutation);
@@ -378,6 +469,11 @@ impl DeltaBuilder {
                 let Some(current_node_delta) = self.current_node_delta.as_mut() else {
                     anyhow::bail!("received a key-value op without a node op before.");
                 };
+                anyhow::ensure!(
+                    current_node_delta.key_values.is_empty()
+                        && current_node_delta.max_version.is_none(),
+                    "set-max-version op must be the only op of a node delta"
+                );
                 current_node_delta.max_version = Some(max_version);
             }
         }
@@ -400,6 +496,7 @@ impl DeltaBuilder {
 ///
 /// We do it by calling `try_add_node_reset`, `try_add_node`, and `try_add_kv`
 /// and stopping as soon as one of this methods returns `false`.
+#[derive(Clone)]
 pub struct DeltaSerializer {
     mtu: usize,
     delta_builder: DeltaBuilder,
@@ -408,9 +505,12 @@ pub struct DeltaSerializer {
 
 const BLOCK_THRESHOLD: u16 = 16_384u16;
 
+/// Smallest MTU accepted by [`DeltaSerializer::with_mtu`].
+pub(crate) const MIN_DELTA_MTU: usize = 100;
+
 impl DeltaSerializer {
     pub fn with_mtu(mtu: usize) -> Self {
-        assert!(mtu >= 100);
+        assert!(mtu >= MIN_DELTA_MTU);
         let block_threshold = u16::try_from((BLOCK_THRESHOLD as usize).min(mtu)).unwrap();
         DeltaSerializer {
             mtu,
@@ -419,6 +519,8 @@ impl DeltaSerializer {
         }
     }
 
+    /// Sets the max version of the last added node, which must not have any key-value. See
+    /// `NodeDelta::max_version`.
     #[must_use]
     pub fn try_set_max_version(&mut self, max_version: Version) -> bool {
         let key_value_op = DeltaOp::SetMaxVersion { max_version };
@@ -488,16 +590,17 @@ mod tests {
         // 4 bytes
         let mut delta_writer = DeltaSerializer::with_mtu(198);
 
-        // ChitchatId takes 27 bytes = 15 bytes + 2 bytes for node length + "node-10001".len().
+        // ChitchatId takes 19 bytes = 8 bytes + 1 byte for node length + "node-10001".len().
         let node1 = ChitchatId::for_local_test(10_001);
 
-        // +37 bytes = 8 bytes (heartbeat) + 2 bytes (empty node delta) + 27 bytes (node).
+        // +22 bytes = 1 byte (op tag) + 19 bytes (node) + 1 byte (last gc version) + 1 byte (from
+        // version).
         assert!(delta_writer.try_add_node(node1, 80u64, 50u64));
 
-        // +9 bytes: +1 bytes + 8 bytes (version)
+        // +2 bytes: 1 byte (op tag) + 1 byte (version)
         assert!(delta_writer.try_set_max_version(100));
 
-        test_aux_delta_writer(delta_writer, 1 + 56);
+        test_aux_delta_writer(delta_writer, 1 + 27);
     }
 
     #[test]
@@ -505,55 +608,55 @@ mod tests {
         // 4 bytes
         let mut delta_writer = DeltaSerializer::with_mtu(198);
 
-        // ChitchatId takes 27 bytes = 15 bytes + 2 bytes for node length + "node-10001".len().
+        // ChitchatId takes 19 bytes = 8 bytes + 1 byte for node length + "node-10001".len().
         let node1 = ChitchatId::for_local_test(10_001);
-        // +37 bytes = 8 bytes (heartbeat) + 2 bytes (empty node delta) + 27 bytes (node).
+        // +22 bytes = 1 byte (op tag) + 19 bytes (node) + 2 bytes (versions).
         assert!(delta_writer.try_add_node(node1, 0u64, 0u64));
 
-        // +23 bytes: 2 bytes (key length) + 5 bytes (key) + 7 bytes (values) + 8 bytes (version) +
-        // 1 bytes (empty tombstone).
+        // +15 bytes: 1 byte (op tag) + 6 bytes (key) + 6 bytes (value) + 1 byte (version) +
+        // 1 byte (status).
         assert!(delta_writer.try_add_kv(
             "key11",
             VersionedValue {
-                value: "val11".to_string(),
+                value: "val11".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             },
         ));
-        // +26 bytes: 2 bytes (key length) + 5 bytes (key) + 8 bytes (version) +
-        // 9 bytes (empty tombstone).
+        // +10 bytes: 1 byte (op tag) + 6 bytes (key) + 1 byte (empty value) + 1 byte (version) +
+        // 1 byte (status).
         assert!(delta_writer.try_add_kv(
             "key12",
             VersionedValue {
-                value: "".to_string(),
+                value: "".into(),
                 version: 2,
                 status: DeletionStatus::Deleted(Instant::now()),
             },
         ));
 
         let node2 = ChitchatId::for_local_test(10_002);
-        // +37 bytes
+        // +22 bytes
         assert!(delta_writer.try_add_node(node2, 0, 0u64));
 
-        // +23 bytes.
+        // +15 bytes.
         assert!(delta_writer.try_add_kv(
             "key21",
             VersionedValue {
-                value: "val21".to_string(),
+                value: "val21".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             },
         ));
-        // +23 bytes.
+        // +15 bytes.
         assert!(delta_writer.try_add_kv(
             "key22",
             VersionedValue {
-                value: "val22".to_string(),
+                value: "val22".into(),
                 version: 3,
                 status: DeletionStatus::Set,
             },
         ));
-        test_aux_delta_writer(delta_writer, 98);
+        test_aux_delta_writer(delta_writer, 86);
     }
 
     #[test]
@@ -561,35 +664,35 @@ mod tests {
         // 1 bytes (End tag)
         let mut delta_writer = DeltaSerializer::with_mtu(140);
 
-        // ChitchatId takes 27 bytes = 15 bytes + 2 bytes for node length + "node-10001".len().
+        // ChitchatId takes 19 bytes = 8 bytes + 1 byte for node length + "node-10001".len().
         let node1 = ChitchatId::for_local_test(10_001);
-        // +37 bytes = 8 bytes (last gc version) + 27 bytes (node) +  2bytes (block length)
+        // +25 bytes = 22 bytes (node op) + 3 bytes (block header)
         assert!(delta_writer.try_add_node(node1, 0, 0u64));
 
-        // +24 bytes (kv + op tag)
+        // +15 bytes (kv + op tag)
         assert!(delta_writer.try_add_kv(
             "key11",
             VersionedValue {
-                value: "val11".to_string(),
+                value: "val11".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
         ));
 
-        // +24 bytes. (kv + op tag)
+        // +15 bytes. (kv + op tag)
         assert!(delta_writer.try_add_kv(
             "key12",
             VersionedValue {
-                value: "val12".to_string(),
+                value: "val12".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             }
         ));
 
         let node2 = ChitchatId::for_local_test(10_002);
-        // +37 bytes = 8 bytes (last gc version) + 2 bytes (empty node delta) + 27 bytes (node).
+        // +22 bytes = 1 byte (op tag) + 19 bytes (node) + 2 bytes (versions).
         assert!(delta_writer.try_add_node(node2, 0, 0u64));
-        test_aux_delta_writer(delta_writer, 80);
+        test_aux_delta_writer(delta_writer, 78);
     }
 
     #[track_caller]
@@ -603,42 +706,39 @@ mod tests {
         // 1 bytes (end tag)
         let mut delta_writer = DeltaSerializer::with_mtu(155);
 
-        // +27 bytes (ChitchatId) + 1 (op tag) + 3 bytes (block len)
-        // = 32 bytes
-
         let node1 = ChitchatId::for_local_test(10_001);
 
-        // +8 bytes (last gc version) + 27 bytes (ChitchatId) + (1 op tag) + 3 bytes (pessimistic
-        // new block) = 71
+        // +2 bytes (versions) + 19 bytes (ChitchatId) + (1 op tag) + 3 bytes (pessimistic
+        // new block) = 26
         assert!(delta_writer.try_add_node(node1, 0u64, 0u64));
 
-        // +23 bytes (kv) + 1 (op tag)
-        // = 95
+        // +14 bytes (kv) + 1 (op tag)
+        // = 41
         assert!(delta_writer.try_add_kv(
             "key11",
             VersionedValue {
-                value: "val11".to_string(),
+                value: "val11".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
         ));
-        // +23 bytes (kv) + 1 (op tag)
-        // = 119
+        // +14 bytes (kv) + 1 (op tag)
+        // = 56
         assert!(delta_writer.try_add_kv(
             "key12",
             VersionedValue {
-                value: "val12".to_string(),
+                value: "val12".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             }
         ));
 
         let node2 = ChitchatId::for_local_test(10_002);
-        // +8 bytes (last gc version) + 27 bytes (ChitchatId) + 1 byte (op tag)
-        // = 155
+        // +2 bytes (versions) + 19 bytes (ChitchatId) + 1 byte (op tag)
+        // = 78
         assert!(delta_writer.try_add_node(node2, 0u64, 0));
         // The block got compressed.
-        test_aux_delta_writer(delta_writer, 80);
+        test_aux_delta_writer(delta_writer, 78);
     }
 
     #[test]
@@ -647,34 +747,33 @@ mod tests {
         let mut delta_writer = DeltaSerializer::with_mtu(100);
 
         let node1 = ChitchatId::for_local_test(10_001);
-        // +37 bytes = 8 bytes (heartbeat) + 2 bytes (empty node delta) + 27 bytes (ChitchatId).
+        // +22 bytes = 1 byte (op tag) + 19 bytes (ChitchatId) + 2 bytes (versions).
         assert!(delta_writer.try_add_node(node1, 0, 0));
 
-        // +23 bytes.
+        // +32 bytes.
         assert!(delta_writer.try_add_kv(
             "key11",
             VersionedValue {
-                value: "val11".to_string(),
+                value: "val11:7f3a9c2e1b4d5e6f".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
         ));
-        // +23 bytes.
+        // +32 bytes.
         assert!(delta_writer.try_add_kv(
             "key12",
             VersionedValue {
-                value: "val12".to_string(),
+                value: "val12:e8b5d0f6a3c7b9a1".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             }
         ));
 
         let node2 = ChitchatId::for_local_test(10_002);
-        // +37 bytes = 8 bytes (heartbeat) + 2 bytes (empty node delta) + 27 bytes (ChitchatId).
+        // +22 bytes.
         assert!(!delta_writer.try_add_node(node2, 0u64, 1u64));
 
-        // The block got compressed.
-        test_aux_delta_writer(delta_writer, 72);
+        test_aux_delta_writer(delta_writer, 90);
     }
 
     #[test]
@@ -684,32 +783,32 @@ mod tests {
 
         let node1 = ChitchatId::for_local_test(10_001);
 
-        // + 3 bytes (block tag) + 35 bytes (node) + 1 byte (op tag)
-        // = 40
+        // + 3 bytes (block tag) + 21 bytes (node) + 1 byte (op tag)
+        // = 26
         assert!(delta_writer.try_add_node(node1, 0u64, 1u64));
 
-        // +23 bytes (kv) + 1 (op tag) + 3 bytes (pessimistic block tag)
-        // = 67
+        // +14 bytes (kv) + 1 (op tag) + 3 bytes (pessimistic block tag)
+        // = 44
         assert!(delta_writer.try_add_kv(
             "key11",
             VersionedValue {
-                value: "val11".to_string(),
+                value: "val11".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
         ));
 
-        // +33 bytes (kv) + 1 (op tag)
-        // = 101 (exceeding mtu!)
+        // +61 bytes (kv) + 1 (op tag)
+        // = 106 (exceeding mtu!)
         assert!(!delta_writer.try_add_kv(
             "key12",
             VersionedValue {
-                value: "val12aaaaaaaaaabcc".to_string(),
+                value: "val12aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaabcc".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             }
         ));
-        test_aux_delta_writer(delta_writer, 72);
+        test_aux_delta_writer(delta_writer, 41);
     }
 
     #[test]
@@ -723,7 +822,7 @@ mod tests {
         assert!(delta_writer.try_add_kv(
             "key11",
             VersionedValue {
-                value: "val11".to_string(),
+                value: "val11".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
@@ -731,7 +830,7 @@ mod tests {
         assert!(!delta_writer.try_add_kv(
             "key12",
             VersionedValue {
-                value: "val12".to_string(),
+                value: "val12".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             }
@@ -739,13 +838,81 @@ mod tests {
         delta_writer.try_add_kv(
             "key13",
             VersionedValue {
-                value: "val12".to_string(),
+                value: "val12".into(),
                 version: 2,
                 status: DeletionStatus::Set,
             },
         );
     }
 
+    #[test]
+    fn test_delta_deserialization_rejects_key_values_with_max_version() {
+        let node1 = ChitchatId::for_local_test(10_001);
+        let key_value_mutation = KeyValueMutation {
+            key: "key1".to_string(),
+            value: "val1".into(),
+            version: 1,
+            status: DeletionStatusMutation::Set,
+        };
+        let node_op = DeltaOpRef::Node {
+            chitchat_id: &node1,
+            last_gc_version: 0,
+            from_version_excluded: 0,
+        };
+        let key_value_op = DeltaOpRef::KeyValue((&key_value_mutation).into());
+        let set_max_version_op = DeltaOpRef::SetMaxVersion { max_version: 2 };
+
+        for (ops, invalid_op_idx) in [
+            ([&node_op, &key_value_op, &set_max_version_op], 2),
+            ([&node_op, &set_max_version_op, &key_value_op], 2),
+            ([&node_op, &set_max_version_op, &set_max_version_op], 2),
+        ] {
+            let mut compressed_stream_writer = CompressedStreamWriter::with_block_threshold(16_384);
+            for op in ops {
+                compressed_stream_writer.append(op);
+            }
+            let buf = compressed_stream_writer.finish();
+            let error = Delta::deserialize(&mut &buf[..]).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("invalid delta op #{invalid_op_idx}")
+            );
+        }
+    }
+
+    #[test]
+    fn test_delta_deserialization_key_value_versions() {
+        let node1 = ChitchatId::for_local_test(10_001);
+        let key_value_mutation = |key: &str, version: Version| KeyValueMutation {
+            key: key.to_string(),
+            value: "val".into(),
+            version,
+            status: DeletionStatusMutation::Set,
+        };
+        let node_op = DeltaOpRef::Node {
+            chitchat_id: &node1,
+            last_gc_version: 0,
+            from_version_excluded: 0,
+        };
+        let deserialize = |key_value_mutations: [KeyValueMutation; 2]| {
+            let mut compressed_stream_writer = CompressedStreamWriter::with_block_threshold(16_384);
+            compressed_stream_writer.append(&node_op);
+            for key_value_mutation in &key_value_mutations {
+                compressed_stream_writer.append(&DeltaOpRef::KeyValue(key_value_mutation.into()));
+            }
+            let buf = compressed_stream_writer.finish();
+            Delta::deserialize(&mut &buf[..])
+        };
+        // Key-values set as a batch share a version.
+        let delta =
+            deserialize([key_value_mutation("key1", 2), key_value_mutation("key2", 2)]).unwrap();
+        assert_eq!(delta.num_tuples(), 2);
+
+        let error = deserialize([key_value_mutation("key1", 2), key_value_mutation("key2", 1)])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "invalid delta op #2");
+    }
+
     #[test]
     fn test_delta_op_tag() {
         let mut num_valid_tags = 0;
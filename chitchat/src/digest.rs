@@ -1,15 +1,35 @@
 use std::collections::BTreeMap;
 
+use anyhow::Context;
+
 use crate::serialize::*;
 use crate::{ChitchatId, Heartbeat, Version};
 
+/// Summary of the state of a node, as known by the node emitting the digest.
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
-pub(crate) struct NodeDigest {
+pub struct NodeDigest {
     pub(crate) heartbeat: Heartbeat,
     pub(crate) last_gc_version: Version,
     pub(crate) max_version: Version,
 }
 
+impl NodeDigest {
+    /// Returns the last heartbeat of the node.
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat
+    }
+
+    /// Returns the version of the last garbage collection of the node's tombstones.
+    pub fn last_gc_version(&self) -> Version {
+        self.last_gc_version
+    }
+
+    /// Returns the max version of the node's key-values.
+    pub fn max_version(&self) -> Version {
+        self.max_version
+    }
+}
+
 impl Serializable for NodeDigest {
     fn serialize(&self, buf: &mut Vec<u8>) {
         self.heartbeat.serialize(buf);
@@ -26,9 +46,10 @@ impl Serializable for NodeDigest {
 
 impl Deserializable for NodeDigest {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
-        let heartbeat = Heartbeat::deserialize(buf)?;
-        let last_gc_version = Version::deserialize(buf)?;
-        let max_version = Version::deserialize(buf)?;
+        let heartbeat = Heartbeat::deserialize(buf).context("failed to deserialize heartbeat")?;
+        let last_gc_version =
+            Version::deserialize(buf).context("failed to deserialize last gc version")?;
+        let max_version = Version::deserialize(buf).context("failed to deserialize max version")?;
         Ok(NodeDigest {
             heartbeat,
             last_gc_version,
@@ -42,11 +63,41 @@ impl Deserializable for NodeDigest {
 ///
 /// It is equivalent to a map
 /// peer -> (heartbeat, max version).
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub struct Digest {
     pub(crate) node_digests: BTreeMap<ChitchatId, NodeDigest>,
 }
 
+impl Digest {
+    /// Returns the digest of the given node, if present.
+    pub fn node_digest(&self, chitchat_id: &ChitchatId) -> Option<&NodeDigest> {
+        self.node_digests.get(chitchat_id)
+    }
+
+    /// Returns an iterator over the node digests, ordered by [`ChitchatId`].
+    pub fn node_digests(&self) -> impl Iterator<Item = (&ChitchatId, &NodeDigest)> {
+        self.node_digests.iter()
+    }
+
+    /// Returns the number of nodes in the digest.
+    pub fn num_nodes(&self) -> usize {
+        self.node_digests.len()
+    }
+
+    /// Returns whether both digests list the same nodes at the same versions, regardless of
+    /// their heartbeats.
+    pub(crate) fn has_same_versions(&self, other: &Digest) -> bool {
+        self.node_digests.len() == other.node_digests.len()
+            && self.node_digests.iter().zip(&other.node_digests).all(
+                |((chitchat_id, node_digest), (other_chitchat_id, other_node_digest))| {
+                    chitchat_id == other_chitchat_id
+                        && node_digest.last_gc_version == other_node_digest.last_gc_version
+                        && node_digest.max_version == other_node_digest.max_version
+                },
+            )
+    }
+}
+
 #[cfg(test)]
 impl Digest {
     pub fn add_node(
@@ -85,12 +136,16 @@ impl Serializable for Digest {
 
 impl Deserializable for Digest {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
-        let num_nodes = u16::deserialize(buf)?;
+        let num_nodes = u16::deserialize(buf).context("failed to deserialize number of nodes")?;
         let mut node_digests: BTreeMap<ChitchatId, NodeDigest> = Default::default();
 
-        for _ in 0..num_nodes {
-            let chitchat_id = ChitchatId::deserialize(buf)?;
-            let node_digest = NodeDigest::deserialize(buf)?;
+        for node_idx in 0..num_nodes {
+            let chitchat_id = ChitchatId::deserialize(buf).with_context(|| {
+                format!("failed to deserialize chitchat id of node digest #{node_idx}")
+            })?;
+            let node_digest = NodeDigest::deserialize(buf).with_context(|| {
+                format!("failed to deserialize node digest #{node_idx} (node `{chitchat_id:?}`)")
+            })?;
             node_digests.insert(chitchat_id, node_digest);
         }
         Ok(Digest { node_digests })
@@ -100,7 +155,7 @@ impl Deserializable for Digest {
 #[cfg(test)]
 mod tests {
     use crate::digest::{Digest, NodeDigest};
-    use crate::serialize::test_serdeser_aux;
+    use crate::serialize::{test_serdeser_aux, Deserializable, Serializable};
     use crate::{ChitchatId, Heartbeat};
 
     #[test]
@@ -110,7 +165,7 @@ mod tests {
             last_gc_version: 2,
             max_version: 3,
         };
-        test_serdeser_aux(&node_digest, 24);
+        test_serdeser_aux(&node_digest, 3);
     }
 
     #[test]
@@ -122,6 +177,43 @@ mod tests {
         digest.add_node(node1, Heartbeat(101), 1, 11);
         digest.add_node(node2, Heartbeat(102), 20, 12);
         digest.add_node(node3, Heartbeat(103), 0, 13);
-        test_serdeser_aux(&digest, 104);
+        test_serdeser_aux(&digest, 46);
+    }
+
+    #[test]
+    fn test_digest_deserialization_error_context() {
+        let mut digest = Digest::default();
+        digest.add_node(ChitchatId::for_local_test(10_001), Heartbeat(101), 1, 11);
+        digest.add_node(ChitchatId::for_local_test(10_002), Heartbeat(102), 20, 12);
+        let mut buf = Vec::new();
+        digest.serialize(&mut buf);
+        buf.truncate(buf.len() - 1);
+
+        let error = Digest::deserialize(&mut &buf[..]).unwrap_err();
+        let error_msg = format!("{error:#}");
+        assert!(error_msg.starts_with("failed to deserialize node digest #1"));
+        assert!(error_msg.contains("failed to deserialize max version"));
+    }
+
+    #[test]
+    fn test_digest_has_same_versions() {
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node2 = ChitchatId::for_local_test(10_002);
+        let mut digest = Digest::default();
+        digest.add_node(node1.clone(), Heartbeat(101), 1, 11);
+        digest.add_node(node2.clone(), Heartbeat(102), 2, 12);
+
+        let mut other_digest = Digest::default();
+        other_digest.add_node(node1.clone(), Heartbeat(201), 1, 11);
+        assert!(!digest.has_same_versions(&other_digest));
+
+        other_digest.add_node(node2.clone(), Heartbeat(202), 2, 12);
+        assert!(digest.has_same_versions(&other_digest));
+
+        other_digest.add_node(node2.clone(), Heartbeat(202), 2, 13);
+        assert!(!digest.has_same_versions(&other_digest));
+
+        other_digest.add_node(node2, Heartbeat(202), 3, 12);
+        assert!(!digest.has_same_versions(&other_digest));
     }
 }
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Errors returned by the public API of chitchat, so that callers can tell failure modes apart.
+///
+/// Transports report their own errors as [`anyhow::Error`]s, which are wrapped in the variants
+/// below.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ChitchatError {
+    /// The transport failed to open the gossip socket.
+    Bind {
+        listen_addr: SocketAddr,
+        source: anyhow::Error,
+    },
+    /// The transport failed to send or receive messages.
+    Io(anyhow::Error),
+    /// A message could not be deserialized.
+    Deserialize(anyhow::Error),
+    /// A serialized message exceeds the maximum transmission unit of the transport: 65,507 bytes
+    /// for the UDP and Unix datagram transports.
+    Mtu { message_len: usize, mtu: usize },
+    /// The server is no longer running: it was shut down or aborted, or it panicked.
+    ServerStopped,
+    /// The peer did not answer a gossip handshake in time.
+    HandshakeTimeout { peer_addr: SocketAddr },
+    /// The SYN message initiating a gossip handshake was not sent because the gossip byte budget
+    /// is spent.
+    GossipThrottled { peer_addr: SocketAddr },
+}
+
+impl fmt::Display for ChitchatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChitchatError::Bind { listen_addr, .. } => {
+                write!(f, "failed to open gossip socket on {listen_addr}")
+            }
+            ChitchatError::Io(_) => write!(f, "failed to send or receive gossip messages"),
+            ChitchatError::Deserialize(_) => write!(f, "failed to deserialize gossip message"),
+            ChitchatError::Mtu { message_len, mtu } => write!(
+                f,
+                "serialized message size ({message_len} bytes) exceeds MTU ({mtu} bytes)"
+            ),
+            ChitchatError::ServerStopped => write!(f, "chitchat server is no longer running"),
+            ChitchatError::HandshakeTimeout { peer_addr } => {
+                write!(f, "gossip handshake with {peer_addr} timed out")
+            }
+            ChitchatError::GossipThrottled { peer_addr } => {
+                write!(f, "SYN to {peer_addr} skipped: gossip byte budget spent")
+            }
+        }
+    }
+}
+
+impl ChitchatError {
+    /// Converts an error returned by a transport, preserving the [`ChitchatError`] it may carry,
+    /// e.g. [`ChitchatError::Mtu`]. Other errors are wrapped in [`ChitchatError::Io`].
+    pub(crate) fn from_transport_error(error: anyhow::Error) -> ChitchatError {
+        match error.downcast::<ChitchatError>() {
+            Ok(chitchat_error) => chitchat_error,
+            Err(error) => ChitchatError::Io(error),
+        }
+    }
+}
+
+impl std::error::Error for ChitchatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChitchatError::Bind { source, .. }
+            | ChitchatError::Io(source)
+            | ChitchatError::Deserialize(source) => Some(source.as_ref()),
+            ChitchatError::Mtu { .. }
+            | ChitchatError::ServerStopped
+            | ChitchatError::HandshakeTimeout { .. }
+            | ChitchatError::GossipThrottled { .. } => None,
+        }
+    }
+}
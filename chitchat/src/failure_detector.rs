@@ -16,17 +16,38 @@ pub struct FailureDetector {
     config: FailureDetectorConfig,
     /// Denotes live nodes.
     live_nodes: HashSet<ChitchatId>,
+    /// Denotes live nodes whose phi is above the suspect threshold.
+    suspected_nodes: HashSet<ChitchatId>,
     /// Denotes dead nodes.
     dead_nodes: HashMap<ChitchatId, Instant>,
+    /// Instant at which the failure detector was created.
+    start: Instant,
+    /// Period following `start` during which nodes that have not been sampled enough are not
+    /// marked as dead.
+    startup_grace_period: Duration,
 }
 
 impl FailureDetector {
+    /// Creates a failure detector without a startup grace period.
+    #[cfg(test)]
     pub fn new(config: FailureDetectorConfig) -> Self {
+        Self::with_startup_grace_period(config, Duration::ZERO)
+    }
+
+    /// Creates a failure detector that does not mark as dead the nodes it has not sampled enough
+    /// during the `startup_grace_period` following its creation.
+    pub fn with_startup_grace_period(
+        config: FailureDetectorConfig,
+        startup_grace_period: Duration,
+    ) -> Self {
         Self {
             node_samples: HashMap::new(),
             config,
             live_nodes: HashSet::new(),
+            suspected_nodes: HashSet::new(),
             dead_nodes: HashMap::new(),
+            start: Instant::now(),
+            startup_grace_period,
         }
     }
 
@@ -53,19 +74,69 @@ impl FailureDetector {
             .report_heartbeat();
     }
 
+    /// Clears the heartbeat intervals sampled for a node, without removing the node from the
+    /// failure detector. Returns `false` if the node is unknown.
+    ///
+    /// The sampling window is seeded with a single interval equal to the initial interval, so
+    /// that the phi of the node remains defined and the node is not declared dead merely because
+    /// its samples were reset. The liveness of the node is reevaluated on the next call to
+    /// [`FailureDetector::update_node_liveness`].
+    pub fn reset_samples(&mut self, chitchat_id: &ChitchatId) -> bool {
+        let Some(sampling_window) = self.node_samples.get_mut(chitchat_id) else {
+            return false;
+        };
+        debug!(node_id=%chitchat_id.node_id, "resetting node heartbeat samples.");
+        sampling_window.reset_to_prior();
+        true
+    }
+
+    /// Overrides the sampling window size of a node, which otherwise defaults to
+    /// [`FailureDetectorConfig::sampling_window_size`].
+    ///
+    /// The sampling window of the node is rebuilt, preserving the most recent intervals that fit
+    /// in the new window. In particular, shrinking the window discards the oldest samples. The
+    /// override is forgotten once the node is garbage collected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_size` is zero.
+    pub fn set_window_size(&mut self, chitchat_id: &ChitchatId, window_size: usize) {
+        assert!(window_size > 0, "sampling window size must be positive");
+        debug!(node_id=%chitchat_id.node_id, window_size, "overriding node sampling window size.");
+        self.get_or_create_sampling_window(chitchat_id)
+            .set_window_size(window_size);
+    }
+
     /// Marks the node as dead or alive based on the current phi value.
+    ///
+    /// During the startup grace period, nodes for which phi cannot be computed yet are left
+    /// untouched rather than marked as dead.
     pub fn update_node_liveness(&mut self, chitchat_id: &ChitchatId) {
         let phi_opt = self.phi(chitchat_id);
-        let is_alive = self
-            .phi(chitchat_id)
+        if phi_opt.is_none() && self.start.elapsed() < self.startup_grace_period {
+            debug!(node_id=%chitchat_id.node_id, "not enough samples to compute node liveness yet");
+            return;
+        }
+        let is_alive = phi_opt
             .map(|phi| phi <= self.config.phi_threshold)
             .unwrap_or(false);
         debug!(node_id=%chitchat_id.node_id, phi=?phi_opt, is_alive=is_alive, "computing node liveness");
         if is_alive {
             self.live_nodes.insert(chitchat_id.clone());
             self.dead_nodes.remove(chitchat_id);
+
+            let is_suspected = match (phi_opt, self.config.suspect_threshold) {
+                (Some(phi), Some(suspect_threshold)) => phi > suspect_threshold,
+                _ => false,
+            };
+            if is_suspected {
+                self.suspected_nodes.insert(chitchat_id.clone());
+            } else {
+                self.suspected_nodes.remove(chitchat_id);
+            }
         } else {
             self.live_nodes.remove(chitchat_id);
+            self.suspected_nodes.remove(chitchat_id);
             if !self.dead_nodes.contains_key(chitchat_id) {
                 self.dead_nodes.insert(chitchat_id.clone(), Instant::now());
             }
@@ -80,9 +151,16 @@ impl FailureDetector {
     /// Removes and returns the list of garbage collectible nodes.
     pub fn garbage_collect(&mut self) -> Vec<ChitchatId> {
         let mut garbage_collected_nodes = Vec::new();
+        let Some(dead_node_grace_period) = self.config.dead_node_grace_period else {
+            return garbage_collected_nodes;
+        };
         let now = Instant::now();
         for (chitchat_id, &time_of_death) in &self.dead_nodes {
-            if now >= time_of_death + self.config.dead_node_grace_period {
+            // A grace period too long to be represented as an `Instant` never elapses.
+            if time_of_death
+                .checked_add(dead_node_grace_period)
+                .is_some_and(|deadline| now >= deadline)
+            {
                 garbage_collected_nodes.push(chitchat_id.clone())
             }
         }
@@ -93,30 +171,92 @@ impl FailureDetector {
         garbage_collected_nodes
     }
 
+    /// Forgets the nodes for which `is_known` returns `false`, along with their sampling window.
+    ///
+    /// Sampling windows are normally removed when their node is garbage collected, but they can
+    /// be recreated afterwards, so this bounds the memory held for nodes that churn in and out of
+    /// the cluster. Returns the number of sampling windows removed.
+    pub fn prune(&mut self, is_known: impl Fn(&ChitchatId) -> bool) -> usize {
+        let num_node_samples = self.node_samples.len();
+        self.node_samples
+            .retain(|chitchat_id, _| is_known(chitchat_id));
+        self.live_nodes.retain(|chitchat_id| is_known(chitchat_id));
+        self.suspected_nodes
+            .retain(|chitchat_id| is_known(chitchat_id));
+        self.dead_nodes
+            .retain(|chitchat_id, _| is_known(chitchat_id));
+        num_node_samples - self.node_samples.len()
+    }
+
     /// Returns the list of nodes considered live by the failure detector.
     pub fn live_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
         self.live_nodes.iter()
     }
 
+    /// Returns the number of nodes considered live by the failure detector.
+    pub fn num_live_nodes(&self) -> usize {
+        self.live_nodes.len()
+    }
+
+    /// Returns the list of live nodes suspected to be failing by the failure detector.
+    pub fn suspected_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.suspected_nodes.iter()
+    }
+
+    /// Returns whether a live node is suspected to be failing by the failure detector.
+    pub fn is_suspected(&self, chitchat_id: &ChitchatId) -> bool {
+        self.suspected_nodes.contains(chitchat_id)
+    }
+
+    /// Returns the time elapsed since the last heartbeat received from a node, or `None` if no
+    /// heartbeat was ever received from it.
+    pub fn last_heartbeat_elapsed(&self, chitchat_id: &ChitchatId) -> Option<Duration> {
+        self.node_samples.get(chitchat_id)?.last_heartbeat_elapsed()
+    }
+
     /// Returns the list of nodes considered dead by the failure detector.
     pub fn dead_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
         self.dead_nodes.keys()
     }
 
+    /// Returns the number of nodes considered dead by the failure detector.
+    pub fn num_dead_nodes(&self) -> usize {
+        self.dead_nodes.len()
+    }
+
     /// Returns the list of nodes considered dead by the failure detector.
     pub fn scheduled_for_deletion_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.dead_nodes_with_deletion_status().filter_map(
+            |(chitchat_id, is_scheduled_for_deletion)| {
+                if is_scheduled_for_deletion {
+                    Some(chitchat_id)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    /// Returns the list of nodes considered dead by the failure detector, along with whether they
+    /// are scheduled for deletion.
+    pub fn dead_nodes_with_deletion_status(&self) -> impl Iterator<Item = (&ChitchatId, bool)> {
         let now = Instant::now();
-        let half_dead_node_grace_period = self.config.dead_node_grace_period.div_f32(2.0f32);
+        let half_dead_node_grace_period_opt = self
+            .config
+            .dead_node_grace_period
+            .map(|dead_node_grace_period| dead_node_grace_period / 2);
         // Note: we can't just compute the threshold now - half_dead_node_grace_period, because it
         // would underflow on some platform (MacOS).
         self.dead_nodes
             .iter()
-            .filter_map(move |(chitchat_id, time_of_death)| {
-                if *time_of_death + half_dead_node_grace_period < now {
-                    Some(chitchat_id)
-                } else {
-                    None
-                }
+            .map(move |(chitchat_id, &time_of_death)| {
+                let is_scheduled_for_deletion =
+                    half_dead_node_grace_period_opt.is_some_and(|half_dead_node_grace_period| {
+                        time_of_death
+                            .checked_add(half_dead_node_grace_period)
+                            .is_some_and(|deadline| deadline < now)
+                    });
+                (chitchat_id, is_scheduled_for_deletion)
             })
     }
 
@@ -133,14 +273,19 @@ impl FailureDetector {
 pub struct FailureDetectorConfig {
     /// Phi threshold value above which a node is flagged as faulty.
     pub phi_threshold: f64,
+    /// Optional phi threshold value, lower than `phi_threshold`, above which a live node is
+    /// flagged as suspected.
+    pub suspect_threshold: Option<f64>,
     /// Sampling window size
     pub sampling_window_size: usize,
     /// Heartbeat longer than this will be dropped.
     pub max_interval: Duration,
     /// Initial interval used on startup when no previous heartbeat exists.
     pub initial_interval: Duration,
-    /// Threshold period after which dead node can be removed from the cluster.
-    pub dead_node_grace_period: Duration,
+    /// Threshold period after which dead node can be removed from the cluster. Dead nodes are
+    /// never garbage collected if `None`.
+    #[serde(default = "FailureDetectorConfig::default_dead_node_grace_period")]
+    pub dead_node_grace_period: Option<Duration>,
 }
 
 impl FailureDetectorConfig {
@@ -153,22 +298,28 @@ impl FailureDetectorConfig {
     ) -> Self {
         Self {
             phi_threshold,
+            suspect_threshold: None,
             sampling_window_size,
             max_interval,
             initial_interval,
-            dead_node_grace_period,
+            dead_node_grace_period: Some(dead_node_grace_period),
         }
     }
+
+    fn default_dead_node_grace_period() -> Option<Duration> {
+        Some(Duration::from_secs(24 * 60 * 60)) // 24 hours
+    }
 }
 
 impl Default for FailureDetectorConfig {
     fn default() -> Self {
         Self {
             phi_threshold: 8.0,
+            suspect_threshold: None,
             sampling_window_size: 1_000,
             max_interval: Duration::from_secs(10),
             initial_interval: Duration::from_secs(5),
-            dead_node_grace_period: Duration::from_secs(24 * 60 * 60), // 24 hours
+            dead_node_grace_period: Self::default_dead_node_grace_period(),
         }
     }
 }
@@ -232,11 +383,28 @@ impl SamplingWindow {
         self.last_heartbeat = Some(now);
     }
 
+    /// Resizes the window, retaining the most recent intervals.
+    pub fn set_window_size(&mut self, window_size: usize) {
+        self.intervals = self.intervals.resized(window_size);
+    }
+
     /// Forget about all previous intervals.
     pub fn reset(&mut self) {
         self.intervals.clear();
     }
 
+    /// Forget about all previous intervals, but the prior interval.
+    pub fn reset_to_prior(&mut self) {
+        self.intervals.clear();
+        self.intervals.append(self.additive_smoothing.prior_mean);
+    }
+
+    /// Returns the time elapsed since the last reported heartbeat.
+    pub fn last_heartbeat_elapsed(&self) -> Option<Duration> {
+        self.last_heartbeat
+            .map(|last_heartbeat| last_heartbeat.elapsed())
+    }
+
     /// Computes the sampling window's phi value.
     /// Returns `None` if have not received two heartbeat yet.
     pub fn phi(&self) -> Option<f64> {
@@ -294,6 +462,22 @@ impl BoundedArrayStats {
         }
     }
 
+    /// Returns a copy of the array with the given capacity, retaining the most recent values.
+    pub fn resized(&self, capacity: usize) -> Self {
+        let mut resized = Self::with_capacity(capacity);
+        let oldest_values = if self.is_filled {
+            &self.values[self.index..]
+        } else {
+            &[]
+        };
+        let values = oldest_values.iter().chain(&self.values[..self.index]);
+        let num_skipped_values = self.len().saturating_sub(capacity);
+        for &value in values.skip(num_skipped_values) {
+            resized.append(value);
+        }
+        resized
+    }
+
     pub fn clear(&mut self) {
         self.index = 0;
         self.is_filled = false;
@@ -323,6 +507,10 @@ mod tests {
         pub fn contains_node(&self, chitchat_id: &ChitchatId) -> bool {
             self.node_samples.contains_key(chitchat_id)
         }
+
+        pub fn num_sampling_windows(&self) -> usize {
+            self.node_samples.len()
+        }
     }
 
     #[test]
@@ -336,6 +524,40 @@ mod tests {
         assert!(failure_detector.live_nodes().next().is_none());
     }
 
+    #[tokio::test]
+    async fn test_failure_detector_startup_grace_period() {
+        tokio::time::pause();
+        let mut failure_detector = FailureDetector::with_startup_grace_period(
+            FailureDetectorConfig::default(),
+            Duration::from_secs(2),
+        );
+        let node_1 = ChitchatId::for_local_test(10_001);
+        let node_2 = ChitchatId::for_local_test(10_002);
+
+        failure_detector.report_heartbeat(&node_1);
+        failure_detector.update_node_liveness(&node_1);
+        failure_detector.update_node_liveness(&node_2);
+        assert!(failure_detector.live_nodes().next().is_none());
+        assert!(failure_detector.dead_nodes().next().is_none());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        failure_detector.report_heartbeat(&node_1);
+        failure_detector.update_node_liveness(&node_1);
+        failure_detector.update_node_liveness(&node_2);
+        assert_eq!(
+            failure_detector.live_nodes().collect::<Vec<_>>(),
+            vec![&node_1]
+        );
+        assert!(failure_detector.dead_nodes().next().is_none());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        failure_detector.update_node_liveness(&node_2);
+        assert_eq!(
+            failure_detector.dead_nodes().collect::<Vec<_>>(),
+            vec![&node_2]
+        );
+    }
+
     #[tokio::test]
     async fn test_failure_detector() {
         tokio::time::pause();
@@ -408,6 +630,100 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_failure_detector_last_heartbeat_elapsed() {
+        tokio::time::pause();
+        let mut failure_detector = FailureDetector::new(FailureDetectorConfig::default());
+        let node_1 = ChitchatId::for_local_test(10_001);
+        assert!(failure_detector.last_heartbeat_elapsed(&node_1).is_none());
+
+        failure_detector.report_heartbeat(&node_1);
+        assert_eq!(
+            failure_detector.last_heartbeat_elapsed(&node_1),
+            Some(Duration::ZERO)
+        );
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert_eq!(
+            failure_detector.last_heartbeat_elapsed(&node_1),
+            Some(Duration::from_secs(3))
+        );
+        // Resetting the samples does not forget the last heartbeat.
+        failure_detector.reset_samples(&node_1);
+        assert_eq!(
+            failure_detector.last_heartbeat_elapsed(&node_1),
+            Some(Duration::from_secs(3))
+        );
+        failure_detector.report_heartbeat(&node_1);
+        assert_eq!(
+            failure_detector.last_heartbeat_elapsed(&node_1),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure_detector_infinite_dead_node_grace_period() {
+        tokio::time::pause();
+        for dead_node_grace_period in [None, Some(Duration::MAX)] {
+            let failure_detector_config = FailureDetectorConfig {
+                dead_node_grace_period,
+                ..Default::default()
+            };
+            let mut failure_detector = FailureDetector::new(failure_detector_config);
+            let node_1 = ChitchatId::for_local_test(10_001);
+            failure_detector.report_heartbeat(&node_1);
+            failure_detector.update_node_liveness(&node_1);
+            assert_eq!(failure_detector.dead_nodes().collect::<Vec<_>>(), [&node_1]);
+
+            tokio::time::advance(Duration::from_secs(365 * 24 * 60 * 60)).await;
+            assert!(failure_detector.garbage_collect().is_empty());
+            assert_eq!(
+                failure_detector
+                    .dead_nodes_with_deletion_status()
+                    .collect::<Vec<_>>(),
+                [(&node_1, false)]
+            );
+            assert!(failure_detector
+                .scheduled_for_deletion_nodes()
+                .next()
+                .is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failure_detector_suspected_nodes() {
+        tokio::time::pause();
+        let failure_detector_config = FailureDetectorConfig {
+            phi_threshold: 8.0,
+            suspect_threshold: Some(4.0),
+            ..Default::default()
+        };
+        let mut failure_detector = FailureDetector::new(failure_detector_config);
+        let node_1 = ChitchatId::for_local_test(10_001);
+
+        for _ in 0..1_000 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            failure_detector.report_heartbeat(&node_1);
+        }
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.live_nodes().count(), 1);
+        assert!(!failure_detector.is_suspected(&node_1));
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.live_nodes().count(), 1);
+        assert!(failure_detector.is_suspected(&node_1));
+        assert_eq!(
+            failure_detector.suspected_nodes().collect::<Vec<_>>(),
+            vec![&node_1]
+        );
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.live_nodes().count(), 0);
+        assert_eq!(failure_detector.dead_nodes().count(), 1);
+        assert!(!failure_detector.is_suspected(&node_1));
+    }
+
     #[tokio::test]
     async fn test_failure_detector_node_state_from_live_to_down_to_live() {
         tokio::time::pause();
@@ -458,6 +774,107 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_failure_detector_reset_samples() {
+        tokio::time::pause();
+        let mut failure_detector = FailureDetector::new(FailureDetectorConfig::default());
+        let node_1 = ChitchatId::for_local_test(10_001);
+        assert!(!failure_detector.reset_samples(&node_1));
+
+        for _ in 0..1_000 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            failure_detector.report_heartbeat(&node_1);
+        }
+        // Network blip: with intervals of 100ms, the node would be declared dead after 1s.
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(failure_detector.reset_samples(&node_1));
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.live_nodes().collect::<Vec<_>>(), [&node_1]);
+        assert!(failure_detector.contains_node(&node_1));
+
+        // The statistics of the node are learned again.
+        for _ in 0..1_000 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            failure_detector.report_heartbeat(&node_1);
+        }
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.live_nodes().count(), 1);
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.dead_nodes().collect::<Vec<_>>(), [&node_1]);
+    }
+
+    #[tokio::test]
+    async fn test_failure_detector_prune() {
+        tokio::time::pause();
+        let mut failure_detector = FailureDetector::new(FailureDetectorConfig::default());
+        let node_1 = ChitchatId::for_local_test(10_001);
+        let node_2 = ChitchatId::for_local_test(10_002);
+        let node_3 = ChitchatId::for_local_test(10_003);
+
+        for _ in 0..3 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            failure_detector.report_heartbeat(&node_1);
+            failure_detector.report_heartbeat(&node_2);
+        }
+        failure_detector.report_heartbeat(&node_3);
+        for chitchat_id in [&node_1, &node_2, &node_3] {
+            failure_detector.update_node_liveness(chitchat_id);
+        }
+        assert_eq!(failure_detector.live_nodes().count(), 2);
+        assert_eq!(failure_detector.dead_nodes().collect::<Vec<_>>(), [&node_3]);
+        assert_eq!(failure_detector.num_sampling_windows(), 3);
+
+        let num_pruned = failure_detector.prune(|chitchat_id| chitchat_id == &node_1);
+        assert_eq!(num_pruned, 2);
+        assert_eq!(failure_detector.num_sampling_windows(), 1);
+        assert!(failure_detector.contains_node(&node_1));
+        assert_eq!(failure_detector.live_nodes().collect::<Vec<_>>(), [&node_1]);
+        assert!(failure_detector.dead_nodes().next().is_none());
+
+        assert_eq!(failure_detector.prune(|_| true), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failure_detector_set_window_size() {
+        tokio::time::pause();
+        let mut failure_detector = FailureDetector::new(FailureDetectorConfig::default());
+        let node_1 = ChitchatId::for_local_test(10_001);
+        for _ in 0..1_000 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            failure_detector.report_heartbeat(&node_1);
+        }
+        // The node slows down its heartbeat cadence.
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            failure_detector.report_heartbeat(&node_1);
+        }
+        // With the default window, the 100ms intervals prevail and a 3s pause is fatal.
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert!(failure_detector.phi(&node_1).unwrap() > 8.0);
+
+        // Shrinking the window keeps only the most recent 1s intervals.
+        failure_detector.set_window_size(&node_1, 10);
+        let sampling_window = failure_detector.get_or_create_sampling_window(&node_1);
+        assert_eq!(sampling_window.intervals.values.len(), 10);
+        assert_nearly_equal(sampling_window.intervals.sum(), 10.0);
+        failure_detector.update_node_liveness(&node_1);
+        assert_eq!(failure_detector.live_nodes().collect::<Vec<_>>(), [&node_1]);
+
+        // The window size of unknown nodes can be set before their first heartbeat.
+        let node_2 = ChitchatId::for_local_test(10_002);
+        failure_detector.set_window_size(&node_2, 3);
+        assert_eq!(
+            failure_detector
+                .get_or_create_sampling_window(&node_2)
+                .intervals
+                .values
+                .len(),
+            3
+        );
+    }
+
     #[tokio::test]
     async fn test_failure_detector_node_state_additive_smoothing_predominant_in_the_beginning() {
         tokio::time::pause();
@@ -595,4 +1012,30 @@ mod tests {
             assert_eq!(bounded_array.len(), capacity);
         }
     }
+
+    #[test]
+    fn test_bounded_array_stats_resized() {
+        let mut bounded_array = BoundedArrayStats::with_capacity(4);
+        bounded_array.append(1.0);
+        bounded_array.append(2.0);
+
+        let grown_array = bounded_array.resized(8);
+        assert_eq!(grown_array.values.len(), 8);
+        assert_eq!(grown_array.len(), 2);
+        assert_nearly_equal(grown_array.sum(), 3.0);
+
+        for i in 3..=6 {
+            bounded_array.append(i as f64);
+        }
+        // The array now holds 3, 4, 5, 6, with the write index wrapped around.
+        let shrunk_array = bounded_array.resized(3);
+        assert_eq!(shrunk_array.len(), 3);
+        assert!(shrunk_array.is_filled);
+        assert_nearly_equal(shrunk_array.sum(), 15.0);
+
+        let mut shrunk_array = bounded_array.resized(2);
+        assert_nearly_equal(shrunk_array.sum(), 11.0);
+        shrunk_array.append(7.0);
+        assert_nearly_equal(shrunk_array.sum(), 13.0);
+    }
 }
@@ -0,0 +1,95 @@
+//! Entry points to the wire format parsers, meant to be called by fuzz targets.
+//!
+//! These functions are only available with the `fuzz` feature. Whatever their input, they are
+//! expected to return an error rather than panic, allocate unbounded amounts of memory, or loop
+//! forever.
+
+use crate::delta::Delta;
+use crate::serialize::Deserializable;
+use crate::{ChitchatError, ChitchatMessage, Digest};
+
+/// Deserializes a gossip message, as done upon receiving a UDP datagram.
+pub fn fuzz_deserialize_message(data: &[u8]) -> Result<ChitchatMessage, ChitchatError> {
+    ChitchatMessage::deserialize(&mut &data[..]).map_err(ChitchatError::Deserialize)
+}
+
+/// Deserializes a digest, as embedded in `Syn` and `SynAck` messages.
+pub fn fuzz_deserialize_digest(data: &[u8]) -> Result<Digest, ChitchatError> {
+    Digest::deserialize(&mut &data[..]).map_err(ChitchatError::Deserialize)
+}
+
+/// Deserializes a delta, as embedded in `SynAck` and `Ack` messages.
+pub fn fuzz_deserialize_delta(data: &[u8]) -> Result<Delta, ChitchatError> {
+    Delta::deserialize(&mut &data[..]).map_err(ChitchatError::Deserialize)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::proptest;
+
+    use super::*;
+    use crate::serialize::Serializable;
+    use crate::{ChitchatId, Heartbeat};
+
+    fn sample_syn_ack_message() -> ChitchatMessage {
+        let node = ChitchatId::for_local_test(10_001);
+        let mut digest = Digest::default();
+        digest.add_node(node.clone(), Heartbeat(3), 1, 2);
+        let mut delta = Delta::default();
+        delta.add_node(node.clone(), 0u64, 0u64);
+        delta.add_kv(&node, "key1", "value1", 1, false);
+        delta.add_kv(&node, "key2", "value2", 2, true);
+        delta.set_serialized_len(56);
+        ChitchatMessage::SynAck { digest, delta }
+    }
+
+    #[test]
+    fn test_fuzz_deserialize_valid_inputs() {
+        let message = sample_syn_ack_message();
+        let message_bytes = message.serialize_to_vec();
+        assert!(fuzz_deserialize_message(&message_bytes).is_ok());
+
+        let ChitchatMessage::SynAck { digest, delta } = message else {
+            unreachable!();
+        };
+        assert_eq!(
+            fuzz_deserialize_digest(&digest.serialize_to_vec()).unwrap(),
+            digest
+        );
+        assert_eq!(
+            fuzz_deserialize_delta(&delta.serialize_to_vec()).unwrap(),
+            delta
+        );
+    }
+
+    #[test]
+    fn test_fuzz_deserialize_truncated_inputs() {
+        let message_bytes = sample_syn_ack_message().serialize_to_vec();
+        for len in 0..message_bytes.len() {
+            let truncated_bytes = &message_bytes[..len];
+            assert!(matches!(
+                fuzz_deserialize_message(truncated_bytes),
+                Err(ChitchatError::Deserialize(_))
+            ));
+            let _ = fuzz_deserialize_digest(truncated_bytes);
+            let _ = fuzz_deserialize_delta(truncated_bytes);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_proptest_fuzz_deserialize_arbitrary_bytes(data in proptest::collection::vec(proptest::num::u8::ANY, 0..512)) {
+            let _ = fuzz_deserialize_message(&data);
+            let _ = fuzz_deserialize_digest(&data);
+            let _ = fuzz_deserialize_delta(&data);
+        }
+
+        #[test]
+        fn test_proptest_fuzz_deserialize_corrupted_message(idx in 0usize..1_000, byte in proptest::num::u8::ANY) {
+            let mut message_bytes = sample_syn_ack_message().serialize_to_vec();
+            let idx = idx % message_bytes.len();
+            message_bytes[idx] = byte;
+            let _ = fuzz_deserialize_message(&message_bytes);
+        }
+    }
+}
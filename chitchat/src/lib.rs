@@ -1,38 +1,71 @@
 #![allow(clippy::type_complexity)]
 #![allow(clippy::derive_partial_eq_without_eq)]
 
+mod auth;
+mod clock;
 mod configuration;
 mod delta;
 mod digest;
+mod error;
 mod failure_detector;
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
 mod listener;
 mod message;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod quarantine;
+mod reader;
 pub(crate) mod serialize;
 mod server;
 mod state;
 pub mod transport;
 mod types;
 
+use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::iter::once;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use delta::Delta;
+use auth::{MessageAuthenticator, AUTHENTICATED_MESSAGE_OVERHEAD};
+use clock::SharedClock;
+pub use clock::{Clock, TokioClock};
 use failure_detector::FailureDetector;
 pub use failure_detector::FailureDetectorConfig;
 pub use listener::ListenerHandle;
+use quarantine::BadClusterQuarantine;
+pub use quarantine::BadClusterQuarantineConfig;
+use reader::{ClusterStatePublisher, ClusterStateSnapshotPublisher};
+pub use reader::{ClusterStateReader, ClusterStateView};
 pub use serialize::Serializable;
 use tokio::sync::watch;
-use tokio_stream::wrappers::WatchStream;
-use tracing::{error, info, warn};
-
-pub use self::configuration::ChitchatConfig;
-pub use self::state::{ClusterStateSnapshot, NodeState};
-use crate::digest::Digest;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, WatchStream};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, error, info, warn};
+
+pub use self::configuration::{
+    ChitchatConfig, ChitchatConfigBuilder, DigestOnlyGossipConfig, DuplicateNodeIdPolicy,
+    MessageTimestampConfig, MAX_KEY_VALUE_HISTORY_DEPTH, MAX_RECENT_DELTAS_CAPACITY,
+};
+pub use self::delta::{Delta, DeltaStats};
+pub use self::state::{AppliedNodeDelta, ClusterStateSnapshot, ClusterStateSnapshotRef, NodeState};
+use crate::delta::MIN_DELTA_MTU;
+pub use crate::digest::{Digest, NodeDigest};
+pub use crate::error::ChitchatError;
 pub use crate::message::ChitchatMessage;
+use crate::message::{IDENTIFIED_MESSAGE_OVERHEAD, TIMESTAMPED_MESSAGE_OVERHEAD};
 pub use crate::server::{spawn_chitchat, ChitchatHandle};
-use crate::state::ClusterState;
-pub use crate::types::{ChitchatId, DeletionStatus, Heartbeat, Version, VersionedValue};
+use crate::state::{ClusterState, DeltaCache};
+pub use crate::types::{
+    ChitchatId, DeletionStatus, DeletionStatusMutation, Heartbeat, KeyEntry, KeyValueMutation,
+    NodeStatus, Version, VersionedValue, MAX_VERSION,
+};
 
 /// Maximum UDP datagram payload size (in bytes).
 ///
@@ -45,6 +78,28 @@ pub use crate::types::{ChitchatId, DeletionStatus, Heartbeat, Version, Versioned
 /// or so.
 pub(crate) const MAX_UDP_DATAGRAM_PAYLOAD_SIZE: usize = 65_507;
 
+/// Serialized size of a key-value assumed by [`Chitchat::estimated_convergence`] when we do not
+/// hold any key-value to measure.
+const DEFAULT_KEY_VALUE_SERIALIZED_LEN: usize = 64;
+
+/// Prefix of the keys under which nodes advertise their service endpoints.
+/// See [`Chitchat::set_service_endpoint`].
+pub const SERVICE_ENDPOINT_KEY_PREFIX: &str = "service_endpoint:";
+
+/// Key under which nodes advertise the address at which they serve RPCs, when it differs from
+/// their gossip advertise address. See [`NodeState::rpc_advertise_addr`].
+pub const RPC_ADVERTISE_ADDR_KEY: &str = "rpc_advertise_addr";
+
+/// Key under which nodes advertise whether they are ready. See [`Chitchat::set_ready`].
+pub const READY_KEY: &str = "_ready";
+
+/// Prefix of the keys under which nodes advertise their tags. See [`NodeState::set_tag`].
+pub const TAG_KEY_PREFIX: &str = "_tag:";
+
+/// Key under which nodes advertise the number of configuration commits they made. See
+/// [`NodeState::commit_config`].
+pub const CONFIG_EPOCH_KEY: &str = "_config_epoch";
+
 pub struct Chitchat {
     config: ChitchatConfig,
     cluster_state: ClusterState,
@@ -53,24 +108,130 @@ pub struct Chitchat {
     previous_live_nodes: HashMap<ChitchatId, Version>,
     live_nodes_watcher_tx: watch::Sender<BTreeMap<ChitchatId, NodeState>>,
     live_nodes_watcher_rx: watch::Receiver<BTreeMap<ChitchatId, NodeState>>,
+    bad_cluster_quarantine_opt: Option<BadClusterQuarantine>,
+    message_authenticator_opt: Option<MessageAuthenticator>,
+    num_rejected_unauthenticated_messages: u64,
+    num_rejected_stale_messages: u64,
+    num_dropped_bad_cluster_messages: u64,
+    /// Identities conflicting with the self node's that have already been reported.
+    superseding_chitchat_ids: HashSet<ChitchatId>,
+    /// Live identities sharing their node ID with another live identity advertising a different
+    /// gossip address. See [`DuplicateNodeIdPolicy`].
+    duplicate_chitchat_ids: HashSet<ChitchatId>,
+    /// Number of consecutive responses sent to each peer without a delta in digest-only gossip
+    /// mode.
+    num_consecutive_digest_only_responses: HashMap<ChitchatId, u32>,
+    /// Max version of the self node at the time the last message was sent, or target version of
+    /// the last call to [`Chitchat::await_propagation`] if greater. Versions up to this one may
+    /// have been observed by peers or be awaited, and must not be compacted.
+    max_gossiped_self_version: Cell<Version>,
+    /// Size of the last delta received from a peer.
+    last_received_delta_stats_opt: Option<DeltaStats>,
+    /// Cumulated size of all the deltas received from peers.
+    received_delta_stats: DeltaStats,
+    num_gossip_rounds: u64,
+    /// Number of SYN messages skipped because the gossip byte budget was spent.
+    num_throttled_syns: u64,
+    /// Deltas sent during the current gossip round, reused for peers at the same versions.
+    delta_cache: RefCell<DeltaCache>,
+    /// Max version of the self node reported in the last digest received from each peer.
+    peer_self_versions_tx: watch::Sender<HashMap<ChitchatId, Version>>,
+    /// Number of versions we were missing compared to the last digest received from each peer.
+    /// See [`Chitchat::estimated_convergence`].
+    peer_num_versions_ahead: HashMap<ChitchatId, u64>,
+    /// Publishes views of the cluster state to lock-free readers.
+    cluster_state_publisher: ClusterStatePublisher,
+    /// Publishes the snapshots returned by [`Chitchat::snapshot_arc`].
+    cluster_state_snapshot_publisher: ClusterStateSnapshotPublisher,
+    /// Keys of the self node whose propagation is tracked. See [`Chitchat::self_set_critical`].
+    critical_keys: Vec<CriticalKey>,
+    /// Clock also used by the cluster state. See [`ChitchatConfig::clock`].
+    clock: SharedClock,
+}
+
+/// A key of the self node whose propagation to the live peers is tracked until its deadline.
+struct CriticalKey {
+    key: String,
+    deadline: Instant,
 }
 
 impl Chitchat {
     pub fn with_chitchat_id_and_seeds(
         config: ChitchatConfig,
         seed_addrs: watch::Receiver<HashSet<SocketAddr>>,
-        initial_key_values: Vec<(String, String)>,
+        mut initial_key_values: Vec<(String, String)>,
     ) -> Self {
-        let failure_detector = FailureDetector::new(config.failure_detector_config.clone());
+        if let Some(rpc_advertise_addr) = config.rpc_advertise_addr {
+            initial_key_values.push((
+                RPC_ADVERTISE_ADDR_KEY.to_string(),
+                rpc_advertise_addr.to_string(),
+            ));
+        }
+        let failure_detector = FailureDetector::with_startup_grace_period(
+            config.failure_detector_config.clone(),
+            config.effective_failure_detector_startup_grace_period(),
+        );
         let previous_live_nodes = HashMap::new();
         let (live_nodes_watcher_tx, live_nodes_watcher_rx) = watch::channel(BTreeMap::new());
+        let bad_cluster_quarantine_opt = config
+            .bad_cluster_quarantine_config
+            .clone()
+            .map(BadClusterQuarantine::new);
+        let message_authenticator_opt = config
+            .message_auth_secret
+            .as_deref()
+            .map(MessageAuthenticator::new);
+        let key_validator_opt = config.key_validator.clone();
+        let value_transform_opt = config.value_transform.clone();
+        if config.debug_buffer_sizes_ignored() {
+            warn!(
+                key_value_history_depth = config.key_value_history_depth,
+                recent_deltas_capacity = config.recent_deltas_capacity,
+                "debug buffer sizes are set but `debug_buffers_enabled` is not: no debug buffer \
+                 will be kept"
+            );
+        }
+        let key_value_history_depth = config.effective_key_value_history_depth();
+        let recent_deltas_capacity = config.effective_recent_deltas_capacity();
+        let clock = config
+            .clock
+            .clone()
+            .map(SharedClock::new)
+            .unwrap_or_default();
         let mut chitchat = Chitchat {
             config,
-            cluster_state: ClusterState::with_seed_addrs(seed_addrs),
+            cluster_state: ClusterState::with_seed_addrs(
+                seed_addrs,
+                key_validator_opt,
+                value_transform_opt,
+                key_value_history_depth,
+                recent_deltas_capacity,
+                clock.clone(),
+            ),
             failure_detector,
             previous_live_nodes,
             live_nodes_watcher_tx,
             live_nodes_watcher_rx,
+            bad_cluster_quarantine_opt,
+            message_authenticator_opt,
+            num_rejected_unauthenticated_messages: 0,
+            num_rejected_stale_messages: 0,
+            num_dropped_bad_cluster_messages: 0,
+            superseding_chitchat_ids: HashSet::new(),
+            duplicate_chitchat_ids: HashSet::new(),
+            num_consecutive_digest_only_responses: HashMap::new(),
+            max_gossiped_self_version: Cell::new(0),
+            last_received_delta_stats_opt: None,
+            received_delta_stats: DeltaStats::default(),
+            num_gossip_rounds: 0,
+            num_throttled_syns: 0,
+            delta_cache: RefCell::default(),
+            peer_self_versions_tx: watch::Sender::new(HashMap::new()),
+            peer_num_versions_ahead: HashMap::new(),
+            cluster_state_publisher: ClusterStatePublisher::default(),
+            cluster_state_snapshot_publisher: ClusterStateSnapshotPublisher::default(),
+            critical_keys: Vec::new(),
+            clock,
         };
 
         let self_node_state = chitchat.self_node_state();
@@ -78,16 +239,15 @@ impl Chitchat {
         // Immediately mark the node as alive to ensure it responds to SYN messages.
         self_node_state.inc_heartbeat();
 
-        // Set initial key/value pairs.
-        for (key, value) in initial_key_values {
-            self_node_state.set(key, value);
-        }
+        // Set initial key/value pairs, all at once so that peers learn about them together.
+        self_node_state.set_batch(initial_key_values);
         chitchat
     }
 
     pub(crate) fn create_syn_message(&self) -> ChitchatMessage {
-        let scheduled_for_deletion: HashSet<_> = self.scheduled_for_deletion_nodes().collect();
-        let digest = self.compute_digest(&scheduled_for_deletion);
+        let excluded_nodes = self.nodes_excluded_from_gossip();
+        let digest = self.compute_digest(&excluded_nodes);
+        self.record_max_gossiped_self_version();
         ChitchatMessage::Syn {
             cluster_id: self.config.cluster_id.clone(),
             digest,
@@ -98,15 +258,160 @@ impl Chitchat {
     /// the cluster.
     fn report_heartbeats_in_digest(&mut self, digest: &Digest) {
         for (chitchat_id, node_digest) in &digest.node_digests {
+            self.maybe_trigger_self_superseded_callback(
+                chitchat_id,
+                Some(node_digest.heartbeat),
+                Some(node_digest.max_version),
+            );
             self.report_heartbeat(chitchat_id, node_digest.heartbeat);
         }
     }
 
-    fn process_delta(&mut self, delta: Delta) {
+    /// Returns the identity of the peer that sent a message from `from_addr`: the sender carried
+    /// by the [`ChitchatMessage::Identified`] envelope if any, or else the node advertising
+    /// `from_addr` as its gossip address.
+    fn resolve_peer(
+        &self,
+        from_addr: SocketAddr,
+        sender_opt: Option<ChitchatId>,
+    ) -> Option<ChitchatId> {
+        sender_opt.or_else(|| {
+            self.cluster_state
+                .nodes()
+                .find(|chitchat_id| chitchat_id.gossip_advertise_addr == from_addr)
+                .cloned()
+        })
+    }
+
+    /// Records the max version of the self node known by `peer`, as reported in its digest.
+    fn record_peer_self_version(&self, peer: &ChitchatId, digest: &Digest) {
+        let peer_self_version = digest
+            .node_digests
+            .get(self.self_chitchat_id())
+            .map(|node_digest| node_digest.max_version)
+            .unwrap_or(0);
+        self.peer_self_versions_tx
+            .send_if_modified(|peer_self_versions| {
+                peer_self_versions.insert(peer.clone(), peer_self_version)
+                    != Some(peer_self_version)
+            });
+    }
+
+    /// Records the number of versions we are missing compared to the digest of `peer`.
+    fn record_peer_num_versions_ahead(&mut self, peer: &ChitchatId, digest: &Digest) {
+        let num_versions_ahead = self
+            .cluster_state
+            .num_versions_ahead(digest, &self.config.chitchat_id);
+        self.peer_num_versions_ahead
+            .insert(peer.clone(), num_versions_ahead);
+    }
+
+    fn process_delta(&mut self, from_addr: SocketAddr, delta: Delta) {
+        let delta_stats = delta.stats();
+        self.last_received_delta_stats_opt = Some(delta_stats);
+        self.received_delta_stats += delta_stats;
+        if self.config.delta_applied_callback.is_none() {
+            self.apply_delta(delta);
+            return;
+        }
+        self.apply_delta(delta.clone());
+        if let Some(delta_applied_callback) = &self.config.delta_applied_callback {
+            delta_applied_callback(from_addr, &delta);
+        }
+    }
+
+    /// Applies a delta obtained out of band rather than through a gossip handshake, e.g. to replay
+    /// a delta computed with [`Chitchat::debug_delta_for`] on another node during a bulk state
+    /// transfer.
+    ///
+    /// The delta is validated and applied exactly like the deltas received from peers: stale node
+    /// deltas are ignored, the key validator and the value transform apply, the node delta about
+    /// the self node is not applied, and listeners and events are triggered, as well as the
+    /// catch-up and self-superseded callbacks. Since the delta does not come from a peer, it is not
+    /// accounted for in the received delta statistics and the delta-applied callback is not
+    /// executed.
+    pub fn apply_delta(&mut self, delta: Delta) {
         self.maybe_trigger_catchup_callback(&delta);
+        for node_delta in &delta.node_deltas {
+            self.maybe_trigger_self_superseded_callback(
+                &node_delta.chitchat_id,
+                None,
+                node_delta.max_version,
+            );
+        }
+        self.apply_delta_to_cluster_state(delta);
+    }
+
+    /// Applies a delta to the cluster state, except for the node delta about the self node, if
+    /// any.
+    ///
+    /// The self node is the only authority on its own state, so peers' claims about it are never
+    /// applied. Such a claim means that a peer knows about versions of the self node that we have
+    /// not produced, typically because we restarted with the same identity and lost our state. In
+    /// that case, we move our versions above the ones the peer knows about, so that our state
+    /// supersedes the stale one across the cluster.
+    fn apply_delta_to_cluster_state(&mut self, mut delta: Delta) {
+        let self_chitchat_id = &self.config.chitchat_id;
+        if let Some(position) = delta
+            .node_deltas
+            .iter()
+            .position(|node_delta| &node_delta.chitchat_id == self_chitchat_id)
+        {
+            let self_node_delta = delta.node_deltas.remove(position);
+            let greatest_version = self_node_delta.greatest_version();
+            let self_node_state = self.cluster_state.node_state_mut(self_chitchat_id);
+
+            if greatest_version > self_node_state.max_version() && greatest_version <= MAX_VERSION {
+                warn!(
+                    version = greatest_version,
+                    max_version = self_node_state.max_version(),
+                    "a peer knows about versions of the self node more recent than ours, \
+                     superseding them"
+                );
+                self_node_state.supersede_versions(greatest_version);
+            }
+        }
         self.cluster_state.apply_delta(delta);
     }
 
+    /// Executes the self superseded callback if `chitchat_id` conflicts with the identity of the
+    /// self node.
+    ///
+    /// The callback is executed at most once per conflicting identity.
+    fn maybe_trigger_self_superseded_callback(
+        &mut self,
+        chitchat_id: &ChitchatId,
+        heartbeat_opt: Option<Heartbeat>,
+        max_version_opt: Option<Version>,
+    ) {
+        let self_chitchat_id = &self.config.chitchat_id;
+        if chitchat_id.node_id != self_chitchat_id.node_id {
+            return;
+        }
+        let is_superseded = if chitchat_id == self_chitchat_id {
+            // Someone is gossiping a state for our exact identity that we never produced.
+            let self_node_state = self.cluster_state.node_state_mut(self_chitchat_id);
+            heartbeat_opt.is_some_and(|heartbeat| heartbeat > self_node_state.heartbeat())
+                || max_version_opt
+                    .is_some_and(|max_version| max_version > self_node_state.max_version())
+        } else {
+            chitchat_id.generation_id >= self_chitchat_id.generation_id
+        };
+        if !is_superseded || self.superseding_chitchat_ids.contains(chitchat_id) {
+            return;
+        }
+        warn!(
+            self_chitchat_id=?self_chitchat_id,
+            superseding_chitchat_id=?chitchat_id,
+            "another node claims the identity of the self node"
+        );
+        self.superseding_chitchat_ids.insert(chitchat_id.clone());
+
+        if let Some(self_superseded_callback) = &self.config.self_superseded_callback {
+            self_superseded_callback(chitchat_id);
+        }
+    }
+
     /// Executes the catch-up callback if necessary.
     fn maybe_trigger_catchup_callback(&self, delta: &Delta) {
         let has_reset = delta.node_deltas.iter().any(|node_delta| {
@@ -120,9 +425,49 @@ impl Chitchat {
         }
     }
 
-    pub(crate) fn process_message(&mut self, msg: ChitchatMessage) -> Option<ChitchatMessage> {
-        self.update_self_heartbeat();
+    #[cfg(any(test, feature = "testsuite"))]
+    pub(crate) fn process_message(
+        &mut self,
+        from_addr: SocketAddr,
+        msg: ChitchatMessage,
+    ) -> Option<ChitchatMessage> {
+        // Receiving a message is a proof of life worth propagating in the response.
+        self.tick_heartbeat();
+        let response_opt = self.process_batched_message(from_addr, msg, usize::MAX);
+        self.publish_cluster_state();
+        response_opt
+    }
+
+    /// Processes one of a batch of messages. Unlike [`Chitchat::process_message`], neither the
+    /// heartbeat is incremented nor the cluster state published: the caller does it once for the
+    /// whole batch.
+    ///
+    /// The delta carried by the response is truncated so that the sealed response fits in
+    /// `max_response_len` bytes, if possible: the digest of a SYN-ACK is always sent whole.
+    pub(crate) fn process_batched_message(
+        &mut self,
+        from_addr: SocketAddr,
+        msg: ChitchatMessage,
+        max_response_len: usize,
+    ) -> Option<ChitchatMessage> {
+        let response_opt = self.process_message_inner(from_addr, msg, max_response_len);
+        if response_opt.is_some() {
+            self.record_max_gossiped_self_version();
+        }
+        response_opt
+    }
 
+    fn process_message_inner(
+        &mut self,
+        from_addr: SocketAddr,
+        msg: ChitchatMessage,
+        max_response_len: usize,
+    ) -> Option<ChitchatMessage> {
+        let max_message_len = self.max_message_len_within(max_response_len);
+        let (sender_opt, msg) = match msg {
+            ChitchatMessage::Identified { sender, message } => (Some(sender), *message),
+            msg => (None, msg),
+        };
         match msg {
             ChitchatMessage::Syn { cluster_id, digest } => {
                 if cluster_id != self.cluster_id() {
@@ -131,18 +476,33 @@ impl Chitchat {
                         their_cluster_id=%cluster_id,
                         "received SYN message addressed to a different cluster"
                     );
+                    if !self.config.respond_to_bad_cluster {
+                        self.num_dropped_bad_cluster_messages += 1;
+                        return None;
+                    }
                     return Some(ChitchatMessage::BadCluster);
                 }
                 self.report_heartbeats_in_digest(&digest);
-                let scheduled_for_deletion: HashSet<_> =
-                    self.scheduled_for_deletion_nodes().collect();
-                let self_digest = self.compute_digest(&scheduled_for_deletion);
-                let delta_mtu = MAX_UDP_DATAGRAM_PAYLOAD_SIZE - 1 - digest.serialized_len();
-                let delta = self.cluster_state.compute_partial_delta_respecting_mtu(
-                    &digest,
-                    delta_mtu,
-                    &scheduled_for_deletion,
-                );
+                let peer_opt = self.resolve_peer(from_addr, sender_opt);
+                if let Some(peer) = &peer_opt {
+                    self.record_peer_self_version(peer, &digest);
+                    self.record_peer_num_versions_ahead(peer, &digest);
+                }
+                let delta_mtu = max_message_len.saturating_sub(1 + digest.serialized_len());
+                let should_send_delta = delta_mtu >= MIN_DELTA_MTU
+                    && self.should_send_delta(peer_opt.as_ref(), &digest);
+                let excluded_nodes = self.nodes_excluded_from_gossip();
+                let self_digest = self.compute_digest(&excluded_nodes);
+                let delta = if should_send_delta {
+                    self.cluster_state.compute_partial_delta_coalesced(
+                        &mut self.delta_cache.borrow_mut(),
+                        &digest,
+                        delta_mtu,
+                        &excluded_nodes,
+                    )
+                } else {
+                    Delta::default()
+                };
                 Some(ChitchatMessage::SynAck {
                     digest: self_digest,
                     delta,
@@ -150,27 +510,359 @@ impl Chitchat {
             }
             ChitchatMessage::SynAck { digest, delta } => {
                 self.report_heartbeats_in_digest(&digest);
-                self.process_delta(delta);
-                let scheduled_for_deletion =
-                    self.scheduled_for_deletion_nodes().collect::<HashSet<_>>();
-                let delta = self.cluster_state.compute_partial_delta_respecting_mtu(
-                    &digest,
-                    MAX_UDP_DATAGRAM_PAYLOAD_SIZE - 1,
-                    &scheduled_for_deletion,
-                );
+                self.process_delta(from_addr, delta);
+                // The peer may only be known once its delta is applied.
+                let peer_opt = self.resolve_peer(from_addr, sender_opt);
+                if let Some(peer) = &peer_opt {
+                    self.record_peer_self_version(peer, &digest);
+                    self.record_peer_num_versions_ahead(peer, &digest);
+                }
+                let delta_mtu = max_message_len.saturating_sub(1);
+                let should_send_delta = delta_mtu >= MIN_DELTA_MTU
+                    && self.should_send_delta(peer_opt.as_ref(), &digest);
+                let excluded_nodes = self.nodes_excluded_from_gossip();
+                let delta = if should_send_delta {
+                    self.cluster_state.compute_partial_delta_coalesced(
+                        &mut self.delta_cache.borrow_mut(),
+                        &digest,
+                        delta_mtu,
+                        &excluded_nodes,
+                    )
+                } else {
+                    Delta::default()
+                };
                 Some(ChitchatMessage::Ack { delta })
             }
             ChitchatMessage::Ack { delta } => {
-                self.process_delta(delta);
+                self.process_delta(from_addr, delta);
                 None
             }
             ChitchatMessage::BadCluster => {
                 warn!("message rejected by peer: wrong cluster");
                 None
             }
+            ChitchatMessage::Authenticated { .. } => {
+                warn!("received authenticated message but no shared secret is configured");
+                None
+            }
+            ChitchatMessage::Timestamped { .. } => {
+                warn!("received timestamped message that was not opened");
+                None
+            }
+            ChitchatMessage::Identified { .. } => {
+                warn!("received nested identified message");
+                None
+            }
+        }
+    }
+
+    /// Decides whether the response to a peer whose digest is `digest` should carry a delta.
+    ///
+    /// This is always the case unless digest-only gossip is enabled.
+    fn should_send_delta(&mut self, peer_opt: Option<&ChitchatId>, digest: &Digest) -> bool {
+        let Some(digest_only_gossip_config) = &self.config.digest_only_gossip_config else {
+            return true;
+        };
+        let num_versions_behind = {
+            let excluded_nodes = self.nodes_excluded_from_gossip();
+            self.cluster_state
+                .num_versions_behind(digest, &excluded_nodes)
+        };
+        if num_versions_behind == 0 {
+            return false;
+        }
+        let full_reconciliation_period = digest_only_gossip_config.full_reconciliation_period;
+        let divergence_threshold = digest_only_gossip_config.divergence_threshold;
+        let num_consecutive_digest_only_responses = peer_opt
+            .and_then(|peer| self.num_consecutive_digest_only_responses.get(peer))
+            .copied()
+            .unwrap_or(0);
+        let is_full_reconciliation_due = full_reconciliation_period > 0
+            && num_consecutive_digest_only_responses + 1 >= full_reconciliation_period;
+        let should_send_delta =
+            num_versions_behind >= divergence_threshold || is_full_reconciliation_due;
+        if let Some(peer) = peer_opt {
+            let num_consecutive_digest_only_responses = if should_send_delta {
+                0
+            } else {
+                num_consecutive_digest_only_responses + 1
+            };
+            self.num_consecutive_digest_only_responses
+                .insert(peer.clone(), num_consecutive_digest_only_responses);
+        }
+        should_send_delta
+    }
+
+    /// Returns the maximum length of the messages we send, leaving room for the envelopes they
+    /// are sealed in.
+    fn max_message_len(&self) -> usize {
+        let mut max_message_len = MAX_UDP_DATAGRAM_PAYLOAD_SIZE
+            - IDENTIFIED_MESSAGE_OVERHEAD
+            - self.config.chitchat_id.serialized_len();
+        if self.message_authenticator_opt.is_some() {
+            max_message_len -= AUTHENTICATED_MESSAGE_OVERHEAD;
+        }
+        if self.config.message_timestamp_config.is_some() {
+            max_message_len -= TIMESTAMPED_MESSAGE_OVERHEAD;
+        }
+        max_message_len
+    }
+
+    /// Returns the greatest length of a message that still fits in `max_sealed_len` bytes once
+    /// sealed.
+    fn max_message_len_within(&self, max_sealed_len: usize) -> usize {
+        let max_message_len = self.max_message_len();
+        let seal_overhead = MAX_UDP_DATAGRAM_PAYLOAD_SIZE - max_message_len;
+        max_message_len.min(max_sealed_len.saturating_sub(seal_overhead))
+    }
+
+    /// Wraps an outgoing SYN or SYN-ACK message into an identified envelope, then any outgoing
+    /// message into a timestamped envelope if message timestamps are configured, and finally
+    /// into an authenticated envelope if a shared secret is configured.
+    pub(crate) fn seal_message(&self, message: ChitchatMessage) -> ChitchatMessage {
+        let message = match message {
+            ChitchatMessage::Syn { .. } | ChitchatMessage::SynAck { .. } => {
+                ChitchatMessage::Identified {
+                    sender: self.config.chitchat_id.clone(),
+                    message: Box::new(message),
+                }
+            }
+            message => message,
+        };
+        let message = if self.config.message_timestamp_config.is_some() {
+            ChitchatMessage::Timestamped {
+                unix_timestamp_millis: unix_timestamp_millis(),
+                message: Box::new(message),
+            }
+        } else {
+            message
+        };
+        if let Some(message_authenticator) = &self.message_authenticator_opt {
+            message_authenticator.seal(&message)
+        } else {
+            message
+        }
+    }
+
+    /// Verifies and unwraps an incoming message if a shared secret is configured, and then checks
+    /// its timestamp if message timestamps are configured.
+    ///
+    /// Returns `None` if the message is not properly authenticated or is stale, in which case it
+    /// should be dropped.
+    pub(crate) fn open_message(
+        &mut self,
+        from_addr: SocketAddr,
+        message: ChitchatMessage,
+    ) -> Option<ChitchatMessage> {
+        let message = if let Some(message_authenticator) = &self.message_authenticator_opt {
+            match message_authenticator.open(message) {
+                Ok(message) => message,
+                Err(error) => {
+                    self.num_rejected_unauthenticated_messages += 1;
+                    warn!(from=%from_addr, error=?error, "rejected unauthenticated message");
+                    return None;
+                }
+            }
+        } else {
+            message
+        };
+        let (unix_timestamp_millis_opt, message) = match message {
+            ChitchatMessage::Timestamped {
+                unix_timestamp_millis,
+                message,
+            } => (Some(unix_timestamp_millis), *message),
+            message => (None, message),
+        };
+        let Some(message_timestamp_config) = &self.config.message_timestamp_config else {
+            return Some(message);
+        };
+        let Some(message_unix_timestamp_millis) = unix_timestamp_millis_opt else {
+            self.num_rejected_stale_messages += 1;
+            warn!(from=%from_addr, "rejected message without timestamp");
+            return None;
+        };
+        let message_age_millis = unix_timestamp_millis().abs_diff(message_unix_timestamp_millis);
+        if message_age_millis > message_timestamp_config.max_message_age.as_millis() as u64 {
+            self.num_rejected_stale_messages += 1;
+            warn!(
+                from=%from_addr,
+                message_age_millis=message_age_millis,
+                "rejected stale message"
+            );
+            return None;
+        }
+        Some(message)
+    }
+
+    /// Returns the number of messages dropped because their timestamp was missing or out of the
+    /// configured window.
+    pub fn num_rejected_stale_messages(&self) -> u64 {
+        self.num_rejected_stale_messages
+    }
+
+    /// Returns the number of messages dropped because they were not properly authenticated.
+    pub fn num_rejected_unauthenticated_messages(&self) -> u64 {
+        self.num_rejected_unauthenticated_messages
+    }
+
+    /// Returns the number of SYN messages addressed to a different cluster that were dropped
+    /// without answering, because [`ChitchatConfig::respond_to_bad_cluster`] is disabled.
+    pub fn num_dropped_bad_cluster_messages(&self) -> u64 {
+        self.num_dropped_bad_cluster_messages
+    }
+
+    /// Returns a summary of the most recently applied node deltas, from the oldest to the most
+    /// recent. It is always empty unless [`ChitchatConfig::debug_buffers_enabled`] is set.
+    pub fn recent_deltas(&self) -> impl Iterator<Item = &AppliedNodeDelta> {
+        self.cluster_state.recent_deltas()
+    }
+
+    /// Returns the size of the last delta received from a peer, in a `SynAck` or an `Ack` message.
+    ///
+    /// A peer that keeps sending large deltas typically reveals a convergence problem.
+    pub fn last_received_delta_stats(&self) -> Option<DeltaStats> {
+        self.last_received_delta_stats_opt
+    }
+
+    /// Returns the cumulated size of all the deltas received from peers.
+    pub fn received_delta_stats(&self) -> DeltaStats {
+        self.received_delta_stats
+    }
+
+    /// Returns the delta the self node would send right now to a peer whose digest is `digest`,
+    /// in a message of at most `mtu` bytes.
+    ///
+    /// This is meant for debugging convergence issues, e.g. by pulling the digest of a peer with
+    /// [`Chitchat::digest`]. It has no side effect: the heartbeat of the self node is not
+    /// incremented and the delta is not accounted for in the statistics.
+    pub fn debug_delta_for(&self, digest: &Digest, mtu: usize) -> Delta {
+        let excluded_nodes = self.nodes_excluded_from_gossip();
+        let (delta, _is_truncated, _reset_nodes) =
+            self.cluster_state
+                .compute_partial_delta(digest, mtu, &excluded_nodes);
+        delta
+    }
+
+    /// Returns the number of gossip rounds initiated by the self node.
+    pub fn num_gossip_rounds(&self) -> u64 {
+        self.num_gossip_rounds
+    }
+
+    /// Returns the number of SYN messages that were not sent because the gossip byte budget of
+    /// the current second was spent. See [`ChitchatConfig::max_gossip_bytes_per_sec`].
+    pub fn num_throttled_syns(&self) -> u64 {
+        self.num_throttled_syns
+    }
+
+    pub(crate) fn record_throttled_syn(&mut self) {
+        self.num_throttled_syns += 1;
+    }
+
+    pub(crate) fn record_gossip_round(&mut self) {
+        self.num_gossip_rounds += 1;
+        self.delta_cache.get_mut().clear();
+    }
+
+    /// Returns the number of deltas sent to peers that could not carry all the stale key-values
+    /// because of the MTU. Peers receiving a truncated delta catch up over several gossip rounds.
+    pub fn num_truncated_deltas(&self) -> u64 {
+        self.cluster_state.num_truncated_deltas()
+    }
+
+    /// Returns the number of deltas sent to peers that were reused from a delta computed earlier
+    /// in the same gossip round, for a peer at the same versions.
+    pub fn num_coalesced_deltas(&self) -> u64 {
+        self.delta_cache.borrow().num_coalesced_deltas()
+    }
+
+    /// Returns the number of node resets sent to peers. See [`ClusterEvent::NodeResetSent`].
+    pub fn num_sent_node_resets(&self) -> u64 {
+        self.cluster_state.num_sent_node_resets()
+    }
+
+    /// Returns the number of key-values marked for deletion and not garbage collected yet, summed
+    /// over all the nodes.
+    ///
+    /// This requires scanning the whole cluster state.
+    pub fn num_tombstones(&self) -> usize {
+        self.cluster_state.num_tombstones()
+    }
+
+    /// Returns the serialized length of the state of each node, as it would appear in a delta
+    /// sent to a peer that knows nothing about the node, before compression. Ranking nodes by this
+    /// length helps find the ones taking up most of the gossip budget.
+    ///
+    /// This requires scanning the whole cluster state.
+    pub fn per_node_serialized_len(&self) -> BTreeMap<ChitchatId, usize> {
+        self.cluster_state.per_node_serialized_len()
+    }
+
+    /// Returns the number of key-values dropped because their key was rejected by the configured
+    /// key validator.
+    pub fn num_rejected_keys(&self) -> u64 {
+        self.cluster_state.key_validation.num_rejected_keys()
+    }
+
+    /// Returns a rough estimate of the time it will take the self node to catch up with its
+    /// peers, e.g. after joining a cluster, or `None` if no live peer has sent us its digest yet.
+    ///
+    /// The estimate assumes that:
+    /// - the missing versions are the most reported by a live peer in its last digest;
+    /// - every gossip round, the self node receives a full delta from each of the `GOSSIP_COUNT`
+    ///   peers it gossips with, disregarding the gossip it receives from the other peers;
+    /// - the missing key-values have the average serialized size of the key-values we hold;
+    /// - the peers stop receiving new updates.
+    ///
+    /// This requires scanning the whole cluster state.
+    pub fn estimated_convergence(&self) -> Option<Duration> {
+        let num_missing_versions = self
+            .live_nodes()
+            .flat_map(|chitchat_id| self.peer_num_versions_ahead.get(chitchat_id))
+            .copied()
+            .max()?;
+        if num_missing_versions == 0 {
+            return Some(Duration::ZERO);
+        }
+        let num_key_values: usize = self
+            .node_states()
+            .values()
+            .map(|node_state| node_state.key_values_including_deleted().count())
+            .sum();
+        let key_value_len = if num_key_values == 0 {
+            DEFAULT_KEY_VALUE_SERIALIZED_LEN
+        } else {
+            let serialized_len: usize = self.per_node_serialized_len().values().sum();
+            serialized_len.div_ceil(num_key_values)
+        };
+        let num_key_values_per_delta = (self.max_message_len() / key_value_len).max(1) as u64;
+        let num_versions_per_round = num_key_values_per_delta * server::GOSSIP_COUNT as u64;
+        let num_rounds = num_missing_versions.div_ceil(num_versions_per_round);
+        Some(
+            self.config
+                .gossip_interval
+                .saturating_mul(num_rounds.try_into().unwrap_or(u32::MAX)),
+        )
+    }
+
+    /// Records that `addr` sent us a message addressed to a different cluster and returns whether
+    /// we should answer it with a `BadCluster` message.
+    pub(crate) fn should_respond_bad_cluster(&mut self, addr: SocketAddr) -> bool {
+        if let Some(bad_cluster_quarantine) = &mut self.bad_cluster_quarantine_opt {
+            bad_cluster_quarantine.should_respond(addr)
+        } else {
+            true
         }
     }
 
+    /// Returns the number of addresses we currently stop answering to, because they keep sending
+    /// us messages addressed to a different cluster.
+    pub fn num_quarantined_addrs(&self) -> usize {
+        self.bad_cluster_quarantine_opt
+            .as_ref()
+            .map(|bad_cluster_quarantine| bad_cluster_quarantine.num_quarantined_addrs())
+            .unwrap_or(0)
+    }
+
     fn gc_keys_marked_for_deletion(&mut self) {
         self.cluster_state
             .gc_keys_marked_for_deletion(self.config.marked_for_deletion_grace_period);
@@ -188,6 +880,60 @@ impl Chitchat {
         }
     }
 
+    /// Looks for live nodes sharing a node ID but advertising different gossip addresses, and
+    /// warns about the newly detected ones. Returns the identities to exclude from the live nodes
+    /// watcher according to the [`DuplicateNodeIdPolicy`].
+    fn check_duplicate_node_ids<'a>(
+        &mut self,
+        live_chitchat_ids: impl Iterator<Item = &'a ChitchatId>,
+    ) -> HashSet<ChitchatId> {
+        let duplicate_node_id_policy = self.config.duplicate_node_id_policy;
+        if duplicate_node_id_policy == DuplicateNodeIdPolicy::Ignore {
+            return HashSet::new();
+        }
+        let mut live_chitchat_ids_by_node_id: HashMap<&str, Vec<&ChitchatId>> = HashMap::new();
+        for chitchat_id in live_chitchat_ids {
+            live_chitchat_ids_by_node_id
+                .entry(&chitchat_id.node_id)
+                .or_default()
+                .push(chitchat_id);
+        }
+        let mut duplicate_chitchat_ids = HashSet::new();
+        let mut rejected_chitchat_ids = HashSet::new();
+
+        for (node_id, chitchat_ids) in live_chitchat_ids_by_node_id {
+            let gossip_advertise_addr = chitchat_ids[0].gossip_advertise_addr;
+            if chitchat_ids
+                .iter()
+                .all(|chitchat_id| chitchat_id.gossip_advertise_addr == gossip_advertise_addr)
+            {
+                continue;
+            }
+            if chitchat_ids
+                .iter()
+                .any(|chitchat_id| !self.duplicate_chitchat_ids.contains(*chitchat_id))
+            {
+                warn!(
+                    node_id=%node_id,
+                    chitchat_ids=?chitchat_ids,
+                    "live nodes share a node ID but advertise different gossip addresses"
+                );
+            }
+            let greatest_chitchat_id = chitchat_ids.iter().max().copied();
+
+            for chitchat_id in chitchat_ids {
+                if duplicate_node_id_policy == DuplicateNodeIdPolicy::Reject
+                    && Some(chitchat_id) != greatest_chitchat_id
+                {
+                    rejected_chitchat_ids.insert(chitchat_id.clone());
+                }
+                duplicate_chitchat_ids.insert(chitchat_id.clone());
+            }
+        }
+        self.duplicate_chitchat_ids = duplicate_chitchat_ids;
+        rejected_chitchat_ids
+    }
+
     /// Marks the node as dead or alive depending on the new phi values and updates the live nodes
     /// watcher accordingly.
     pub(crate) fn update_nodes_liveness(&mut self) {
@@ -208,8 +954,10 @@ impl Chitchat {
             .collect::<HashMap<_, _>>();
 
         if self.previous_live_nodes != current_live_nodes {
+            let rejected_chitchat_ids = self.check_duplicate_node_ids(current_live_nodes.keys());
             let live_nodes = current_live_nodes
                 .keys()
+                .filter(|chitchat_id| !rejected_chitchat_ids.contains(*chitchat_id))
                 .cloned()
                 .flat_map(|chitchat_id| {
                     let node_state = self.node_state(&chitchat_id)?;
@@ -223,6 +971,21 @@ impl Chitchat {
                 .collect::<BTreeMap<_, _>>();
             self.previous_live_nodes = current_live_nodes;
 
+            let previous_live_nodes = self.live_nodes_watcher_rx.borrow().clone();
+            for chitchat_id in previous_live_nodes.keys() {
+                if !live_nodes.contains_key(chitchat_id) {
+                    self.cluster_state
+                        .listeners
+                        .trigger_cluster_event(|| ClusterEvent::NodeLeft(chitchat_id.clone()));
+                }
+            }
+            for chitchat_id in live_nodes.keys() {
+                if !previous_live_nodes.contains_key(chitchat_id) {
+                    self.cluster_state
+                        .listeners
+                        .trigger_cluster_event(|| ClusterEvent::NodeJoined(chitchat_id.clone()));
+                }
+            }
             if self.live_nodes_watcher_tx.send(live_nodes).is_err() {
                 error!(current_node = ?self.self_chitchat_id(), "error while reporting membership change event.")
             }
@@ -232,6 +995,50 @@ impl Chitchat {
         for chitchat_id in &garbage_collected_nodes {
             self.cluster_state.remove_node(chitchat_id);
         }
+        let num_pruned_sampling_windows = self
+            .failure_detector
+            .prune(|chitchat_id| self.cluster_state.node_state(chitchat_id).is_some());
+        if num_pruned_sampling_windows > 0 {
+            debug!(
+                num_pruned_sampling_windows,
+                "pruned failure detector state of nodes absent from the cluster state"
+            );
+        }
+        if !garbage_collected_nodes.is_empty() {
+            self.peer_self_versions_tx
+                .send_if_modified(|peer_self_versions| {
+                    let num_peers = peer_self_versions.len();
+                    for chitchat_id in &garbage_collected_nodes {
+                        peer_self_versions.remove(chitchat_id);
+                    }
+                    peer_self_versions.len() != num_peers
+                });
+            for chitchat_id in &garbage_collected_nodes {
+                self.peer_num_versions_ahead.remove(chitchat_id);
+                self.num_consecutive_digest_only_responses
+                    .remove(chitchat_id);
+            }
+        }
+        self.publish_cluster_state();
+    }
+
+    /// Returns a handle to read the cluster state without taking the [`Chitchat`] lock.
+    ///
+    /// The cluster state is only published once a reader has been created, so that the gossip
+    /// loop does not pay for it otherwise.
+    pub fn cluster_state_reader(&self) -> ClusterStateReader {
+        let cluster_state_reader = self.cluster_state_publisher.reader();
+        self.publish_cluster_state();
+        cluster_state_reader
+    }
+
+    /// Publishes a view and a snapshot of the cluster state to the lock-free readers and to
+    /// [`Chitchat::snapshot_arc`], if they were ever requested.
+    pub(crate) fn publish_cluster_state(&self) {
+        self.cluster_state_publisher
+            .publish(&self.cluster_state, self.live_nodes());
+        self.cluster_state_snapshot_publisher
+            .publish(&self.cluster_state);
     }
 
     pub fn node_states(&self) -> &BTreeMap<ChitchatId, NodeState> {
@@ -246,12 +1053,232 @@ impl Chitchat {
         self.cluster_state.node_state_mut(&self.config.chitchat_id)
     }
 
+    /// Freezes the key-values of the self node, e.g. during a controlled shutdown sequence.
+    ///
+    /// While draining, writes to the self node state, such as [`NodeState::set`] or
+    /// [`NodeState::delete`], are dropped with a warning. Gossip is not affected: the self node
+    /// keeps sending its current state and heartbeats to its peers. Local key-values, which are
+    /// never gossiped, can still be written, and so can the internal keys maintained by chitchat
+    /// such as [`READY_KEY`]: [`Chitchat::set_ready`] keeps working while draining.
+    pub fn enter_drain_mode(&mut self) {
+        self.self_node_state().set_draining(true);
+    }
+
+    /// Accepts writes to the self node state again. See [`Chitchat::enter_drain_mode`].
+    pub fn leave_drain_mode(&mut self) {
+        self.self_node_state().set_draining(false);
+    }
+
+    /// Returns whether the self node is draining. See [`Chitchat::enter_drain_mode`].
+    pub fn is_draining(&self) -> bool {
+        self.cluster_state
+            .node_state(&self.config.chitchat_id)
+            .is_some_and(NodeState::is_draining)
+    }
+
+    /// Returns the value associated to `key` in the self node state.
+    ///
+    /// Values set with [`Chitchat::self_set`] are visible right away, before being gossiped.
+    pub fn self_get(&self, key: &str) -> Option<&str> {
+        self.cluster_state
+            .node_state(&self.config.chitchat_id)?
+            .get(key)
+    }
+
+    /// Sets a key-value in the self node state. This is the key-value that gets gossiped to the
+    /// other nodes.
+    pub fn self_set(&mut self, key: impl ToString, value: impl ToString) {
+        self.self_node_state().set(key, value);
+    }
+
+    /// Sets a critical key-value in the self node state, whose delivery to every live peer is
+    /// tracked for at most `timeout`, e.g. a cluster-wide epoch number.
+    ///
+    /// Until every live peer has acknowledged the key-value, or the timeout has expired:
+    /// - the deltas sent to the peers that lack it carry the self node first;
+    /// - every gossip round also targets up to 3 of the live peers that have not acknowledged it
+    ///   yet, on top of the randomly selected ones.
+    ///
+    /// A peer acknowledges the key-value once its digest reports a max version for the self node
+    /// at least equal to the version of the key-value. See [`Chitchat::unacknowledged_peers`].
+    ///
+    /// This is a best-effort reliability boost: it gives no stronger guarantee than eventual
+    /// consistency, and the other keys are gossiped as usual. Setting the key again, critical or
+    /// not, keeps tracking its latest version.
+    pub fn self_set_critical(
+        &mut self,
+        key: impl ToString,
+        value: impl ToString,
+        timeout: Duration,
+    ) {
+        let key = key.to_string();
+        self.self_node_state().set(&key, value);
+        self.critical_keys
+            .retain(|critical_key| critical_key.key != key);
+        self.critical_keys.push(CriticalKey {
+            key,
+            deadline: self.clock.now() + timeout,
+        });
+        self.update_critical_keys();
+    }
+
+    /// Returns the live peers that have not acknowledged the critical key `key` yet, or `None` if
+    /// the key is not tracked, because it was never set with [`Chitchat::self_set_critical`], it
+    /// has been acknowledged by every live peer, or its timeout has expired.
+    pub fn unacknowledged_peers(&self, key: &str) -> Option<Vec<&ChitchatId>> {
+        self.critical_keys
+            .iter()
+            .any(|critical_key| critical_key.key == key)
+            .then(|| self.unacknowledged_peers_for_key(key))
+    }
+
+    fn unacknowledged_peers_for_key(&self, key: &str) -> Vec<&ChitchatId> {
+        let self_chitchat_id = self.self_chitchat_id();
+        let Some(version) = self
+            .cluster_state
+            .node_state(self_chitchat_id)
+            .and_then(|node_state| node_state.get_versioned(key))
+            .map(|versioned_value| versioned_value.version)
+        else {
+            return Vec::new();
+        };
+        let peer_self_versions = self.peer_self_versions_tx.borrow();
+        self.failure_detector
+            .live_nodes()
+            .filter(|chitchat_id| {
+                peer_self_versions
+                    .get(*chitchat_id)
+                    .is_none_or(|peer_self_version| *peer_self_version < version)
+            })
+            .collect()
+    }
+
+    /// Stops tracking the critical keys that were acknowledged by every live peer or whose
+    /// timeout has expired, and returns the addresses of the live peers lacking any of the others.
+    pub(crate) fn update_critical_keys(&mut self) -> HashSet<SocketAddr> {
+        if self.critical_keys.is_empty() {
+            return HashSet::new();
+        }
+        let now = self.clock.now();
+        let mut critical_keys = std::mem::take(&mut self.critical_keys);
+        let mut unacknowledged_peer_addrs = HashSet::new();
+
+        critical_keys.retain(|critical_key| {
+            let unacknowledged_peers = self.unacknowledged_peers_for_key(&critical_key.key);
+            if unacknowledged_peers.is_empty() {
+                return false;
+            }
+            if now >= critical_key.deadline {
+                warn!(
+                    key=%critical_key.key,
+                    unacknowledged_peers=?unacknowledged_peers,
+                    "critical key was not acknowledged by every live peer before the timeout"
+                );
+                return false;
+            }
+            unacknowledged_peer_addrs.extend(
+                unacknowledged_peers
+                    .into_iter()
+                    .map(|chitchat_id| chitchat_id.gossip_advertise_addr),
+            );
+            true
+        });
+        let self_node_state = self.cluster_state.node_state_mut(&self.config.chitchat_id);
+        let critical_version_opt = critical_keys
+            .iter()
+            .flat_map(|critical_key| self_node_state.get_versioned(&critical_key.key))
+            .map(|versioned_value| versioned_value.version)
+            .max();
+        self_node_state.set_critical_version(critical_version_opt);
+        self.critical_keys = critical_keys;
+        unacknowledged_peer_addrs
+    }
+
+    /// Advertises whether the self node is ready, typically as reported by an asynchronous health
+    /// check that holds on to the [`Chitchat`] handle and calls this method whenever its outcome
+    /// changes.
+    ///
+    /// Readiness is gossiped under the key [`READY_KEY`]. Setting
+    /// [`ChitchatConfig::extra_liveness_predicate`] to [`NodeState::is_ready`] restricts the live
+    /// nodes watcher to the nodes that are ready.
+    pub fn set_ready(&mut self, ready: bool) {
+        self.self_node_state().set_bool(READY_KEY, ready);
+    }
+
+    /// Advertises the address at which the self node serves `service`, e.g. a gRPC server.
+    ///
+    /// The address is stored under the key `service_endpoint:{service}`.
+    pub fn set_service_endpoint(&mut self, service: &str, addr: SocketAddr) {
+        let key = format!("{SERVICE_ENDPOINT_KEY_PREFIX}{service}");
+        self.self_node_state().set(key, addr.to_string());
+    }
+
+    /// Returns the live nodes advertising an endpoint for `service`, along with the address of
+    /// that endpoint.
+    ///
+    /// Endpoints that cannot be parsed as a socket address are skipped.
+    pub fn service_endpoints<'a>(
+        &'a self,
+        service: &'a str,
+    ) -> impl Iterator<Item = (&'a ChitchatId, SocketAddr)> + 'a {
+        let key = format!("{SERVICE_ENDPOINT_KEY_PREFIX}{service}");
+        self.live_nodes().filter_map(move |chitchat_id| {
+            let endpoint = self.node_state(chitchat_id)?.get(&key)?;
+            match endpoint.parse() {
+                Ok(addr) => Some((chitchat_id, addr)),
+                Err(_) => {
+                    warn!(node=?chitchat_id, service, endpoint, "invalid service endpoint");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Returns the live nodes carrying the tag `key` with the value `value`.
+    pub fn nodes_with_tag<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+    ) -> impl Iterator<Item = &'a ChitchatId> + 'a {
+        self.live_nodes().filter(move |chitchat_id| {
+            self.node_state(chitchat_id)
+                .and_then(|node_state| node_state.get_tag(key))
+                == Some(value)
+        })
+    }
+
+    /// Forgets a key-value of a remote node, e.g. a stale entry left behind by a node that is
+    /// gone for good, without waiting for it to be garbage collected. Returns `false` if the node
+    /// or the key is unknown, or if the node is the self node, whose keys must be deleted with
+    /// [`NodeState::delete`] instead.
+    ///
+    /// This only affects our local view of the cluster: we do not own the node's state, so no
+    /// tombstone is gossiped. If the owner of the key is still alive, the key-value will be
+    /// learned again as soon as the owner updates it, or if our view of the node gets reset.
+    pub fn forget_key(&mut self, chitchat_id: &ChitchatId, key: &str) -> bool {
+        if chitchat_id == self.self_chitchat_id() {
+            return false;
+        }
+        self.cluster_state.forget_key(chitchat_id, key)
+    }
+
     /// Returns the set of nodes considered alive by the failure detector. It includes the
     /// current node (also called "self node"), which is always considered alive.
     pub fn live_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
         once(self.self_chitchat_id()).chain(self.failure_detector.live_nodes())
     }
 
+    /// Returns the number of live nodes, including the self node, in constant time.
+    pub fn num_live_nodes(&self) -> usize {
+        1 + self.failure_detector.num_live_nodes()
+    }
+
+    /// Returns the number of nodes in the cluster state, including the self node, whether they
+    /// are live or dead, in constant time.
+    pub fn num_nodes(&self) -> usize {
+        self.cluster_state.num_nodes()
+    }
+
     /// Returns a watch stream for monitoring changes in the cluster.
     ///
     /// The stream will emit a new value whenever a node:
@@ -259,23 +1286,277 @@ impl Chitchat {
     /// - leaves the cluster
     /// - updates its max version
     ///
+    /// Nodes that do not satisfy [`ChitchatConfig::extra_liveness_predicate`] are left out, so a
+    /// node becoming ready or not ready is notified as it joins or leaves the cluster.
     /// Heartbeats are not notified.
     pub fn live_nodes_watch_stream(&self) -> WatchStream<BTreeMap<ChitchatId, NodeState>> {
         WatchStream::new(self.live_nodes_watcher_rx.clone())
     }
 
+    /// Returns a watcher of the live nodes, which emits the same values as
+    /// [`Chitchat::live_nodes_watch_stream`].
     pub fn live_nodes_watcher(&self) -> watch::Receiver<BTreeMap<ChitchatId, NodeState>> {
         self.live_nodes_watcher_rx.clone()
     }
 
-    /// Returns the set of nodes considered dead by the failure detector.
-    pub fn dead_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
-        self.failure_detector.dead_nodes()
-    }
-
-    /// Returns the set of nodes considered dead by the failure detector.
-    pub fn scheduled_for_deletion_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
-        self.failure_detector.scheduled_for_deletion_nodes()
+    /// Returns a stream of the changes of the cluster: nodes joining or leaving the set of live
+    /// nodes, node states being reset, and keys being set or deleted.
+    ///
+    /// Events are delivered in the order in which chitchat applied them. Only the events
+    /// happening after the call are emitted.
+    ///
+    /// Up to 1,024 events are buffered for a stream that is not consumed. Chitchat never waits
+    /// for slow consumers: when the buffer is full, the oldest events are dropped, and the stream
+    /// emits [`ClusterEvent::EventsDropped`] in their place. A consumer receiving it should
+    /// resynchronize from the current state, e.g. with [`Chitchat::state_snapshot`].
+    pub fn event_stream(&self) -> impl Stream<Item = ClusterEvent> + Send + 'static {
+        BroadcastStream::new(self.cluster_state.listeners.subscribe_cluster_events()).map(
+            |cluster_event_result| match cluster_event_result {
+                Ok(cluster_event) => cluster_event,
+                Err(BroadcastStreamRecvError::Lagged(num_dropped_events)) => {
+                    ClusterEvent::EventsDropped { num_dropped_events }
+                }
+            },
+        )
+    }
+
+    /// Returns a future that resolves once the given node is live.
+    ///
+    /// The future resolves immediately if the node is already live. It does not borrow `self`,
+    /// so it can be awaited without holding the chitchat lock.
+    pub fn wait_for_live(
+        &self,
+        chitchat_id: &ChitchatId,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let chitchat_id = chitchat_id.clone();
+        self.wait_for_live_nodes(move |live_nodes| live_nodes.contains_key(&chitchat_id))
+    }
+
+    /// Returns a future that resolves once at least `num_nodes` nodes, including the self node,
+    /// are live.
+    pub fn wait_for_live_count(
+        &self,
+        num_nodes: usize,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        self.wait_for_live_nodes(move |live_nodes| live_nodes.len() >= num_nodes)
+    }
+
+    /// Calls `callback` once at least `quorum` nodes, including the self node, are live.
+    ///
+    /// The callback is called at most once, right away if the quorum is already met, and is not
+    /// called again if the set of live nodes changes afterwards. Aborting the returned handle
+    /// cancels the callback if it has not been called yet.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn on_quorum(
+        &self,
+        quorum: usize,
+        callback: impl FnOnce() + Send + 'static,
+    ) -> JoinHandle<()> {
+        let wait_for_quorum = self.wait_for_live_count(quorum);
+        tokio::spawn(async move {
+            wait_for_quorum.await;
+            callback();
+        })
+    }
+
+    /// Returns a future that resolves once every live peer has caught up with the self node up to
+    /// `version`, e.g. the version returned by [`NodeState::max_version`] right after updating the
+    /// self node state. The future fails if this takes longer than `timeout`.
+    ///
+    /// A peer has caught up once the last digest received from it reports a max version for the
+    /// self node at least equal to `version`. The set of live peers is the one of
+    /// [`Chitchat::live_nodes_watcher`], and is reevaluated as nodes join or leave.
+    ///
+    /// When [`ChitchatConfig::compact_self_versions`] is enabled, the self node versions up to
+    /// `version` are no longer compacted, so that `version` keeps designating the same state.
+    /// `version` must therefore be read without releasing the lock on chitchat in between:
+    /// a version read before a gossip round may already have been compacted away.
+    ///
+    /// Like [`Chitchat::wait_for_live`], the future does not borrow `self`. Must be awaited from
+    /// within a tokio runtime.
+    pub fn await_propagation(
+        &self,
+        version: Version,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), tokio::time::error::Elapsed>> + Send + 'static {
+        if let Some(self_node_state) = self.cluster_state.node_state(&self.config.chitchat_id) {
+            let pinned_version = version.min(self_node_state.max_version());
+            if pinned_version > self.max_gossiped_self_version.get() {
+                self.max_gossiped_self_version.set(pinned_version);
+            }
+        }
+        let self_chitchat_id = self.self_chitchat_id().clone();
+        let mut live_nodes_watcher_rx = self.live_nodes_watcher_rx.clone();
+        let mut peer_self_versions_rx = self.peer_self_versions_tx.subscribe();
+        let wait_for_propagation = async move {
+            loop {
+                let is_propagated = {
+                    let live_nodes = live_nodes_watcher_rx.borrow_and_update();
+                    let peer_self_versions = peer_self_versions_rx.borrow_and_update();
+                    live_nodes
+                        .keys()
+                        .filter(|chitchat_id| **chitchat_id != self_chitchat_id)
+                        .all(|chitchat_id| {
+                            peer_self_versions
+                                .get(chitchat_id)
+                                .is_some_and(|peer_self_version| *peer_self_version >= version)
+                        })
+                };
+                if is_propagated {
+                    return;
+                }
+                let changed_result = tokio::select! {
+                    changed_result = live_nodes_watcher_rx.changed() => changed_result,
+                    changed_result = peer_self_versions_rx.changed() => changed_result,
+                };
+                if changed_result.is_err() {
+                    // Chitchat was dropped: the condition will never be met.
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        tokio::time::timeout(timeout, wait_for_propagation)
+    }
+
+    fn wait_for_live_nodes(
+        &self,
+        predicate: impl Fn(&BTreeMap<ChitchatId, NodeState>) -> bool + Send + 'static,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let mut live_nodes_watcher_rx = self.live_nodes_watcher_rx.clone();
+        async move {
+            loop {
+                if predicate(&live_nodes_watcher_rx.borrow_and_update()) {
+                    return;
+                }
+                if live_nodes_watcher_rx.changed().await.is_err() {
+                    // Chitchat was dropped: the condition will never be met.
+                    std::future::pending::<()>().await;
+                }
+            }
+        }
+    }
+
+    /// Clears the heartbeat intervals sampled by the failure detector for a node, while keeping
+    /// the node in the cluster. Returns `false` if the node is unknown to the failure detector.
+    ///
+    /// This is useful to forget the statistics skewed by a known network disruption, e.g. a
+    /// planned maintenance, instead of waiting for the failure detector to learn them again.
+    pub fn reset_failure_detector_samples(&mut self, chitchat_id: &ChitchatId) -> bool {
+        self.failure_detector.reset_samples(chitchat_id)
+    }
+
+    /// Overrides the number of heartbeat intervals sampled by the failure detector for a node,
+    /// which otherwise defaults to [`FailureDetectorConfig::sampling_window_size`].
+    ///
+    /// This is useful for nodes whose heartbeat cadence differs from the rest of the cluster.
+    /// Shrinking the window discards the oldest samples. Nodes absent from the cluster state are
+    /// ignored, as their sampling window would be pruned on the next liveness update.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_size` is zero.
+    pub fn set_failure_detector_window_size(
+        &mut self,
+        chitchat_id: &ChitchatId,
+        window_size: usize,
+    ) {
+        if self.cluster_state.node_state(chitchat_id).is_none() {
+            return;
+        }
+        self.failure_detector
+            .set_window_size(chitchat_id, window_size);
+    }
+
+    /// Reports a heartbeat of a node learned through an out-of-band channel, e.g. the health feed
+    /// of a load balancer, to the failure detector. Liveness is then decided from the reports
+    /// received through gossip and through this method alike.
+    ///
+    /// Both kinds of reports are sampled in the same window, so the failure detector expects the
+    /// node to be heard of at the combined cadence. If the external channel reports much more
+    /// frequently than the gossip interval and goes silent, gossip alone may not be enough to keep
+    /// the node live until the samples of the external channel age out. Reporting at a cadence
+    /// close to the gossip interval avoids this, and so does calling
+    /// [`Chitchat::reset_failure_detector_samples`] when the external channel is known to stop.
+    ///
+    /// The report is local to the self node: it is not gossiped, and it does not change the
+    /// heartbeat of the node in the cluster state. The self node and nodes absent from the cluster
+    /// state are ignored, as the latter would be pruned on the next liveness update.
+    pub fn report_bridged_heartbeat(&mut self, chitchat_id: &ChitchatId) {
+        if chitchat_id == self.self_chitchat_id()
+            || self.cluster_state.node_state(chitchat_id).is_none()
+        {
+            return;
+        }
+        self.failure_detector.report_heartbeat(chitchat_id);
+    }
+
+    /// Returns the wall-clock time elapsed since the self node last received a heartbeat from a
+    /// node, i.e. since it last heard of the node through gossip.
+    ///
+    /// Returns `None` for the self node, and for nodes from which no heartbeat was received yet or
+    /// that were garbage collected.
+    pub fn last_heartbeat_elapsed(&self, chitchat_id: &ChitchatId) -> Option<Duration> {
+        self.failure_detector.last_heartbeat_elapsed(chitchat_id)
+    }
+
+    /// Returns the set of live nodes suspected to be failing by the failure detector.
+    ///
+    /// This set is always empty if no suspect threshold is configured.
+    pub fn suspected_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.failure_detector.suspected_nodes()
+    }
+
+    /// Returns the set of nodes considered dead by the failure detector.
+    pub fn dead_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.failure_detector.dead_nodes()
+    }
+
+    /// Returns the number of nodes considered dead by the failure detector, in constant time.
+    pub fn num_dead_nodes(&self) -> usize {
+        self.failure_detector.num_dead_nodes()
+    }
+
+    /// Returns the set of nodes considered dead by the failure detector.
+    pub fn scheduled_for_deletion_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.failure_detector.scheduled_for_deletion_nodes()
+    }
+
+    /// Returns the nodes whose state is left out of the digests and deltas sent to peers: the
+    /// nodes scheduled for deletion, and the self node if it is an observer.
+    fn nodes_excluded_from_gossip(&self) -> HashSet<&ChitchatId> {
+        let mut excluded_nodes: HashSet<&ChitchatId> =
+            self.scheduled_for_deletion_nodes().collect();
+        if self.config.observer {
+            excluded_nodes.insert(self.self_chitchat_id());
+        }
+        excluded_nodes
+    }
+
+    /// Returns the nodes known by the failure detector along with their status. Live nodes,
+    /// including the self node, come first.
+    ///
+    /// The set of nodes is the union of [`Chitchat::live_nodes`] and [`Chitchat::dead_nodes`].
+    pub fn node_statuses(&self) -> impl Iterator<Item = (&ChitchatId, NodeStatus)> {
+        let live_nodes = self.live_nodes().map(|chitchat_id| {
+            let node_status = if self.failure_detector.is_suspected(chitchat_id) {
+                NodeStatus::Suspected
+            } else {
+                NodeStatus::Live
+            };
+            (chitchat_id, node_status)
+        });
+        let dead_nodes = self.failure_detector.dead_nodes_with_deletion_status().map(
+            |(chitchat_id, is_scheduled_for_deletion)| {
+                let node_status = if is_scheduled_for_deletion {
+                    NodeStatus::ScheduledForDeletion
+                } else {
+                    NodeStatus::Dead
+                };
+                (chitchat_id, node_status)
+            },
+        );
+        live_nodes.chain(dead_nodes)
     }
 
     /// Returns the set of seed nodes.
@@ -283,10 +1564,48 @@ impl Chitchat {
         self.cluster_state.seed_addrs()
     }
 
+    /// Returns an iterator over the current seed addresses.
+    ///
+    /// The addresses are copied out of the seed watch, so the iterator does not hold any lock and
+    /// does not reflect updates made after this call.
+    pub fn seeds(&self) -> impl Iterator<Item = SocketAddr> {
+        let seed_addrs: Vec<SocketAddr> = self
+            .cluster_state
+            .seed_addrs_watcher()
+            .borrow()
+            .iter()
+            .copied()
+            .collect();
+        seed_addrs.into_iter()
+    }
+
+    /// Returns a receiver over the seed addresses, notified whenever the seeds are re-resolved.
+    pub fn seeds_watcher(&self) -> watch::Receiver<HashSet<SocketAddr>> {
+        self.cluster_state.seed_addrs_watcher()
+    }
+
+    /// Returns the ID of the cluster this node belongs to. Messages from nodes of other clusters
+    /// are rejected.
     pub fn cluster_id(&self) -> &str {
         &self.config.cluster_id
     }
 
+    /// Returns whether the self node is an observer. See [`ChitchatConfig::observer`].
+    ///
+    /// An observer gossips like any other node to learn the state of the cluster, but the self
+    /// node is left out of the digests and deltas it sends. Peers therefore never learn about it:
+    /// it does not appear in their cluster state nor in their live or dead nodes, and they never
+    /// select it as a gossip target. They still respond to its SYN messages, which are handled
+    /// like the ones of any other node of the same cluster, and they gossip with it if it is one
+    /// of their seed nodes or static peers.
+    ///
+    /// The observer keeps a state for the self node, including its heartbeat and the key-values
+    /// set with [`Chitchat::self_set`], but it is only visible locally. This is what sets an
+    /// observer apart from a node with an empty state, whose heartbeat is still gossiped.
+    pub fn is_observer(&self) -> bool {
+        self.config.observer
+    }
+
     /// Returns the current node's Chitchat ID.
     pub fn self_chitchat_id(&self) -> &ChitchatId {
         &self.config.chitchat_id
@@ -297,6 +1616,27 @@ impl Chitchat {
         ClusterStateSnapshot::from(&self.cluster_state)
     }
 
+    /// Returns the last snapshot of the cluster state published by the gossip loop, shared by
+    /// all the callers, so that serving it to concurrent readers only costs a reference count
+    /// increment rather than a deep clone per reader.
+    ///
+    /// Snapshots are published along with the views of [`Chitchat::cluster_state_reader`], once
+    /// the first snapshot has been requested. A snapshot may lag behind the latest mutations
+    /// made directly on a locked [`Chitchat`], and its heartbeats are only refreshed along with
+    /// the key-values.
+    pub fn snapshot_arc(&self) -> Arc<ClusterStateSnapshot> {
+        self.cluster_state_snapshot_publisher
+            .snapshot(&self.cluster_state)
+    }
+
+    /// Returns a serializable view of the cluster state borrowing it instead of cloning it.
+    ///
+    /// This is cheaper than [`Chitchat::state_snapshot`] when the state only needs to be
+    /// serialized, for instance by an admin endpoint.
+    pub fn state_snapshot_ref(&self) -> ClusterStateSnapshotRef<'_> {
+        ClusterStateSnapshotRef::from(&self.cluster_state)
+    }
+
     /// Resets the entire node state.
     ///
     /// Updated key-values will see their listeners called.
@@ -304,6 +1644,9 @@ impl Chitchat {
     ///
     /// Existing key-values that are not present in `key_values` will be deleted
     /// (not marked with a tombstone).
+    ///
+    /// The reset is ignored, and the state left unchanged, if it targets the self node or if it
+    /// does not bring the node state to a more recent version.
     pub fn reset_node_state(
         &mut self,
         chitchat_id: &ChitchatId,
@@ -311,11 +1654,25 @@ impl Chitchat {
         max_version: Version,
         last_gc_version: Version,
     ) {
-        let node_state = self.cluster_state.node_state_mut(chitchat_id);
-
-        if node_state.max_version() >= max_version {
+        if chitchat_id == self.self_chitchat_id() {
+            warn!(node=?chitchat_id, "cannot reset the self node state, ignoring reset");
+            return;
+        }
+        let current_max_version = self
+            .cluster_state
+            .node_state(chitchat_id)
+            .map(|node_state| node_state.max_version())
+            .unwrap_or(0);
+        if current_max_version >= max_version {
+            warn!(
+                node=?chitchat_id,
+                max_version,
+                current_max_version,
+                "reset does not bring the node state to a more recent version, ignoring reset"
+            );
             return;
         }
+        let node_state = self.cluster_state.node_state_mut(chitchat_id);
 
         // We make sure that the node is listed in the failure detector,
         // so that we won't forget to GC the state.
@@ -341,18 +1698,45 @@ impl Chitchat {
         node_state.set_last_gc_version(last_gc_version);
     }
 
-    pub(crate) fn update_self_heartbeat(&mut self) {
+    /// Increments the heartbeat of the self node.
+    ///
+    /// This is the only way the self node heartbeat is bumped: it is called once per gossip round
+    /// and every time a message is processed. Computing a digest never has this side effect.
+    ///
+    /// If configured, the versions of the self node that were not gossiped yet are compacted
+    /// beforehand.
+    pub(crate) fn tick_heartbeat(&mut self) {
+        if self.config.compact_self_versions {
+            let max_gossiped_self_version = self.max_gossiped_self_version.get();
+            self.self_node_state()
+                .compact_versions(max_gossiped_self_version);
+        }
         self.self_node_state().inc_heartbeat();
     }
 
+    /// Records that the current versions of the self node may have been observed by peers.
+    fn record_max_gossiped_self_version(&self) {
+        if let Some(self_node_state) = self.cluster_state.node_state(&self.config.chitchat_id) {
+            self.max_gossiped_self_version
+                .set(self_node_state.max_version());
+        }
+    }
+
     pub(crate) fn cluster_state(&self) -> &ClusterState {
         &self.cluster_state
     }
 
+    /// Returns the digest of the local view of the cluster, as it would be sent to peers.
+    ///
+    /// Contrary to gossiping, this does not increment the heartbeat of the self node.
+    pub fn digest(&self) -> Digest {
+        let excluded_nodes = self.nodes_excluded_from_gossip();
+        self.compute_digest(&excluded_nodes)
+    }
+
     /// Computes the node's digest.
-    fn compute_digest(&self, scheduled_for_deletion_nodes: &HashSet<&ChitchatId>) -> Digest {
-        self.cluster_state
-            .compute_digest(scheduled_for_deletion_nodes)
+    fn compute_digest(&self, excluded_nodes: &HashSet<&ChitchatId>) -> Digest {
+        self.cluster_state.compute_digest(excluded_nodes)
     }
 
     /// Subscribes a callback that will be called every time a key matching the supplied prefix
@@ -379,6 +1763,81 @@ impl Chitchat {
             .listeners
             .subscribe_event(key_prefix, callback)
     }
+
+    /// Same as [`Chitchat::subscribe_event`], except that the callback is only called for the
+    /// events accepted by `filter`, e.g. to skip a noisy key sharing the prefix. As for the
+    /// callback, the filter is called with the key stripped of the prefix.
+    #[must_use]
+    pub fn subscribe_event_filtered(
+        &self,
+        key_prefix: impl ToString,
+        filter: impl Fn(&KeyChangeEvent) -> bool + 'static + Send + Sync,
+        callback: impl Fn(KeyChangeEvent) + 'static + Send + Sync,
+    ) -> ListenerHandle {
+        self.cluster_state()
+            .listeners
+            .subscribe_event_filtered(key_prefix, filter, callback)
+    }
+
+    /// Same as [`Chitchat::subscribe_event`], except that keys are matched against a glob pattern
+    /// rather than a prefix: `*` matches any sequence of characters and `?` matches a single
+    /// character. For instance, `shard_*_leader` matches `shard_1_leader`.
+    ///
+    /// The callback is called with the whole key. Matching a pattern is more expensive than
+    /// matching a prefix, so prefixes should be preferred when they are enough.
+    #[must_use]
+    pub fn subscribe_event_pattern(
+        &self,
+        key_pattern: &str,
+        callback: impl Fn(KeyChangeEvent) + 'static + Send + Sync,
+    ) -> ListenerHandle {
+        self.cluster_state()
+            .listeners
+            .subscribe_event_pattern(key_pattern, callback)
+    }
+
+    /// Same as [`Chitchat::subscribe_event`], except that the callback is executed on a dedicated
+    /// thread, so that a slow callback (doing I/O for instance) does not block gossip.
+    ///
+    /// Up to `capacity` events are buffered, and delivered in order. If the callback cannot keep
+    /// up and the buffer is full, events are dropped. They are counted in
+    /// [`ListenerHandle::num_dropped_events`].
+    ///
+    /// Dropping the handle waits for the thread to deliver the buffered events and exit.
+    #[must_use]
+    pub fn subscribe_event_buffered(
+        &self,
+        key_prefix: impl ToString,
+        capacity: usize,
+        callback: impl Fn(KeyChangeEvent) + 'static + Send + Sync,
+    ) -> ListenerHandle {
+        self.cluster_state()
+            .listeners
+            .subscribe_event_buffered(key_prefix, capacity, callback)
+    }
+}
+
+/// Drives a [`Chitchat`] instance without a server, e.g. for benchmarks.
+#[cfg(feature = "testsuite")]
+impl Chitchat {
+    /// Returns the SYN message initiating a handshake with a peer.
+    pub fn create_syn_message_for_test(&self) -> ChitchatMessage {
+        self.create_syn_message()
+    }
+
+    /// Processes a message received from `from_addr` and returns the response, if any.
+    pub fn process_message_for_test(
+        &mut self,
+        from_addr: SocketAddr,
+        msg: ChitchatMessage,
+    ) -> Option<ChitchatMessage> {
+        self.process_message(from_addr, msg)
+    }
+
+    /// Starts a new gossip round, as the server does before sending SYN messages.
+    pub fn record_gossip_round_for_test(&mut self) {
+        self.record_gossip_round();
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -391,8 +1850,40 @@ pub struct KeyChangeEvent<'a> {
     pub node: &'a ChitchatId,
 }
 
+/// An event describing a change of the cluster, as observed by the local node.
+///
+/// See [`Chitchat::event_stream`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ClusterEvent {
+    /// A node joined the set of live nodes.
+    NodeJoined(ChitchatId),
+    /// A node left the set of live nodes.
+    NodeLeft(ChitchatId),
+    /// A key was set to a new value.
+    KeyChanged {
+        node: ChitchatId,
+        key: String,
+        value: Arc<str>,
+    },
+    /// A key was deleted.
+    KeyDeleted { node: ChitchatId, key: String },
+    /// The state of a node was reset: everything previously known about the node was forgotten,
+    /// and replaced by the state received from a peer. It is followed by a
+    /// [`ClusterEvent::KeyDeleted`] event for every key the node had, then by the key changes of
+    /// the new state.
+    NodeReset(ChitchatId),
+    /// A delta resetting the state of a node was sent to a peer, because the peer missed
+    /// tombstones of that node that were garbage collected since. The whole state of the node is
+    /// sent again, so frequent resets usually mean that the marked-for-deletion grace period is
+    /// too short.
+    NodeResetSent(ChitchatId),
+    /// Events were dropped because the consumer of the stream fell too far behind. See
+    /// [`Chitchat::event_stream`].
+    EventsDropped { num_dropped_events: u64 },
+}
+
 impl KeyChangeEvent<'_> {
-    fn strip_key_prefix(&self, prefix: &str) -> Option<KeyChangeEvent> {
+    fn strip_key_prefix(&self, prefix: &str) -> Option<KeyChangeEvent<'_>> {
         let key_without_prefix = self.key.strip_prefix(prefix)?;
         Some(KeyChangeEvent {
             key: key_without_prefix,
@@ -402,6 +1893,14 @@ impl KeyChangeEvent<'_> {
     }
 }
 
+/// Returns the wall-clock time, in milliseconds since the Unix epoch.
+fn unix_timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use std::ops::{Add, RangeInclusive};
@@ -409,11 +1908,13 @@ mod tests {
     use std::sync::Arc;
     use std::time::Duration;
 
-    use tokio::sync::Mutex;
+    use tokio::sync::{oneshot, Mutex};
     use tokio::time;
     use tokio_stream::StreamExt;
 
     use super::*;
+    use crate::clock::tests::ManualClock;
+    use crate::listener::CLUSTER_EVENTS_CAPACITY;
     use crate::server::{spawn_chitchat, ChitchatHandle};
     use crate::transport::{ChannelTransport, Transport};
 
@@ -421,9 +1922,17 @@ mod tests {
 
     fn run_chitchat_handshake(initiating_node: &mut Chitchat, peer_node: &mut Chitchat) {
         let syn_message = initiating_node.create_syn_message();
-        let syn_ack_message = peer_node.process_message(syn_message).unwrap();
-        let ack_message = initiating_node.process_message(syn_ack_message).unwrap();
-        assert!(peer_node.process_message(ack_message).is_none());
+        let initiating_addr = initiating_node.self_chitchat_id().gossip_advertise_addr;
+        let peer_addr = peer_node.self_chitchat_id().gossip_advertise_addr;
+        let syn_ack_message = peer_node
+            .process_message(initiating_addr, syn_message)
+            .unwrap();
+        let ack_message = initiating_node
+            .process_message(peer_addr, syn_ack_message)
+            .unwrap();
+        assert!(peer_node
+            .process_message(initiating_addr, ack_message)
+            .is_none());
     }
 
     /// Checks that all of the non-deleted key-values pairs are the same in
@@ -472,14 +1981,36 @@ mod tests {
             listen_addr: chitchat_id.gossip_advertise_addr,
             seed_nodes: seeds.to_vec(),
             failure_detector_config: FailureDetectorConfig {
-                dead_node_grace_period: DEAD_NODE_GRACE_PERIOD,
+                dead_node_grace_period: Some(DEAD_NODE_GRACE_PERIOD),
                 phi_threshold: 5.0,
                 initial_interval: Duration::from_millis(100),
                 ..Default::default()
             },
+            failure_detector_startup_grace_period: None,
             marked_for_deletion_grace_period: Duration::from_secs(3_600),
             catchup_callback: None,
+            self_superseded_callback: None,
+            delta_applied_callback: None,
             extra_liveness_predicate: None,
+            duplicate_node_id_policy: DuplicateNodeIdPolicy::Ignore,
+            bad_cluster_quarantine_config: None,
+            respond_to_bad_cluster: true,
+            dead_node_gossip_probability_multiplier: 1.0,
+            min_seed_nodes_per_round: 1,
+            static_peers: Vec::new(),
+            max_gossip_bytes_per_sec: None,
+            observer: false,
+            message_auth_secret: None,
+            key_validator: None,
+            value_transform: None,
+            debug_buffers_enabled: false,
+            key_value_history_depth: 0,
+            recent_deltas_capacity: 0,
+            digest_only_gossip_config: None,
+            clock: None,
+            compact_self_versions: false,
+            rpc_advertise_addr: None,
+            message_timestamp_config: None,
         };
         start_node_with_config(transport, config).await
     }
@@ -568,6 +2099,250 @@ mod tests {
         assert_nodes_sync(&[&node1, &node2]);
     }
 
+    #[test]
+    fn test_process_batched_message_max_response_len() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        for i in 0..100u64 {
+            let value = format!("{:x}", i.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            node1.self_node_state().set(format!("key-{i}"), value);
+        }
+        let node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        let node2_addr = node2.self_chitchat_id().gossip_advertise_addr;
+
+        let syn_ack = node1
+            .process_batched_message(node2_addr, node2.create_syn_message(), usize::MAX)
+            .unwrap();
+        assert!(syn_ack.serialized_len() > 500);
+
+        let syn_ack = node1
+            .process_batched_message(node2_addr, node2.create_syn_message(), 500)
+            .unwrap();
+        assert!(syn_ack.serialized_len() <= 500);
+        let ChitchatMessage::SynAck { delta, .. } = syn_ack else {
+            panic!("expected a SYN-ACK, got {syn_ack:?}");
+        };
+        assert!(delta.num_tuples() > 0);
+
+        // The digest is sent even if the delta does not fit at all.
+        let syn_ack = node1
+            .process_batched_message(node2_addr, node2.create_syn_message(), 0)
+            .unwrap();
+        assert!(matches!(syn_ack, ChitchatMessage::SynAck { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_node_id_policy() {
+        tokio::time::pause();
+        let duplicate_chitchat_ids = [
+            ChitchatId::new("node-dup".to_string(), 1, ([127, 0, 0, 1], 10_011).into()),
+            ChitchatId::new("node-dup".to_string(), 2, ([127, 0, 0, 1], 10_012).into()),
+        ];
+        // A node restarting on the same address is not a duplicate.
+        let restarted_chitchat_ids = [
+            ChitchatId::new(
+                "node-restart".to_string(),
+                1,
+                ([127, 0, 0, 1], 10_013).into(),
+            ),
+            ChitchatId::new(
+                "node-restart".to_string(),
+                2,
+                ([127, 0, 0, 1], 10_013).into(),
+            ),
+        ];
+        for duplicate_node_id_policy in [
+            DuplicateNodeIdPolicy::Ignore,
+            DuplicateNodeIdPolicy::Warn,
+            DuplicateNodeIdPolicy::Reject,
+        ] {
+            let config = ChitchatConfig {
+                duplicate_node_id_policy,
+                ..ChitchatConfig::for_test(10_001)
+            };
+            let empty_seeds = watch::channel(Default::default()).1;
+            let mut node = Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new());
+            for heartbeat in 1..=3 {
+                tokio::time::advance(Duration::from_millis(100)).await;
+                for chitchat_id in duplicate_chitchat_ids.iter().chain(&restarted_chitchat_ids) {
+                    node.report_heartbeat(chitchat_id, Heartbeat(heartbeat));
+                }
+            }
+            node.update_nodes_liveness();
+            assert_eq!(node.live_nodes().count(), 5);
+
+            let live_nodes = node.live_nodes_watcher().borrow().clone();
+            assert!(live_nodes.contains_key(&duplicate_chitchat_ids[1]));
+            assert!(live_nodes.contains_key(&restarted_chitchat_ids[0]));
+            assert!(live_nodes.contains_key(&restarted_chitchat_ids[1]));
+            assert_eq!(
+                live_nodes.contains_key(&duplicate_chitchat_ids[0]),
+                duplicate_node_id_policy != DuplicateNodeIdPolicy::Reject
+            );
+            let expected_num_duplicates =
+                if duplicate_node_id_policy == DuplicateNodeIdPolicy::Ignore {
+                    0
+                } else {
+                    2
+                };
+            assert_eq!(node.duplicate_chitchat_ids.len(), expected_num_duplicates);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_report_bridged_heartbeat() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let config = ChitchatConfig {
+            failure_detector_config: FailureDetectorConfig {
+                initial_interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..ChitchatConfig::for_test(10_001)
+        };
+        let mut node = Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new());
+        let bridged_chitchat_id = ChitchatId::for_local_test(10_002);
+        let gossiped_chitchat_id = ChitchatId::for_local_test(10_003);
+        for heartbeat in 1..=3 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            node.report_heartbeat(&bridged_chitchat_id, Heartbeat(heartbeat));
+            node.report_heartbeat(&gossiped_chitchat_id, Heartbeat(heartbeat));
+        }
+        node.update_nodes_liveness();
+        assert_eq!(node.live_nodes().count(), 3);
+
+        // Gossip is delayed, but the liveness of one of the nodes is bridged.
+        for _ in 0..20 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            node.report_bridged_heartbeat(&bridged_chitchat_id);
+        }
+        node.update_nodes_liveness();
+        assert_eq!(
+            node.live_nodes().cloned().collect::<Vec<_>>(),
+            [node.self_chitchat_id().clone(), bridged_chitchat_id]
+        );
+        assert_eq!(
+            node.dead_nodes().collect::<Vec<_>>(),
+            [&gossiped_chitchat_id]
+        );
+
+        // The self node and unknown nodes are ignored.
+        node.report_bridged_heartbeat(&node.self_chitchat_id().clone());
+        node.report_bridged_heartbeat(&ChitchatId::for_local_test(10_004));
+        assert_eq!(node.failure_detector.num_sampling_windows(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_prune_failure_detector_sampling_windows() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        let long_lived_chitchat_id = ChitchatId::for_local_test(10_002);
+        // Short-lived nodes come and go: their node state is removed from the cluster state while
+        // the failure detector still holds their sampling window.
+        for port in 20_000..20_100 {
+            let short_lived_chitchat_id = ChitchatId::for_local_test(port);
+            for heartbeat in 1..=3 {
+                tokio::time::advance(Duration::from_millis(100)).await;
+                node.report_heartbeat(&long_lived_chitchat_id, Heartbeat(heartbeat));
+                node.report_heartbeat(&short_lived_chitchat_id, Heartbeat(heartbeat));
+            }
+            node.cluster_state.remove_node(&short_lived_chitchat_id);
+            node.update_nodes_liveness();
+            assert_eq!(node.failure_detector.num_sampling_windows(), 1);
+        }
+        assert_eq!(
+            node.live_nodes().cloned().collect::<Vec<_>>(),
+            [
+                node.self_chitchat_id().clone(),
+                long_lived_chitchat_id.clone()
+            ]
+        );
+
+        // Overriding the window size of a node absent from the cluster state is a no-op.
+        node.set_failure_detector_window_size(&ChitchatId::for_local_test(20_000), 10);
+        assert_eq!(node.failure_detector.num_sampling_windows(), 1);
+        node.set_failure_detector_window_size(&long_lived_chitchat_id, 10);
+        assert_eq!(node.failure_detector.num_sampling_windows(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_estimated_convergence() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        for i in 0..20_000 {
+            node1.self_node_state().set(format!("k{i}"), i);
+        }
+        assert_eq!(node2.estimated_convergence(), None);
+
+        let node1_addr = node1.self_chitchat_id().gossip_advertise_addr;
+        for _ in 0..3 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            node1.tick_heartbeat();
+            let syn_message = node1.create_syn_message();
+            node2.process_message(node1_addr, syn_message).unwrap();
+        }
+        node2.update_nodes_liveness();
+        assert_eq!(node2.live_nodes().count(), 2);
+
+        // node2 does not hold any key-value yet: each delta is assumed to carry 65_507 / 64 =
+        // 1_023 key-values, i.e. 3_069 versions per round, hence 7 rounds.
+        let gossip_interval = node2.config.gossip_interval;
+        assert_eq!(node2.estimated_convergence(), Some(gossip_interval * 7));
+
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            run_chitchat_handshake(&mut node2, &mut node1);
+        }
+        assert_eq!(node2.estimated_convergence(), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_estimated_convergence_behind_nat() {
+        tokio::time::pause();
+        let node2_addr: SocketAddr = ([192, 0, 2, 1], 40_002).into();
+        let (mut node1, mut node2) = setup_nodes_behind_nat(
+            [
+                ChitchatConfig::for_test(10_001),
+                ChitchatConfig::for_test(10_002),
+            ],
+            node2_addr,
+        )
+        .await;
+        assert_eq!(node1.estimated_convergence(), Some(Duration::ZERO));
+
+        for i in 0..20_000 {
+            node2.self_node_state().set(format!("k{i}"), i);
+        }
+        let syn_message = node2.seal_message(node2.create_syn_message());
+        node1.process_message(node2_addr, syn_message).unwrap();
+        assert!(node1
+            .estimated_convergence()
+            .is_some_and(|estimated_convergence| estimated_convergence > Duration::ZERO));
+    }
+
     #[test]
     fn test_chitchat_dead_node_liveness() {
         let node_config1 = ChitchatConfig::for_test(10_001);
@@ -640,26 +2415,503 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_live_node_channel_with_extra_predicate() {
-        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
-        let chitchat_ids: Vec<ChitchatId> = (1..=3).map(ChitchatId::for_local_test).collect();
-        let make_config = |chitchat_id: &ChitchatId| ChitchatConfig {
-            chitchat_id: chitchat_id.clone(),
-            cluster_id: "default-cluster".to_string(),
-            gossip_interval: Duration::from_millis(100),
-            listen_addr: chitchat_id.gossip_advertise_addr,
-            seed_nodes: vec![chitchat_ids[0].gossip_advertise_addr.to_string()],
-            failure_detector_config: FailureDetectorConfig {
-                dead_node_grace_period: DEAD_NODE_GRACE_PERIOD,
-                phi_threshold: 5.0,
-                initial_interval: Duration::from_millis(100),
-                ..Default::default()
+    async fn test_num_nodes() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        assert_eq!(node.num_nodes(), 1);
+        assert_eq!(node.num_live_nodes(), 1);
+        assert_eq!(node.num_dead_nodes(), 0);
+
+        let live_chitchat_id = ChitchatId::for_local_test(10_002);
+        let dead_chitchat_id = ChitchatId::for_local_test(10_003);
+        node.cluster_state.node_state_mut(&live_chitchat_id);
+        node.cluster_state.node_state_mut(&dead_chitchat_id);
+        node.failure_detector.report_heartbeat(&dead_chitchat_id);
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            node.failure_detector.report_heartbeat(&live_chitchat_id);
+        }
+        node.update_nodes_liveness();
+        assert_eq!(node.num_nodes(), 3);
+        assert_eq!(node.num_live_nodes(), node.live_nodes().count());
+        assert_eq!(node.num_live_nodes(), 2);
+        assert_eq!(node.num_dead_nodes(), node.dead_nodes().count());
+        assert_eq!(node.num_dead_nodes(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_on_quorum() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        node.update_nodes_liveness();
+
+        // The quorum is already met.
+        let (quorum_tx, quorum_rx) = oneshot::channel();
+        node.on_quorum(1, move || quorum_tx.send(()).unwrap());
+        tokio::time::timeout(Duration::from_millis(10), quorum_rx)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let num_calls = Arc::new(AtomicUsize::new(0));
+        let num_calls_clone = num_calls.clone();
+        let quorum_handle = node.on_quorum(2, move || {
+            num_calls_clone.fetch_add(1, Ordering::SeqCst);
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(num_calls.load(Ordering::SeqCst), 0);
+
+        let chitchat_id = ChitchatId::for_local_test(10_002);
+        node.cluster_state
+            .node_state_mut(&chitchat_id)
+            .set("key", "value");
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            node.failure_detector.report_heartbeat(&chitchat_id);
+        }
+        node.update_nodes_liveness();
+        tokio::time::timeout(Duration::from_secs(1), quorum_handle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(num_calls.load(Ordering::SeqCst), 1);
+
+        // Membership churn does not call the callback again.
+        tokio::time::advance(Duration::from_secs(60)).await;
+        node.update_nodes_liveness();
+        assert_eq!(node.live_nodes().count(), 1);
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            node.failure_detector.report_heartbeat(&chitchat_id);
+        }
+        node.update_nodes_liveness();
+        assert_eq!(node.live_nodes().count(), 2);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(num_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_live() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let nodes = setup_nodes(20011..=20013, &transport).await;
+        let (wait_for_node3, wait_for_three_nodes) = {
+            let chitchat = nodes[0].chitchat();
+            let chitchat_guard = chitchat.lock().await;
+            (
+                chitchat_guard.wait_for_live(nodes[2].chitchat_id()),
+                chitchat_guard.wait_for_live_count(3),
+            )
+        };
+        tokio::time::timeout(Duration::from_secs(10), async {
+            wait_for_node3.await;
+            wait_for_three_nodes.await;
+        })
+        .await
+        .unwrap();
+
+        // Resolves immediately when the condition is already met.
+        let wait_for_node2 = nodes[0]
+            .chitchat()
+            .lock()
+            .await
+            .wait_for_live(nodes[1].chitchat_id());
+        tokio::time::timeout(Duration::from_millis(10), wait_for_node2)
+            .await
+            .unwrap();
+        shutdown_nodes(nodes).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_await_propagation() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let nodes = setup_nodes(20041..=20043, &transport).await;
+        let wait_for_three_nodes = nodes[0].chitchat().lock().await.wait_for_live_count(3);
+        tokio::time::timeout(Duration::from_secs(10), wait_for_three_nodes)
+            .await
+            .unwrap();
+
+        let (await_propagation, await_future_version) = {
+            let chitchat = nodes[0].chitchat();
+            let mut chitchat_guard = chitchat.lock().await;
+            chitchat_guard.self_node_state().set("config", "v2");
+            let version = chitchat_guard.self_node_state().max_version();
+            (
+                chitchat_guard.await_propagation(version, Duration::from_secs(10)),
+                chitchat_guard.await_propagation(version + 1, Duration::from_millis(500)),
+            )
+        };
+        await_propagation.await.unwrap();
+        for node in &nodes[1..] {
+            let chitchat = node.chitchat();
+            let chitchat_guard = chitchat.lock().await;
+            let node0_state = chitchat_guard.node_state(nodes[0].chitchat_id()).unwrap();
+            assert_eq!(node0_state.get("config"), Some("v2"));
+        }
+        // No peer can report a version the self node has not reached yet.
+        assert!(await_future_version.await.is_err());
+        shutdown_nodes(nodes).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_await_propagation_with_compact_self_versions() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let chitchat_ids: Vec<ChitchatId> =
+            (20071..=20072).map(ChitchatId::for_local_test).collect();
+        let mut nodes = Vec::new();
+        for chitchat_id in &chitchat_ids {
+            let config = ChitchatConfig {
+                chitchat_id: chitchat_id.clone(),
+                listen_addr: chitchat_id.gossip_advertise_addr,
+                seed_nodes: vec![chitchat_ids[0].gossip_advertise_addr.to_string()],
+                compact_self_versions: true,
+                ..ChitchatConfig::for_test(chitchat_id.gossip_advertise_addr.port())
+            };
+            nodes.push(start_node_with_config(&transport, config).await);
+        }
+        let wait_for_two_nodes = nodes[0].chitchat().lock().await.wait_for_live_count(2);
+        tokio::time::timeout(Duration::from_secs(10), wait_for_two_nodes)
+            .await
+            .unwrap();
+
+        let await_propagation = {
+            let chitchat = nodes[0].chitchat();
+            let mut chitchat_guard = chitchat.lock().await;
+            for i in 0..10 {
+                chitchat_guard.self_set("config", i);
+            }
+            let version = chitchat_guard.self_node_state().max_version();
+            let await_propagation =
+                chitchat_guard.await_propagation(version, Duration::from_secs(10));
+            // The next gossip round would otherwise compact the overwritten versions, and the
+            // peer would never report `version`.
+            chitchat_guard.tick_heartbeat();
+            assert_eq!(chitchat_guard.self_node_state().max_version(), version);
+            await_propagation
+        };
+        await_propagation.await.unwrap();
+        let chitchat = nodes[1].chitchat();
+        let chitchat_guard = chitchat.lock().await;
+        let node0_state = chitchat_guard.node_state(nodes[0].chitchat_id()).unwrap();
+        assert_eq!(node0_state.get("config"), Some("9"));
+        drop(chitchat_guard);
+        shutdown_nodes(nodes).await.unwrap();
+    }
+
+    /// Runs a handshake initiated by `node2`, whose messages reach `node1` from `node2_addr`
+    /// rather than from its gossip advertise address, e.g. because it sits behind a NAT.
+    fn run_chitchat_handshake_from_addr(
+        node1: &mut Chitchat,
+        node2: &mut Chitchat,
+        node2_addr: SocketAddr,
+    ) {
+        let node1_addr = node1.self_chitchat_id().gossip_advertise_addr;
+        let syn = node2.seal_message(node2.create_syn_message());
+        let syn_ack = node1.process_message(node2_addr, syn).unwrap();
+        let ack = node2
+            .process_message(node1_addr, node1.seal_message(syn_ack))
+            .unwrap();
+        assert!(node1
+            .process_message(node2_addr, node2.seal_message(ack))
+            .is_none());
+    }
+
+    /// Returns two nodes that see each other as live, the messages of the second one reaching
+    /// the first one from `node2_addr`.
+    async fn setup_nodes_behind_nat(
+        configs: [ChitchatConfig; 2],
+        node2_addr: SocketAddr,
+    ) -> (Chitchat, Chitchat) {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let [config1, config2] = configs.map(|config| ChitchatConfig {
+            failure_detector_config: FailureDetectorConfig {
+                initial_interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..config
+        });
+        let mut node1 =
+            Chitchat::with_chitchat_id_and_seeds(config1, empty_seeds.clone(), Vec::new());
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(config2, empty_seeds, Vec::new());
+        for _ in 0..3 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            run_chitchat_handshake_from_addr(&mut node1, &mut node2, node2_addr);
+        }
+        node1.update_nodes_liveness();
+        assert_eq!(node1.live_nodes().count(), 2);
+        (node1, node2)
+    }
+
+    #[tokio::test]
+    async fn test_await_propagation_behind_nat() {
+        tokio::time::pause();
+        let node2_addr: SocketAddr = ([192, 0, 2, 1], 40_002).into();
+        let (mut node1, mut node2) = setup_nodes_behind_nat(
+            [
+                ChitchatConfig::for_test(10_001),
+                ChitchatConfig::for_test(10_002),
+            ],
+            node2_addr,
+        )
+        .await;
+        assert_ne!(node2.self_chitchat_id().gossip_advertise_addr, node2_addr);
+
+        node1.self_node_state().set("config", "v2");
+        let version = node1.self_node_state().max_version();
+        let await_propagation = node1.await_propagation(version, Duration::from_secs(10));
+        // The first handshake delivers the update, the second one acknowledges it.
+        for _ in 0..2 {
+            run_chitchat_handshake_from_addr(&mut node1, &mut node2, node2_addr);
+        }
+        await_propagation.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_datagram_transport() {
+        let socket_dir = std::env::temp_dir().join(format!("chitchat-{}", ulid::Ulid::new()));
+        std::fs::create_dir(&socket_dir).unwrap();
+        let transport = crate::transport::UnixDatagramTransport::new(&socket_dir);
+        let nodes = setup_nodes(20061..=20062, &transport).await;
+        let wait_for_two_nodes = nodes[1].chitchat().lock().await.wait_for_live_count(2);
+        tokio::time::timeout(Duration::from_secs(10), wait_for_two_nodes)
+            .await
+            .unwrap();
+        shutdown_nodes(nodes).await.unwrap();
+        std::fs::remove_dir_all(&socket_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_self_set_critical() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let nodes = setup_nodes(20051..=20053, &transport).await;
+        let wait_for_three_nodes = nodes[0].chitchat().lock().await.wait_for_live_count(3);
+        tokio::time::timeout(Duration::from_secs(10), wait_for_three_nodes)
+            .await
+            .unwrap();
+        let chitchat = nodes[0].chitchat();
+        {
+            let mut chitchat_guard = chitchat.lock().await;
+            assert!(chitchat_guard.unacknowledged_peers("epoch").is_none());
+
+            chitchat_guard.self_set_critical("epoch", "2", Duration::from_secs(10));
+            let mut unacknowledged_peers = chitchat_guard.unacknowledged_peers("epoch").unwrap();
+            unacknowledged_peers.sort();
+            assert_eq!(
+                unacknowledged_peers,
+                vec![nodes[1].chitchat_id(), nodes[2].chitchat_id()]
+            );
+            assert_eq!(chitchat_guard.update_critical_keys().len(), 2);
+
+            // An expired critical key is no longer tracked.
+            chitchat_guard.self_set_critical("generation", "7", Duration::ZERO);
+            chitchat_guard.update_critical_keys();
+            assert!(chitchat_guard.unacknowledged_peers("generation").is_none());
+        }
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let mut chitchat_guard = chitchat.lock().await;
+                chitchat_guard.update_critical_keys();
+                if chitchat_guard.unacknowledged_peers("epoch").is_none() {
+                    break;
+                }
+                drop(chitchat_guard);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        for node in &nodes[1..] {
+            let chitchat = node.chitchat();
+            let chitchat_guard = chitchat.lock().await;
+            let node0_state = chitchat_guard.node_state(nodes[0].chitchat_id()).unwrap();
+            assert_eq!(node0_state.get("epoch"), Some("2"));
+        }
+        shutdown_nodes(nodes).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_self_set_critical_with_manual_clock() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let manual_clock = Arc::new(ManualClock::new());
+        let config = ChitchatConfig {
+            clock: Some(manual_clock.clone()),
+            failure_detector_config: FailureDetectorConfig {
+                initial_interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..ChitchatConfig::for_test(10_001)
+        };
+        let mut node = Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new());
+        let peer_chitchat_id = ChitchatId::for_local_test(10_002);
+        for heartbeat in 1..=3 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            node.report_heartbeat(&peer_chitchat_id, Heartbeat(heartbeat));
+        }
+        node.update_nodes_liveness();
+
+        node.self_set_critical("epoch", "2", Duration::from_secs(10));
+        // The timeout is measured with the configured clock, not the tokio one.
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert_eq!(
+            node.update_critical_keys(),
+            HashSet::from([peer_chitchat_id.gossip_advertise_addr])
+        );
+        manual_clock.advance(Duration::from_secs(10));
+        assert!(node.update_critical_keys().is_empty());
+        assert!(node.unacknowledged_peers("epoch").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_self_set_critical_behind_nat() {
+        tokio::time::pause();
+        let node2_addr: SocketAddr = ([192, 0, 2, 1], 40_002).into();
+        let (mut node1, mut node2) = setup_nodes_behind_nat(
+            [
+                ChitchatConfig::for_test(10_001),
+                ChitchatConfig::for_test(10_002),
+            ],
+            node2_addr,
+        )
+        .await;
+        let node2_chitchat_id = node2.self_chitchat_id().clone();
+
+        node1.self_set_critical("epoch", "2", Duration::from_secs(10));
+        assert_eq!(
+            node1.unacknowledged_peers("epoch").unwrap(),
+            [&node2_chitchat_id]
+        );
+        // The first handshake delivers the key-value, the second one acknowledges it.
+        for _ in 0..2 {
+            run_chitchat_handshake_from_addr(&mut node1, &mut node2, node2_addr);
+        }
+        assert!(node1.unacknowledged_peers("epoch").unwrap().is_empty());
+        assert!(node1.update_critical_keys().is_empty());
+        assert!(node1.unacknowledged_peers("epoch").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_set_ready() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let chitchat_ids: Vec<ChitchatId> = (1..=2).map(ChitchatId::for_local_test).collect();
+        let mut nodes = Vec::new();
+        for chitchat_id in &chitchat_ids {
+            let config = ChitchatConfig {
+                chitchat_id: chitchat_id.clone(),
+                listen_addr: chitchat_id.gossip_advertise_addr,
+                seed_nodes: vec![chitchat_ids[0].gossip_advertise_addr.to_string()],
+                extra_liveness_predicate: Some(Box::new(NodeState::is_ready)),
+                ..ChitchatConfig::for_test(chitchat_id.gossip_advertise_addr.port())
+            };
+            nodes.push(start_node_with_config(&transport, config).await);
+        }
+        nodes[1].chitchat().lock().await.set_ready(true);
+
+        // The readiness of the first node depends on an asynchronous probe.
+        let chitchat = nodes[0].chitchat();
+        let (probe_tx, probe_rx) = oneshot::channel::<bool>();
+        tokio::spawn(async move {
+            let ready = probe_rx.await.unwrap();
+            chitchat.lock().await.set_ready(ready);
+        });
+
+        let mut live_nodes_stream = nodes[1].chitchat().lock().await.live_nodes_watch_stream();
+        let live_nodes = loop {
+            let live_nodes = live_nodes_stream.next().await.unwrap();
+            if live_nodes.contains_key(&chitchat_ids[1]) {
+                break live_nodes;
+            }
+        };
+        assert!(!live_nodes.contains_key(&chitchat_ids[0]));
+
+        probe_tx.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let live_nodes = live_nodes_stream.next().await.unwrap();
+                if live_nodes.len() == 2 {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            nodes[1]
+                .chitchat()
+                .lock()
+                .await
+                .node_state(&chitchat_ids[0])
+                .unwrap()
+                .get(READY_KEY),
+            Some("true")
+        );
+        nodes[0].chitchat().lock().await.set_ready(false);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let live_nodes = live_nodes_stream.next().await.unwrap();
+                if live_nodes.len() == 1 {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        shutdown_nodes(nodes).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_live_node_channel_with_extra_predicate() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let chitchat_ids: Vec<ChitchatId> = (1..=3).map(ChitchatId::for_local_test).collect();
+        let make_config = |chitchat_id: &ChitchatId| ChitchatConfig {
+            chitchat_id: chitchat_id.clone(),
+            cluster_id: "default-cluster".to_string(),
+            gossip_interval: Duration::from_millis(100),
+            listen_addr: chitchat_id.gossip_advertise_addr,
+            seed_nodes: vec![chitchat_ids[0].gossip_advertise_addr.to_string()],
+            failure_detector_config: FailureDetectorConfig {
+                dead_node_grace_period: Some(DEAD_NODE_GRACE_PERIOD),
+                phi_threshold: 5.0,
+                initial_interval: Duration::from_millis(100),
+                ..Default::default()
             },
+            failure_detector_startup_grace_period: None,
             marked_for_deletion_grace_period: Duration::from_secs(3_600),
             catchup_callback: None,
+            self_superseded_callback: None,
+            delta_applied_callback: None,
             extra_liveness_predicate: Some(Box::new(|node_state| {
                 node_state.get("READY") == Some("true")
             })),
+            duplicate_node_id_policy: DuplicateNodeIdPolicy::Ignore,
+            bad_cluster_quarantine_config: None,
+            respond_to_bad_cluster: true,
+            dead_node_gossip_probability_multiplier: 1.0,
+            min_seed_nodes_per_round: 1,
+            static_peers: Vec::new(),
+            max_gossip_bytes_per_sec: None,
+            observer: false,
+            message_auth_secret: None,
+            key_validator: None,
+            value_transform: None,
+            debug_buffers_enabled: false,
+            key_value_history_depth: 0,
+            recent_deltas_capacity: 0,
+            digest_only_gossip_config: None,
+            clock: None,
+            compact_self_versions: false,
+            rpc_advertise_addr: None,
+            message_timestamp_config: None,
         };
         let mut nodes = Vec::new();
         for chitchat_id in &chitchat_ids {
@@ -915,13 +3167,28 @@ mod tests {
                 .get("test_key")
                 .unwrap();
             assert_eq!(test_val, "test_val");
-
-            // Let's check that node1 is seen as dead.
-            let dead_nodes: HashSet<&ChitchatId> = new_node_chitchat_guard.dead_nodes().collect();
-            assert_eq!(dead_nodes.len(), 1);
-            assert!(dead_nodes.contains(&node1_id));
         }
 
+        // Let's check that node1 is seen as dead, once the startup grace period of the failure
+        // detector has elapsed.
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                {
+                    let new_node_chitchat_guard = new_node_chitchat.lock().await;
+                    let dead_nodes: HashSet<&ChitchatId> =
+                        new_node_chitchat_guard.dead_nodes().collect();
+                    if !dead_nodes.is_empty() {
+                        assert_eq!(dead_nodes.len(), 1);
+                        assert!(dead_nodes.contains(&node1_id));
+                        break;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap();
+
         nodes.push(new_node_chitchat_handle);
         shutdown_nodes(nodes).await?;
         Ok(())
@@ -1013,62 +3280,1028 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_node_statuses() {
+        tokio::time::pause();
+        let node_config = ChitchatConfig::for_test(10_001);
+        let dead_node_grace_period = node_config
+            .failure_detector_config
+            .dead_node_grace_period
+            .unwrap();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(node_config, empty_seeds, Vec::new());
+        let live_chitchat_id = ChitchatId::for_local_test(10_002);
+        let dead_chitchat_id = ChitchatId::for_local_test(10_003);
+
+        node.failure_detector.report_heartbeat(&dead_chitchat_id);
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            node.failure_detector.report_heartbeat(&live_chitchat_id);
+        }
+        node.failure_detector
+            .update_node_liveness(&live_chitchat_id);
+        node.failure_detector
+            .update_node_liveness(&dead_chitchat_id);
+
+        let node_statuses: HashMap<ChitchatId, NodeStatus> = node
+            .node_statuses()
+            .map(|(chitchat_id, node_status)| (chitchat_id.clone(), node_status))
+            .collect();
+        assert_eq!(node_statuses.len(), 3);
+        assert_eq!(
+            node_statuses[&ChitchatId::for_local_test(10_001)],
+            NodeStatus::Live
+        );
+        assert_eq!(node_statuses[&live_chitchat_id], NodeStatus::Live);
+        assert_eq!(node_statuses[&dead_chitchat_id], NodeStatus::Dead);
+
+        tokio::time::advance(dead_node_grace_period / 2 + Duration::from_secs(1)).await;
+        let dead_node_status = node
+            .node_statuses()
+            .find(|(chitchat_id, _)| **chitchat_id == dead_chitchat_id)
+            .map(|(_, node_status)| node_status);
+        assert_eq!(dead_node_status, Some(NodeStatus::ScheduledForDeletion));
+    }
+
     #[test]
-    fn test_chitchat_listener() {
-        let node_config1 = ChitchatConfig::for_test(10_001);
+    fn test_digest() {
         let empty_seeds = watch::channel(Default::default()).1;
         let mut node1 = Chitchat::with_chitchat_id_and_seeds(
-            node_config1,
+            ChitchatConfig::for_test(10_001),
             empty_seeds.clone(),
-            vec![("self1:suffix1".to_string(), "hello1".to_string())],
+            vec![("key1".to_string(), "value1".to_string())],
         );
-        let counter_self_key: Arc<AtomicUsize> = Default::default();
-        let counter_other_key: Arc<AtomicUsize> = Default::default();
-
-        let counter_self_key_clone = counter_self_key.clone();
-        node1
-            .subscribe_event("self1:", move |evt| {
-                assert_eq!(evt.key, "suffix1");
-                assert_eq!(evt.value, "updated");
-                counter_self_key_clone.fetch_add(1, Ordering::SeqCst);
-            })
-            .forever();
-        let counter_other_key_clone = counter_other_key.clone();
-        node1
-            .subscribe_event("other:", move |evt| {
-                assert_eq!(evt.key, "suffix");
-                assert_eq!(evt.value, "hello");
-                counter_other_key_clone.fetch_add(1, Ordering::SeqCst);
-            })
-            .forever();
-
-        let counter_self_key_clone = counter_self_key.clone();
-        node1
-            .subscribe_event("self2:", move |evt| {
-                assert_eq!(evt.key, "suffix2");
-                assert_eq!(evt.value, "hello2");
-                counter_self_key_clone.fetch_add(1, Ordering::SeqCst);
-            })
-            .forever();
-
-        let node_config2 = ChitchatConfig::for_test(10_002);
         let mut node2 = Chitchat::with_chitchat_id_and_seeds(
-            node_config2,
+            ChitchatConfig::for_test(10_002),
             empty_seeds,
-            vec![("other:suffix".to_string(), "hello".to_string())],
+            Vec::new(),
         );
-
-        assert_eq!(counter_self_key.load(Ordering::SeqCst), 0);
-        assert_eq!(counter_other_key.load(Ordering::SeqCst), 0);
-
         run_chitchat_handshake(&mut node1, &mut node2);
-        assert_nodes_sync(&[&node1, &node2]);
 
-        assert_eq!(counter_self_key.load(Ordering::SeqCst), 0);
-        assert_eq!(counter_other_key.load(Ordering::SeqCst), 1);
+        let digest = node2.digest();
+        assert_eq!(digest.num_nodes(), 2);
+        let node1_digest = digest.node_digest(node1.self_chitchat_id()).unwrap();
+        assert_eq!(node1_digest.max_version(), 1);
+        assert_eq!(node1_digest.last_gc_version(), 0);
+        assert_eq!(
+            node1_digest.heartbeat(),
+            node2
+                .node_state(node1.self_chitchat_id())
+                .unwrap()
+                .heartbeat()
+        );
+        let chitchat_ids: Vec<&ChitchatId> = digest
+            .node_digests()
+            .map(|(chitchat_id, _)| chitchat_id)
+            .collect();
+        assert_eq!(
+            chitchat_ids,
+            [node1.self_chitchat_id(), node2.self_chitchat_id()]
+        );
+        // Reading the digest has no side effect.
+        assert_eq!(node2.digest(), digest);
+    }
 
-        // useless handshake
-        run_chitchat_handshake(&mut node1, &mut node2);
+    #[test]
+    fn test_key_validator() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node_config1 = ChitchatConfig::for_test(10_001);
+        node_config1.key_validator = Some(Arc::new(|key: &str| !key.starts_with("invalid")));
+        let mut node1 =
+            Chitchat::with_chitchat_id_and_seeds(node_config1, empty_seeds.clone(), Vec::new());
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        // Keys set locally.
+        node1.self_node_state().set("invalid_key", "value");
+        node1.self_node_state().set("key", "value");
+        assert_eq!(node1.self_node_state().max_version(), 1);
+        assert!(node1.self_node_state().get("invalid_key").is_none());
+        assert_eq!(node1.num_rejected_keys(), 1);
+
+        // Keys received from a peer.
+        node2.self_node_state().set("key1", "value1");
+        node2.self_node_state().set("invalid_key", "value");
+        node2.self_node_state().set("key2", "value2");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node2_state = node1.node_state(node2.self_chitchat_id()).unwrap();
+        assert_eq!(node2_state.max_version(), 3);
+        assert_eq!(node2_state.get("key1"), Some("value1"));
+        assert_eq!(node2_state.get("key2"), Some("value2"));
+        assert!(node2_state.get("invalid_key").is_none());
+        assert_eq!(node1.num_rejected_keys(), 2);
+
+        // The rejected key is not requested again.
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_eq!(node1.num_rejected_keys(), 2);
+    }
+
+    #[test]
+    fn test_value_transform() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node_config1 = ChitchatConfig::for_test(10_001);
+        node_config1.value_transform = Some(Arc::new(|key: &str, value: &str| {
+            if key.starts_with("secret") {
+                return None;
+            }
+            Some(value.replace("hunter2", "***"))
+        }));
+        let mut node1 =
+            Chitchat::with_chitchat_id_and_seeds(node_config1, empty_seeds.clone(), Vec::new());
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        // Values set locally.
+        node1.self_node_state().set("secret_key", "value");
+        node1.self_node_state().set("url", "user:hunter2@host");
+        node1.self_node_state().set("key", "value");
+        assert_eq!(node1.self_node_state().max_version(), 2);
+        assert!(node1.self_node_state().get("secret_key").is_none());
+        assert_eq!(node1.self_get("url"), Some("user:***@host"));
+        assert_eq!(node1.self_get("key"), Some("value"));
+
+        // Setting the same value again leaves the version untouched, as the transformed value
+        // is compared with the stored one.
+        node1.self_node_state().set("url", "user:hunter2@host");
+        node1
+            .self_node_state()
+            .set_batch([("url", "user:hunter2@host"), ("key", "value")]);
+        assert_eq!(node1.self_node_state().max_version(), 2);
+
+        // Deletions are not affected.
+        node1.self_node_state().delete("url");
+        assert!(node1
+            .self_node_state()
+            .get_versioned("url")
+            .unwrap()
+            .is_deleted());
+
+        // Values received from a peer.
+        node2.self_node_state().set("key1", "hunter2");
+        node2.self_node_state().set("secret_key", "value");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node2_state = node1.node_state(node2.self_chitchat_id()).unwrap();
+        assert_eq!(node2_state.max_version(), 2);
+        assert_eq!(node2_state.get("key1"), Some("***"));
+        assert!(node2_state.get("secret_key").is_none());
+    }
+
+    #[test]
+    fn test_tick_heartbeat() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        let heartbeat = node1.self_node_state().heartbeat();
+        node1.digest();
+        let syn_message = node1.create_syn_message();
+        assert_eq!(node1.self_node_state().heartbeat(), heartbeat);
+
+        node1.tick_heartbeat();
+        assert_eq!(
+            node1.self_node_state().heartbeat(),
+            Heartbeat(heartbeat.0 + 1)
+        );
+
+        let heartbeat = node2.self_node_state().heartbeat();
+        node2
+            .process_message(node1.self_chitchat_id().gossip_advertise_addr, syn_message)
+            .unwrap();
+        assert_eq!(
+            node2.self_node_state().heartbeat(),
+            Heartbeat(heartbeat.0 + 1)
+        );
+    }
+
+    #[test]
+    fn test_compact_self_versions() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig {
+                compact_self_versions: true,
+                ..ChitchatConfig::for_test(10_001)
+            },
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        let node1_id = node1.self_chitchat_id().clone();
+        for i in 0..10 {
+            node1.self_set("key1", i);
+        }
+        node1.self_node_state().delete("key1");
+        node1.self_set("key1", "final");
+        node1.self_set("key2", "value2");
+        assert_eq!(node1.self_node_state().max_version(), 13);
+
+        // As in a gossip round, the heartbeat is ticked before sending the syn message.
+        node1.tick_heartbeat();
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_eq!(node1.self_node_state().max_version(), 2);
+        let node1_state_in_node2 = node2.node_state(&node1_id).unwrap();
+        assert_eq!(node1_state_in_node2.max_version(), 2);
+        assert_eq!(node1_state_in_node2.get("key1"), Some("final"));
+        assert_eq!(node1_state_in_node2.get("key2"), Some("value2"));
+
+        // Versions that were gossiped are left untouched.
+        node1.self_set("key2", "value2-bis");
+        node1.self_set("key2", "value2-ter");
+        node1.tick_heartbeat();
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_eq!(node1.self_node_state().max_version(), 3);
+        let node1_state_in_node2 = node2.node_state(&node1_id).unwrap();
+        assert_eq!(node1_state_in_node2.max_version(), 3);
+        assert_eq!(node1_state_in_node2.get("key1"), Some("final"));
+        assert_eq!(node1_state_in_node2.get("key2"), Some("value2-ter"));
+    }
+
+    #[test]
+    fn test_rpc_advertise_addr() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let rpc_advertise_addr: SocketAddr = "10.0.0.1:7281".parse().unwrap();
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig {
+                rpc_advertise_addr: Some(rpc_advertise_addr),
+                ..ChitchatConfig::for_test(10_001)
+            },
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        assert_eq!(
+            node1.self_node_state().rpc_advertise_addr().unwrap(),
+            rpc_advertise_addr
+        );
+        let node2_gossip_advertise_addr = node2.self_chitchat_id().gossip_advertise_addr;
+        assert_eq!(
+            node2.self_node_state().rpc_advertise_addr().unwrap(),
+            node2_gossip_advertise_addr
+        );
+
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node1_id = node1.self_chitchat_id().clone();
+        let node2_id = node2.self_chitchat_id().clone();
+        assert_eq!(
+            node2
+                .node_state(&node1_id)
+                .unwrap()
+                .rpc_advertise_addr()
+                .unwrap(),
+            rpc_advertise_addr
+        );
+        assert_eq!(
+            node1
+                .node_state(&node2_id)
+                .unwrap()
+                .rpc_advertise_addr()
+                .unwrap(),
+            node2_gossip_advertise_addr
+        );
+
+        node2.self_set(RPC_ADVERTISE_ADDR_KEY, "not-an-address");
+        assert!(node2.self_node_state().rpc_advertise_addr().is_err());
+    }
+
+    #[test]
+    fn test_delta_coalescing() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut peers: Vec<Chitchat> = (10_002..10_007)
+            .map(|port| {
+                Chitchat::with_chitchat_id_and_seeds(
+                    ChitchatConfig::for_test(port),
+                    empty_seeds.clone(),
+                    Vec::new(),
+                )
+            })
+            .collect();
+        for _ in 0..2 {
+            for peer in &mut peers {
+                run_chitchat_handshake(&mut node1, peer);
+            }
+        }
+        let mut nodes: Vec<&Chitchat> = vec![&node1];
+        nodes.extend(&peers);
+        assert_nodes_sync(&nodes);
+
+        // All the peers are at the same versions: the delta is computed once per round.
+        node1.self_set("key1", "value1");
+        node1.self_set("key2", "value2");
+        node1.record_gossip_round();
+        let num_coalesced_deltas = node1.num_coalesced_deltas();
+        let mut ack_deltas = Vec::new();
+        for peer in &mut peers {
+            let syn_ack_message = peer
+                .process_message(([127, 0, 0, 1], 10_001).into(), node1.create_syn_message())
+                .unwrap();
+            let peer_addr = peer.self_chitchat_id().gossip_advertise_addr;
+            let Some(ChitchatMessage::Ack { delta }) =
+                node1.process_message(peer_addr, syn_ack_message)
+            else {
+                panic!("expected an ACK message");
+            };
+            ack_deltas.push(delta);
+        }
+        assert_eq!(node1.num_coalesced_deltas(), num_coalesced_deltas + 4);
+        assert_eq!(ack_deltas[0].stats().num_key_values, 2);
+        assert!(ack_deltas.iter().all(|delta| delta == &ack_deltas[0]));
+
+        // Cached deltas are dropped as soon as the cluster state changes.
+        node1.self_set("key3", "value3");
+        let peer = &mut peers[0];
+        let syn_ack_message = peer
+            .process_message(([127, 0, 0, 1], 10_001).into(), node1.create_syn_message())
+            .unwrap();
+        let peer_addr = peer.self_chitchat_id().gossip_advertise_addr;
+        let Some(ChitchatMessage::Ack { delta }) =
+            node1.process_message(peer_addr, syn_ack_message)
+        else {
+            panic!("expected an ACK message");
+        };
+        assert_eq!(delta.stats().num_key_values, 3);
+        assert_eq!(node1.num_coalesced_deltas(), num_coalesced_deltas + 4);
+    }
+
+    #[test]
+    fn test_received_delta_stats() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            vec![("key1a".to_string(), "1".to_string())],
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            vec![
+                ("key2a".to_string(), "2".to_string()),
+                ("key2b".to_string(), "2".to_string()),
+            ],
+        );
+        assert_eq!(node1.last_received_delta_stats(), None);
+        assert_eq!(node1.received_delta_stats(), DeltaStats::default());
+
+        let node1_addr = node1.self_chitchat_id().gossip_advertise_addr;
+        let node2_addr = node2.self_chitchat_id().gossip_advertise_addr;
+        let syn_message = node1.create_syn_message();
+        let syn_ack_message = node2.process_message(node1_addr, syn_message).unwrap();
+        let ChitchatMessage::SynAck { delta, .. } = &syn_ack_message else {
+            panic!("expected a SynAck message");
+        };
+        let syn_ack_delta_len = delta.serialized_len();
+        let ack_message = node1.process_message(node2_addr, syn_ack_message).unwrap();
+        let ChitchatMessage::Ack { delta } = &ack_message else {
+            panic!("expected an Ack message");
+        };
+        let ack_delta_len = delta.serialized_len();
+        node2.process_message(node1_addr, ack_message);
+
+        let expected_node1_stats = DeltaStats {
+            num_node_deltas: 1,
+            num_key_values: 2,
+            num_bytes: syn_ack_delta_len,
+        };
+        assert_eq!(
+            node1.last_received_delta_stats(),
+            Some(expected_node1_stats)
+        );
+        assert_eq!(node1.received_delta_stats(), expected_node1_stats);
+        let expected_node2_stats = DeltaStats {
+            num_node_deltas: 1,
+            num_key_values: 1,
+            num_bytes: ack_delta_len,
+        };
+        assert_eq!(
+            node2.last_received_delta_stats(),
+            Some(expected_node2_stats)
+        );
+
+        // An up-to-date peer sends empty deltas.
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_eq!(node1.last_received_delta_stats().unwrap().num_key_values, 0);
+        assert_eq!(node1.received_delta_stats().num_key_values, 2);
+        assert!(node1.received_delta_stats().num_bytes > syn_ack_delta_len);
+    }
+
+    #[test]
+    fn test_cluster_state_reader() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        node1.self_set("key", "1");
+        let cluster_state_reader = node1.cluster_state_reader();
+        let view = cluster_state_reader.load();
+        assert_eq!(view.get(node1.self_chitchat_id(), "key"), Some("1"));
+        assert_eq!(view.nodes().count(), 1);
+
+        node2.self_set("key", "2");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let view = cluster_state_reader.load();
+        assert_eq!(view.get(node2.self_chitchat_id(), "key"), Some("2"));
+        assert_eq!(view.nodes().count(), 2);
+    }
+
+    #[test]
+    fn test_snapshot_arc() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        let snapshot = node1.snapshot_arc();
+        assert_eq!(snapshot.node_states.len(), 1);
+        assert!(Arc::ptr_eq(&snapshot, &node1.snapshot_arc()));
+
+        node2.self_set("key", "2");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let new_snapshot = node1.snapshot_arc();
+        assert_eq!(new_snapshot.node_states.len(), 2);
+        assert_eq!(snapshot.node_states.len(), 1);
+    }
+
+    #[test]
+    fn test_heartbeat_does_not_generate_deltas() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        node2.self_set("key", "value");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node2_max_version = node2.self_node_state().max_version();
+
+        // The cluster is idle: only heartbeats change.
+        for _ in 0..10 {
+            node2.tick_heartbeat();
+        }
+        assert_eq!(node2.self_node_state().max_version(), node2_max_version);
+
+        let node1_addr = node1.self_chitchat_id().gossip_advertise_addr;
+        let syn_message = node1.create_syn_message();
+        let ChitchatMessage::SynAck { digest, delta } =
+            node2.process_message(node1_addr, syn_message).unwrap()
+        else {
+            panic!("expected a SynAck message");
+        };
+        assert_eq!(delta.num_tuples(), 0);
+        assert_eq!(delta.nodes().count(), 0);
+        let node2_digest = &digest.node_digests[node2.self_chitchat_id()];
+        assert!(
+            node2_digest.heartbeat
+                > node1
+                    .node_state(node2.self_chitchat_id())
+                    .unwrap()
+                    .heartbeat()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seeds() {
+        let seed_addr1: SocketAddr = ([127, 0, 0, 1], 20_001).into();
+        let seed_addr2: SocketAddr = ([127, 0, 0, 1], 20_002).into();
+        let (seed_addrs_tx, seed_addrs_rx) = watch::channel(HashSet::from([seed_addr1]));
+        let node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            seed_addrs_rx,
+            Vec::new(),
+        );
+        assert_eq!(node.seeds().collect::<Vec<_>>(), vec![seed_addr1]);
+
+        let mut seeds_watcher = node.seeds_watcher();
+        seed_addrs_tx
+            .send(HashSet::from([seed_addr1, seed_addr2]))
+            .unwrap();
+        seeds_watcher.changed().await.unwrap();
+        assert_eq!(seeds_watcher.borrow().len(), 2);
+
+        let mut seeds: Vec<SocketAddr> = node.seeds().collect();
+        seeds.sort();
+        assert_eq!(seeds, vec![seed_addr1, seed_addr2]);
+    }
+
+    #[test]
+    fn test_self_get_and_set() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        let other_chitchat_id = ChitchatId::for_local_test(10_002);
+        node.cluster_state
+            .node_state_mut(&other_chitchat_id)
+            .set("key", "other-value");
+        assert_eq!(node.self_get("key"), None);
+
+        node.self_set("key", "value");
+        assert_eq!(node.self_get("key"), Some("value"));
+        let max_version = node.self_node_state().max_version();
+
+        // Neither computing a digest nor bumping the heartbeat affects the key-values.
+        node.digest();
+        node.tick_heartbeat();
+        assert_eq!(node.self_get("key"), Some("value"));
+        assert_eq!(node.self_node_state().max_version(), max_version);
+        assert_eq!(
+            node.node_state(&other_chitchat_id).unwrap().get("key"),
+            Some("other-value")
+        );
+    }
+
+    #[test]
+    fn test_drain_mode() {
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            watch::channel(Default::default()).1,
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            watch::channel(Default::default()).1,
+            Vec::new(),
+        );
+        node1.self_set("key_a", "1");
+        node1.self_set("key_b", "2");
+        node1.set_ready(true);
+        let max_version = node1.self_node_state().max_version();
+
+        node1.enter_drain_mode();
+        assert!(node1.is_draining());
+        node1.self_set("key_a", "3");
+        node1.self_set("key_c", "4");
+        node1.self_node_state().set_batch([("key_d", "5")]);
+        node1.self_node_state().set_with_ttl("key_e", "6");
+        node1.self_node_state().delete("key_b");
+        node1.self_node_state().delete_after_ttl("key_b");
+        node1.self_node_state().set_local("local_key", "7");
+        assert_eq!(node1.self_node_state().max_version(), max_version);
+        assert_eq!(
+            node1.self_node_state().key_values().collect::<Vec<_>>(),
+            [("_ready", "true"), ("key_a", "1"), ("key_b", "2")]
+        );
+        assert_eq!(node1.self_node_state().get_local("local_key"), Some("7"));
+
+        // A draining node can still advertise that it is no longer ready.
+        node1.set_ready(false);
+        assert!(!node1.self_node_state().is_ready());
+        assert_eq!(node1.self_node_state().max_version(), max_version + 1);
+
+        // The current state is still gossiped.
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node1_state = node2.node_state(node1.self_chitchat_id()).unwrap();
+        assert_eq!(node1_state.get("key_a"), Some("1"));
+        assert_eq!(node1_state.get("key_b"), Some("2"));
+        assert!(!node1_state.is_ready());
+
+        node1.leave_drain_mode();
+        assert!(!node1.is_draining());
+        node1.self_set("key_a", "3");
+        assert_eq!(node1.self_get("key_a"), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn test_service_endpoints() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        let live_chitchat_id = ChitchatId::for_local_test(10_002);
+        let invalid_chitchat_id = ChitchatId::for_local_test(10_003);
+        let dead_chitchat_id = ChitchatId::for_local_test(10_004);
+
+        node.set_service_endpoint("grpc", "127.0.0.1:20001".parse().unwrap());
+        node.cluster_state
+            .node_state_mut(&live_chitchat_id)
+            .set("service_endpoint:grpc", "127.0.0.1:20002");
+        node.cluster_state
+            .node_state_mut(&live_chitchat_id)
+            .set("service_endpoint:rest", "127.0.0.1:30002");
+        node.cluster_state
+            .node_state_mut(&invalid_chitchat_id)
+            .set("service_endpoint:grpc", "not-an-address");
+        node.cluster_state
+            .node_state_mut(&dead_chitchat_id)
+            .set("service_endpoint:grpc", "127.0.0.1:20004");
+
+        node.failure_detector.report_heartbeat(&dead_chitchat_id);
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            node.failure_detector.report_heartbeat(&live_chitchat_id);
+            node.failure_detector.report_heartbeat(&invalid_chitchat_id);
+        }
+        for chitchat_id in [&live_chitchat_id, &invalid_chitchat_id, &dead_chitchat_id] {
+            node.failure_detector.update_node_liveness(chitchat_id);
+        }
+        let grpc_endpoints: HashMap<ChitchatId, SocketAddr> = node
+            .service_endpoints("grpc")
+            .map(|(chitchat_id, addr)| (chitchat_id.clone(), addr))
+            .collect();
+        assert_eq!(grpc_endpoints.len(), 2);
+        assert_eq!(
+            grpc_endpoints[&ChitchatId::for_local_test(10_001)],
+            "127.0.0.1:20001".parse().unwrap()
+        );
+        assert_eq!(
+            grpc_endpoints[&live_chitchat_id],
+            "127.0.0.1:20002".parse().unwrap()
+        );
+        assert_eq!(node.service_endpoints("rest").count(), 1);
+        assert_eq!(node.service_endpoints("unknown").count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_nodes_with_tag() {
+        tokio::time::pause();
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        let live_chitchat_id = ChitchatId::for_local_test(10_002);
+        let other_region_chitchat_id = ChitchatId::for_local_test(10_003);
+        let dead_chitchat_id = ChitchatId::for_local_test(10_004);
+
+        node.self_node_state().set_tag("region", "us-east-1");
+        node.cluster_state
+            .node_state_mut(&live_chitchat_id)
+            .set_tag("region", "us-east-1");
+        node.cluster_state
+            .node_state_mut(&other_region_chitchat_id)
+            .set_tag("region", "eu-west-1");
+        node.cluster_state
+            .node_state_mut(&dead_chitchat_id)
+            .set_tag("region", "us-east-1");
+
+        node.failure_detector.report_heartbeat(&dead_chitchat_id);
+        for _ in 0..10 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            node.failure_detector.report_heartbeat(&live_chitchat_id);
+            node.failure_detector
+                .report_heartbeat(&other_region_chitchat_id);
+        }
+        for chitchat_id in [
+            &live_chitchat_id,
+            &other_region_chitchat_id,
+            &dead_chitchat_id,
+        ] {
+            node.failure_detector.update_node_liveness(chitchat_id);
+        }
+        let us_east_nodes: HashSet<&ChitchatId> =
+            node.nodes_with_tag("region", "us-east-1").collect();
+        assert_eq!(
+            us_east_nodes,
+            HashSet::from([&ChitchatId::for_local_test(10_001), &live_chitchat_id])
+        );
+        assert_eq!(
+            node.nodes_with_tag("region", "eu-west-1")
+                .collect::<Vec<_>>(),
+            [&other_region_chitchat_id]
+        );
+        assert_eq!(node.nodes_with_tag("role", "indexer").count(), 0);
+    }
+
+    #[test]
+    fn test_digest_only_gossip() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let digest_only_gossip_config = DigestOnlyGossipConfig {
+            divergence_threshold: 3,
+            full_reconciliation_period: 2,
+        };
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig {
+                digest_only_gossip_config: Some(digest_only_gossip_config.clone()),
+                ..ChitchatConfig::for_test(10_001)
+            },
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig {
+                digest_only_gossip_config: Some(digest_only_gossip_config),
+                ..ChitchatConfig::for_test(10_002)
+            },
+            empty_seeds,
+            Vec::new(),
+        );
+        let chitchat_id1 = node1.self_chitchat_id().clone();
+
+        // Node 2 lags 2 versions behind: below the divergence threshold.
+        node1.self_node_state().set("key1", "value1");
+        node1.self_node_state().set("key2", "value2");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node2_view_of_node1 = node2.node_state(&chitchat_id1).unwrap();
+        assert!(node2_view_of_node1.get("key1").is_none());
+        // Digests are still exchanged.
+        assert!(node2_view_of_node1.heartbeat() > Heartbeat(0));
+
+        // The full reconciliation is due.
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_eq!(
+            node2.node_state(&chitchat_id1).unwrap().get("key2"),
+            Some("value2")
+        );
+
+        // Node 2 lags 1 version behind.
+        node1.self_node_state().set("key3", "value3");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert!(node2
+            .node_state(&chitchat_id1)
+            .unwrap()
+            .get("key3")
+            .is_none());
+
+        // Node 2 lags 3 versions behind: the divergence threshold is reached.
+        node1.self_node_state().set("key4", "value4");
+        node1.self_node_state().set("key5", "value5");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_nodes_sync(&[&node1, &node2]);
+    }
+
+    #[test]
+    fn test_digest_only_gossip_counts_responses_per_peer() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let digest_only_gossip_config = DigestOnlyGossipConfig {
+            divergence_threshold: 10,
+            full_reconciliation_period: 2,
+        };
+        let mut nodes: Vec<Chitchat> = (10_001..=10_003)
+            .map(|port| {
+                Chitchat::with_chitchat_id_and_seeds(
+                    ChitchatConfig {
+                        digest_only_gossip_config: Some(digest_only_gossip_config.clone()),
+                        ..ChitchatConfig::for_test(port)
+                    },
+                    empty_seeds.clone(),
+                    Vec::new(),
+                )
+            })
+            .collect();
+        let [node1, node2, node3] = &mut nodes[..] else {
+            unreachable!();
+        };
+        let chitchat_id1 = node1.self_chitchat_id().clone();
+        node1.self_node_state().set("key1", "value1");
+
+        // Node 1 withholds its delta from each lagging peer on their first exchange, no matter
+        // how many digest-only responses it sent to the other peer.
+        run_chitchat_handshake(node1, node2);
+        run_chitchat_handshake(node1, node3);
+        for node in [&*node2, &*node3] {
+            assert!(node
+                .node_state(&chitchat_id1)
+                .unwrap()
+                .get("key1")
+                .is_none());
+        }
+
+        // The full reconciliation is due for each peer on its second exchange.
+        run_chitchat_handshake(node1, node2);
+        assert_eq!(
+            node2.node_state(&chitchat_id1).unwrap().get("key1"),
+            Some("value1")
+        );
+        assert!(node3
+            .node_state(&chitchat_id1)
+            .unwrap()
+            .get("key1")
+            .is_none());
+        run_chitchat_handshake(node1, node3);
+        assert_eq!(
+            node3.node_state(&chitchat_id1).unwrap().get("key1"),
+            Some("value1")
+        );
+    }
+
+    #[test]
+    fn test_forget_key() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            vec![("key1".to_string(), "value1".to_string())],
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            vec![("key2".to_string(), "value2".to_string())],
+        );
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let chitchat_id1 = node1.self_chitchat_id().clone();
+        let chitchat_id2 = node2.self_chitchat_id().clone();
+
+        assert!(node2.forget_key(&chitchat_id1, "key1"));
+        assert!(node2
+            .node_state(&chitchat_id1)
+            .unwrap()
+            .get("key1")
+            .is_none());
+        assert!(!node2.forget_key(&chitchat_id1, "key1"));
+        assert!(!node2.forget_key(&ChitchatId::for_local_test(10_003), "key1"));
+        // The keys of the self node cannot be forgotten.
+        assert!(!node2.forget_key(&chitchat_id2, "key2"));
+        assert_eq!(node2.self_node_state().get("key2"), Some("value2"));
+
+        // The key is not learned again until its owner updates it.
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert!(node2
+            .node_state(&chitchat_id1)
+            .unwrap()
+            .get("key1")
+            .is_none());
+
+        node1.self_node_state().set("key1", "value1bis");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_eq!(
+            node2.node_state(&chitchat_id1).unwrap().get("key1"),
+            Some("value1bis")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        let mut event_stream = node2.event_stream();
+
+        node2.update_nodes_liveness();
+        node1.self_node_state().set("key1", "value1");
+        node1.self_node_state().set("key2", "value2");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        node1.self_node_state().delete("key1");
+        run_chitchat_handshake(&mut node1, &mut node2);
+        node2.self_node_state().set("key3", "value3");
+
+        let chitchat_id1 = node1.self_chitchat_id().clone();
+        let chitchat_id2 = node2.self_chitchat_id().clone();
+        let expected_events = [
+            ClusterEvent::NodeJoined(chitchat_id2.clone()),
+            ClusterEvent::KeyChanged {
+                node: chitchat_id1.clone(),
+                key: "key1".to_string(),
+                value: "value1".into(),
+            },
+            ClusterEvent::KeyChanged {
+                node: chitchat_id1.clone(),
+                key: "key2".to_string(),
+                value: "value2".into(),
+            },
+            ClusterEvent::KeyDeleted {
+                node: chitchat_id1,
+                key: "key1".to_string(),
+            },
+            ClusterEvent::KeyChanged {
+                node: chitchat_id2,
+                key: "key3".to_string(),
+                value: "value3".into(),
+            },
+        ];
+        for expected_event in expected_events {
+            assert_eq!(event_stream.next().await.unwrap(), expected_event);
+        }
+        drop(node2);
+        assert!(event_stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_event_stream_drops_oldest_events() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        let mut event_stream = node.event_stream();
+        for i in 0..CLUSTER_EVENTS_CAPACITY + 2 {
+            node.self_node_state().set(format!("key{i:04}"), "value");
+        }
+        assert_eq!(
+            event_stream.next().await.unwrap(),
+            ClusterEvent::EventsDropped {
+                num_dropped_events: 2
+            }
+        );
+        assert_eq!(
+            event_stream.next().await.unwrap(),
+            ClusterEvent::KeyChanged {
+                node: node.self_chitchat_id().clone(),
+                key: "key0002".to_string(),
+                value: "value".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_chitchat_listener() {
+        let node_config1 = ChitchatConfig::for_test(10_001);
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            node_config1,
+            empty_seeds.clone(),
+            vec![("self1:suffix1".to_string(), "hello1".to_string())],
+        );
+        let counter_self_key: Arc<AtomicUsize> = Default::default();
+        let counter_other_key: Arc<AtomicUsize> = Default::default();
+
+        let counter_self_key_clone = counter_self_key.clone();
+        node1
+            .subscribe_event("self1:", move |evt| {
+                assert_eq!(evt.key, "suffix1");
+                assert_eq!(evt.value, "updated");
+                counter_self_key_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .forever();
+        let counter_other_key_clone = counter_other_key.clone();
+        node1
+            .subscribe_event("other:", move |evt| {
+                assert_eq!(evt.key, "suffix");
+                assert_eq!(evt.value, "hello");
+                counter_other_key_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .forever();
+
+        let counter_self_key_clone = counter_self_key.clone();
+        node1
+            .subscribe_event("self2:", move |evt| {
+                assert_eq!(evt.key, "suffix2");
+                assert_eq!(evt.value, "hello2");
+                counter_self_key_clone.fetch_add(1, Ordering::SeqCst);
+            })
+            .forever();
+
+        let node_config2 = ChitchatConfig::for_test(10_002);
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            node_config2,
+            empty_seeds,
+            vec![("other:suffix".to_string(), "hello".to_string())],
+        );
+
+        assert_eq!(counter_self_key.load(Ordering::SeqCst), 0);
+        assert_eq!(counter_other_key.load(Ordering::SeqCst), 0);
+
+        run_chitchat_handshake(&mut node1, &mut node2);
+        assert_nodes_sync(&[&node1, &node2]);
+
+        assert_eq!(counter_self_key.load(Ordering::SeqCst), 0);
+        assert_eq!(counter_other_key.load(Ordering::SeqCst), 1);
+
+        // useless handshake
+        run_chitchat_handshake(&mut node1, &mut node2);
 
         assert_eq!(counter_self_key.load(Ordering::SeqCst), 0);
         assert_eq!(counter_other_key.load(Ordering::SeqCst), 1);
@@ -1106,17 +4339,323 @@ mod tests {
         let (_seed_addrs_rx, seed_addrs_tx) = watch::channel(Default::default());
 
         let mut node = Chitchat::with_chitchat_id_and_seeds(config, seed_addrs_tx, Vec::new());
+        let peer_addr: SocketAddr = ([127, 0, 0, 1], 10_002).into();
         let delta = Delta::default();
-        node.process_delta(delta);
+        node.process_delta(peer_addr, delta);
 
         let mut delta = Delta::default();
         let chitchat_id = ChitchatId::for_local_test(10_002);
         delta.add_node(chitchat_id, 1000u64, 0u64);
-        node.process_delta(delta);
+        node.process_delta(peer_addr, delta);
 
         assert_eq!(catchup_callback_counter.load(Ordering::Acquire), 1);
     }
 
+    #[test]
+    fn test_delta_applied_callback() {
+        let applied_deltas = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let applied_deltas_clone = applied_deltas.clone();
+
+        let mut config1 = ChitchatConfig::for_test(10_001);
+        config1.delta_applied_callback = Some(Box::new(move |from_addr, delta| {
+            let key_values: Vec<(ChitchatId, String, String)> = delta
+                .key_values()
+                .map(|(chitchat_id, key_value_mutation)| {
+                    (
+                        chitchat_id.clone(),
+                        key_value_mutation.key().to_string(),
+                        key_value_mutation.value().to_string(),
+                    )
+                })
+                .collect();
+            applied_deltas_clone
+                .lock()
+                .unwrap()
+                .push((from_addr, key_values));
+        }));
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 =
+            Chitchat::with_chitchat_id_and_seeds(config1, empty_seeds.clone(), Vec::new());
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        node2.self_set("key", "value");
+        run_chitchat_handshake(&mut node1, &mut node2);
+
+        let node2_id = node2.self_chitchat_id().clone();
+        assert_eq!(
+            *applied_deltas.lock().unwrap(),
+            [(
+                node2_id.gossip_advertise_addr,
+                vec![(node2_id.clone(), "key".to_string(), "value".to_string())]
+            )]
+        );
+        assert_eq!(
+            node1.node_state(&node2_id).unwrap().get("key"),
+            Some("value")
+        );
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let key_validator: crate::configuration::KeyValidator =
+            Arc::new(|key: &str| !key.starts_with("secret"));
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig {
+                key_validator: Some(key_validator),
+                ..ChitchatConfig::for_test(10_002)
+            },
+            empty_seeds,
+            Vec::new(),
+        );
+        let node1_chitchat_id = node1.self_chitchat_id().clone();
+        let node2_chitchat_id = node2.self_chitchat_id().clone();
+        node1.self_set("key", "value");
+        node1.self_set("secret_key", "value");
+        // A stale view of node 2, which node 2 must not apply.
+        node1
+            .cluster_state
+            .node_state_mut(&node2_chitchat_id)
+            .set("stale_key", "value");
+        let key_changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let key_changes_clone = key_changes.clone();
+        let _listener_handle = node2.subscribe_event("", move |event| {
+            key_changes_clone
+                .lock()
+                .unwrap()
+                .push(event.key.to_string());
+        });
+
+        let delta = node1.debug_delta_for(&node2.digest(), MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        node2.apply_delta(delta);
+        let node1_state = node2.node_state(&node1_chitchat_id).unwrap();
+        assert_eq!(node1_state.get("key"), Some("value"));
+        assert!(node1_state.get("secret_key").is_none());
+        assert!(node2.self_node_state().get("stale_key").is_none());
+        assert_eq!(*key_changes.lock().unwrap(), ["key"]);
+        // The delta does not come from a peer.
+        assert_eq!(node2.received_delta_stats(), DeltaStats::default());
+
+        // Applying the same delta again is a no-op.
+        let delta = node1.debug_delta_for(&Digest::default(), MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        node2.apply_delta(delta);
+        assert_eq!(key_changes.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_observer() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut observer = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig {
+                observer: true,
+                ..ChitchatConfig::for_test(10_001)
+            },
+            empty_seeds.clone(),
+            vec![("key".to_string(), "value".to_string())],
+        );
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        assert!(observer.is_observer());
+        assert!(!node.is_observer());
+        let observer_chitchat_id = observer.self_chitchat_id().clone();
+        let node_chitchat_id = node.self_chitchat_id().clone();
+        node.self_set("key", "value");
+
+        // The observer learns about the node, whoever initiates the handshake, but the node never
+        // learns about the observer.
+        run_chitchat_handshake(&mut observer, &mut node);
+        run_chitchat_handshake(&mut node, &mut observer);
+        assert_eq!(
+            observer.node_state(&node_chitchat_id).unwrap().get("key"),
+            Some("value")
+        );
+        assert!(node.node_state(&observer_chitchat_id).is_none());
+        assert!(!observer
+            .digest()
+            .node_digests
+            .contains_key(&observer_chitchat_id));
+        let delta = observer.debug_delta_for(&Digest::default(), MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        assert!(delta.get(&observer_chitchat_id).is_none());
+        // The observer keeps its own state locally.
+        assert_eq!(observer.self_node_state().get("key"), Some("value"));
+    }
+
+    #[test]
+    fn test_debug_delta_for() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            empty_seeds,
+            Vec::new(),
+        );
+        run_chitchat_handshake(&mut node1, &mut node2);
+        node2.self_set("key", "value");
+        node2.self_set("large_key", "x".repeat(200));
+
+        let heartbeat = node2.self_node_state().heartbeat();
+        let delta = node2.debug_delta_for(&node1.digest(), MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let key_values: Vec<(&ChitchatId, &str, &str)> = delta
+            .key_values()
+            .map(|(chitchat_id, key_value_mutation)| {
+                (
+                    chitchat_id,
+                    key_value_mutation.key(),
+                    key_value_mutation.value(),
+                )
+            })
+            .collect();
+        assert_eq!(key_values.len(), 2);
+        assert_eq!(key_values[0], (node2.self_chitchat_id(), "key", "value"));
+        // Computing the delta is free of side effects.
+        assert_eq!(node2.self_node_state().heartbeat(), heartbeat);
+
+        // A truncated delta is not accounted for in the statistics.
+        let truncated_delta = node2.debug_delta_for(&node1.digest(), 100);
+        assert!(truncated_delta.key_values().count() < 2);
+        assert_eq!(node2.num_truncated_deltas(), 0);
+        node2.cluster_state.compute_partial_delta_respecting_mtu(
+            &node1.digest(),
+            100,
+            &HashSet::new(),
+        );
+        assert_eq!(node2.num_truncated_deltas(), 1);
+    }
+
+    #[test]
+    fn test_respond_to_bad_cluster() {
+        let from_addr: SocketAddr = ([127, 0, 0, 1], 10_002).into();
+        let syn = || ChitchatMessage::Syn {
+            cluster_id: "other-cluster".to_string(),
+            digest: Digest::default(),
+        };
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds.clone(),
+            Vec::new(),
+        );
+        assert_eq!(node.cluster_id(), "default-cluster");
+        assert_eq!(
+            node.process_message(from_addr, syn()),
+            Some(ChitchatMessage::BadCluster)
+        );
+        assert_eq!(node.num_dropped_bad_cluster_messages(), 0);
+
+        let mut config = ChitchatConfig::for_test(10_001);
+        config.respond_to_bad_cluster = false;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(config, empty_seeds, Vec::new());
+        assert_eq!(node.process_message(from_addr, syn()), None);
+        assert_eq!(node.process_message(from_addr, syn()), None);
+        assert_eq!(node.num_dropped_bad_cluster_messages(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_maybe_trigger_self_superseded_callback() {
+        let superseding_chitchat_ids = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let superseding_chitchat_ids_clone = superseding_chitchat_ids.clone();
+
+        let mut config = ChitchatConfig::for_test(10_001);
+        config.chitchat_id.generation_id = 1;
+        config.self_superseded_callback = Some(Box::new(move |chitchat_id| {
+            superseding_chitchat_ids_clone
+                .lock()
+                .unwrap()
+                .push(chitchat_id.clone());
+        }));
+        let self_chitchat_id = config.chitchat_id.clone();
+        let (_seed_addrs_rx, seed_addrs_tx) = watch::channel(Default::default());
+        let mut node = Chitchat::with_chitchat_id_and_seeds(config, seed_addrs_tx, Vec::new());
+        let peer_addr: SocketAddr = ([127, 0, 0, 1], 10_002).into();
+
+        // Older generations and other nodes are fine.
+        let mut older_chitchat_id = self_chitchat_id.clone();
+        older_chitchat_id.generation_id = 0;
+        let mut delta = Delta::default();
+        delta.add_node(older_chitchat_id, 0u64, 0u64);
+        delta.add_node(ChitchatId::for_local_test(10_002), 0u64, 0u64);
+        node.process_delta(peer_addr, delta);
+        assert!(superseding_chitchat_ids.lock().unwrap().is_empty());
+
+        // The exact same identity with a more recent state.
+        let mut digest = Digest::default();
+        digest.add_node(self_chitchat_id.clone(), Heartbeat(1_000), 0, 0);
+        node.report_heartbeats_in_digest(&digest);
+        assert_eq!(
+            *superseding_chitchat_ids.lock().unwrap(),
+            vec![self_chitchat_id.clone()]
+        );
+
+        // The same node ID with a newer generation, reported only once.
+        let mut newer_chitchat_id = self_chitchat_id.clone();
+        newer_chitchat_id.generation_id = 2;
+        for _ in 0..2 {
+            let mut delta = Delta::default();
+            delta.add_node(newer_chitchat_id.clone(), 0u64, 0u64);
+            node.process_delta(peer_addr, delta);
+        }
+        assert_eq!(
+            *superseding_chitchat_ids.lock().unwrap(),
+            vec![self_chitchat_id, newer_chitchat_id]
+        );
+    }
+
+    #[test]
+    fn test_delta_about_self_node_is_not_applied() {
+        let mut node1 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            watch::channel(Default::default()).1,
+            Vec::new(),
+        );
+        let mut node2 = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_002),
+            watch::channel(Default::default()).1,
+            Vec::new(),
+        );
+        let node1_id = node1.self_chitchat_id().clone();
+
+        // node2 remembers the state of a previous incarnation of node1 with the same identity,
+        // which went further than the current one.
+        let stale_node1_state = node2.cluster_state.node_state_mut(&node1_id);
+        for i in 0..10 {
+            stale_node1_state.set("stale_key", i);
+        }
+        stale_node1_state.set("key", "stale_value");
+        node1.self_set("key", "value");
+        node1.self_set("other_key", "value");
+
+        run_chitchat_handshake(&mut node1, &mut node2);
+        // node1 did not apply the stale state, and moved its versions above it.
+        let node1_state = node1.node_state(&node1_id).unwrap();
+        assert_eq!(node1_state.get("key"), Some("value"));
+        assert_eq!(node1_state.get("stale_key"), None);
+        assert_eq!(node1_state.last_gc_version(), 12);
+        assert_eq!(node1_state.max_version(), 14);
+
+        // node2 resets its view of node1 and converges to the actual state.
+        run_chitchat_handshake(&mut node1, &mut node2);
+        let node1_state = node2.node_state(&node1_id).unwrap();
+        assert_eq!(node1_state.get("key"), Some("value"));
+        assert_eq!(node1_state.get("other_key"), Some("value"));
+        assert_eq!(node1_state.get("stale_key"), None);
+        assert_eq!(node1_state.max_version(), 14);
+    }
+
     #[tokio::test]
     async fn test_reset_node_state() {
         let config = ChitchatConfig::for_test(10_001);
@@ -1199,5 +4738,20 @@ mod tests {
         assert_eq!(node_state.get("qux"), Some("baz"));
         assert_eq!(node_state.get("toto"), Some("titi"));
         assert_eq!(node_state.max_version(), 3);
+
+        // A reset without any newer version leaves unknown nodes unknown.
+        let chitchat_id = ChitchatId::for_local_test(10_005);
+        node.reset_node_state(&chitchat_id, std::iter::empty(), 0, 1337);
+        assert!(node.cluster_state.node_state(&chitchat_id).is_none());
+        assert!(!node.failure_detector.contains_node(&chitchat_id));
+
+        // The self node state cannot be reset.
+        let self_chitchat_id = node.self_chitchat_id().clone();
+        node.self_node_state().set("foo", "bar");
+        let self_max_version = node.self_node_state().max_version();
+        node.reset_node_state(&self_chitchat_id, std::iter::empty(), 1_000, 1337);
+        assert_eq!(node.self_node_state().get("foo"), Some("bar"));
+        assert_eq!(node.self_node_state().max_version(), self_max_version);
+        assert_eq!(node.self_node_state().last_gc_version(), 0);
     }
 }
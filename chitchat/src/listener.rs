@@ -1,23 +1,38 @@
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Bound;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, RwLock, Weak};
+use std::thread::{self, JoinHandle};
 
-use crate::KeyChangeEvent;
+use tokio::sync::broadcast;
+
+use crate::{ChitchatId, ClusterEvent, KeyChangeEvent};
 
 pub struct ListenerHandle {
-    prefix: String,
+    subscription: Subscription,
     listener_id: usize,
     listeners: Weak<RwLock<InnerListeners>>,
+    num_dropped_events: Arc<AtomicU64>,
+    // Thread running the callback of a buffered listener.
+    thread_opt: Option<JoinHandle<()>>,
 }
 
 impl ListenerHandle {
+    /// Returns the number of events dropped because the buffer of the listener was full.
+    ///
+    /// This is always 0 for listeners that are not buffered.
+    pub fn num_dropped_events(&self) -> u64 {
+        self.num_dropped_events.load(Ordering::Relaxed)
+    }
+
     // By default, a listener is cancelled when its handle is dropped.
     // Calling forever prevents that.
     //
     // The listener itself will only be dropped when the Listeners object is dropped.
     pub fn forever(mut self) {
         self.listeners = Weak::new();
+        // The thread of a buffered listener lives as long as the listener: it is detached.
+        self.thread_opt = None;
     }
 }
 
@@ -25,16 +40,100 @@ impl Drop for ListenerHandle {
     fn drop(&mut self) {
         if let Some(listeners) = self.listeners.upgrade() {
             let mut listeners_guard = listeners.write().unwrap();
-            listeners_guard.remove_listener(&self.prefix, self.listener_id);
+            listeners_guard.remove_listener(&self.subscription, self.listener_id);
+        }
+        // Removing the listener dropped the sender end of its channel, so the thread exits once
+        // it has delivered the buffered events. The handle may be dropped by the callback itself,
+        // in which case the thread cannot be joined.
+        if let Some(thread) = self.thread_opt.take() {
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
         }
     }
 }
 
 type BoxedListener = Box<dyn Fn(KeyChangeEvent) + 'static + Send + Sync>;
 
-#[derive(Default, Clone)]
+/// Keys a listener is subscribed to.
+enum Subscription {
+    Prefix(String),
+    Pattern,
+}
+
+/// A glob pattern matched against whole keys.
+///
+/// `*` matches any sequence of characters, including the empty one, and `?` matches exactly one
+/// character. All the other characters match themselves.
+struct KeyPattern {
+    pattern: Vec<char>,
+}
+
+impl KeyPattern {
+    fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+        }
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        let key: Vec<char> = key.chars().collect();
+        let mut pattern_idx = 0;
+        let mut key_idx = 0;
+        // Position of the last `*` in the pattern, and of the key character it was matched up to.
+        let mut backtrack_opt: Option<(usize, usize)> = None;
+
+        while key_idx < key.len() {
+            match self.pattern.get(pattern_idx) {
+                Some('*') => {
+                    backtrack_opt = Some((pattern_idx, key_idx));
+                    pattern_idx += 1;
+                }
+                Some(&pattern_char) if pattern_char == '?' || pattern_char == key[key_idx] => {
+                    pattern_idx += 1;
+                    key_idx += 1;
+                }
+                _ => {
+                    // Let the last `*` absorb one more character.
+                    let Some((star_idx, star_key_idx)) = backtrack_opt else {
+                        return false;
+                    };
+                    backtrack_opt = Some((star_idx, star_key_idx + 1));
+                    pattern_idx = star_idx + 1;
+                    key_idx = star_key_idx + 1;
+                }
+            }
+        }
+        self.pattern[pattern_idx..]
+            .iter()
+            .all(|&pattern_char| pattern_char == '*')
+    }
+}
+
+/// A [`KeyChangeEvent`] sent to a buffered listener.
+struct OwnedKeyChangeEvent {
+    key: String,
+    value: Arc<str>,
+    node: ChitchatId,
+}
+
+/// Number of cluster events buffered for each subscriber. A subscriber lagging further behind
+/// misses the oldest events, see [`ClusterEvent::EventsDropped`].
+pub(crate) const CLUSTER_EVENTS_CAPACITY: usize = 1_024;
+
+#[derive(Clone)]
 pub(crate) struct Listeners {
     inner: Arc<RwLock<InnerListeners>>,
+    cluster_event_tx: broadcast::Sender<ClusterEvent>,
+}
+
+impl Default for Listeners {
+    fn default() -> Self {
+        Listeners {
+            inner: Default::default(),
+            cluster_event_tx: broadcast::Sender::new(CLUSTER_EVENTS_CAPACITY),
+        }
+    }
 }
 
 impl Listeners {
@@ -62,15 +161,126 @@ impl Listeners {
             .fetch_add(1, Ordering::Relaxed);
         inner_listener_guard.subscribe_event(&key_prefix, new_idx, boxed_listener);
         ListenerHandle {
-            prefix: key_prefix,
+            subscription: Subscription::Prefix(key_prefix),
             listener_id: new_idx,
             listeners: weak_listeners,
+            num_dropped_events: Default::default(),
+            thread_opt: None,
         }
     }
 
+    /// Same as [`Listeners::subscribe_event`], except that the callback is only called for the
+    /// events accepted by `filter`. The filter sees the key stripped of the prefix.
+    #[must_use]
+    pub(crate) fn subscribe_event_filtered(
+        &self,
+        key_prefix: impl ToString,
+        filter: impl Fn(&KeyChangeEvent) -> bool + 'static + Send + Sync,
+        callback: impl Fn(KeyChangeEvent) + 'static + Send + Sync,
+    ) -> ListenerHandle {
+        self.subscribe_event(key_prefix, move |key_change_event| {
+            if filter(&key_change_event) {
+                callback(key_change_event);
+            }
+        })
+    }
+
+    /// Subscribes a listener to the keys matching a glob pattern. See [`KeyPattern`].
+    ///
+    /// Contrary to prefix listeners, the listener is called with the whole key.
+    #[must_use]
+    pub(crate) fn subscribe_event_pattern(
+        &self,
+        key_pattern: &str,
+        callback: impl Fn(KeyChangeEvent) + 'static + Send + Sync,
+    ) -> ListenerHandle {
+        let key_pattern = KeyPattern::new(key_pattern);
+        let weak_listeners = Arc::downgrade(&self.inner);
+        let mut inner_listener_guard = self.inner.write().unwrap();
+        let new_idx = inner_listener_guard
+            .listener_idx
+            .fetch_add(1, Ordering::Relaxed);
+        inner_listener_guard
+            .pattern_listeners
+            .insert(new_idx, (key_pattern, Box::new(callback)));
+        ListenerHandle {
+            subscription: Subscription::Pattern,
+            listener_id: new_idx,
+            listeners: weak_listeners,
+            num_dropped_events: Default::default(),
+            thread_opt: None,
+        }
+    }
+
+    /// Subscribes a listener that is called on a dedicated thread rather than synchronously.
+    ///
+    /// Events are buffered in a channel of the given capacity, and delivered in order. When the
+    /// listener is too slow and the buffer is full, events are dropped and counted.
+    ///
+    /// The thread stops when the listener is removed. Panics if `capacity` is 0.
+    #[must_use]
+    pub(crate) fn subscribe_event_buffered(
+        &self,
+        key_prefix: impl ToString,
+        capacity: usize,
+        callback: impl Fn(KeyChangeEvent) + 'static + Send + Sync,
+    ) -> ListenerHandle {
+        assert!(capacity > 0, "buffered listener capacity must be positive");
+        let (event_tx, event_rx) = mpsc::sync_channel::<OwnedKeyChangeEvent>(capacity);
+        let thread = thread::Builder::new()
+            .name("chitchat-listener".to_string())
+            .spawn(move || {
+                while let Ok(event) = event_rx.recv() {
+                    callback(KeyChangeEvent {
+                        key: &event.key,
+                        value: &event.value,
+                        node: &event.node,
+                    });
+                }
+            })
+            .expect("failed to spawn the buffered listener thread");
+        let num_dropped_events: Arc<AtomicU64> = Default::default();
+        let num_dropped_events_clone = num_dropped_events.clone();
+        let mut listener_handle = self.subscribe_event(key_prefix, move |key_change_event| {
+            let event = OwnedKeyChangeEvent {
+                key: key_change_event.key.to_string(),
+                value: key_change_event.value.into(),
+                node: key_change_event.node.clone(),
+            };
+            if event_tx.try_send(event).is_err() {
+                num_dropped_events_clone.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        listener_handle.num_dropped_events = num_dropped_events;
+        listener_handle.thread_opt = Some(thread);
+        listener_handle
+    }
+
     pub(crate) fn trigger_event(&mut self, key_change_event: KeyChangeEvent) {
+        self.trigger_cluster_event(|| ClusterEvent::KeyChanged {
+            node: key_change_event.node.clone(),
+            key: key_change_event.key.to_string(),
+            value: key_change_event.value.into(),
+        });
         self.inner.read().unwrap().trigger_event(key_change_event);
     }
+
+    /// Returns a receiver of all the cluster events triggered from now on, in order. At most
+    /// [`CLUSTER_EVENTS_CAPACITY`] events are buffered: beyond that, the oldest ones are dropped
+    /// and the receiver reports how many it missed.
+    pub(crate) fn subscribe_cluster_events(&self) -> broadcast::Receiver<ClusterEvent> {
+        self.cluster_event_tx.subscribe()
+    }
+
+    /// Sends a cluster event to the subscribers. The event is only built if there is at least
+    /// one subscriber.
+    pub(crate) fn trigger_cluster_event(&self, cluster_event_fn: impl FnOnce() -> ClusterEvent) {
+        if self.cluster_event_tx.receiver_count() == 0 {
+            return;
+        }
+        // Sending only fails if there are no receivers left.
+        let _ = self.cluster_event_tx.send(cluster_event_fn());
+    }
 }
 
 #[derive(Default)]
@@ -78,6 +288,9 @@ struct InnerListeners {
     // A trie would have been more efficient, but in reality we don't have
     // that many listeners.
     listeners: BTreeMap<String, HashMap<usize, BoxedListener>>,
+    // Pattern listeners are matched one by one against every key, so they are slower than prefix
+    // listeners.
+    pattern_listeners: HashMap<usize, (KeyPattern, BoxedListener)>,
     listener_idx: AtomicUsize,
 }
 
@@ -94,6 +307,11 @@ impl InnerListeners {
     }
 
     fn trigger_event(&self, key_change_event: KeyChangeEvent) {
+        for (key_pattern, listener) in self.pattern_listeners.values() {
+            if key_pattern.matches(key_change_event.key) {
+                (*listener)(key_change_event);
+            }
+        }
         // We treat the empty prefix a tiny bit separately to get able to at least
         // use the first character as a range bound, as if we were going to the first level of
         // a trie.
@@ -122,15 +340,24 @@ impl InnerListeners {
         }
     }
 
-    fn remove_listener(&mut self, key_prefix: &str, idx: usize) {
-        if let Some(callbacks) = self.listeners.get_mut(key_prefix) {
-            callbacks.remove(&idx);
+    fn remove_listener(&mut self, subscription: &Subscription, idx: usize) {
+        match subscription {
+            Subscription::Prefix(key_prefix) => {
+                if let Some(callbacks) = self.listeners.get_mut(key_prefix) {
+                    callbacks.remove(&idx);
+                }
+            }
+            Subscription::Pattern => {
+                self.pattern_listeners.remove(&idx);
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     use super::*;
     use crate::ChitchatId;
 
@@ -166,6 +393,91 @@ mod tests {
         assert_eq!(counter.load(Ordering::Relaxed), 1);
     }
 
+    #[test]
+    fn test_key_pattern() {
+        let key_pattern = KeyPattern::new("shard_*_leader");
+        assert!(key_pattern.matches("shard_1_leader"));
+        assert!(key_pattern.matches("shard__leader"));
+        assert!(key_pattern.matches("shard_1_leader_leader"));
+        assert!(!key_pattern.matches("shard_1_follower"));
+        assert!(!key_pattern.matches("shard_1_leader_"));
+        assert!(!key_pattern.matches("my_shard_1_leader"));
+
+        let key_pattern = KeyPattern::new("node?:*");
+        assert!(key_pattern.matches("node1:"));
+        assert!(key_pattern.matches("nodeé:key"));
+        assert!(!key_pattern.matches("node:key"));
+        assert!(!key_pattern.matches("node12:key"));
+
+        assert!(KeyPattern::new("*").matches(""));
+        assert!(KeyPattern::new("**a*").matches("bab"));
+        assert!(KeyPattern::new("").matches(""));
+        assert!(!KeyPattern::new("").matches("key"));
+        assert!(KeyPattern::new("key").matches("key"));
+        assert!(!KeyPattern::new("key").matches("key1"));
+    }
+
+    #[test]
+    fn test_listeners_pattern() {
+        let mut listeners = Listeners::default();
+        let keys: Arc<Mutex<Vec<String>>> = Default::default();
+        let keys_clone = keys.clone();
+        let handle = listeners.subscribe_event_pattern("shard_*_leader", move |key_change_event| {
+            keys_clone
+                .lock()
+                .unwrap()
+                .push(key_change_event.key.to_string());
+        });
+        let node_id = chitchat_id(7280u16);
+        for key in ["shard_1_leader", "shard_1_follower", "shard_2_leader"] {
+            listeners.trigger_event(KeyChangeEvent {
+                key,
+                value: "value",
+                node: &node_id,
+            });
+        }
+        assert_eq!(*keys.lock().unwrap(), ["shard_1_leader", "shard_2_leader"]);
+
+        std::mem::drop(handle);
+        listeners.trigger_event(KeyChangeEvent {
+            key: "shard_3_leader",
+            value: "value",
+            node: &node_id,
+        });
+        assert_eq!(keys.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_listeners_filtered() {
+        let mut listeners = Listeners::default();
+        let keys: Arc<Mutex<Vec<String>>> = Default::default();
+        let keys_clone = keys.clone();
+        let _handle = listeners.subscribe_event_filtered(
+            "prefix:",
+            |key_change_event| key_change_event.key != "hot_key",
+            move |key_change_event| {
+                keys_clone
+                    .lock()
+                    .unwrap()
+                    .push(key_change_event.key.to_string());
+            },
+        );
+        let node_id = chitchat_id(7280u16);
+        for key in [
+            "prefix:key1",
+            "prefix:hot_key",
+            "prefix:hot_key",
+            "prefix:key2",
+        ] {
+            listeners.trigger_event(KeyChangeEvent {
+                key,
+                value: "value",
+                node: &node_id,
+            });
+        }
+        assert_eq!(*keys.lock().unwrap(), ["key1", "key2"]);
+    }
+
     #[test]
     fn test_listeners_empty_prefix() {
         let mut listeners = Listeners::default();
@@ -188,6 +500,70 @@ mod tests {
         listeners.trigger_event(key_change_event);
         assert_eq!(counter.load(Ordering::Relaxed), 1);
     }
+    #[test]
+    fn test_listeners_buffered() {
+        let mut listeners = Listeners::default();
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (unblock_tx, unblock_rx) = std::sync::mpsc::channel::<()>();
+        let unblock_rx = std::sync::Mutex::new(unblock_rx);
+        let (received_tx, received_rx) = std::sync::mpsc::channel();
+        let handle = listeners.subscribe_event_buffered("prefix:", 2, move |key_change_event| {
+            if key_change_event.key == "1" {
+                started_tx.send(()).unwrap();
+                // This listener is slow.
+                unblock_rx.lock().unwrap().recv().unwrap();
+            }
+            received_tx
+                .send(key_change_event.value.to_string())
+                .unwrap();
+        });
+        let node_id = chitchat_id(7280u16);
+        let mut trigger_event = |key: &str| {
+            listeners.trigger_event(KeyChangeEvent {
+                key: &format!("prefix:{key}"),
+                value: key,
+                node: &node_id,
+            });
+        };
+        trigger_event("1");
+        started_rx.recv().unwrap();
+        // The listener is blocked: the events are buffered, or dropped when the buffer is full.
+        for key in ["2", "3", "4"] {
+            trigger_event(key);
+        }
+        assert_eq!(handle.num_dropped_events(), 1);
+        unblock_tx.send(()).unwrap();
+
+        let received: Vec<String> = (0..3).map(|_| received_rx.recv().unwrap()).collect();
+        assert_eq!(received, ["1", "2", "3"]);
+
+        trigger_event("5");
+        assert_eq!(received_rx.recv().unwrap(), "5");
+        assert_eq!(handle.num_dropped_events(), 1);
+    }
+
+    #[test]
+    fn test_listeners_buffered_drop_stops_thread() {
+        let mut listeners = Listeners::default();
+        let callback_state: Arc<AtomicUsize> = Default::default();
+        let callback_state_clone = callback_state.clone();
+        let handle = listeners.subscribe_event_buffered("prefix:", 8, move |_key_change_event| {
+            callback_state_clone.fetch_add(1, Ordering::Relaxed);
+        });
+        let node_id = chitchat_id(7280u16);
+        for _ in 0..3 {
+            listeners.trigger_event(KeyChangeEvent {
+                key: "prefix:key",
+                value: "value",
+                node: &node_id,
+            });
+        }
+        std::mem::drop(handle);
+        // The buffered events were delivered, and the thread exited, dropping the callback.
+        assert_eq!(callback_state.load(Ordering::Relaxed), 3);
+        assert_eq!(Arc::strong_count(&callback_state), 1);
+    }
+
     #[test]
     fn test_listeners_forever() {
         let mut listeners = Listeners::default();
@@ -2,12 +2,22 @@ use std::io::BufRead;
 
 use anyhow::{bail, Context};
 
+use crate::auth::MAC_LEN;
 use crate::delta::Delta;
 use crate::digest::Digest;
 use crate::serialize::{Deserializable, Serializable};
+use crate::ChitchatId;
 
 const MAGIC_NUMBER: u16 = 45_139;
 
+/// Maximum number of extra bytes added by the [`ChitchatMessage::Timestamped`] envelope: the
+/// envelope header and the timestamp.
+pub(crate) const TIMESTAMPED_MESSAGE_OVERHEAD: usize = 2 + 1 + 1 + 10;
+
+/// Number of extra bytes added by the [`ChitchatMessage::Identified`] envelope, not counting the
+/// identity of the sender.
+pub(crate) const IDENTIFIED_MESSAGE_OVERHEAD: usize = 2 + 1 + 1;
+
 /// Chitchat message.
 ///
 /// Each variant represents a step of the gossip "handshake"
@@ -26,18 +36,55 @@ pub enum ChitchatMessage {
 
     /// Node B rejects the SYN message because node A and B belong to different clusters.
     BadCluster,
+
+    /// Envelope carrying another serialized message along with its message authentication code.
+    /// It is used when the cluster is configured with a shared secret.
+    Authenticated {
+        mac: [u8; MAC_LEN],
+        payload: Vec<u8>,
+    },
+
+    /// Envelope carrying another message along with the wall-clock time at which it was sent,
+    /// expressed in milliseconds since the Unix epoch. It is used when the cluster is configured
+    /// to drop stale messages.
+    Timestamped {
+        unix_timestamp_millis: u64,
+        message: Box<ChitchatMessage>,
+    },
+
+    /// Envelope carrying a SYN or SYN-ACK message along with the identity of the node that sent
+    /// it, so that the receiver can tell its peers apart even when the address messages are
+    /// received from differs from their gossip advertise address, e.g. behind a NAT.
+    Identified {
+        sender: ChitchatId,
+        message: Box<ChitchatMessage>,
+    },
 }
 
+/// Version of the wire format.
+///
+/// Nodes only accept messages serialized with the current protocol version: nodes running
+/// different protocol versions cannot gossip with each other. Upgrading a cluster across a
+/// protocol version bump therefore temporarily splits it into two groups of nodes, one per
+/// protocol version, until all the nodes are upgraded.
 #[derive(Clone, Copy, Eq, PartialEq)]
 #[repr(u8)]
 enum ProtocolVersion {
+    /// Integers are encoded as fixed-size little-endian integers.
     V0 = 0,
+    /// Versions, heartbeats, generation ids, and string lengths are encoded as LEB128 varints.
+    /// The key-values of a node delta may share a version, when they were set as a batch: V0
+    /// nodes reject such deltas.
+    V1 = 1,
 }
 
 impl ProtocolVersion {
+    const CURRENT: ProtocolVersion = ProtocolVersion::V1;
+
     pub fn from_code(code: u8) -> Option<Self> {
         match code {
             0 => Some(Self::V0),
+            1 => Some(Self::V1),
             _ => None,
         }
     }
@@ -54,6 +101,9 @@ enum MessageType {
     SynAck = 1u8,
     Ack = 2u8,
     BadCluster = 3u8,
+    Authenticated = 4u8,
+    Timestamped = 5u8,
+    Identified = 6u8,
 }
 
 impl MessageType {
@@ -63,6 +113,9 @@ impl MessageType {
             1 => Some(Self::SynAck),
             2 => Some(Self::Ack),
             3 => Some(Self::BadCluster),
+            4 => Some(Self::Authenticated),
+            5 => Some(Self::Timestamped),
+            6 => Some(Self::Identified),
             _ => None,
         }
     }
@@ -72,10 +125,21 @@ impl MessageType {
     }
 }
 
+impl ChitchatMessage {
+    /// Returns the message wrapped in a [`ChitchatMessage::Identified`] envelope, or the message
+    /// itself if it is not identified.
+    pub(crate) fn unidentified(&self) -> &ChitchatMessage {
+        match self {
+            ChitchatMessage::Identified { message, .. } => message,
+            message => message,
+        }
+    }
+}
+
 impl Serializable for ChitchatMessage {
     fn serialize(&self, buf: &mut Vec<u8>) {
         buf.extend(MAGIC_NUMBER.to_le_bytes());
-        ProtocolVersion::V0.to_code().serialize(buf);
+        ProtocolVersion::CURRENT.to_code().serialize(buf);
 
         match self {
             ChitchatMessage::Syn { cluster_id, digest } => {
@@ -95,6 +159,24 @@ impl Serializable for ChitchatMessage {
             ChitchatMessage::BadCluster => {
                 buf.push(MessageType::BadCluster.to_code());
             }
+            ChitchatMessage::Authenticated { mac, payload } => {
+                buf.push(MessageType::Authenticated.to_code());
+                mac.serialize(buf);
+                buf.extend_from_slice(payload);
+            }
+            ChitchatMessage::Timestamped {
+                unix_timestamp_millis,
+                message,
+            } => {
+                buf.push(MessageType::Timestamped.to_code());
+                unix_timestamp_millis.serialize(buf);
+                message.serialize(buf);
+            }
+            ChitchatMessage::Identified { sender, message } => {
+                buf.push(MessageType::Identified.to_code());
+                sender.serialize(buf);
+                message.serialize(buf);
+            }
         }
     }
 
@@ -109,6 +191,16 @@ impl Serializable for ChitchatMessage {
                 }
                 ChitchatMessage::Ack { delta } => 1 + delta.serialized_len(),
                 ChitchatMessage::BadCluster => 1,
+                ChitchatMessage::Authenticated { mac, payload } => {
+                    1 + mac.serialized_len() + payload.len()
+                }
+                ChitchatMessage::Timestamped {
+                    unix_timestamp_millis,
+                    message,
+                } => 1 + unix_timestamp_millis.serialized_len() + message.serialized_len(),
+                ChitchatMessage::Identified { sender, message } => {
+                    1 + sender.serialized_len() + message.serialized_len()
+                }
             }
     }
 }
@@ -125,7 +217,7 @@ impl Deserializable for ChitchatMessage {
         let protocol_version =
             ProtocolVersion::from_code(buf[2]).context("invalid protocol version")?;
 
-        if protocol_version != ProtocolVersion::V0 {
+        if protocol_version != ProtocolVersion::CURRENT {
             bail!(
                 "unsupported protocol version `{}`",
                 protocol_version.to_code()
@@ -142,26 +234,68 @@ impl Deserializable for ChitchatMessage {
 
         match message_type {
             MessageType::Syn => {
-                let digest = Digest::deserialize(buf)?;
-                let cluster_id = String::deserialize(buf)?;
+                let digest =
+                    Digest::deserialize(buf).context("failed to deserialize syn digest")?;
+                let cluster_id =
+                    String::deserialize(buf).context("failed to deserialize syn cluster id")?;
                 Ok(Self::Syn { cluster_id, digest })
             }
             MessageType::SynAck => {
-                let digest = Digest::deserialize(buf)?;
-                let delta = Delta::deserialize(buf)?;
+                let digest =
+                    Digest::deserialize(buf).context("failed to deserialize syn-ack digest")?;
+                let delta =
+                    Delta::deserialize(buf).context("failed to deserialize syn-ack delta")?;
                 Ok(Self::SynAck { digest, delta })
             }
             MessageType::Ack => {
-                let delta = Delta::deserialize(buf)?;
+                let delta = Delta::deserialize(buf).context("failed to deserialize ack delta")?;
                 Ok(Self::Ack { delta })
             }
             MessageType::BadCluster => Ok(Self::BadCluster),
+            MessageType::Authenticated => {
+                let mac = <[u8; MAC_LEN]>::deserialize(buf)
+                    .context("failed to deserialize message authentication code")?;
+                // The payload spans until the end of the buffer.
+                let payload = buf.to_vec();
+                buf.consume(payload.len());
+                Ok(Self::Authenticated { mac, payload })
+            }
+            MessageType::Timestamped => {
+                let unix_timestamp_millis =
+                    u64::deserialize(buf).context("failed to deserialize message timestamp")?;
+                let message = ChitchatMessage::deserialize(buf)
+                    .context("failed to deserialize timestamped message")?;
+                if matches!(message, ChitchatMessage::Timestamped { .. }) {
+                    bail!("timestamped envelopes cannot be nested");
+                }
+                Ok(Self::Timestamped {
+                    unix_timestamp_millis,
+                    message: Box::new(message),
+                })
+            }
+            MessageType::Identified => {
+                let sender =
+                    ChitchatId::deserialize(buf).context("failed to deserialize message sender")?;
+                let message = ChitchatMessage::deserialize(buf)
+                    .context("failed to deserialize identified message")?;
+                if !matches!(
+                    message,
+                    ChitchatMessage::Syn { .. } | ChitchatMessage::SynAck { .. }
+                ) {
+                    bail!("only SYN and SYN-ACK messages can be identified");
+                }
+                Ok(Self::Identified {
+                    sender,
+                    message: Box::new(message),
+                })
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::serialize::test_serdeser_aux;
     use crate::{ChitchatId, ChitchatMessage, Delta, Digest, Heartbeat};
 
@@ -172,7 +306,7 @@ mod tests {
                 cluster_id: "cluster-a".to_string(),
                 digest: Digest::default(),
             };
-            test_serdeser_aux(&syn, 17);
+            test_serdeser_aux(&syn, 16);
         }
         {
             let mut digest = Digest::default();
@@ -183,7 +317,7 @@ mod tests {
                 cluster_id: "cluster-a".to_string(),
                 digest,
             };
-            test_serdeser_aux(&syn, 68);
+            test_serdeser_aux(&syn, 38);
         }
     }
 
@@ -202,26 +336,25 @@ mod tests {
             // 2 bytes.
             let mut digest = Digest::default();
             let node = ChitchatId::for_local_test(10_001);
-            // +43 bytes = 27 bytes (ChitchatId) + 8 (hearbeat) + 8 (max_version).
+            // +22 bytes = 19 bytes (ChitchatId) + 1 (heartbeat) + 1 (last_gc_version) + 1
+            // (max_version).
             digest.add_node(node, Heartbeat(0), 0, 0);
 
             // 4 bytes
             let mut delta = Delta::default();
             let node = ChitchatId::for_local_test(10_001);
-            // +27 bytes (ChitchatId)
-            // + 2 bytes (node delta len)
-            // + 8 bytes (last_gc_version)
-            // + 8 bytes (from_version).
+            // +19 bytes (ChitchatId)
+            // + 1 byte (last_gc_version)
+            // + 1 byte (from_version).
             delta.add_node(node.clone(), 0u64, 0u64);
-            // +29 bytes.
+            // +13 bytes.
             delta.add_kv(&node, "key", "value", 0, true);
-            // That's compression kicking in.
-            delta.set_serialized_len(60);
+            delta.set_serialized_len(39);
 
             let syn_ack = ChitchatMessage::SynAck { digest, delta };
-            // 1 byte (protocol version) + 1 byte (message tag) + 53 bytes (digest) + 60 bytes
-            // (delta).
-            test_serdeser_aux(&syn_ack, 2 + 1 + 1 + 53 + 60);
+            // 2 bytes (magic number) + 1 byte (protocol version) + 1 byte (message tag) + 24
+            // bytes (digest) + 39 bytes (delta).
+            test_serdeser_aux(&syn_ack, 2 + 1 + 1 + 24 + 39);
         }
     }
 
@@ -236,18 +369,83 @@ mod tests {
             // 4 bytes.
             let mut delta = Delta::default();
             let node = ChitchatId::for_local_test(10_001);
-            // +37 bytes = 27 bytes (ChitchatId) + 2 bytes (node delta len) + 8 bytes (heartbeat).
+            // +21 bytes = 19 bytes (ChitchatId) + 1 byte (last_gc_version) + 1 byte
+            // (from_version).
             delta.add_node(node.clone(), 0u64, 0u64);
-            // +29 bytes.
+            // +13 bytes.
             delta.add_kv(&node, "key", "value", 0, true);
-            delta.set_serialized_len(60);
+            delta.set_serialized_len(39);
             let ack = ChitchatMessage::Ack { delta };
-            test_serdeser_aux(&ack, 2 + 1 + 1 + 60);
+            test_serdeser_aux(&ack, 2 + 1 + 1 + 39);
         }
     }
 
+    #[test]
+    fn test_unsupported_protocol_version() {
+        let mut buf = ChitchatMessage::BadCluster.serialize_to_vec();
+        assert_eq!(buf[2], ProtocolVersion::CURRENT.to_code());
+        buf[2] = ProtocolVersion::V0.to_code();
+        let error = ChitchatMessage::deserialize(&mut &buf[..]).unwrap_err();
+        assert_eq!(error.to_string(), "unsupported protocol version `0`");
+    }
+
     #[test]
     fn test_bad_cluster() {
         test_serdeser_aux(&ChitchatMessage::BadCluster, 4);
     }
+
+    #[test]
+    fn test_authenticated() {
+        let authenticated = ChitchatMessage::Authenticated {
+            mac: [1u8; 32],
+            payload: b"payload".to_vec(),
+        };
+        test_serdeser_aux(&authenticated, 2 + 1 + 1 + 32 + 7);
+    }
+
+    #[test]
+    fn test_timestamped() {
+        let timestamped = ChitchatMessage::Timestamped {
+            unix_timestamp_millis: 1_700_000_000_000,
+            message: Box::new(ChitchatMessage::BadCluster),
+        };
+        // 2 bytes (magic number) + 1 byte (protocol version) + 1 byte (message tag) + 6 bytes
+        // (timestamp) + 4 bytes (message).
+        test_serdeser_aux(&timestamped, 2 + 1 + 1 + 6 + 4);
+
+        let nested_timestamped = ChitchatMessage::Timestamped {
+            unix_timestamp_millis: 1_700_000_000_000,
+            message: Box::new(timestamped),
+        };
+        let buf = nested_timestamped.serialize_to_vec();
+        let error = ChitchatMessage::deserialize(&mut &buf[..]).unwrap_err();
+        assert_eq!(error.to_string(), "timestamped envelopes cannot be nested");
+    }
+
+    #[test]
+    fn test_identified() {
+        let identified = ChitchatMessage::Identified {
+            sender: ChitchatId::for_local_test(10_001),
+            message: Box::new(ChitchatMessage::SynAck {
+                digest: Digest::default(),
+                delta: Delta::default(),
+            }),
+        };
+        // 2 bytes (magic number) + 1 byte (protocol version) + 1 byte (message tag) + 19 bytes
+        // (ChitchatId) + 7 bytes (message).
+        test_serdeser_aux(&identified, 2 + 1 + 1 + 19 + 7);
+
+        let identified_ack = ChitchatMessage::Identified {
+            sender: ChitchatId::for_local_test(10_001),
+            message: Box::new(ChitchatMessage::Ack {
+                delta: Delta::default(),
+            }),
+        };
+        let buf = identified_ack.serialize_to_vec();
+        let error = ChitchatMessage::deserialize(&mut &buf[..]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "only SYN and SYN-ACK messages can be identified"
+        );
+    }
 }
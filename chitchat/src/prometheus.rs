@@ -0,0 +1,199 @@
+//! Exposition of the chitchat metrics in the Prometheus text format.
+//!
+//! This module is only available with the `prometheus` feature.
+
+use std::fmt::Write;
+
+use crate::Chitchat;
+
+#[derive(Clone, Copy)]
+enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        }
+    }
+}
+
+fn write_metric(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    metric_type: MetricType,
+    samples: &[(&str, u64)],
+) {
+    let _ = writeln!(output, "# HELP chitchat_{name} {help}");
+    let _ = writeln!(output, "# TYPE chitchat_{name} {}", metric_type.as_str());
+    for (labels, value) in samples {
+        let _ = writeln!(output, "chitchat_{name}{labels} {value}");
+    }
+}
+
+impl Chitchat {
+    /// Returns the metrics of the node in the Prometheus text exposition format, ready to be served
+    /// on a `/metrics` endpoint.
+    ///
+    /// Computing the tombstone backlog requires scanning the whole cluster state, so this should
+    /// not be called more often than the metrics are scraped.
+    pub fn metrics_text(&self) -> String {
+        let mut output = String::new();
+        write_metric(
+            &mut output,
+            "nodes",
+            "Number of nodes in the cluster state, including the self node.",
+            MetricType::Gauge,
+            &[("", self.num_nodes() as u64)],
+        );
+        write_metric(
+            &mut output,
+            "live_nodes",
+            "Number of live nodes, including the self node.",
+            MetricType::Gauge,
+            &[("", self.num_live_nodes() as u64)],
+        );
+        write_metric(
+            &mut output,
+            "dead_nodes",
+            "Number of nodes considered dead by the failure detector.",
+            MetricType::Gauge,
+            &[("", self.num_dead_nodes() as u64)],
+        );
+        write_metric(
+            &mut output,
+            "tombstones",
+            "Number of key-values marked for deletion and not garbage collected yet.",
+            MetricType::Gauge,
+            &[("", self.num_tombstones() as u64)],
+        );
+        write_metric(
+            &mut output,
+            "gossip_rounds_total",
+            "Number of gossip rounds initiated by the self node.",
+            MetricType::Counter,
+            &[("", self.num_gossip_rounds())],
+        );
+        write_metric(
+            &mut output,
+            "throttled_syns_total",
+            "Number of SYN messages skipped because the gossip byte budget was spent.",
+            MetricType::Counter,
+            &[("", self.num_throttled_syns())],
+        );
+        let received_delta_stats = self.received_delta_stats();
+        write_metric(
+            &mut output,
+            "received_delta_bytes_total",
+            "Number of bytes of the deltas received from peers.",
+            MetricType::Counter,
+            &[("", received_delta_stats.num_bytes as u64)],
+        );
+        write_metric(
+            &mut output,
+            "received_delta_key_values_total",
+            "Number of key-values carried by the deltas received from peers.",
+            MetricType::Counter,
+            &[("", received_delta_stats.num_key_values as u64)],
+        );
+        write_metric(
+            &mut output,
+            "truncated_deltas_total",
+            "Number of deltas sent to peers that were truncated because of the MTU.",
+            MetricType::Counter,
+            &[("", self.num_truncated_deltas())],
+        );
+        write_metric(
+            &mut output,
+            "coalesced_deltas_total",
+            "Number of deltas sent to peers that were reused rather than computed.",
+            MetricType::Counter,
+            &[("", self.num_coalesced_deltas())],
+        );
+        write_metric(
+            &mut output,
+            "sent_node_resets_total",
+            "Number of node resets sent to peers that missed garbage collected tombstones.",
+            MetricType::Counter,
+            &[("", self.num_sent_node_resets())],
+        );
+        write_metric(
+            &mut output,
+            "rejected_messages_total",
+            "Number of messages received from peers and dropped.",
+            MetricType::Counter,
+            &[
+                (
+                    "{reason=\"unauthenticated\"}",
+                    self.num_rejected_unauthenticated_messages(),
+                ),
+                ("{reason=\"stale\"}", self.num_rejected_stale_messages()),
+                (
+                    "{reason=\"bad_cluster\"}",
+                    self.num_dropped_bad_cluster_messages(),
+                ),
+            ],
+        );
+        write_metric(
+            &mut output,
+            "rejected_keys_total",
+            "Number of key-values dropped because their key was rejected by the key validator.",
+            MetricType::Counter,
+            &[("", self.num_rejected_keys())],
+        );
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::watch;
+
+    use crate::{Chitchat, ChitchatConfig};
+
+    #[test]
+    fn test_metrics_text() {
+        let empty_seeds = watch::channel(Default::default()).1;
+        let mut node = Chitchat::with_chitchat_id_and_seeds(
+            ChitchatConfig::for_test(10_001),
+            empty_seeds,
+            Vec::new(),
+        );
+        node.self_set("key1", "value1");
+        node.self_set("key2", "value2");
+        node.self_node_state().delete("key1");
+        node.record_gossip_round();
+
+        let metrics_text = node.metrics_text();
+        let metric_lines: Vec<&str> = metrics_text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        assert_eq!(
+            metric_lines,
+            [
+                "chitchat_nodes 1",
+                "chitchat_live_nodes 1",
+                "chitchat_dead_nodes 0",
+                "chitchat_tombstones 1",
+                "chitchat_gossip_rounds_total 1",
+                "chitchat_throttled_syns_total 0",
+                "chitchat_received_delta_bytes_total 0",
+                "chitchat_received_delta_key_values_total 0",
+                "chitchat_truncated_deltas_total 0",
+                "chitchat_coalesced_deltas_total 0",
+                "chitchat_sent_node_resets_total 0",
+                "chitchat_rejected_messages_total{reason=\"unauthenticated\"} 0",
+                "chitchat_rejected_messages_total{reason=\"stale\"} 0",
+                "chitchat_rejected_messages_total{reason=\"bad_cluster\"} 0",
+                "chitchat_rejected_keys_total 0",
+            ]
+        );
+        assert!(metrics_text.contains("# TYPE chitchat_live_nodes gauge\n"));
+        assert!(metrics_text.contains("# TYPE chitchat_gossip_rounds_total counter\n"));
+    }
+}
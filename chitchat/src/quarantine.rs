@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Configuration of the quarantine applied to peers that keep sending us messages addressed to a
+/// different cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BadClusterQuarantineConfig {
+    /// Number of `BadCluster` responses sent to a given address within `window` before the
+    /// address gets quarantined.
+    pub max_bad_cluster_responses: usize,
+    /// Sliding window over which `BadCluster` responses are counted.
+    pub window: Duration,
+    /// Period during which we stop responding to a quarantined address.
+    pub cooldown: Duration,
+}
+
+impl Default for BadClusterQuarantineConfig {
+    fn default() -> Self {
+        Self {
+            max_bad_cluster_responses: 10,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PeerRecord {
+    window_start: Instant,
+    num_bad_cluster_responses: usize,
+    quarantined_until: Option<Instant>,
+}
+
+impl PeerRecord {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            num_bad_cluster_responses: 0,
+            quarantined_until: None,
+        }
+    }
+
+    fn is_quarantined(&self, now: Instant) -> bool {
+        self.quarantined_until
+            .map(|quarantined_until| now < quarantined_until)
+            .unwrap_or(false)
+    }
+
+    fn is_expired(&self, now: Instant, window: Duration) -> bool {
+        !self.is_quarantined(now) && now >= self.window_start + window
+    }
+}
+
+/// Keeps track of the addresses we keep answering `BadCluster` to, and decides when to stop
+/// answering them.
+///
+/// This prevents a misconfigured node and ours from ping-ponging `Syn`/`BadCluster` messages
+/// forever.
+#[derive(Debug)]
+pub(crate) struct BadClusterQuarantine {
+    config: BadClusterQuarantineConfig,
+    peers: HashMap<SocketAddr, PeerRecord>,
+}
+
+impl BadClusterQuarantine {
+    pub fn new(config: BadClusterQuarantineConfig) -> Self {
+        Self {
+            config,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Records that `addr` sent us a message addressed to another cluster, and returns whether we
+    /// should answer it with a `BadCluster` message.
+    pub fn should_respond(&mut self, addr: SocketAddr) -> bool {
+        let now = Instant::now();
+        let window = self.config.window;
+        self.peers
+            .retain(|_, peer_record| !peer_record.is_expired(now, window));
+
+        let peer_record = self
+            .peers
+            .entry(addr)
+            .or_insert_with(|| PeerRecord::new(now));
+
+        if peer_record.is_quarantined(now) {
+            return false;
+        }
+        if peer_record.quarantined_until.is_some() {
+            // The quarantine has expired. We start from a clean slate.
+            *peer_record = PeerRecord::new(now);
+        }
+        peer_record.num_bad_cluster_responses += 1;
+
+        if peer_record.num_bad_cluster_responses > self.config.max_bad_cluster_responses {
+            warn!(
+                addr=%addr,
+                cooldown=?self.config.cooldown,
+                "quarantining peer repeatedly sending messages addressed to a different cluster"
+            );
+            peer_record.quarantined_until = Some(now + self.config.cooldown);
+            return false;
+        }
+        true
+    }
+
+    /// Returns the number of addresses currently in quarantine.
+    pub fn num_quarantined_addrs(&self) -> usize {
+        let now = Instant::now();
+        self.peers
+            .values()
+            .filter(|peer_record| peer_record.is_quarantined(now))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> BadClusterQuarantineConfig {
+        BadClusterQuarantineConfig {
+            max_bad_cluster_responses: 2,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_cluster_quarantine() {
+        tokio::time::pause();
+        let mut quarantine = BadClusterQuarantine::new(test_config());
+        let addr: SocketAddr = ([127, 0, 0, 1], 10_001).into();
+        let other_addr: SocketAddr = ([127, 0, 0, 1], 10_002).into();
+
+        assert!(quarantine.should_respond(addr));
+        assert!(quarantine.should_respond(addr));
+        assert_eq!(quarantine.num_quarantined_addrs(), 0);
+
+        assert!(!quarantine.should_respond(addr));
+        assert_eq!(quarantine.num_quarantined_addrs(), 1);
+
+        // Other addresses are not affected.
+        assert!(quarantine.should_respond(other_addr));
+
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(!quarantine.should_respond(addr));
+
+        // The quarantine expires automatically.
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(quarantine.num_quarantined_addrs(), 0);
+        assert!(quarantine.should_respond(addr));
+    }
+
+    #[tokio::test]
+    async fn test_bad_cluster_quarantine_window() {
+        tokio::time::pause();
+        let mut quarantine = BadClusterQuarantine::new(test_config());
+        let addr: SocketAddr = ([127, 0, 0, 1], 10_001).into();
+
+        assert!(quarantine.should_respond(addr));
+        assert!(quarantine.should_respond(addr));
+
+        // The counter is reset once the window has elapsed.
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(quarantine.should_respond(addr));
+        assert!(quarantine.should_respond(addr));
+        assert_eq!(quarantine.num_quarantined_addrs(), 0);
+        assert!(quarantine.peers.contains_key(&addr));
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+
+use crate::state::ClusterState;
+use crate::{ChitchatId, ClusterStateSnapshot, NodeState, Version};
+
+/// Immutable view of the cluster state, as published by the gossip loop.
+#[derive(Debug, Default)]
+pub struct ClusterStateView {
+    node_states: BTreeMap<ChitchatId, Arc<NodeState>>,
+    live_nodes: BTreeSet<ChitchatId>,
+}
+
+impl ClusterStateView {
+    /// Returns the state of a node, whether it is live or dead.
+    pub fn node_state(&self, chitchat_id: &ChitchatId) -> Option<&NodeState> {
+        self.node_states.get(chitchat_id).map(Arc::as_ref)
+    }
+
+    /// Returns the value associated with a key of a node, unless it is marked for deletion.
+    pub fn get(&self, chitchat_id: &ChitchatId, key: &str) -> Option<&str> {
+        self.node_state(chitchat_id)?.get(key)
+    }
+
+    /// Returns the nodes of the cluster state, whether they are live or dead.
+    pub fn nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.node_states.keys()
+    }
+
+    /// Returns the nodes considered alive by the failure detector, including the self node.
+    pub fn live_nodes(&self) -> impl Iterator<Item = &ChitchatId> {
+        self.live_nodes.iter()
+    }
+
+    pub fn is_live(&self, chitchat_id: &ChitchatId) -> bool {
+        self.live_nodes.contains(chitchat_id)
+    }
+}
+
+/// Lock-free read access to the cluster state.
+///
+/// Readers never take the [`crate::Chitchat`] lock: they load the last [`ClusterStateView`]
+/// published by the gossip loop, which is refreshed after every handled batch of messages, gossip
+/// round, and call to [`crate::ChitchatHandle::with_chitchat`]. A loaded view is consistent but
+/// may lag behind the latest mutations made directly on a locked [`crate::Chitchat`].
+///
+/// Views are about key-values: the state of a node is only refreshed when its key-values change,
+/// so the heartbeats of the node states in a view are not up to date.
+#[derive(Clone)]
+pub struct ClusterStateReader {
+    view: Arc<ArcSwap<ClusterStateView>>,
+}
+
+impl ClusterStateReader {
+    /// Returns the last published view of the cluster state.
+    pub fn load(&self) -> Arc<ClusterStateView> {
+        self.view.load_full()
+    }
+}
+
+/// Publishes views of the cluster state to the [`ClusterStateReader`]s.
+#[derive(Default)]
+pub(crate) struct ClusterStatePublisher {
+    view: Arc<ArcSwap<ClusterStateView>>,
+}
+
+impl ClusterStatePublisher {
+    pub fn reader(&self) -> ClusterStateReader {
+        ClusterStateReader {
+            view: self.view.clone(),
+        }
+    }
+
+    fn has_readers(&self) -> bool {
+        Arc::strong_count(&self.view) > 1
+    }
+
+    /// Publishes a new view of the cluster state, unless no reader was ever created.
+    ///
+    /// Node states are shared with the previous view when their key-values have not changed, so
+    /// that publishing does not clone the whole cluster state every time.
+    pub fn publish<'a>(
+        &self,
+        cluster_state: &ClusterState,
+        live_nodes: impl Iterator<Item = &'a ChitchatId>,
+    ) {
+        if !self.has_readers() {
+            return;
+        }
+        let previous_view = self.view.load();
+        let node_states = cluster_state
+            .node_states
+            .iter()
+            .map(|(chitchat_id, node_state)| {
+                let previous_node_state_opt =
+                    previous_view
+                        .node_states
+                        .get(chitchat_id)
+                        .filter(|previous_node_state| {
+                            fingerprint(previous_node_state) == fingerprint(node_state)
+                        });
+                let node_state = if let Some(previous_node_state) = previous_node_state_opt {
+                    previous_node_state.clone()
+                } else {
+                    Arc::new(node_state.clone())
+                };
+                (chitchat_id.clone(), node_state)
+            })
+            .collect();
+        let live_nodes = live_nodes.cloned().collect();
+        let view = ClusterStateView {
+            node_states,
+            live_nodes,
+        };
+        self.view.store(Arc::new(view));
+    }
+}
+
+/// Publishes snapshots of the cluster state, so that readers share the last one until the cluster
+/// state changes.
+#[derive(Default)]
+pub(crate) struct ClusterStateSnapshotPublisher {
+    published_snapshot: ArcSwapOption<PublishedSnapshot>,
+}
+
+struct PublishedSnapshot {
+    fingerprints: Vec<(ChitchatId, Fingerprint)>,
+    snapshot: Arc<ClusterStateSnapshot>,
+}
+
+impl PublishedSnapshot {
+    fn is_up_to_date(&self, cluster_state: &ClusterState) -> bool {
+        self.fingerprints.len() == cluster_state.node_states.len()
+            && self
+                .fingerprints
+                .iter()
+                .zip(&cluster_state.node_states)
+                .all(
+                    |((cached_chitchat_id, cached_fingerprint), (chitchat_id, node_state))| {
+                        cached_chitchat_id == chitchat_id
+                            && *cached_fingerprint == fingerprint(node_state)
+                    },
+                )
+            && cluster_state.seed_addrs_eq(&self.snapshot.seed_addrs)
+    }
+}
+
+impl ClusterStateSnapshotPublisher {
+    /// Returns the last published snapshot. The first call publishes one: snapshots are only
+    /// published once they have been requested, so that the gossip loop does not pay for them
+    /// otherwise.
+    pub fn snapshot(&self, cluster_state: &ClusterState) -> Arc<ClusterStateSnapshot> {
+        if let Some(published_snapshot) = &*self.published_snapshot.load() {
+            return published_snapshot.snapshot.clone();
+        }
+        self.publish_snapshot(cluster_state)
+    }
+
+    /// Publishes a new snapshot if the cluster state changed since the last one was published,
+    /// unless no snapshot was ever requested.
+    pub fn publish(&self, cluster_state: &ClusterState) {
+        let published_snapshot_guard = self.published_snapshot.load();
+        let Some(published_snapshot) = &*published_snapshot_guard else {
+            return;
+        };
+        if published_snapshot.is_up_to_date(cluster_state) {
+            return;
+        }
+        self.publish_snapshot(cluster_state);
+    }
+
+    fn publish_snapshot(&self, cluster_state: &ClusterState) -> Arc<ClusterStateSnapshot> {
+        let fingerprints = cluster_state
+            .node_states
+            .iter()
+            .map(|(chitchat_id, node_state)| (chitchat_id.clone(), fingerprint(node_state)))
+            .collect();
+        let snapshot = Arc::new(ClusterStateSnapshot::from(cluster_state));
+        self.published_snapshot
+            .store(Some(Arc::new(PublishedSnapshot {
+                fingerprints,
+                snapshot: snapshot.clone(),
+            })));
+        snapshot
+    }
+}
+
+type Fingerprint = (Version, Version, usize, u64);
+
+/// Summarizes the key-values of a node: any mutation of its key-values changes its max version,
+/// its last GC version, its number of key-values, or its number of local writes.
+///
+/// Heartbeats are left out on purpose: they are not part of the key-values, and the heartbeat of
+/// the self node is incremented by every gossip exchange.
+fn fingerprint(node_state: &NodeState) -> Fingerprint {
+    (
+        node_state.max_version(),
+        node_state.last_gc_version(),
+        node_state.num_key_values_including_deleted(),
+        node_state.num_local_writes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_state_publisher() {
+        let publisher = ClusterStatePublisher::default();
+        let mut cluster_state = ClusterState::default();
+        let self_node = ChitchatId::for_local_test(10_001);
+        let node2 = ChitchatId::for_local_test(10_002);
+        cluster_state.node_state_mut(&self_node).set("key", "1");
+        cluster_state.node_state_mut(&node2).set("key", "2");
+
+        // Nothing is published until a reader is created.
+        publisher.publish(&cluster_state, [&self_node].into_iter());
+        assert!(publisher.view.load().nodes().next().is_none());
+
+        let reader = publisher.reader();
+        publisher.publish(&cluster_state, [&self_node].into_iter());
+        let view = reader.load();
+        assert_eq!(view.nodes().count(), 2);
+        assert_eq!(view.get(&self_node, "key"), Some("1"));
+        assert_eq!(view.get(&node2, "key"), Some("2"));
+        assert!(view.is_live(&self_node));
+        assert!(!view.is_live(&node2));
+
+        cluster_state.node_state_mut(&node2).set("key", "3");
+        publisher.publish(&cluster_state, [&self_node, &node2].into_iter());
+        // Previously loaded views are immutable.
+        assert_eq!(view.get(&node2, "key"), Some("2"));
+        let new_view = reader.load();
+        assert_eq!(new_view.get(&node2, "key"), Some("3"));
+        assert!(new_view.is_live(&node2));
+
+        // Unchanged node states are shared between views.
+        publisher.publish(&cluster_state, [&self_node, &node2].into_iter());
+        let newest_view = reader.load();
+        assert!(Arc::ptr_eq(
+            &new_view.node_states[&node2],
+            &newest_view.node_states[&node2]
+        ));
+        assert!(Arc::ptr_eq(
+            &new_view.node_states[&self_node],
+            &newest_view.node_states[&self_node]
+        ));
+
+        // Heartbeats alone do not refresh a node state, local key-values do.
+        cluster_state.node_state_mut(&self_node).inc_heartbeat();
+        publisher.publish(&cluster_state, [&self_node, &node2].into_iter());
+        assert!(Arc::ptr_eq(
+            &newest_view.node_states[&self_node],
+            &reader.load().node_states[&self_node]
+        ));
+        cluster_state
+            .node_state_mut(&self_node)
+            .set_local("local_key", "1");
+        publisher.publish(&cluster_state, [&self_node, &node2].into_iter());
+        let view = reader.load();
+        assert_eq!(
+            view.node_state(&self_node).unwrap().get_local("local_key"),
+            Some("1")
+        );
+        assert!(Arc::ptr_eq(
+            &newest_view.node_states[&node2],
+            &view.node_states[&node2]
+        ));
+    }
+
+    #[test]
+    fn test_cluster_state_snapshot_publisher() {
+        let snapshot_publisher = ClusterStateSnapshotPublisher::default();
+        let mut cluster_state = ClusterState::default();
+        let node1 = ChitchatId::for_local_test(10_001);
+        cluster_state.node_state_mut(&node1).set("key", "1");
+
+        // Nothing is published until a snapshot is requested.
+        snapshot_publisher.publish(&cluster_state);
+        assert!(snapshot_publisher.published_snapshot.load().is_none());
+
+        let snapshot = snapshot_publisher.snapshot(&cluster_state);
+        assert_eq!(snapshot.node_states.len(), 1);
+        assert!(Arc::ptr_eq(
+            &snapshot,
+            &snapshot_publisher.snapshot(&cluster_state)
+        ));
+
+        // Snapshots are not taken on read, but when the cluster state is published.
+        cluster_state.node_state_mut(&node1).set("key", "2");
+        assert!(Arc::ptr_eq(
+            &snapshot,
+            &snapshot_publisher.snapshot(&cluster_state)
+        ));
+        snapshot_publisher.publish(&cluster_state);
+        let new_snapshot = snapshot_publisher.snapshot(&cluster_state);
+        assert!(!Arc::ptr_eq(&snapshot, &new_snapshot));
+        assert_eq!(new_snapshot.node_states[0].get("key"), Some("2"));
+
+        // Heartbeats alone do not trigger a new snapshot.
+        cluster_state.node_state_mut(&node1).inc_heartbeat();
+        snapshot_publisher.publish(&cluster_state);
+        assert!(Arc::ptr_eq(
+            &new_snapshot,
+            &snapshot_publisher.snapshot(&cluster_state)
+        ));
+
+        let node2 = ChitchatId::for_local_test(10_002);
+        cluster_state.node_state_mut(&node2);
+        snapshot_publisher.publish(&cluster_state);
+        let newest_snapshot = snapshot_publisher.snapshot(&cluster_state);
+        assert_eq!(newest_snapshot.node_states.len(), 2);
+        // Previously returned snapshots are immutable.
+        assert_eq!(new_snapshot.node_states.len(), 1);
+    }
+}
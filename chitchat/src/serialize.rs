@@ -78,18 +78,38 @@ impl Deserializable for u32 {
     }
 }
 
+/// `u64` values (versions, heartbeats, generation ids, and lengths) are encoded as LEB128
+/// varints: 7 bits per byte, least significant group first, with the high bit of each byte set
+/// if more bytes follow. Small values, which are the common case, take 1 or 2 bytes instead of 8.
 impl Serializable for u64 {
     fn serialize(&self, buf: &mut Vec<u8>) {
-        self.to_le_bytes().serialize(buf);
+        let mut val = *self;
+        while val >= 0x80 {
+            buf.push((val as u8) | 0x80);
+            val >>= 7;
+        }
+        buf.push(val as u8);
     }
     fn serialized_len(&self) -> usize {
-        8
+        let num_bits = (u64::BITS - self.leading_zeros()).max(1) as usize;
+        num_bits.div_ceil(7)
     }
 }
 impl Deserializable for u64 {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
-        let u64_bytes: [u8; 8] = Deserializable::deserialize(buf)?;
-        Ok(Self::from_le_bytes(u64_bytes))
+        let mut val = 0u64;
+        for shift in (0..u64::BITS).step_by(7) {
+            let byte = u8::deserialize(buf).context("failed to deserialize varint")?;
+            let bits = (byte & 0x7F) as u64;
+            if shift == 63 && bits > 1 {
+                bail!("varint overflows u64");
+            }
+            val |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+        bail!("varint is too long")
     }
 }
 
@@ -180,7 +200,7 @@ impl Serializable for String {
 
 impl Deserializable for String {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
-        let len: usize = u16::deserialize(buf)? as usize;
+        let len = usize::try_from(u64::deserialize(buf)?)?;
         let str_bytes = buf.get(..len).with_context(|| {
             format!(
                 "failed to deserialize string, buffer too short (str_len={len}, buf_len={})",
@@ -195,12 +215,12 @@ impl Deserializable for String {
 
 impl Serializable for str {
     fn serialize(&self, buf: &mut Vec<u8>) {
-        (self.len() as u16).serialize(buf);
+        (self.len() as u64).serialize(buf);
         buf.extend(self.as_bytes())
     }
 
     fn serialized_len(&self) -> usize {
-        2 + self.len()
+        (self.len() as u64).serialized_len() + self.len()
     }
 }
 
@@ -259,9 +279,12 @@ impl Serializable for ChitchatId {
 
 impl Deserializable for ChitchatId {
     fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
-        let node_id = String::deserialize(buf)?;
-        let generation_id = u64::deserialize(buf)?;
-        let gossip_advertise_addr = SocketAddr::deserialize(buf)?;
+        let node_id = String::deserialize(buf).context("failed to deserialize node id")?;
+        let generation_id = u64::deserialize(buf)
+            .with_context(|| format!("failed to deserialize generation id of node `{node_id}`"))?;
+        let gossip_advertise_addr = SocketAddr::deserialize(buf).with_context(|| {
+            format!("failed to deserialize gossip advertise address of node `{node_id}`")
+        })?;
         Ok(Self {
             node_id,
             generation_id,
@@ -295,6 +318,7 @@ impl Deserializable for Heartbeat {
 /// The stream gives the client an upperbound of what the overall payload length would
 /// be if another item was appended.
 /// This makes it possible to enforce a `mtu`.
+#[derive(Clone)]
 pub struct CompressedStreamWriter {
     output: Vec<u8>,
     // temporary buffer used for block compression.
@@ -423,7 +447,8 @@ pub fn deserialize_stream<D: Deserializable>(buf: &mut &[u8]) -> anyhow::Result<
     let mut decompressed_cursor = &decompressed_data[..];
     let mut items = Vec::new();
     while !decompressed_cursor.is_empty() {
-        let item = D::deserialize(&mut decompressed_cursor)?;
+        let item = D::deserialize(&mut decompressed_cursor)
+            .with_context(|| format!("failed to deserialize stream item #{}", items.len()))?;
         items.push(item);
     }
     Ok(items)
@@ -489,13 +514,37 @@ mod tests {
     fn test_serialize_chitchat_id() {
         test_serdeser_aux(
             &ChitchatId::new("node-id".to_string(), 1, "127.0.0.1:7280".parse().unwrap()),
-            24,
+            16,
         );
     }
 
     #[test]
     fn test_serialize_heartbeat() {
-        test_serdeser_aux(&Heartbeat(1), 8);
+        test_serdeser_aux(&Heartbeat(1), 1);
+    }
+
+    #[test]
+    fn test_serialize_u64() {
+        test_serdeser_aux(&0u64, 1);
+        test_serdeser_aux(&127u64, 1);
+        test_serdeser_aux(&128u64, 2);
+        test_serdeser_aux(&16_383u64, 2);
+        test_serdeser_aux(&16_384u64, 3);
+        test_serdeser_aux(&(u32::MAX as u64), 5);
+        test_serdeser_aux(&u64::MAX, 10);
+        assert_eq!(300u64.serialize_to_vec(), [0xAC, 0x02]);
+    }
+
+    #[test]
+    fn test_deserialize_u64_invalid() {
+        // Missing continuation byte.
+        assert!(u64::deserialize(&mut &[0x80][..]).is_err());
+        // Too many continuation bytes.
+        assert!(u64::deserialize(&mut &[0xFF; 11][..]).is_err());
+        // Overflow in the tenth byte.
+        let mut buf = [0xFF; 10];
+        buf[9] = 0x02;
+        assert!(u64::deserialize(&mut &buf[..]).is_err());
     }
 
     #[test]
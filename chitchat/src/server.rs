@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -6,17 +6,22 @@ use std::time::Duration;
 use rand::prelude::*;
 use tokio::net::lookup_host;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
-use tokio::sync::{watch, Mutex};
+use tokio::sync::{oneshot, watch, Mutex};
 use tokio::task::JoinHandle;
-use tokio::time;
-use tracing::{debug, info, warn};
+use tokio::time::{self, Instant};
+use tracing::{debug, debug_span, error, info, trace, warn, Instrument};
 
 use crate::message::ChitchatMessage;
+use crate::serialize::Serializable;
 use crate::transport::{Socket, Transport};
-use crate::{Chitchat, ChitchatConfig, ChitchatId};
+use crate::{Chitchat, ChitchatConfig, ChitchatError, ChitchatId};
 
 /// Number of nodes picked for random gossip.
-const GOSSIP_COUNT: usize = 3;
+pub(crate) const GOSSIP_COUNT: usize = 3;
+
+/// Maximum number of live peers lacking a critical key that a gossip round targets on top of the
+/// randomly selected nodes. See [`Chitchat::self_set_critical`].
+const MAX_CRITICAL_NODES_PER_ROUND: usize = GOSSIP_COUNT;
 
 /// UDP Chitchat server handler.
 ///
@@ -26,7 +31,8 @@ pub struct ChitchatHandle {
     chitchat_id: ChitchatId,
     command_tx: UnboundedSender<Command>,
     chitchat: Arc<Mutex<Chitchat>>,
-    join_handle: JoinHandle<Result<(), anyhow::Error>>,
+    gossip_round_count_rx: watch::Receiver<u64>,
+    join_handle: JoinHandle<Result<(), ChitchatError>>,
 }
 
 impl ChitchatHandle {
@@ -37,6 +43,11 @@ impl ChitchatHandle {
 
 const DNS_POLLING_DURATION: Duration = Duration::from_secs(60);
 
+/// Maximum number of inbound messages processed by the server loop each time it wakes up to
+/// receive a message. Messages already queued on the socket are processed along with the one that
+/// woke the loop up, under a single acquisition of the chitchat lock.
+const MAX_MESSAGES_PER_WAKEUP: usize = 32;
+
 async fn dns_refresh_loop(
     seed_hosts_requiring_dns: HashSet<String>,
     seed_addrs_not_requiring_resolution: HashSet<SocketAddr>,
@@ -130,30 +141,45 @@ pub async fn spawn_chitchat(
     config: ChitchatConfig,
     initial_key_values: Vec<(String, String)>,
     transport: &dyn Transport,
-) -> anyhow::Result<ChitchatHandle> {
+) -> Result<ChitchatHandle, ChitchatError> {
     let (command_tx, command_rx) = mpsc::unbounded_channel();
 
     let seed_addrs: watch::Receiver<HashSet<SocketAddr>> =
         spawn_dns_refresh_loop(&config.seed_nodes).await;
 
-    let socket = transport.open(config.listen_addr).await?;
+    let socket =
+        transport
+            .open(config.listen_addr)
+            .await
+            .map_err(|source| ChitchatError::Bind {
+                listen_addr: config.listen_addr,
+                source,
+            })?;
     let chitchat_id = config.chitchat_id.clone();
 
     let chitchat = Chitchat::with_chitchat_id_and_seeds(config, seed_addrs, initial_key_values);
     let chitchat_arc = Arc::new(Mutex::new(chitchat));
     let chitchat_arc_clone = chitchat_arc.clone();
+    let (gossip_round_count_tx, gossip_round_count_rx) = watch::channel(0);
 
     let join_handle = tokio::spawn(async move {
-        Server::new(command_rx, chitchat_arc_clone, socket)
-            .await
-            .run()
-            .await
+        Server::new(
+            command_rx,
+            chitchat_arc_clone,
+            socket,
+            gossip_round_count_tx,
+        )
+        .await
+        .run()
+        .await
+        .map_err(ChitchatError::from_transport_error)
     });
 
     Ok(ChitchatHandle {
         chitchat_id,
         command_tx,
         chitchat: chitchat_arc,
+        gossip_round_count_rx,
         join_handle,
     })
 }
@@ -171,19 +197,69 @@ impl ChitchatHandle {
     pub async fn with_chitchat<F, T>(&self, mut fun: F) -> T
     where F: FnMut(&mut Chitchat) -> T {
         let mut chitchat = self.chitchat.lock().await;
-        fun(&mut chitchat)
+        let output = fun(&mut chitchat);
+        chitchat.publish_cluster_state();
+        output
+    }
+
+    /// Returns a watcher of the number of gossip rounds completed by the server.
+    ///
+    /// The count is incremented once the self node has gossiped with all the peers selected for
+    /// the round and the liveness of the nodes has been updated, so awaiting a change is a
+    /// deterministic way to wait for the next gossip round.
+    pub fn gossip_round_count(&self) -> watch::Receiver<u64> {
+        self.gossip_round_count_rx.clone()
     }
 
     /// Shuts the server down.
-    pub async fn shutdown(self) -> Result<(), anyhow::Error> {
+    ///
+    /// Returns the error that stopped the server beforehand, if any.
+    pub async fn shutdown(self) -> Result<(), ChitchatError> {
         let _ = self.command_tx.send(Command::Shutdown);
-        self.join_handle.await?
+        self.join_handle.await.map_err(|join_error| {
+            if join_error.is_cancelled() {
+                debug!("chitchat server task was aborted");
+            } else {
+                error!(error=%join_error, "chitchat server task failed");
+            }
+            ChitchatError::ServerStopped
+        })?
     }
 
     /// Performs a Chitchat "handshake" with another UDP server.
-    pub fn gossip(&self, addr: SocketAddr) -> Result<(), anyhow::Error> {
-        self.command_tx.send(Command::Gossip(addr))?;
-        Ok(())
+    pub fn gossip(&self, addr: SocketAddr) -> Result<(), ChitchatError> {
+        self.command_tx
+            .send(Command::Gossip(addr))
+            .map_err(|_| ChitchatError::ServerStopped)
+    }
+
+    /// Performs a Chitchat "handshake" with another UDP server, and waits for the peer's SYN-ACK
+    /// to be processed, i.e. for the delta it carries to be applied and for the ACK to be sent.
+    ///
+    /// If the SYN is not sent, the call fails right away: with
+    /// [`ChitchatError::GossipThrottled`] if the gossip byte budget is spent, or with
+    /// [`ChitchatError::Io`] if the transport fails to send it. If no SYN-ACK is received within
+    /// `timeout`, e.g. because the peer is unreachable or belongs to another cluster,
+    /// [`ChitchatError::HandshakeTimeout`] is returned.
+    ///
+    /// The SYN-ACK is correlated with the SYN by the address of the peer only: the first SYN-ACK
+    /// received from `addr` after the SYN was sent completes the handshake. It may answer an
+    /// earlier SYN, e.g. one sent by the regular gossip rounds, in which case the delta it
+    /// carries can miss the updates made by the peer in the meantime.
+    pub async fn gossip_and_wait(
+        &self,
+        addr: SocketAddr,
+        timeout: Duration,
+    ) -> Result<(), ChitchatError> {
+        let (handshake_tx, handshake_rx) = oneshot::channel();
+        self.command_tx
+            .send(Command::GossipAndWait(addr, handshake_tx))
+            .map_err(|_| ChitchatError::ServerStopped)?;
+        match time::timeout(timeout, handshake_rx).await {
+            Ok(Ok(handshake_result)) => handshake_result,
+            Ok(Err(_)) => Err(ChitchatError::ServerStopped),
+            Err(_) => Err(ChitchatError::HandshakeTimeout { peer_addr: addr }),
+        }
     }
 }
 
@@ -193,6 +269,10 @@ struct Server {
     chitchat: Arc<Mutex<Chitchat>>,
     transport: Box<dyn Socket>,
     rng: SmallRng,
+    gossip_budget_opt: Option<GossipBudget>,
+    gossip_round_count_tx: watch::Sender<u64>,
+    // Callers of `ChitchatHandle::gossip_and_wait` waiting for a SYN-ACK, by peer address.
+    pending_handshakes: HashMap<SocketAddr, Vec<HandshakeSender>>,
 }
 
 impl Server {
@@ -200,13 +280,23 @@ impl Server {
         command_rx: UnboundedReceiver<Command>,
         chitchat: Arc<Mutex<Chitchat>>,
         transport: Box<dyn Socket>,
+        gossip_round_count_tx: watch::Sender<u64>,
     ) -> Self {
         let rng = SmallRng::from_rng(thread_rng()).expect("failed to seed random generator");
+        let gossip_budget_opt = chitchat
+            .lock()
+            .await
+            .config
+            .max_gossip_bytes_per_sec
+            .map(GossipBudget::new);
         Self {
             chitchat,
             command_rx,
             transport,
             rng,
+            gossip_budget_opt,
+            gossip_round_count_tx,
+            pending_handshakes: HashMap::new(),
         }
     }
 
@@ -218,7 +308,7 @@ impl Server {
             tokio::select! {
                 result = self.transport.recv() => match result {
                     Ok((from_addr, message)) => {
-                        let _ = self.handle_message(from_addr, message).await;
+                        self.handle_messages(from_addr, message).await?;
                     }
                     Err(err) => return Err(err),
                 },
@@ -227,7 +317,22 @@ impl Server {
                 },
                 command = self.command_rx.recv() => match command {
                     Some(Command::Gossip(addr)) => {
-                        let _ = self.gossip(addr).await;
+                        let _ = self.gossip(addr, GossipTarget::Requested).await;
+                    },
+                    Some(Command::GossipAndWait(addr, handshake_tx)) => {
+                        // Messages are handled by this task too, so the SYN-ACK cannot be
+                        // processed before the caller is registered.
+                        match self.gossip(addr, GossipTarget::Requested).await {
+                            Ok(true) => self.add_pending_handshake(addr, handshake_tx),
+                            Ok(false) => {
+                                let error = ChitchatError::GossipThrottled { peer_addr: addr };
+                                let _ = handshake_tx.send(Err(error));
+                            }
+                            Err(error) => {
+                                let error = ChitchatError::from_transport_error(error);
+                                let _ = handshake_tx.send(Err(error));
+                            }
+                        }
                     },
                     Some(Command::Shutdown) | None => break,
                 }
@@ -236,27 +341,109 @@ impl Server {
         Ok(())
     }
 
-    /// Processes a single UDP datagram.
-    async fn handle_message(
+    /// Processes the given message, along with up to [`MAX_MESSAGES_PER_WAKEUP`] - 1 messages
+    /// already queued on the socket.
+    ///
+    /// Only returns an error if the transport is broken.
+    async fn handle_messages(
         &mut self,
         from_addr: SocketAddr,
         message: ChitchatMessage,
     ) -> anyhow::Result<()> {
-        // Handle gossip message from other servers.
-        let response = self.chitchat.lock().await.process_message(message);
-        // Send reply if necessary.
-        if let Some(message) = response {
-            self.transport.send(from_addr, message).await?;
+        let mut messages = vec![(from_addr, message)];
+        while messages.len() < MAX_MESSAGES_PER_WAKEUP {
+            let Some(message) = self.transport.try_recv()? else {
+                break;
+            };
+            messages.push(message);
+        }
+        // Handle gossip messages from other servers.
+        let mut syn_ack_addrs = Vec::new();
+        let mut response_budget = self
+            .gossip_budget_opt
+            .as_mut()
+            .map_or(usize::MAX, GossipBudget::remaining);
+        let responses: Vec<(SocketAddr, ChitchatMessage)> = {
+            let mut chitchat_guard = self.chitchat.lock().await;
+            let messages: Vec<(SocketAddr, ChitchatMessage)> = messages
+                .into_iter()
+                .filter_map(|(from_addr, message)| {
+                    let message = chitchat_guard.open_message(from_addr, message)?;
+                    Some((from_addr, message))
+                })
+                .collect();
+            if !messages.is_empty() {
+                // Receiving messages is a proof of life worth propagating in the responses.
+                chitchat_guard.tick_heartbeat();
+            }
+            let responses = messages
+                .into_iter()
+                .filter_map(|(from_addr, message)| {
+                    if matches!(message.unidentified(), ChitchatMessage::SynAck { .. }) {
+                        syn_ack_addrs.push(from_addr);
+                    }
+                    let response = chitchat_guard.process_batched_message(
+                        from_addr,
+                        message,
+                        response_budget,
+                    )?;
+                    if matches!(response, ChitchatMessage::BadCluster)
+                        && !chitchat_guard.should_respond_bad_cluster(from_addr)
+                    {
+                        return None;
+                    }
+                    let response = chitchat_guard.seal_message(response);
+                    response_budget = response_budget.saturating_sub(response.serialized_len());
+                    Some((from_addr, response))
+                })
+                .collect();
+            chitchat_guard.publish_cluster_state();
+            responses
+        };
+        // Send replies.
+        for (to_addr, message) in responses {
+            if let Some(gossip_budget) = &mut self.gossip_budget_opt {
+                gossip_budget.spend(message.serialized_len());
+            }
+            if let Err(error) = self.transport.send(to_addr, message).await {
+                debug!(error=?error, to_addr=%to_addr, "failed to send reply");
+            }
+        }
+        for syn_ack_addr in syn_ack_addrs {
+            self.complete_pending_handshakes(syn_ack_addr);
         }
         Ok(())
     }
 
+    fn add_pending_handshake(&mut self, addr: SocketAddr, handshake_tx: HandshakeSender) {
+        // Forget about the callers that gave up waiting for a peer that never answered.
+        self.pending_handshakes.retain(|_addr, handshake_txs| {
+            handshake_txs.retain(|handshake_tx| !handshake_tx.is_closed());
+            !handshake_txs.is_empty()
+        });
+        self.pending_handshakes
+            .entry(addr)
+            .or_default()
+            .push(handshake_tx);
+    }
+
+    fn complete_pending_handshakes(&mut self, addr: SocketAddr) {
+        let Some(handshake_txs) = self.pending_handshakes.remove(&addr) else {
+            return;
+        };
+        for handshake_tx in handshake_txs {
+            let _ = handshake_tx.send(Ok(()));
+        }
+    }
+
     /// Gossip to multiple randomly chosen nodes.
     async fn gossip_multiple(&mut self) {
         // Gossip with live nodes & probabilistically include a random dead node
         let mut chitchat_guard = self.chitchat.lock().await;
         let cluster_state = chitchat_guard.cluster_state();
 
+        // Gossip targets are always reached through their gossip advertise address, never through
+        // the RPC address they may advertise in their node state.
         let peer_nodes = cluster_state
             .nodes()
             .filter(|chitchat_id| *chitchat_id != chitchat_guard.self_chitchat_id())
@@ -276,64 +463,258 @@ impl Server {
             .into_iter()
             .filter(|addr| *addr != chitchat_guard.self_chitchat_id().gossip_advertise_addr)
             .collect();
-        let (selected_nodes, random_dead_node_opt, random_seed_node_opt) = select_nodes_for_gossip(
+        let num_peer_nodes = peer_nodes.len();
+        let num_live_nodes = live_nodes.len();
+        let num_dead_nodes = dead_nodes.len();
+        let num_seed_nodes = seed_nodes.len();
+        let dead_node_gossip_probability_multiplier = chitchat_guard
+            .config
+            .dead_node_gossip_probability_multiplier;
+        let min_seed_nodes_per_round = chitchat_guard.config.min_seed_nodes_per_round;
+        let (selected_nodes, random_dead_node_opt, selected_seed_nodes) = select_nodes_for_gossip(
             &mut self.rng,
             peer_nodes,
             live_nodes,
             dead_nodes,
             seed_nodes,
+            dead_node_gossip_probability_multiplier,
+            min_seed_nodes_per_round,
         );
 
-        chitchat_guard.update_self_heartbeat();
+        let is_selected = |addr: &SocketAddr| {
+            selected_nodes.contains(addr)
+                || selected_seed_nodes.contains(addr)
+                || random_dead_node_opt == Some(*addr)
+        };
+        // Static peers are gossiped with every round, on top of the randomly selected nodes.
+        let self_addr = chitchat_guard.self_chitchat_id().gossip_advertise_addr;
+        let static_nodes: Vec<SocketAddr> = chitchat_guard
+            .config
+            .static_peers
+            .iter()
+            .copied()
+            .filter(|addr| *addr != self_addr && !is_selected(addr))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        // Live peers that have not acknowledged a critical key yet are gossiped with on top of
+        // the randomly selected nodes. They are sampled so that a large cluster does not turn a
+        // critical key into a broadcast.
+        let critical_nodes: Vec<SocketAddr> = chitchat_guard
+            .update_critical_keys()
+            .into_iter()
+            .filter(|addr| !is_selected(addr) && !static_nodes.contains(addr))
+            .choose_multiple(&mut self.rng, MAX_CRITICAL_NODES_PER_ROUND);
+
+        chitchat_guard.record_gossip_round();
+        chitchat_guard.tick_heartbeat();
         chitchat_guard.gc_keys_marked_for_deletion();
 
         // Drop lock to prevent deadlock in [`UdpSocket::gossip`].
         drop(chitchat_guard);
 
-        for node in selected_nodes {
-            if let Err(error) = self.gossip(node).await {
-                warn!(error=?error, node_address=%node, "Failed to gossip with live node.");
-            }
+        // A node alone in its cluster, e.g. a single-node cluster with no seeds, has no one to
+        // gossip with. The round is still recorded and waits for the next gossip tick, like any
+        // other round.
+        if selected_nodes.is_empty()
+            && random_dead_node_opt.is_none()
+            && selected_seed_nodes.is_empty()
+            && static_nodes.is_empty()
+            && critical_nodes.is_empty()
+        {
+            trace!("no peer to gossip with, gossip round is a no-op");
         }
-        if let Some(random_dead_node) = random_dead_node_opt {
-            if let Err(error) = self.gossip(random_dead_node).await {
-                debug!(error=?error, node_address=%random_dead_node, "Failed to gossip with dead node.");
+
+        let gossip_round_span = debug_span!(
+            "gossip_round",
+            num_peer_nodes,
+            num_live_nodes,
+            num_dead_nodes,
+            num_seed_nodes,
+            selected_live_nodes = ?selected_nodes,
+            selected_dead_node = ?random_dead_node_opt,
+            selected_seed_nodes = ?selected_seed_nodes,
+            static_nodes = ?static_nodes,
+            critical_nodes = ?critical_nodes,
+        );
+        async {
+            for node in selected_nodes {
+                if let Err(error) = self.gossip(node, GossipTarget::Live).await {
+                    warn!(error=?error, node_address=%node, "Failed to gossip with live node.");
+                }
             }
-        }
-        if let Some(random_seed_node) = random_seed_node_opt {
-            if let Err(error) = self.gossip(random_seed_node).await {
-                warn!(error=?error, node_address=%random_seed_node, "Failed to gossip with seed node.");
+            if let Some(random_dead_node) = random_dead_node_opt {
+                if let Err(error) = self.gossip(random_dead_node, GossipTarget::Dead).await {
+                    debug!(error=?error, node_address=%random_dead_node, "Failed to gossip with dead node.");
+                }
+            }
+            for seed_node in selected_seed_nodes {
+                if let Err(error) = self.gossip(seed_node, GossipTarget::Seed).await {
+                    warn!(error=?error, node_address=%seed_node, "Failed to gossip with seed node.");
+                }
+            }
+            for static_node in static_nodes {
+                if let Err(error) = self.gossip(static_node, GossipTarget::Static).await {
+                    warn!(error=?error, node_address=%static_node, "Failed to gossip with static peer.");
+                }
+            }
+            for critical_node in critical_nodes {
+                if let Err(error) = self.gossip(critical_node, GossipTarget::Critical).await {
+                    warn!(error=?error, node_address=%critical_node, "Failed to gossip with node lacking a critical key.");
+                }
             }
         }
+        .instrument(gossip_round_span)
+        .await;
         // Update nodes liveness.
         let mut chitchat_guard = self.chitchat.lock().await;
         chitchat_guard.update_nodes_liveness();
+        drop(chitchat_guard);
+        self.gossip_round_count_tx
+            .send_modify(|gossip_round_count| *gossip_round_count += 1);
     }
 
     /// Gossips with another peer.
-    async fn gossip(&mut self, addr: SocketAddr) -> anyhow::Result<()> {
-        let syn = self.chitchat.lock().await.create_syn_message();
-        self.transport.send(addr, syn).await?;
-        Ok(())
+    ///
+    /// Returns `false` if the SYN was skipped because the gossip byte budget is spent.
+    async fn gossip(&mut self, addr: SocketAddr, target: GossipTarget) -> anyhow::Result<bool> {
+        let syn = {
+            let mut chitchat_guard = self.chitchat.lock().await;
+            let syn = chitchat_guard.seal_message(chitchat_guard.create_syn_message());
+            if let Some(gossip_budget) = &mut self.gossip_budget_opt {
+                if !gossip_budget.try_spend(syn.serialized_len()) {
+                    debug!(target_addr=%addr, target=target.as_str(), "gossip byte budget spent, SYN skipped");
+                    chitchat_guard.record_throttled_syn();
+                    return Ok(false);
+                }
+            }
+            syn
+        };
+        let num_bytes = syn.serialized_len();
+        let gossip_span = debug_span!(
+            "gossip",
+            target_addr = %addr,
+            target = target.as_str(),
+            message_type = "syn",
+            num_bytes,
+        );
+        let send_result = self
+            .transport
+            .send(addr, syn)
+            .instrument(gossip_span.clone())
+            .await;
+        gossip_span.in_scope(|| match &send_result {
+            Ok(()) => debug!(result = "ok", "sent gossip message"),
+            Err(error) => debug!(result = "error", error = %error, "failed to send gossip message"),
+        });
+        send_result.map(|()| true)
+    }
+}
+
+/// Number of bytes sent during the current one-second window, capped by
+/// [`ChitchatConfig::max_gossip_bytes_per_sec`].
+struct GossipBudget {
+    max_bytes_per_sec: u64,
+    window_start: Instant,
+    num_bytes_sent: u64,
+}
+
+impl GossipBudget {
+    fn new(max_bytes_per_sec: u64) -> Self {
+        GossipBudget {
+            max_bytes_per_sec,
+            window_start: Instant::now(),
+            num_bytes_sent: 0,
+        }
+    }
+
+    fn start_new_window_if_elapsed(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.num_bytes_sent = 0;
+        }
+    }
+
+    /// Records `num_bytes` as sent and returns `true` if they fit in the remaining budget.
+    /// Otherwise, returns `false` and leaves the budget untouched.
+    fn try_spend(&mut self, num_bytes: usize) -> bool {
+        self.start_new_window_if_elapsed();
+        if self.num_bytes_sent + num_bytes as u64 > self.max_bytes_per_sec {
+            return false;
+        }
+        self.num_bytes_sent += num_bytes as u64;
+        true
+    }
+
+    /// Returns the number of bytes that can still be sent during the current window.
+    fn remaining(&mut self) -> usize {
+        self.start_new_window_if_elapsed();
+        self.max_bytes_per_sec.saturating_sub(self.num_bytes_sent) as usize
+    }
+
+    /// Records `num_bytes` as sent, even if they exceed the remaining budget.
+    fn spend(&mut self, num_bytes: usize) {
+        self.start_new_window_if_elapsed();
+        self.num_bytes_sent += num_bytes as u64;
+    }
+}
+
+/// Reason why a peer was picked for gossip, reported in the gossip logs.
+#[derive(Clone, Copy, Debug)]
+enum GossipTarget {
+    Live,
+    Dead,
+    Seed,
+    /// Gossip requested through [`ChitchatHandle::gossip`].
+    Requested,
+    /// Static peer, see [`ChitchatConfig::static_peers`].
+    Static,
+    /// Live peer that has not acknowledged a critical key yet. See
+    /// [`Chitchat::self_set_critical`].
+    Critical,
+}
+
+impl GossipTarget {
+    fn as_str(self) -> &'static str {
+        match self {
+            GossipTarget::Live => "live",
+            GossipTarget::Dead => "dead",
+            GossipTarget::Seed => "seed",
+            GossipTarget::Requested => "requested",
+            GossipTarget::Static => "static",
+            GossipTarget::Critical => "critical",
+        }
     }
 }
 
 #[derive(Debug)]
 enum Command {
     Gossip(SocketAddr),
+    GossipAndWait(SocketAddr, HandshakeSender),
     Shutdown,
 }
 
+/// Notifies a caller of [`ChitchatHandle::gossip_and_wait`] of the outcome of the handshake.
+type HandshakeSender = oneshot::Sender<Result<(), ChitchatError>>;
+
 fn select_nodes_for_gossip<R>(
     rng: &mut R,
     peer_nodes: HashSet<SocketAddr>,
     live_nodes: HashSet<SocketAddr>,
     dead_nodes: HashSet<SocketAddr>,
     seed_nodes: HashSet<SocketAddr>,
-) -> (Vec<SocketAddr>, Option<SocketAddr>, Option<SocketAddr>)
+    dead_node_gossip_probability_multiplier: f64,
+    min_seed_nodes_per_round: usize,
+) -> (Vec<SocketAddr>, Option<SocketAddr>, Vec<SocketAddr>)
 where
     R: Rng + ?Sized,
 {
+    // Live and dead nodes are peer nodes: with no peer and no seed node, there is nothing to
+    // select from.
+    if peer_nodes.is_empty() && seed_nodes.is_empty() {
+        return (Vec::new(), None, Vec::new());
+    }
     let live_nodes_count = live_nodes.len();
     let dead_nodes_count = dead_nodes.len();
 
@@ -348,17 +729,20 @@ where
     .cloned()
     .choose_multiple(rng, GOSSIP_COUNT);
 
-    let mut has_gossiped_with_a_seed_node = false;
-    for chitchat_id in &nodes {
-        if seed_nodes.contains(chitchat_id) {
-            has_gossiped_with_a_seed_node = true;
-            break;
-        }
-    }
+    let num_gossiped_seed_nodes = nodes
+        .iter()
+        .filter(|node| seed_nodes.contains(node))
+        .count();
+    let has_gossiped_with_a_seed_node = num_gossiped_seed_nodes > 0;
 
     // Select a dead node for potential gossip.
-    let random_dead_node_opt: Option<SocketAddr> =
-        select_dead_node_to_gossip_with(rng, &dead_nodes, live_nodes_count, dead_nodes_count);
+    let random_dead_node_opt: Option<SocketAddr> = select_dead_node_to_gossip_with(
+        rng,
+        &dead_nodes,
+        live_nodes_count,
+        dead_nodes_count,
+        dead_node_gossip_probability_multiplier,
+    );
 
     // Select a seed node for potential gossip.
     // It prevents network partition caused by the number of seeds.
@@ -369,21 +753,45 @@ where
         } else {
             None
         };
-
-    (nodes, random_dead_node_opt, random_seed_node_opt)
+    let mut selected_seed_nodes: Vec<SocketAddr> = random_seed_node_opt.into_iter().collect();
+
+    // Top up the selected seed nodes with distinct seed nodes to reach the configured minimum.
+    if min_seed_nodes_per_round > 1 {
+        let num_selected_seed_nodes = num_gossiped_seed_nodes
+            + selected_seed_nodes
+                .iter()
+                .filter(|seed_node| !nodes.contains(seed_node))
+                .count();
+        let num_missing_seed_nodes =
+            min_seed_nodes_per_round.saturating_sub(num_selected_seed_nodes);
+        let additional_seed_nodes = seed_nodes
+            .iter()
+            .filter(|seed_node| {
+                !nodes.contains(seed_node) && !selected_seed_nodes.contains(seed_node)
+            })
+            .cloned()
+            .choose_multiple(rng, num_missing_seed_nodes);
+        selected_seed_nodes.extend(additional_seed_nodes);
+    }
+    (nodes, random_dead_node_opt, selected_seed_nodes)
 }
 
 /// Selects a dead node to gossip with, with some probability.
+///
+/// The probability is `dead_nodes_count / (live_nodes_count + 1)`, scaled by
+/// `probability_multiplier`.
 fn select_dead_node_to_gossip_with<R>(
     rng: &mut R,
     dead_nodes: &HashSet<SocketAddr>,
     live_nodes_count: usize,
     dead_nodes_count: usize,
+    probability_multiplier: f64,
 ) -> Option<SocketAddr>
 where
     R: Rng + ?Sized,
 {
-    let selection_probability = dead_nodes_count as f64 / (live_nodes_count + 1) as f64;
+    let selection_probability =
+        probability_multiplier * dead_nodes_count as f64 / (live_nodes_count + 1) as f64;
     if selection_probability > rng.gen::<f64>() {
         return dead_nodes.iter().choose(rng).cloned();
     }
@@ -417,9 +825,13 @@ mod tests {
     use tokio_stream::{Stream, StreamExt};
 
     use super::*;
+    use crate::auth::MessageAuthenticator;
     use crate::message::ChitchatMessage;
     use crate::transport::{ChannelTransport, Transport};
-    use crate::{Heartbeat, NodeState, MAX_UDP_DATAGRAM_PAYLOAD_SIZE};
+    use crate::{
+        unix_timestamp_millis, BadClusterQuarantineConfig, Heartbeat, MessageTimestampConfig,
+        NodeState, MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+    };
 
     #[derive(Debug, Default)]
     struct RngForTest {
@@ -469,7 +881,7 @@ mod tests {
         server.gossip(peer_addr).unwrap();
         let (from, message) = timeout(peer_transport.recv()).await.unwrap();
         assert_eq!(from, test_addr);
-        match message {
+        match message.unidentified() {
             ChitchatMessage::Syn { cluster_id, digest } => {
                 assert_eq!(cluster_id, "default-cluster");
                 assert_eq!(digest.node_digests.len(), 1);
@@ -478,6 +890,41 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_chitchat_handle_errors() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let test_config = ChitchatConfig::for_test(1113);
+        let listen_addr = test_config.listen_addr;
+        let server = spawn_chitchat(test_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+        let error = spawn_chitchat(ChitchatConfig::for_test(1113), Vec::new(), &transport)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            ChitchatError::Bind { listen_addr: addr, .. } if addr == listen_addr
+        ));
+
+        server.abort();
+        let peer_addr: SocketAddr = ([127u8, 0u8, 0u8, 1u8], 1111u16).into();
+        timeout(async {
+            while server.gossip(peer_addr).is_ok() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await;
+        assert!(matches!(
+            server.gossip(peer_addr),
+            Err(ChitchatError::ServerStopped)
+        ));
+        assert!(matches!(
+            server.shutdown().await,
+            Err(ChitchatError::ServerStopped)
+        ));
+    }
+
     #[cfg(test)]
     fn empty_seeds() -> watch::Receiver<HashSet<SocketAddr>> {
         watch::channel(Default::default()).1
@@ -506,12 +953,156 @@ mod tests {
 
         let (from1, msg) = transport2.recv().await.unwrap();
         assert_eq!(from1, addr1);
-        match msg {
+        match msg.unidentified() {
             ChitchatMessage::SynAck { .. } => (),
             message => panic!("unexpected message: {message:?}"),
         }
     }
 
+    #[tokio::test]
+    async fn test_syn_ack_to_queued_syns() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let server_config = ChitchatConfig::for_test(2229);
+        let server_addr = server_config.chitchat_id.gossip_advertise_addr;
+        let _handler = spawn_chitchat(server_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        // More SYN messages than the server processes per wakeup are queued before the server
+        // gets a chance to process any of them.
+        let num_peers = MAX_MESSAGES_PER_WAKEUP + 8;
+        let mut peers = Vec::with_capacity(num_peers);
+        for peer_id in 0..num_peers {
+            let peer_config = ChitchatConfig::for_test(3000 + peer_id as u16);
+            let peer_transport = transport
+                .open(peer_config.chitchat_id.gossip_advertise_addr)
+                .await
+                .unwrap();
+            let peer = Chitchat::with_chitchat_id_and_seeds(peer_config, empty_seeds(), Vec::new());
+            peers.push((peer, peer_transport));
+        }
+        for (peer, peer_transport) in &mut peers {
+            let syn = peer.create_syn_message();
+            peer_transport.send(server_addr, syn).await.unwrap();
+        }
+        for (_peer, peer_transport) in &mut peers {
+            let (from_addr, syn_ack) = timeout(peer_transport.recv()).await.unwrap();
+            assert_eq!(from_addr, server_addr);
+            assert!(matches!(
+                syn_ack.unidentified(),
+                ChitchatMessage::SynAck { .. }
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gossip_and_wait() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let mut config1 = ChitchatConfig::for_test(2231);
+        config1.gossip_interval = Duration::from_secs(3_600);
+        let chitchat_id1 = config1.chitchat_id.clone();
+        let addr1 = chitchat_id1.gossip_advertise_addr;
+        let handle1 = spawn_chitchat(
+            config1,
+            vec![("key".to_string(), "value".to_string())],
+            &transport,
+        )
+        .await
+        .unwrap();
+        let mut config2 = ChitchatConfig::for_test(2232);
+        config2.gossip_interval = Duration::from_secs(3_600);
+        let handle2 = spawn_chitchat(config2, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        handle2
+            .gossip_and_wait(addr1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        // The delta carried by the SYN-ACK was applied when `gossip_and_wait` returned.
+        let value = handle2
+            .with_chitchat(|chitchat| {
+                chitchat
+                    .node_state(&chitchat_id1)
+                    .and_then(|node_state| node_state.get("key"))
+                    .map(str::to_string)
+            })
+            .await;
+        assert_eq!(value.as_deref(), Some("value"));
+
+        let unbound_addr: SocketAddr = ([127u8, 0u8, 0u8, 1u8], 2233u16).into();
+        let error = handle2
+            .gossip_and_wait(unbound_addr, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ChitchatError::HandshakeTimeout { peer_addr } if peer_addr == unbound_addr
+        ));
+
+        handle1.shutdown().await.unwrap();
+        let error = handle2
+            .gossip_and_wait(addr1, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChitchatError::HandshakeTimeout { .. }));
+
+        handle2.abort();
+        timeout(async {
+            while handle2.gossip(addr1).is_ok() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await;
+        let error = handle2
+            .gossip_and_wait(addr1, Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChitchatError::ServerStopped));
+    }
+
+    #[tokio::test]
+    async fn test_gossip_and_wait_throttled() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let config1 = ChitchatConfig::for_test(2234);
+        let addr1 = config1.chitchat_id.gossip_advertise_addr;
+        let _handle1 = spawn_chitchat(config1, Vec::new(), &transport)
+            .await
+            .unwrap();
+        let mut config2 = ChitchatConfig::for_test(2235);
+        config2.gossip_interval = Duration::from_secs(3_600);
+        // The budget is too small for any SYN message.
+        config2.max_gossip_bytes_per_sec = Some(1);
+        let handle2 = spawn_chitchat(config2, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        // The call fails right away instead of waiting for the timeout.
+        let error = timeout(handle2.gossip_and_wait(addr1, Duration::from_secs(3_600)))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ChitchatError::GossipThrottled { peer_addr } if peer_addr == addr1
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gossip_and_wait_mtu() {
+        // The MTU is too small for any SYN message.
+        let transport = ChannelTransport::with_mtu(10);
+        let mut config = ChitchatConfig::for_test(2236);
+        config.gossip_interval = Duration::from_secs(3_600);
+        let handle = spawn_chitchat(config, Vec::new(), &transport)
+            .await
+            .unwrap();
+        let peer_addr: SocketAddr = ([127, 0, 0, 1], 2239).into();
+        let error = timeout(handle.gossip_and_wait(peer_addr, Duration::from_secs(3_600)))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChitchatError::Mtu { mtu: 10, .. }));
+    }
+
     #[tokio::test]
     async fn test_syn_bad_cluster() {
         let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
@@ -540,6 +1131,169 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_syn_authenticated() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let mut client_config = ChitchatConfig::for_test(2228);
+        client_config.message_auth_secret = Some(b"secret".to_vec());
+        let mut client_transport = transport
+            .open(client_config.chitchat_id.gossip_advertise_addr)
+            .await
+            .unwrap();
+        let mut client =
+            Chitchat::with_chitchat_id_and_seeds(client_config, empty_seeds(), Vec::new());
+
+        let mut server_config = ChitchatConfig::for_test(2227);
+        server_config.message_auth_secret = Some(b"secret".to_vec());
+        let server_addr = server_config.chitchat_id.gossip_advertise_addr;
+        let server_handle = spawn_chitchat(server_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        // Messages without a valid authentication code are dropped.
+        let syn = client.create_syn_message();
+        client_transport.send(server_addr, syn).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), client_transport.recv())
+                .await
+                .is_err()
+        );
+        let num_rejected_messages = server_handle
+            .with_chitchat(|chitchat| chitchat.num_rejected_unauthenticated_messages())
+            .await;
+        assert_eq!(num_rejected_messages, 1);
+
+        let syn = client.seal_message(client.create_syn_message());
+        client_transport.send(server_addr, syn).await.unwrap();
+        let (from_addr, syn_ack) = timeout(client_transport.recv()).await.unwrap();
+        assert!(matches!(syn_ack, ChitchatMessage::Authenticated { .. }));
+        let syn_ack = client.open_message(from_addr, syn_ack).unwrap();
+        assert!(matches!(
+            syn_ack.unidentified(),
+            ChitchatMessage::SynAck { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_syn_timestamped() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let mut client_config = ChitchatConfig::for_test(2238);
+        client_config.message_auth_secret = Some(b"secret".to_vec());
+        client_config.message_timestamp_config = Some(MessageTimestampConfig::default());
+        let mut client_transport = transport
+            .open(client_config.chitchat_id.gossip_advertise_addr)
+            .await
+            .unwrap();
+        let mut client =
+            Chitchat::with_chitchat_id_and_seeds(client_config, empty_seeds(), Vec::new());
+        let authenticator = MessageAuthenticator::new(b"secret");
+
+        let mut server_config = ChitchatConfig::for_test(2237);
+        server_config.message_auth_secret = Some(b"secret".to_vec());
+        server_config.message_timestamp_config = Some(MessageTimestampConfig {
+            max_message_age: Duration::from_secs(60),
+        });
+        let server_addr = server_config.chitchat_id.gossip_advertise_addr;
+        let server_handle = spawn_chitchat(server_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        // Messages without a timestamp, or with a timestamp out of the window, are dropped.
+        let now_millis = unix_timestamp_millis();
+        for unix_timestamp_millis_opt in
+            [None, Some(now_millis - 120_000), Some(now_millis + 120_000)]
+        {
+            let syn = client.create_syn_message();
+            let syn = if let Some(unix_timestamp_millis) = unix_timestamp_millis_opt {
+                ChitchatMessage::Timestamped {
+                    unix_timestamp_millis,
+                    message: Box::new(syn),
+                }
+            } else {
+                syn
+            };
+            client_transport
+                .send(server_addr, authenticator.seal(&syn))
+                .await
+                .unwrap();
+        }
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), client_transport.recv())
+                .await
+                .is_err()
+        );
+        let num_rejected_messages = server_handle
+            .with_chitchat(|chitchat| chitchat.num_rejected_stale_messages())
+            .await;
+        assert_eq!(num_rejected_messages, 3);
+
+        // Messages within the clock skew window are accepted.
+        let syn = ChitchatMessage::Timestamped {
+            unix_timestamp_millis: now_millis - 30_000,
+            message: Box::new(client.create_syn_message()),
+        };
+        client_transport
+            .send(server_addr, authenticator.seal(&syn))
+            .await
+            .unwrap();
+        let (from_addr, syn_ack) = timeout(client_transport.recv()).await.unwrap();
+        // The response is timestamped as well.
+        let syn_ack = client.open_message(from_addr, syn_ack).unwrap();
+        assert!(matches!(
+            syn_ack.unidentified(),
+            ChitchatMessage::SynAck { .. }
+        ));
+        assert_eq!(client.num_rejected_stale_messages(), 0);
+
+        let sealed_message = client.seal_message(ChitchatMessage::BadCluster);
+        assert!(matches!(
+            authenticator.open(sealed_message).unwrap(),
+            ChitchatMessage::Timestamped { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_syn_bad_cluster_quarantine() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let mut outsider_config = ChitchatConfig::for_test(2226);
+        outsider_config.cluster_id = "another-cluster".to_string();
+        let mut outsider_transport = transport
+            .open(outsider_config.chitchat_id.gossip_advertise_addr)
+            .await
+            .unwrap();
+        let outsider =
+            Chitchat::with_chitchat_id_and_seeds(outsider_config, empty_seeds(), Vec::new());
+
+        let mut server_config = ChitchatConfig::for_test(2225);
+        server_config.bad_cluster_quarantine_config = Some(BadClusterQuarantineConfig {
+            max_bad_cluster_responses: 2,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(60),
+        });
+        let server_addr = server_config.chitchat_id.gossip_advertise_addr;
+        let server_handle = spawn_chitchat(server_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            let syn = outsider.create_syn_message();
+            outsider_transport.send(server_addr, syn).await.unwrap();
+            let (_from_addr, syn_ack) = timeout(outsider_transport.recv()).await.unwrap();
+            assert_eq!(syn_ack, ChitchatMessage::BadCluster);
+        }
+        let syn = outsider.create_syn_message();
+        outsider_transport.send(server_addr, syn).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), outsider_transport.recv())
+                .await
+                .is_err()
+        );
+        let num_quarantined_addrs = server_handle
+            .with_chitchat(|chitchat| chitchat.num_quarantined_addrs())
+            .await;
+        assert_eq!(num_quarantined_addrs, 1);
+    }
+
     #[tokio::test]
     async fn test_seeding() {
         let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
@@ -557,12 +1311,131 @@ mod tests {
         let (from, message) = timeout(seed_transport.recv()).await.unwrap();
         assert_eq!(from, client_addr);
 
-        match message {
+        match message.unidentified() {
             ChitchatMessage::Syn { .. } => (),
             message => panic!("unexpected message: {message:?}"),
         }
     }
 
+    #[tokio::test]
+    async fn test_static_peers() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let static_peer_addr: SocketAddr = ([127u8, 0u8, 0u8, 1u8], 5553u16).into();
+        let mut static_peer_transport = transport.open(static_peer_addr).await.unwrap();
+
+        let mut client_config = ChitchatConfig::for_test(5554);
+        let client_addr = client_config.chitchat_id.gossip_advertise_addr;
+        client_config.static_peers = vec![static_peer_addr, client_addr];
+        let _handler = spawn_chitchat(client_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+
+        // The static peer is unknown and never answers, but it is gossiped with every round.
+        for _ in 0..3 {
+            let (from, message) = timeout(static_peer_transport.recv()).await.unwrap();
+            assert_eq!(from, client_addr);
+            assert!(matches!(
+                message.unidentified(),
+                ChitchatMessage::Syn { .. }
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gossip_round_count() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let static_peer_addr: SocketAddr = ([127u8, 0u8, 0u8, 1u8], 5557u16).into();
+        let mut static_peer_transport = transport.open(static_peer_addr).await.unwrap();
+
+        let mut client_config = ChitchatConfig::for_test(5558);
+        client_config.static_peers = vec![static_peer_addr];
+        let handle = spawn_chitchat(client_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+        let mut gossip_round_count_rx = handle.gossip_round_count();
+
+        for _ in 0..2 {
+            timeout(gossip_round_count_rx.changed()).await.unwrap();
+            // The static peer was gossiped with during the round that just completed.
+            let (_, message) = timeout(static_peer_transport.recv()).await.unwrap();
+            assert!(matches!(
+                message.unidentified(),
+                ChitchatMessage::Syn { .. }
+            ));
+        }
+        let gossip_round_count = *gossip_round_count_rx.borrow();
+        assert!(gossip_round_count >= 2);
+        assert!(handle.chitchat().lock().await.num_gossip_rounds() >= gossip_round_count);
+        handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gossip_empty_cluster() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let config = ChitchatConfig::for_test(5559);
+        let gossip_interval = config.gossip_interval;
+        let start = Instant::now();
+        let handle = spawn_chitchat(config, Vec::new(), &transport)
+            .await
+            .unwrap();
+        let mut gossip_round_count_rx = handle.gossip_round_count();
+        for _ in 0..2 {
+            timeout(gossip_round_count_rx.changed()).await.unwrap();
+        }
+        // Gossip rounds keep happening at the gossip interval pace, without spinning.
+        tokio::time::sleep(gossip_interval * 4).await;
+        let gossip_round_count = *gossip_round_count_rx.borrow();
+        let max_gossip_round_count =
+            (start.elapsed().as_millis() / gossip_interval.as_millis()) as u64 + 1;
+        assert!(gossip_round_count >= 2);
+        assert!(gossip_round_count <= max_gossip_round_count);
+        {
+            let chitchat_guard = handle.chitchat();
+            let chitchat_guard = chitchat_guard.lock().await;
+            assert_eq!(chitchat_guard.num_nodes(), 1);
+            assert_eq!(chitchat_guard.num_live_nodes(), 1);
+        }
+        handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gossip_budget() {
+        tokio::time::pause();
+        let mut gossip_budget = GossipBudget::new(100);
+        assert!(gossip_budget.try_spend(60));
+        assert_eq!(gossip_budget.remaining(), 40);
+        assert!(!gossip_budget.try_spend(41));
+        assert!(gossip_budget.try_spend(40));
+        // Responses are always sent.
+        gossip_budget.spend(10);
+        assert_eq!(gossip_budget.remaining(), 0);
+        assert!(!gossip_budget.try_spend(1));
+
+        tokio::time::advance(Duration::from_millis(999)).await;
+        assert!(!gossip_budget.try_spend(1));
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(gossip_budget.try_spend(100));
+    }
+
+    #[tokio::test]
+    async fn test_max_gossip_bytes_per_sec() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
+        let static_peer_addr: SocketAddr = ([127u8, 0u8, 0u8, 1u8], 5555u16).into();
+        let mut static_peer_transport = transport.open(static_peer_addr).await.unwrap();
+
+        // The budget is too small for any SYN message.
+        let mut client_config = ChitchatConfig::for_test(5556);
+        client_config.static_peers = vec![static_peer_addr];
+        client_config.max_gossip_bytes_per_sec = Some(1);
+        let handle = spawn_chitchat(client_config, Vec::new(), &transport)
+            .await
+            .unwrap();
+        let recv_result =
+            tokio::time::timeout(Duration::from_millis(300), static_peer_transport.recv()).await;
+        assert!(recv_result.is_err());
+        assert!(handle.chitchat().lock().await.num_throttled_syns() > 0);
+    }
+
     #[tokio::test]
     async fn test_heartbeat() {
         let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE);
@@ -588,10 +1461,10 @@ mod tests {
         // Add our test socket to the server's nodes.
         server_handle
             .with_chitchat(|server_chitchat| {
-                server_chitchat.update_self_heartbeat();
+                server_chitchat.tick_heartbeat();
                 let syn = server_chitchat.create_syn_message();
-                let syn_ack = test_chitchat.process_message(syn).unwrap();
-                server_chitchat.process_message(syn_ack);
+                let syn_ack = test_chitchat.process_message(server_addr, syn).unwrap();
+                server_chitchat.process_message(test_addr, syn_ack);
             })
             .await;
 
@@ -607,7 +1480,7 @@ mod tests {
         let (_, syn) = timeout(test_transport.recv()).await.unwrap();
 
         // Reply.
-        let syn_ack = test_chitchat.process_message(syn).unwrap();
+        let syn_ack = test_chitchat.process_message(server_addr, syn).unwrap();
         test_transport.send(server_addr, syn_ack).await.unwrap();
 
         // Wait for delta to ensure heartbeat key was incremented.
@@ -678,7 +1551,7 @@ mod tests {
         let node2 = ChitchatId::for_local_test(10_002);
         let node3 = ChitchatId::for_local_test(10_003);
         let mut rng = RngForTest::default();
-        let (nodes, dead_node, seed_node) = select_nodes_for_gossip(
+        let (nodes, dead_node, seed_nodes) = select_nodes_for_gossip(
             &mut rng,
             to_hash_set(vec![
                 node1.gossip_advertise_addr,
@@ -691,11 +1564,13 @@ mod tests {
             ]),
             to_hash_set(vec![node3.gossip_advertise_addr]),
             to_hash_set(vec![node2.gossip_advertise_addr]),
+            1.0,
+            1,
         );
         assert_eq!(nodes.len(), 2);
         assert_eq!(dead_node, Some(node3.gossip_advertise_addr));
-        assert_eq!(
-            seed_node, None,
+        assert!(
+            seed_nodes.is_empty(),
             "Should have already gossiped with a seed node."
         );
     }
@@ -707,16 +1582,37 @@ mod tests {
             .map(|chitchat_id| chitchat_id.gossip_advertise_addr)
             .collect();
         let mut rng = RngForTest::default();
-        let (nodes, dead_node, seed_node) = select_nodes_for_gossip(
+        let (nodes, dead_node, seed_nodes) = select_nodes_for_gossip(
             &mut rng,
             nodes.clone(),
             nodes,
             to_hash_set(Vec::new()),
             to_hash_set(Vec::new()),
+            1.0,
+            1,
         );
         assert_eq!(nodes.len(), 3);
         assert_eq!(dead_node, None);
-        assert_eq!(seed_node, None);
+        assert!(seed_nodes.is_empty());
+    }
+
+    #[test]
+    fn test_select_nodes_for_gossip_empty_cluster() {
+        let mut rng = RngForTest::default();
+        for min_seed_nodes_per_round in [0, 1, 3] {
+            let (nodes, dead_node, seed_nodes) = select_nodes_for_gossip(
+                &mut rng,
+                HashSet::new(),
+                HashSet::new(),
+                HashSet::new(),
+                HashSet::new(),
+                1.0,
+                min_seed_nodes_per_round,
+            );
+            assert!(nodes.is_empty());
+            assert_eq!(dead_node, None);
+            assert!(seed_nodes.is_empty());
+        }
     }
 
     #[test]
@@ -727,15 +1623,65 @@ mod tests {
             .collect();
         let seeds: HashSet<SocketAddr> = nodes[3..5].iter().cloned().collect();
         let mut rng = RngForTest::default();
-        let (gossip_nodes, gossip_dead_node, gossip_seed_node) = select_nodes_for_gossip(
+        let (gossip_nodes, gossip_dead_node, gossip_seed_nodes) = select_nodes_for_gossip(
             &mut rng,
             to_hash_set(nodes.clone()),
             to_hash_set(vec![nodes[0]]),
             nodes[1..].iter().cloned().collect(),
             seeds,
+            1.0,
+            1,
         );
         assert_eq!(gossip_nodes, &[nodes[0]]);
         assert!(gossip_dead_node.is_some());
-        assert!(gossip_seed_node.is_some());
+        assert_eq!(gossip_seed_nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_select_nodes_for_gossip_min_seed_nodes_per_round() {
+        let nodes: Vec<SocketAddr> = (10_001..=10_010)
+            .map(ChitchatId::for_local_test)
+            .map(|chitchat_id| chitchat_id.gossip_advertise_addr)
+            .collect();
+        let seeds: HashSet<SocketAddr> = nodes[5..].iter().cloned().collect();
+        let mut rng = RngForTest::default();
+        for min_seed_nodes_per_round in [2, 4, 5, 10] {
+            let (gossip_nodes, _, gossip_seed_nodes) = select_nodes_for_gossip(
+                &mut rng,
+                to_hash_set(nodes.clone()),
+                to_hash_set(nodes.clone()),
+                HashSet::new(),
+                seeds.clone(),
+                1.0,
+                min_seed_nodes_per_round,
+            );
+            let gossiped_seed_nodes: HashSet<SocketAddr> = gossip_nodes
+                .iter()
+                .chain(&gossip_seed_nodes)
+                .filter(|node| seeds.contains(node))
+                .cloned()
+                .collect();
+            assert!(gossiped_seed_nodes.len() >= min_seed_nodes_per_round.min(seeds.len()));
+            // Seed nodes already selected among the live nodes are not gossiped with twice.
+            assert!(gossip_seed_nodes
+                .iter()
+                .all(|seed_node| !gossip_nodes.contains(seed_node)));
+        }
+    }
+
+    #[test]
+    fn test_select_dead_node_to_gossip_with_probability_multiplier() {
+        let dead_nodes: HashSet<SocketAddr> = (10_001..=10_004)
+            .map(ChitchatId::for_local_test)
+            .map(|chitchat_id| chitchat_id.gossip_advertise_addr)
+            .collect();
+        let mut rng = RngForTest::default();
+        for _ in 0..10 {
+            assert!(select_dead_node_to_gossip_with(&mut rng, &dead_nodes, 2, 4, 0.0).is_none());
+        }
+        // With a multiplier of 1, the probability would be 4 / 3.
+        for _ in 0..10 {
+            assert!(select_dead_node_to_gossip_with(&mut rng, &dead_nodes, 2, 4, 1.0).is_some());
+        }
     }
 }
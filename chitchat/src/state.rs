@@ -1,32 +1,66 @@
 use std::cmp::Ordering;
 use std::collections::btree_map::Entry;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::net::{Ipv4Addr, SocketAddr};
 use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use itertools::Itertools;
+use anyhow::{bail, Context};
+use itertools::{Either, EitherOrBoth, Itertools};
 use rand::prelude::SliceRandom;
 use rand::Rng;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use tokio::sync::watch;
 use tokio::time::Instant;
 use tracing::{info, warn};
 
+use crate::clock::SharedClock;
+use crate::configuration::{KeyValidator, ValueTransform};
 use crate::delta::{Delta, DeltaSerializer, NodeDelta};
 use crate::digest::{Digest, NodeDigest};
 use crate::listener::Listeners;
-use crate::types::{DeletionStatus, DeletionStatusMutation};
-use crate::{ChitchatId, Heartbeat, KeyChangeEvent, Version, VersionedValue};
+use crate::serialize::{deserialize_stream, CompressedStreamWriter, Deserializable};
+use crate::types::{
+    DeletionStatus, DeletionStatusMutation, KeyEntry, KeyValueMutation, KeyValueMutationRef,
+};
+use crate::{
+    ChitchatId, ClusterEvent, Heartbeat, KeyChangeEvent, Serializable, Version, VersionedValue,
+    CONFIG_EPOCH_KEY, MAX_VERSION, READY_KEY, RPC_ADVERTISE_ADDR_KEY, TAG_KEY_PREFIX,
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct NodeState {
     chitchat_id: ChitchatId,
     heartbeat: Heartbeat,
     key_values: BTreeMap<String, VersionedValue>,
+    // Secondary index over `key_values`, used to find stale key-values without a full scan.
+    #[serde(skip)]
+    keys_by_version: KeysByVersion,
+    // Key-values that are only visible locally. They are not versioned and never gossiped.
+    #[serde(skip)]
+    local_key_values: BTreeMap<String, String>,
+    // Number of writes to `local_key_values`, which are not versioned. Used to tell whether they
+    // changed.
+    #[serde(skip)]
+    num_local_writes: u64,
     #[serde(skip)]
     listeners: Listeners,
+    #[serde(skip)]
+    key_validation: KeyValidation,
+    #[serde(skip)]
+    key_value_history: KeyValueHistory,
+    #[serde(skip)]
+    clock: SharedClock,
+    // When draining, local writes are rejected. See `Chitchat::enter_drain_mode`.
+    #[serde(skip)]
+    draining: bool,
+    // Greatest version of the critical keys still being propagated, if any. Deltas for peers that
+    // lack it carry this node first. See `Chitchat::self_set_critical`.
+    #[serde(skip)]
+    critical_version_opt: Option<Version>,
     max_version: Version,
     // This is the maximum version of the last tombstone GC.
     //
@@ -60,19 +94,34 @@ impl Debug for NodeState {
         f.debug_struct("NodeState")
             .field("heartbeat", &self.heartbeat)
             .field("key_values", &self.key_values)
+            .field("local_key_values", &self.local_key_values)
             .field("max_version", &self.max_version)
             .finish()
     }
 }
 
 impl NodeState {
-    fn new(chitchat_id: ChitchatId, listeners: Listeners) -> NodeState {
+    fn new(
+        chitchat_id: ChitchatId,
+        listeners: Listeners,
+        key_validation: KeyValidation,
+        key_value_history_depth: usize,
+        clock: SharedClock,
+    ) -> NodeState {
         NodeState {
             chitchat_id,
             heartbeat: Heartbeat(0),
             key_values: Default::default(),
+            keys_by_version: Default::default(),
+            local_key_values: Default::default(),
             max_version: 0u64,
             listeners,
+            key_validation,
+            key_value_history: KeyValueHistory::with_depth(key_value_history_depth),
+            clock,
+            draining: false,
+            critical_version_opt: None,
+            num_local_writes: 0,
             last_gc_version: 0u64,
         }
     }
@@ -89,6 +138,43 @@ impl NodeState {
         self.last_gc_version = last_gc_version;
     }
 
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    pub(crate) fn set_draining(&mut self, draining: bool) {
+        self.draining = draining;
+    }
+
+    pub(crate) fn set_critical_version(&mut self, critical_version_opt: Option<Version>) {
+        self.critical_version_opt = critical_version_opt;
+    }
+
+    // Returns the version of the next local write. Versions received from peers never exceed
+    // `MAX_VERSION`, so this does not saturate in practice.
+    fn next_version(&self) -> Version {
+        self.max_version.saturating_add(1)
+    }
+
+    // Returns `true`, and logs a warning, if the node state is draining and `key` is not an
+    // internal key, in which case the local write must be dropped.
+    fn rejects_local_write(&self, key: &str) -> bool {
+        if !self.draining || is_internal_key(key) {
+            return false;
+        }
+        warn!(node=?self.chitchat_id, key=key, "node is draining, local write dropped");
+        true
+    }
+
+    // Same as `rejects_local_write` for a batch of key-values. Batches are all-or-nothing, so
+    // they are dropped while draining whatever their keys.
+    fn rejects_local_batch_write(&self) -> bool {
+        if self.draining {
+            warn!(node=?self.chitchat_id, "node is draining, local write dropped");
+        }
+        self.draining
+    }
+
     pub fn for_test() -> NodeState {
         NodeState {
             chitchat_id: ChitchatId {
@@ -98,12 +184,36 @@ impl NodeState {
             },
             heartbeat: Heartbeat(0),
             key_values: Default::default(),
+            keys_by_version: Default::default(),
+            local_key_values: Default::default(),
             max_version: Default::default(),
             listeners: Listeners::default(),
+            key_validation: KeyValidation::default(),
+            key_value_history: KeyValueHistory::default(),
+            clock: SharedClock::default(),
+            draining: false,
+            critical_version_opt: None,
+            num_local_writes: 0,
             last_gc_version: 0u64,
         }
     }
 
+    /// Returns the address at which the node serves RPCs.
+    ///
+    /// This is the address advertised under [`RPC_ADVERTISE_ADDR_KEY`], or the gossip advertise
+    /// address of the node if it does not advertise any RPC address. Gossip itself always
+    /// targets the gossip advertise address.
+    ///
+    /// Returns an error if the advertised RPC address is not a valid socket address.
+    pub fn rpc_advertise_addr(&self) -> anyhow::Result<SocketAddr> {
+        let Some(rpc_advertise_addr_str) = self.get(RPC_ADVERTISE_ADDR_KEY) else {
+            return Ok(self.chitchat_id.gossip_advertise_addr);
+        };
+        rpc_advertise_addr_str.parse().with_context(|| {
+            format!("failed to parse RPC advertise address `{rpc_advertise_addr_str}`")
+        })
+    }
+
     /// Returns the node's last heartbeat value.
     pub fn heartbeat(&self) -> Heartbeat {
         self.heartbeat
@@ -122,11 +232,23 @@ impl NodeState {
             .map(|(key, versioned_value)| (key.as_str(), versioned_value))
     }
 
+    /// Returns an iterator over all the keys, including the ones marked for deletion, along with
+    /// their value, version, and deletion status. This is meant for introspection tooling.
+    pub fn debug_entries(&self) -> impl Iterator<Item = KeyEntry<'_>> {
+        self.key_values_including_deleted()
+            .map(|(key, versioned_value)| KeyEntry {
+                key,
+                value: &versioned_value.value,
+                version: versioned_value.version,
+                status: versioned_value.status.into(),
+            })
+    }
+
     /// Returns an iterator over all of the (non-deleted) key-values.
     pub fn key_values(&self) -> impl Iterator<Item = (&str, &str)> {
         self.key_values_including_deleted()
             .filter(|(_, versioned_value)| !versioned_value.is_deleted())
-            .map(|(key, versioned_value)| (key, versioned_value.value.as_str()))
+            .map(|(key, versioned_value)| (key, &*versioned_value.value))
     }
 
     pub fn set_max_version(&mut self, max_version: Version) {
@@ -134,15 +256,30 @@ impl NodeState {
     }
 
     // Prepare the node state to receive a delta.
-    // Returns `true` if the delta can be applied. In that case, the node state may be mutated (if a
-    // reset is required) Returns `false` if the delta cannot be applied. In that case, the node
+    // Returns `Apply` or `ResetAndApply` if the delta can be applied. In the latter case, the node
+    // state was reset. Returns `Ignore` if the delta cannot be applied. In that case, the node
     // state is not modified.
     #[must_use]
-    fn prepare_apply_delta(&mut self, node_delta: &NodeDelta) -> bool {
+    fn prepare_apply_delta(&mut self, node_delta: &NodeDelta) -> DeltaPreparation {
+        let greatest_version = node_delta.greatest_version();
+        if greatest_version > MAX_VERSION {
+            warn!(
+                node=?node_delta.chitchat_id,
+                version=greatest_version,
+                "received a delta with a version greater than the maximum version, ignoring it"
+            );
+            return DeltaPreparation::Ignore;
+        }
         if node_delta.from_version_excluded > self.max_version {
             // This delta is coming from the future.
             // We probably experienced a reset and this delta is not usable for us anymore.
             // This is not a bug, it can happen, but we just need to ignore it!
+            //
+            // This includes deltas introducing a node we have never heard of (`max_version` is 0)
+            // with a nonzero `from_version_excluded`: the key-values up to that version are
+            // missing from the delta, so accepting it would leave us with a partial state
+            // advertising a max version we have not caught up with. Peers that do not find the
+            // node in our digest send it from version 0, which is always applied.
             info!(
                 node=?node_delta.chitchat_id,
                 from_version=node_delta.from_version_excluded,
@@ -150,13 +287,13 @@ impl NodeState {
                 current_last_gc_version=self.last_gc_version,
                 "received delta from the future, ignoring it"
             );
-            return false;
+            return DeltaPreparation::Ignore;
         }
 
         if self.max_version > node_delta.last_gc_version {
             // The GCed tombstone have all been already received.
             // We won't miss anything by applying the delta!
-            return true;
+            return DeltaPreparation::Apply;
         }
 
         // This delta might be missing tombstones with a version within
@@ -165,7 +302,7 @@ impl NodeState {
         // It is ok if we don't have the associated values to begin
         // with.
         if self.last_gc_version >= node_delta.last_gc_version {
-            return true;
+            return DeltaPreparation::Apply;
         }
 
         if node_delta.from_version_excluded > 0 {
@@ -186,7 +323,7 @@ impl NodeState {
         else {
             // This can happen if we just hit the mtu at the moment
             // of writing the SetMaxVersion operation.
-            return false;
+            return DeltaPreparation::Ignore;
         };
 
         if (node_delta.last_gc_version, delta_max_version)
@@ -200,7 +337,7 @@ impl NodeState {
                 last_gc_version=node_delta.last_gc_version,
                 current_last_gc_version=self.last_gc_version,
                 "received a delta that does not bring us to a fresher state, ignoring it");
-            return false;
+            return DeltaPreparation::Ignore;
         }
 
         // We are out of sync. This delta is an invitation to `reset` our state.
@@ -209,26 +346,46 @@ impl NodeState {
             last_gc_version=node_delta.last_gc_version,
             current_last_gc_version=self.last_gc_version,
             "resetting node");
-        *self = NodeState::new(node_delta.chitchat_id.clone(), self.listeners.clone());
-        // The node_delta max_version  whe
+        let local_key_values = std::mem::take(&mut self.local_key_values);
+        let previous_key_values = std::mem::take(&mut self.key_values);
+        *self = NodeState::new(
+            node_delta.chitchat_id.clone(),
+            self.listeners.clone(),
+            self.key_validation.clone(),
+            self.key_value_history.depth,
+            self.clock.clone(),
+        );
+        self.local_key_values = local_key_values;
+        // A node delta carrying a max version has no key-values: the node state we are resetting
+        // to is empty up to that version, e.g. because all of its key-values were tombstones that
+        // have been garbage collected since.
         if let Some(max_version) = node_delta.max_version {
-            if node_delta.key_values.is_empty() {
-                self.max_version = max_version;
-            } else {
-                warn!(
-                    "Received a delta with a max_version, and key_values as well. This is \
-                     unexpected, please report."
-                );
-            }
+            self.max_version = max_version;
         }
         // We need to reset our `last_gc_version`.
         self.last_gc_version = node_delta.last_gc_version;
-        true
+        self.listeners
+            .trigger_cluster_event(|| ClusterEvent::NodeReset(node_delta.chitchat_id.clone()));
+        for (key, versioned_value) in previous_key_values {
+            if !versioned_value.is_deleted() {
+                self.listeners
+                    .trigger_cluster_event(|| ClusterEvent::KeyDeleted {
+                        node: node_delta.chitchat_id.clone(),
+                        key,
+                    });
+            }
+        }
+        DeltaPreparation::ResetAndApply
     }
 
-    fn apply_delta(&mut self, node_delta: NodeDelta, now: Instant) {
-        if !self.prepare_apply_delta(&node_delta) {
-            return;
+    fn apply_delta(&mut self, node_delta: NodeDelta, now: Instant) -> DeltaPreparation {
+        #[cfg(debug_assertions)]
+        let versions_before_delta = (self.max_version, self.last_gc_version);
+        #[cfg(debug_assertions)]
+        let delta_last_gc_version = node_delta.last_gc_version;
+        let delta_preparation = self.prepare_apply_delta(&node_delta);
+        if delta_preparation == DeltaPreparation::Ignore {
+            return delta_preparation;
         }
         let current_max_version = self.max_version();
         for key_value_mutation in node_delta.key_values {
@@ -242,12 +399,86 @@ impl NodeState {
                     continue;
                 }
             }
+            // Key-values rejected by the key validator or dropped by the value transform are
+            // considered applied anyway, so that we do not ask for them again.
+            self.max_version = key_value_mutation.version.max(self.max_version);
+            if !self
+                .key_validation
+                .is_valid(&self.chitchat_id, &key_value_mutation.key)
+            {
+                continue;
+            }
             let new_versioned_value = VersionedValue {
                 value: key_value_mutation.value,
                 version: key_value_mutation.version,
                 status: key_value_mutation.status.into_status(now),
             };
-            self.set_versioned_value(key_value_mutation.key, new_versioned_value);
+            self.set_versioned_value_internal(key_value_mutation.key, new_versioned_value);
+        }
+        // Without a reset, the max version of the node delta, if any, is not applied. It only
+        // tells that the sender has no key-values to share in (`from_version_excluded`,
+        // `max_version`], which may be because it forgot some of them (see
+        // `ClusterState::forget_key`) rather than because they were garbage collected. Adopting
+        // it could make us skip key-values that the other nodes can still send us.
+        #[cfg(debug_assertions)]
+        self.check_delta_applied_invariants(
+            delta_preparation,
+            versions_before_delta,
+            delta_last_gc_version,
+        );
+        delta_preparation
+    }
+
+    /// Panics if the node state breaks an invariant after a delta was applied. These checks
+    /// target the subtle interactions between resets, garbage collection and versions, and are
+    /// only compiled in debug builds.
+    #[cfg(debug_assertions)]
+    fn check_delta_applied_invariants(
+        &self,
+        delta_preparation: DeltaPreparation,
+        (previous_max_version, previous_last_gc_version): (Version, Version),
+        delta_last_gc_version: Version,
+    ) {
+        let chitchat_id = &self.chitchat_id;
+        // Versions up to this one were in the node state before the delta.
+        let applied_floor_version = if delta_preparation == DeltaPreparation::ResetAndApply {
+            assert_eq!(
+                self.last_gc_version, delta_last_gc_version,
+                "node {chitchat_id:?} was reset without adopting the last GC version of the delta"
+            );
+            0
+        } else {
+            assert!(
+                self.max_version >= previous_max_version,
+                "max version of node {chitchat_id:?} went down from {previous_max_version} to {} \
+                 without a reset",
+                self.max_version
+            );
+            assert_eq!(
+                self.last_gc_version, previous_last_gc_version,
+                "last GC version of node {chitchat_id:?} changed without a reset"
+            );
+            previous_max_version
+        };
+        for (key, versioned_value) in &self.key_values {
+            assert!(
+                versioned_value.version <= self.max_version,
+                "key `{key}` of node {chitchat_id:?} has version {} above the max version {}",
+                versioned_value.version,
+                self.max_version
+            );
+            let is_applied_tombstone = versioned_value.version > applied_floor_version
+                && versioned_value
+                    .status
+                    .time_of_start_scheduled_for_deletion()
+                    .is_some();
+            assert!(
+                !is_applied_tombstone || versioned_value.version > self.last_gc_version,
+                "tombstone of key `{key}` of node {chitchat_id:?} was applied with version {}, \
+                 which was already garbage collected (last GC version {})",
+                versioned_value.version,
+                self.last_gc_version
+            );
         }
     }
 
@@ -274,12 +505,111 @@ impl NodeState {
         self.get(key).is_some()
     }
 
+    /// Returns the value associated to the given key.
+    ///
+    /// Gossiped key-values take precedence over local key-values sharing the same key.
     pub fn get(&self, key: &str) -> Option<&str> {
-        let versioned_value = self.get_versioned(key)?;
+        let Some(versioned_value) = self.get_versioned(key) else {
+            return self.get_local(key);
+        };
         if versioned_value.is_deleted() {
             return None;
         }
-        Some(versioned_value.value.as_str())
+        Some(&versioned_value.value)
+    }
+
+    /// Returns the boolean value associated to the given key, as set by [`NodeState::set_bool`].
+    ///
+    /// Returns `None` if the key is absent or if its value is neither `true` nor `false`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer value associated to the given key, as set by [`NodeState::set_u64`].
+    ///
+    /// Returns `None` if the key is absent or if its value is not a decimal integer.
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key)?.parse().ok()
+    }
+
+    /// Returns whether the node advertises that it is ready. See
+    /// [`Chitchat::set_ready`](crate::Chitchat::set_ready).
+    pub fn is_ready(&self) -> bool {
+        self.get_bool(READY_KEY) == Some(true)
+    }
+
+    /// Returns the value of the tag `key`, as set by [`NodeState::set_tag`].
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        self.get(&format!("{TAG_KEY_PREFIX}{key}"))
+    }
+
+    /// Returns an iterator over the tags of the node, without the [`TAG_KEY_PREFIX`].
+    pub fn tags(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.iter_prefix(TAG_KEY_PREFIX)
+            .map(|(key, versioned_value)| (&key[TAG_KEY_PREFIX.len()..], &*versioned_value.value))
+    }
+
+    /// Returns the configuration epoch of the node, i.e. the number of calls to
+    /// [`NodeState::commit_config`] that changed at least one key-value.
+    ///
+    /// Returns 0 if the node never committed a configuration.
+    pub fn config_epoch(&self) -> u64 {
+        self.get_u64(CONFIG_EPOCH_KEY).unwrap_or(0)
+    }
+
+    /// Returns the versioned value that was current for `key` at `version`, which may be a
+    /// tombstone.
+    ///
+    /// Older values are only available if a key-value history depth is configured, and only as
+    /// far back as that depth. `None` is returned if the key did not exist at that version, or if
+    /// the value is not available anymore.
+    pub fn get_at_version(&self, key: &str, version: Version) -> Option<&VersionedValue> {
+        let versioned_value = self.key_values.get(key)?;
+        if versioned_value.version <= version {
+            return Some(versioned_value);
+        }
+        self.key_value_history.get_at_version(key, version)
+    }
+
+    /// Returns the value of a local key-value, set with [`NodeState::set_local`].
+    pub fn get_local(&self, key: &str) -> Option<&str> {
+        self.local_key_values.get(key).map(String::as_str)
+    }
+
+    /// Returns an iterator over the local key-values.
+    pub fn local_key_values(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.local_key_values
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Sets a local key-value.
+    ///
+    /// Local key-values are never gossiped to peers: they are not versioned, do not appear in
+    /// digests or deltas, and do not trigger key change listeners.
+    pub fn set_local(&mut self, key: impl ToString, value: impl ToString) {
+        self.local_key_values
+            .insert(key.to_string(), value.to_string());
+        self.num_local_writes += 1;
+    }
+
+    /// Removes a local key-value. Returns the removed value, if any.
+    pub fn delete_local(&mut self, key: &str) -> Option<String> {
+        self.num_local_writes += 1;
+        self.local_key_values.remove(key)
+    }
+
+    pub(crate) fn num_local_writes(&self) -> u64 {
+        self.num_local_writes
+    }
+
+    /// Returns the number of key-values, including the deleted ones, without scanning them.
+    pub(crate) fn num_key_values_including_deleted(&self) -> usize {
+        self.key_values.len()
     }
 
     /// If the key is tombstoned, this method will still return the versioned value.
@@ -287,6 +617,16 @@ impl NodeState {
         self.key_values.get(key)
     }
 
+    /// Returns the versioned value associated to the given key, unless it is deleted.
+    ///
+    /// Unlike [`NodeState::get`], this gives access to the version of the value, e.g. to tell
+    /// whether it changed since a version observed earlier. Key-values scheduled for deletion
+    /// after a TTL are still returned, and local key-values are ignored.
+    pub fn get_versioned_live(&self, key: &str) -> Option<&VersionedValue> {
+        self.get_versioned(key)
+            .filter(|versioned_value| !versioned_value.is_deleted())
+    }
+
     /// Sets a new value for a given key.
     ///
     /// Setting a new value automatically increments the
@@ -294,7 +634,12 @@ impl NodeState {
     /// the same.
     pub fn set(&mut self, key: impl ToString, value: impl ToString) {
         let key = key.to_string();
-        let value = value.to_string();
+        if self.rejects_local_write(&key) {
+            return;
+        }
+        let Some(value) = self.prepare_local_value(&key, value.to_string()) else {
+            return;
+        };
         if let Some(previous_versioned_value) = self.get_versioned(&key) {
             if previous_versioned_value.value == value
                 && matches!(previous_versioned_value.status, DeletionStatus::Set)
@@ -303,14 +648,148 @@ impl NodeState {
                 return;
             }
         }
-        let new_version = self.max_version + 1;
-        self.set_with_version(key, value, new_version);
+        let new_version = self.next_version();
+        self.store_versioned_value(
+            key,
+            VersionedValue {
+                value,
+                version: new_version,
+                status: DeletionStatus::Set,
+            },
+        );
+    }
+
+    /// Sets a boolean value for a given key, represented as `true` or `false`.
+    pub fn set_bool(&mut self, key: impl ToString, value: bool) {
+        self.set(key, if value { "true" } else { "false" });
+    }
+
+    /// Sets an integer value for a given key, represented in decimal.
+    pub fn set_u64(&mut self, key: impl ToString, value: u64) {
+        self.set(key, value);
+    }
+
+    /// Sets the tag `key` to `value`, e.g. `region=us-east-1`.
+    ///
+    /// Tags are regular key-values stored under the key `_tag:{key}`. Use
+    /// [`Chitchat::nodes_with_tag`](crate::Chitchat::nodes_with_tag) to find the live nodes
+    /// carrying a given tag.
+    pub fn set_tag(&mut self, key: &str, value: impl ToString) {
+        self.set(format!("{TAG_KEY_PREFIX}{key}"), value);
+    }
+
+    /// Sets several key-values at once, all with the same new version.
+    ///
+    /// Peers always learn about key-values sharing a version together. Key-values whose value
+    /// stays the same are left untouched.
+    pub fn set_batch<K: ToString, V: ToString>(
+        &mut self,
+        key_values: impl IntoIterator<Item = (K, V)>,
+    ) {
+        if self.rejects_local_batch_write() {
+            return;
+        }
+        let new_version = self.next_version();
+        self.set_batch_with_version(key_values, new_version);
+    }
+
+    /// Sets several interdependent key-values at once, like [`NodeState::set_batch`], and
+    /// increments the configuration epoch of the node if any of them changed.
+    ///
+    /// The epoch is gossiped under the key [`CONFIG_EPOCH_KEY`], with the same version as the
+    /// key-values, so peers can detect that the configuration of a node changed by comparing its
+    /// [`NodeState::config_epoch`], rather than tracking each key.
+    pub fn commit_config<K: ToString, V: ToString>(
+        &mut self,
+        key_values: impl IntoIterator<Item = (K, V)>,
+    ) {
+        if self.rejects_local_batch_write() {
+            return;
+        }
+        let previous_max_version = self.max_version;
+        let new_version = self.next_version();
+        self.set_batch_with_version(key_values, new_version);
+        if self.max_version == previous_max_version {
+            return;
+        }
+        let config_epoch = self.config_epoch() + 1;
+        self.set_batch_with_version([(CONFIG_EPOCH_KEY, config_epoch)], new_version);
+    }
+
+    fn set_batch_with_version<K: ToString, V: ToString>(
+        &mut self,
+        key_values: impl IntoIterator<Item = (K, V)>,
+        new_version: Version,
+    ) {
+        for (key, value) in key_values {
+            let key = key.to_string();
+            let Some(value) = self.prepare_local_value(&key, value.to_string()) else {
+                continue;
+            };
+            if let Some(previous_versioned_value) = self.get_versioned(&key) {
+                if previous_versioned_value.value == value
+                    && matches!(previous_versioned_value.status, DeletionStatus::Set)
+                {
+                    continue;
+                }
+            }
+            self.store_versioned_value(
+                key,
+                VersionedValue {
+                    value,
+                    version: new_version,
+                    status: DeletionStatus::Set,
+                },
+            );
+        }
+    }
+
+    /// Replaces all the key-values of the node with `key_values`, in a single version.
+    ///
+    /// Keys absent from `key_values` are deleted, and the others are set. Key-values whose value
+    /// stays the same are left untouched, so listeners are only called for the keys that actually
+    /// change, once per key. This is typically useful to reflect a configuration reload.
+    pub fn replace_all<K: ToString, V: ToString>(
+        &mut self,
+        key_values: impl IntoIterator<Item = (K, V)>,
+    ) {
+        if self.rejects_local_batch_write() {
+            return;
+        }
+        let key_values: BTreeMap<String, String> = key_values
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let new_version = self.next_version();
+        let now = self.clock.now();
+        let deleted_keys: Vec<String> = self
+            .key_values()
+            .map(|(key, _value)| key)
+            .filter(|key| !key_values.contains_key(*key))
+            .map(str::to_string)
+            .collect();
+        for key in deleted_keys {
+            self.set_versioned_value_internal(
+                key,
+                VersionedValue {
+                    value: "".into(),
+                    version: new_version,
+                    status: DeletionStatusMutation::Delete.into_status(now),
+                },
+            );
+        }
+        self.set_batch_with_version(key_values, new_version);
     }
 
     /// Sets a new value with a TTL.
     pub fn set_with_ttl(&mut self, key: impl ToString, value: impl ToString) {
         let key = key.to_string();
-        let value = value.to_string();
+        if self.rejects_local_write(&key) {
+            return;
+        }
+        let Some(value) = self.prepare_local_value(&key, value.to_string()) else {
+            return;
+        };
         if let Some(previous_versioned_value) = self.get_versioned(&key) {
             if previous_versioned_value.value == value
                 && matches!(
@@ -322,13 +801,13 @@ impl NodeState {
                 return;
             }
         }
-        let new_version = self.max_version + 1;
-        self.set_versioned_value(
-            key.to_string(),
+        let new_version = self.next_version();
+        self.store_versioned_value(
+            key,
             VersionedValue {
-                value: value.to_string(),
+                value,
                 version: new_version,
-                status: DeletionStatus::DeleteAfterTtl(Instant::now()),
+                status: DeletionStatus::DeleteAfterTtl(self.clock.now()),
             },
         );
     }
@@ -342,14 +821,25 @@ impl NodeState {
     /// That tombstone is annotated with the time of removal, so that after a configurable
     /// grace period, it will be remove by the garbage collection.
     pub fn delete(&mut self, key: &str) {
+        if self.rejects_local_write(key) {
+            return;
+        }
         let Some(versioned_value) = self.key_values.get_mut(key) else {
             warn!("Key `{key}` does not exist in the node's state and could not be deleted.",);
             return;
         };
-        self.max_version += 1;
+        self.key_value_history.record(key, versioned_value);
+        self.max_version = self.max_version.saturating_add(1);
+        self.keys_by_version
+            .update(key, versioned_value.version, self.max_version);
         versioned_value.version = self.max_version;
-        versioned_value.value = "".to_string();
-        versioned_value.status = DeletionStatusMutation::Delete.into_status(Instant::now());
+        versioned_value.value = "".into();
+        versioned_value.status = DeletionStatusMutation::Delete.into_status(self.clock.now());
+        self.listeners
+            .trigger_cluster_event(|| ClusterEvent::KeyDeleted {
+                node: self.chitchat_id.clone(),
+                key: key.to_string(),
+            });
     }
 
     /// Contrary to `delete`, this does not delete an entry right away,
@@ -361,6 +851,9 @@ impl NodeState {
     /// Implementation wise, the only difference with `delete` is that it is
     /// treated as if it was present during the grace period.``
     pub fn delete_after_ttl(&mut self, key: &str) {
+        if self.rejects_local_write(key) {
+            return;
+        }
         let Some(versioned_value) = self.key_values.get_mut(key) else {
             warn!(
                 "Key `{key}` does not exist in the node's state and could not scheduled for an \
@@ -368,9 +861,13 @@ impl NodeState {
             );
             return;
         };
-        self.max_version += 1;
+        self.key_value_history.record(key, versioned_value);
+        self.max_version = self.max_version.saturating_add(1);
+        self.keys_by_version
+            .update(key, versioned_value.version, self.max_version);
         versioned_value.version = self.max_version;
-        versioned_value.status = DeletionStatusMutation::DeleteAfterTtl.into_status(Instant::now());
+        versioned_value.status =
+            DeletionStatusMutation::DeleteAfterTtl.into_status(self.clock.now());
     }
 
     pub(crate) fn inc_heartbeat(&mut self) {
@@ -407,10 +904,12 @@ impl NodeState {
 
     /// Removes the keys marked for deletion such that `tombstone + grace_period > heartbeat`.
     fn gc_keys_marked_for_deletion(&mut self, grace_period: Duration) {
-        let now = Instant::now();
+        let now = self.clock.now();
         let mut max_deleted_version = self.last_gc_version;
+        let key_value_history = &mut self.key_value_history;
+        let keys_by_version = &mut self.keys_by_version;
         self.key_values
-            .retain(|_, versioned_value: &mut VersionedValue| {
+            .retain(|key, versioned_value: &mut VersionedValue| {
                 let Some(deleted_start_instant) = versioned_value
                     .status
                     .time_of_start_scheduled_for_deletion()
@@ -424,6 +923,8 @@ impl NodeState {
                 }
                 // We have exceeded the tombstone grace period. Time to remove it.
                 max_deleted_version = versioned_value.version.max(max_deleted_version);
+                key_value_history.remove(key);
+                keys_by_version.remove(key, versioned_value.version);
                 false
             });
         self.last_gc_version = max_deleted_version;
@@ -434,31 +935,135 @@ impl NodeState {
     /// Most of the time, you do not want to call this method but,
     /// `mark_for_deletion` instead.
     pub(crate) fn remove_key_value_internal(&mut self, key: &str) {
-        self.key_values.remove(key);
+        if let Some(versioned_value) = self.key_values.remove(key) {
+            self.keys_by_version.remove(key, versioned_value.version);
+        }
+        self.key_value_history.remove(key);
+    }
+
+    /// Renumbers the versions strictly greater than `floor_version` so that they are contiguous,
+    /// starting at `floor_version + 1`, while preserving their order. The previous values of the
+    /// key-values above `floor_version` are dropped from the history.
+    ///
+    /// This must only be called with a `floor_version` above any version that may have been
+    /// observed by peers.
+    pub(crate) fn compact_versions(&mut self, floor_version: Version) {
+        if self.max_version <= floor_version || self.last_gc_version > floor_version {
+            return;
+        }
+        if !self.keys_by_version.is_in_sync_with(&self.key_values) {
+            self.keys_by_version = KeysByVersion::from_key_values(&self.key_values);
+        }
+        let new_versions: HashMap<Version, Version> = self
+            .keys_by_version
+            .versions_above(floor_version)
+            .zip(floor_version + 1..)
+            .collect();
+        for versioned_value in self.key_values.values_mut() {
+            if let Some(&new_version) = new_versions.get(&versioned_value.version) {
+                versioned_value.version = new_version;
+            }
+        }
+        self.keys_by_version = KeysByVersion::from_key_values(&self.key_values);
+        self.key_value_history.truncate_above(floor_version);
+        self.max_version = floor_version + new_versions.len() as Version;
+    }
+
+    /// Moves all the versions of the node state above `version`, so that peers that know about
+    /// versions of the node up to `version` reset it and receive its whole state again.
+    ///
+    /// The key-values, tombstones included, are renumbered starting at `version + 2` while
+    /// preserving their order, and the versions up to `version + 1` are considered garbage
+    /// collected. The history of previous values is dropped. Listeners are not notified, since
+    /// no value changes.
+    pub(crate) fn supersede_versions(&mut self, version: Version) {
+        let last_gc_version = version.saturating_add(1);
+        let keys_by_version = KeysByVersion::from_key_values(&self.key_values);
+        let new_versions: HashMap<Version, Version> = keys_by_version
+            .versions_above(0)
+            .zip(last_gc_version + 1..)
+            .collect();
+        for versioned_value in self.key_values.values_mut() {
+            versioned_value.version = new_versions[&versioned_value.version];
+        }
+        self.keys_by_version = KeysByVersion::from_key_values(&self.key_values);
+        self.key_value_history.truncate_above(0);
+        self.last_gc_version = last_gc_version;
+        self.max_version = last_gc_version + new_versions.len() as Version;
     }
 
     /// Returns an iterator over the versioned values that are strictly greater than
     /// `floor_version`. The floor version typically comes from the max version of a digest.
     ///
-    /// This includes keys marked for deletion.
+    /// This includes keys marked for deletion. Key-values are returned in increasing version order,
+    /// and key-values sharing a version are returned in key order.
     fn stale_key_values(
         &self,
         floor_version: u64,
     ) -> impl Iterator<Item = (&str, &VersionedValue)> {
-        // TODO optimize by checking the max version.
-        self.key_values_including_deleted()
-            .filter(move |(_key, versioned_value)| versioned_value.version > floor_version)
+        if !self.keys_by_version.is_in_sync_with(&self.key_values) {
+            // The index is not serialized, so it is missing from deserialized node states.
+            let stale_key_values = self
+                .key_values_including_deleted()
+                .filter(move |(_key, versioned_value)| versioned_value.version > floor_version)
+                .sorted_unstable_by_key(|(key, versioned_value)| (versioned_value.version, *key));
+            return Either::Left(stale_key_values);
+        }
+        let stale_key_values = self
+            .keys_by_version
+            .keys_above(floor_version)
+            .map(|key| (key, &self.key_values[key]));
+        Either::Right(stale_key_values)
     }
 
     /// Sets a new versioned value to associate to a given key.
     /// This operation is ignored if the key value inserted has a version that is obsolete.
     ///
     /// This method also update the max_version if necessary.
+    /// Sets a versioned value, unless its key is rejected by the key validator.
     pub(crate) fn set_versioned_value(
         &mut self,
         key: String,
         versioned_value_update: VersionedValue,
     ) {
+        if !self.key_validation.is_valid(&self.chitchat_id, &key) {
+            return;
+        }
+        self.set_versioned_value_internal(key, versioned_value_update);
+    }
+
+    fn set_versioned_value_internal(
+        &mut self,
+        key: String,
+        mut versioned_value_update: VersionedValue,
+    ) {
+        if !versioned_value_update.is_deleted()
+            && !self.key_validation.transform_value(
+                &self.chitchat_id,
+                &key,
+                &mut versioned_value_update.value,
+            )
+        {
+            return;
+        }
+        self.store_versioned_value(key, versioned_value_update);
+    }
+
+    /// Validates the key and transforms the value of a local write, so that the value can be
+    /// compared with the stored one before a new version is allocated. Returns `None` if the
+    /// key-value must be dropped.
+    fn prepare_local_value(&self, key: &str, value: String) -> Option<Arc<str>> {
+        if !self.key_validation.is_valid(&self.chitchat_id, key) {
+            return None;
+        }
+        let mut value: Arc<str> = value.into();
+        self.key_validation
+            .transform_value(&self.chitchat_id, key, &mut value)
+            .then_some(value)
+    }
+
+    /// Stores a versioned value whose key was validated and value transformed already.
+    fn store_versioned_value(&mut self, key: String, versioned_value_update: VersionedValue) {
         let key_clone = key.clone();
         let key_change_event = KeyChangeEvent {
             key: key_clone.as_str(),
@@ -474,23 +1079,44 @@ impl NodeState {
                 if occupied_versioned_value.version >= versioned_value_update.version {
                     return;
                 }
+                self.key_value_history
+                    .record(&key_clone, occupied_versioned_value);
+                self.keys_by_version.update(
+                    &key_clone,
+                    occupied_versioned_value.version,
+                    versioned_value_update.version,
+                );
                 *occupied_versioned_value = versioned_value_update.clone();
             }
             Entry::Vacant(vacant) => {
+                self.keys_by_version
+                    .insert(&key_clone, versioned_value_update.version);
                 vacant.insert(versioned_value_update.clone());
             }
         };
         if !versioned_value_update.is_deleted() {
             self.listeners.trigger_event(key_change_event);
+        } else {
+            self.listeners
+                .trigger_cluster_event(|| ClusterEvent::KeyDeleted {
+                    node: self.chitchat_id.clone(),
+                    key: key_clone,
+                });
         }
     }
 
-    fn set_with_version(&mut self, key: impl ToString, value: impl ToString, version: Version) {
+    #[cfg(test)]
+    fn set_with_version(
+        &mut self,
+        key: impl ToString,
+        value: impl Into<Arc<str>>,
+        version: Version,
+    ) {
         assert!(version > self.max_version);
         self.set_versioned_value(
             key.to_string(),
             VersionedValue {
-                value: value.to_string(),
+                value: value.into(),
                 version,
                 status: DeletionStatus::Set,
             },
@@ -498,52 +1124,323 @@ impl NodeState {
     }
 }
 
-pub(crate) struct ClusterState {
-    pub(crate) node_states: BTreeMap<ChitchatId, NodeState>,
-    seed_addrs: watch::Receiver<HashSet<SocketAddr>>,
-    pub(crate) listeners: Listeners,
+/// Outcome of the preparation of a node state to receive a delta.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum DeltaPreparation {
+    /// The delta cannot be applied and must be ignored.
+    Ignore,
+    /// The delta can be applied.
+    Apply,
+    /// The node state was reset, and the delta can be applied.
+    ResetAndApply,
 }
 
-impl Debug for ClusterState {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        f.debug_struct("Cluster")
-            .field("seed_addrs", &self.seed_addrs.borrow())
-            .field("node_states", &self.node_states)
-            .finish()
-    }
+/// Summary of a node delta applied to the cluster state.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AppliedNodeDelta {
+    /// The node the delta is about.
+    pub chitchat_id: ChitchatId,
+    /// All the key-values with a version greater than this one are in the delta.
+    pub from_version_excluded: Version,
+    /// The last GC version of the node state the delta was computed from.
+    pub last_gc_version: Version,
+    /// The number of key-values in the delta.
+    pub num_key_values: usize,
+    /// Whether applying the delta required resetting the node state first.
+    pub reset: bool,
 }
 
-#[cfg(test)]
-impl Default for ClusterState {
-    fn default() -> Self {
-        let (_seed_addrs_tx, seed_addrs_rx) = watch::channel(Default::default());
-        Self {
-            node_states: Default::default(),
-            seed_addrs: seed_addrs_rx,
-            listeners: Default::default(),
+/// Index of the keys of a node state by version.
+///
+/// Several keys can share a version, for instance when they were set in a batch.
+#[derive(Clone, Default)]
+struct KeysByVersion {
+    keys: BTreeMap<Version, BTreeSet<String>>,
+    num_keys: usize,
+}
+
+impl KeysByVersion {
+    fn insert(&mut self, key: &str, version: Version) {
+        if self
+            .keys
+            .entry(version)
+            .or_default()
+            .insert(key.to_string())
+        {
+            self.num_keys += 1;
         }
     }
-}
 
-impl ClusterState {
-    pub fn with_seed_addrs(seed_addrs: watch::Receiver<HashSet<SocketAddr>>) -> ClusterState {
-        ClusterState {
-            seed_addrs,
-            node_states: BTreeMap::new(),
-            listeners: Default::default(),
+    fn remove(&mut self, key: &str, version: Version) {
+        let Entry::Occupied(mut occupied) = self.keys.entry(version) else {
+            return;
+        };
+        if occupied.get_mut().remove(key) {
+            self.num_keys -= 1;
+        }
+        if occupied.get().is_empty() {
+            occupied.remove();
         }
     }
 
-    pub(crate) fn node_state_mut(&mut self, chitchat_id: &ChitchatId) -> &mut NodeState {
-        // TODO use the `hash_raw_entry` feature once it gets stabilized.
-        // Most of the time the entry is already present. We avoid cloning chitchat_id with
-        // this if statement.
-        self.node_states
-            .entry(chitchat_id.clone())
-            .or_insert_with(|| NodeState::new(chitchat_id.clone(), self.listeners.clone()))
+    fn update(&mut self, key: &str, previous_version: Version, new_version: Version) {
+        self.remove(key, previous_version);
+        self.insert(key, new_version);
     }
 
-    pub fn node_state(&self, chitchat_id: &ChitchatId) -> Option<&NodeState> {
+    /// Returns the keys with a version strictly greater than `floor_version`, in increasing
+    /// version order.
+    fn keys_above(&self, floor_version: Version) -> impl Iterator<Item = &str> {
+        self.keys
+            .range((Bound::Excluded(floor_version), Bound::Unbounded))
+            .flat_map(|(_version, keys)| keys.iter().map(String::as_str))
+    }
+
+    fn from_key_values(key_values: &BTreeMap<String, VersionedValue>) -> Self {
+        let mut keys_by_version = KeysByVersion::default();
+        for (key, versioned_value) in key_values {
+            keys_by_version.insert(key, versioned_value.version);
+        }
+        keys_by_version
+    }
+
+    /// Returns the versions strictly greater than `floor_version`, in increasing order.
+    fn versions_above(&self, floor_version: Version) -> impl Iterator<Item = Version> + '_ {
+        self.keys
+            .range((Bound::Excluded(floor_version), Bound::Unbounded))
+            .map(|(&version, _keys)| version)
+    }
+
+    /// Returns whether the index covers the given key-values.
+    ///
+    /// Only the number of keys is compared, which is enough to detect a missing index, e.g. on
+    /// deserialized node states. In debug builds, the whole index is checked as well.
+    fn is_in_sync_with(&self, key_values: &BTreeMap<String, VersionedValue>) -> bool {
+        if self.num_keys != key_values.len() {
+            return false;
+        }
+        debug_assert!(
+            self.matches(key_values),
+            "the index of keys by version does not match the key-values"
+        );
+        true
+    }
+
+    /// Returns whether the index contains exactly the (key, version) pairs of the given
+    /// key-values.
+    fn matches(&self, key_values: &BTreeMap<String, VersionedValue>) -> bool {
+        // As long as the numbers of keys are equal, every key of the index mapping to its
+        // version implies that every key-value is in the index.
+        self.num_keys == key_values.len()
+            && self.keys.iter().all(|(version, keys)| {
+                keys.iter().all(|key| {
+                    key_values
+                        .get(key)
+                        .is_some_and(|versioned_value| versioned_value.version == *version)
+                })
+            })
+    }
+}
+
+/// Keeps the previous values of every key, up to a given depth.
+#[derive(Clone, Default)]
+struct KeyValueHistory {
+    depth: usize,
+    // Previous values of each key, from the oldest to the most recent.
+    previous_values: HashMap<String, VecDeque<VersionedValue>>,
+}
+
+impl KeyValueHistory {
+    fn with_depth(depth: usize) -> Self {
+        KeyValueHistory {
+            depth,
+            previous_values: HashMap::new(),
+        }
+    }
+
+    /// Records the value of `key` that is about to be replaced.
+    fn record(&mut self, key: &str, previous_versioned_value: &VersionedValue) {
+        if self.depth == 0 {
+            return;
+        }
+        let previous_values = self.previous_values.entry(key.to_string()).or_default();
+        if previous_values.len() == self.depth {
+            previous_values.pop_front();
+        }
+        previous_values.push_back(previous_versioned_value.clone());
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.previous_values.remove(key);
+    }
+
+    /// Drops the previous values with a version strictly greater than `floor_version`.
+    fn truncate_above(&mut self, floor_version: Version) {
+        self.previous_values.retain(|_key, previous_values| {
+            previous_values.retain(|versioned_value| versioned_value.version <= floor_version);
+            !previous_values.is_empty()
+        });
+    }
+
+    fn get_at_version(&self, key: &str, version: Version) -> Option<&VersionedValue> {
+        self.previous_values
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|versioned_value| versioned_value.version <= version)
+    }
+}
+
+/// Drops the key-values whose key is rejected by the configured key validator, and counts them.
+/// Also rewrites or drops values with the configured value transform.
+///
+/// It is shared by all the node states of a cluster state.
+#[derive(Clone, Default)]
+pub(crate) struct KeyValidation {
+    key_validator_opt: Option<KeyValidator>,
+    value_transform_opt: Option<ValueTransform>,
+    num_rejected_keys: Arc<AtomicU64>,
+}
+
+impl KeyValidation {
+    pub fn new(
+        key_validator_opt: Option<KeyValidator>,
+        value_transform_opt: Option<ValueTransform>,
+    ) -> Self {
+        KeyValidation {
+            key_validator_opt,
+            value_transform_opt,
+            num_rejected_keys: Default::default(),
+        }
+    }
+
+    /// Passes `value` through the value transform, if any. Returns `false` if the key-value must
+    /// be dropped.
+    fn transform_value(&self, chitchat_id: &ChitchatId, key: &str, value: &mut Arc<str>) -> bool {
+        let Some(value_transform) = &self.value_transform_opt else {
+            return true;
+        };
+        match value_transform(key, value) {
+            Some(new_value) => {
+                if *new_value != **value {
+                    *value = new_value.into();
+                }
+                true
+            }
+            None => {
+                warn!(node=?chitchat_id, key=%key, "value dropped by the value transform");
+                false
+            }
+        }
+    }
+
+    fn is_valid(&self, chitchat_id: &ChitchatId, key: &str) -> bool {
+        let Some(key_validator) = &self.key_validator_opt else {
+            return true;
+        };
+        if key_validator(key) {
+            return true;
+        }
+        warn!(node=?chitchat_id, key=%key, "rejected invalid key");
+        self.num_rejected_keys.fetch_add(1, AtomicOrdering::Relaxed);
+        false
+    }
+
+    pub fn num_rejected_keys(&self) -> u64 {
+        self.num_rejected_keys.load(AtomicOrdering::Relaxed)
+    }
+}
+
+pub(crate) struct ClusterState {
+    pub(crate) node_states: BTreeMap<ChitchatId, NodeState>,
+    seed_addrs: watch::Receiver<HashSet<SocketAddr>>,
+    pub(crate) listeners: Listeners,
+    pub(crate) key_validation: KeyValidation,
+    key_value_history_depth: usize,
+    // The most recently applied node deltas, from the oldest to the most recent.
+    recent_deltas: VecDeque<AppliedNodeDelta>,
+    recent_deltas_capacity: usize,
+    clock: SharedClock,
+    // Number of deltas that could not carry all the stale key-values because of the MTU.
+    num_truncated_deltas: AtomicU64,
+    // Number of node resets sent to peers, see `ClusterEvent::NodeResetSent`.
+    num_sent_node_resets: AtomicU64,
+    // Max version of the nodes last reported as having key-values too large to fit in a delta,
+    // so that they are reported once per change of their state rather than once per delta.
+    oversized_nodes: Mutex<HashMap<ChitchatId, Version>>,
+}
+
+impl Debug for ClusterState {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("seed_addrs", &self.seed_addrs.borrow())
+            .field("node_states", &self.node_states)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+impl Default for ClusterState {
+    fn default() -> Self {
+        let (_seed_addrs_tx, seed_addrs_rx) = watch::channel(Default::default());
+        Self {
+            node_states: Default::default(),
+            seed_addrs: seed_addrs_rx,
+            listeners: Default::default(),
+            key_validation: Default::default(),
+            key_value_history_depth: 0,
+            recent_deltas: VecDeque::new(),
+            recent_deltas_capacity: 0,
+            clock: SharedClock::default(),
+            num_truncated_deltas: AtomicU64::new(0),
+            num_sent_node_resets: AtomicU64::new(0),
+            oversized_nodes: Mutex::default(),
+        }
+    }
+}
+
+impl ClusterState {
+    pub fn with_seed_addrs(
+        seed_addrs: watch::Receiver<HashSet<SocketAddr>>,
+        key_validator_opt: Option<KeyValidator>,
+        value_transform_opt: Option<ValueTransform>,
+        key_value_history_depth: usize,
+        recent_deltas_capacity: usize,
+        clock: SharedClock,
+    ) -> ClusterState {
+        ClusterState {
+            seed_addrs,
+            node_states: BTreeMap::new(),
+            listeners: Default::default(),
+            key_validation: KeyValidation::new(key_validator_opt, value_transform_opt),
+            key_value_history_depth,
+            recent_deltas: VecDeque::with_capacity(recent_deltas_capacity),
+            recent_deltas_capacity,
+            clock,
+            num_truncated_deltas: AtomicU64::new(0),
+            num_sent_node_resets: AtomicU64::new(0),
+            oversized_nodes: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn node_state_mut(&mut self, chitchat_id: &ChitchatId) -> &mut NodeState {
+        // TODO use the `hash_raw_entry` feature once it gets stabilized.
+        // Most of the time the entry is already present. We avoid cloning chitchat_id with
+        // this if statement.
+        self.node_states
+            .entry(chitchat_id.clone())
+            .or_insert_with(|| {
+                NodeState::new(
+                    chitchat_id.clone(),
+                    self.listeners.clone(),
+                    self.key_validation.clone(),
+                    self.key_value_history_depth,
+                    self.clock.clone(),
+                )
+            })
+    }
+
+    pub fn node_state(&self, chitchat_id: &ChitchatId) -> Option<&NodeState> {
         self.node_states.get(chitchat_id)
     }
 
@@ -551,23 +1448,166 @@ impl ClusterState {
         self.node_states.keys()
     }
 
+    /// Returns the number of nodes, including the self node.
+    pub fn num_nodes(&self) -> usize {
+        self.node_states.len()
+    }
+
+    /// Returns the number of deltas computed so far that could not carry all the stale
+    /// key-values because of the MTU.
+    pub fn num_truncated_deltas(&self) -> u64 {
+        self.num_truncated_deltas.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Returns the number of node resets sent to peers so far. See
+    /// [`ClusterEvent::NodeResetSent`].
+    pub fn num_sent_node_resets(&self) -> u64 {
+        self.num_sent_node_resets.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Returns the number of key-values marked for deletion and not garbage collected yet, summed
+    /// over all the nodes.
+    pub fn num_tombstones(&self) -> usize {
+        self.node_states
+            .values()
+            .map(|node_state| {
+                node_state
+                    .key_values_including_deleted()
+                    .filter(|(_, versioned_value)| versioned_value.is_deleted())
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Returns the serialized length of the state of each node, as it would appear in a delta
+    /// sent to a peer that knows nothing about the node, before compression.
+    ///
+    /// This helps identify the nodes that take up most of the gossip budget.
+    pub fn per_node_serialized_len(&self) -> BTreeMap<ChitchatId, usize> {
+        self.node_states
+            .iter()
+            .map(|(chitchat_id, node_state)| {
+                // One tag byte, the node ID, the last GC version, and the "from" version.
+                let node_op_len = 1
+                    + chitchat_id.serialized_len()
+                    + node_state.last_gc_version.serialized_len()
+                    + 0u64.serialized_len();
+                let key_value_ops_len: usize = node_state
+                    .key_values_including_deleted()
+                    .map(|(key, versioned_value)| {
+                        let key_value_mutation = KeyValueMutationRef {
+                            key,
+                            value: &versioned_value.value,
+                            version: versioned_value.version,
+                            state: versioned_value.status.into(),
+                        };
+                        1 + key_value_mutation.serialized_len()
+                    })
+                    .sum();
+                (chitchat_id.clone(), node_op_len + key_value_ops_len)
+            })
+            .collect()
+    }
+
     pub fn seed_addrs(&self) -> HashSet<SocketAddr> {
         self.seed_addrs.borrow().clone()
     }
 
+    /// Returns a receiver over the seed addresses, notified whenever they are re-resolved.
+    pub fn seed_addrs_watcher(&self) -> watch::Receiver<HashSet<SocketAddr>> {
+        self.seed_addrs.clone()
+    }
+
+    /// Returns true if the seed addresses are `seed_addrs`, without cloning them.
+    pub(crate) fn seed_addrs_eq(&self, seed_addrs: &HashSet<SocketAddr>) -> bool {
+        *self.seed_addrs.borrow() == *seed_addrs
+    }
+
     pub(crate) fn remove_node(&mut self, chitchat_id: &ChitchatId) {
         self.node_states.remove(chitchat_id);
+        self.oversized_nodes.lock().unwrap().remove(chitchat_id);
+    }
+
+    /// Records that the key-values of a node at `max_version` cannot fit in a delta, and returns
+    /// whether this was not reported yet for this state of the node.
+    fn record_oversized_node(&self, chitchat_id: &ChitchatId, max_version: Version) -> bool {
+        let mut oversized_nodes = self.oversized_nodes.lock().unwrap();
+        oversized_nodes.insert(chitchat_id.clone(), max_version) != Some(max_version)
+    }
+
+    /// Removes a key-value from our view of a node's state, without leaving a tombstone. Returns
+    /// `false` if the node or the key is unknown.
+    pub(crate) fn forget_key(&mut self, chitchat_id: &ChitchatId, key: &str) -> bool {
+        let Some(node_state) = self.node_states.get_mut(chitchat_id) else {
+            return false;
+        };
+        if node_state.get_versioned(key).is_none() {
+            return false;
+        }
+        node_state.remove_key_value_internal(key);
+        true
     }
 
     pub(crate) fn apply_delta(&mut self, delta: Delta) {
-        let now = Instant::now();
+        let now = self.clock.now();
         // Apply delta.
         for node_delta in delta.node_deltas {
+            let applied_node_delta_opt =
+                (self.recent_deltas_capacity > 0).then(|| AppliedNodeDelta {
+                    chitchat_id: node_delta.chitchat_id.clone(),
+                    from_version_excluded: node_delta.from_version_excluded,
+                    last_gc_version: node_delta.last_gc_version,
+                    num_key_values: node_delta.key_values.len(),
+                    reset: false,
+                });
             let node_state = self.node_state_mut(&node_delta.chitchat_id);
-            node_state.apply_delta(node_delta, now);
+            let delta_preparation = node_state.apply_delta(node_delta, now);
+
+            if let Some(mut applied_node_delta) = applied_node_delta_opt {
+                if delta_preparation == DeltaPreparation::Ignore {
+                    continue;
+                }
+                applied_node_delta.reset = delta_preparation == DeltaPreparation::ResetAndApply;
+                if self.recent_deltas.len() == self.recent_deltas_capacity {
+                    self.recent_deltas.pop_front();
+                }
+                self.recent_deltas.push_back(applied_node_delta);
+            }
         }
     }
 
+    /// Returns the most recently applied node deltas, from the oldest to the most recent.
+    pub fn recent_deltas(&self) -> impl Iterator<Item = &AppliedNodeDelta> {
+        self.recent_deltas.iter()
+    }
+
+    /// Returns an iterator over the node states along with the digest of the same node in
+    /// `digest`, if any.
+    ///
+    /// The node states and the digest are both sorted by node, so they are walked in lockstep
+    /// rather than looking each node up in the digest. On large clusters, these lookups are the
+    /// bulk of the cost of computing a delta.
+    fn node_states_with_digest<'a>(
+        &'a self,
+        digest: &'a Digest,
+    ) -> impl Iterator<Item = (&'a ChitchatId, &'a NodeState, Option<&'a NodeDigest>)> {
+        self.node_states
+            .iter()
+            .merge_join_by(
+                &digest.node_digests,
+                |(chitchat_id, _), (digest_chitchat_id, _)| chitchat_id.cmp(digest_chitchat_id),
+            )
+            .filter_map(|node_state_and_digest| match node_state_and_digest {
+                EitherOrBoth::Both((chitchat_id, node_state), (_, node_digest)) => {
+                    Some((chitchat_id, node_state, Some(node_digest)))
+                }
+                EitherOrBoth::Left((chitchat_id, node_state)) => {
+                    Some((chitchat_id, node_state, None))
+                }
+                EitherOrBoth::Right(_) => None,
+            })
+    }
+
     pub fn compute_digest(&self, scheduled_for_deletion: &HashSet<&ChitchatId>) -> Digest {
         Digest {
             node_digests: self
@@ -585,25 +1625,130 @@ impl ClusterState {
         }
     }
 
+    /// Returns the number of versions the owner of `digest` is missing compared to our state,
+    /// summed over all the nodes.
+    ///
+    /// Nodes that are scheduled for deletion (as passed by argument) are not taken into account.
+    pub(crate) fn num_versions_behind(
+        &self,
+        digest: &Digest,
+        scheduled_for_deletion: &HashSet<&ChitchatId>,
+    ) -> u64 {
+        self.node_states_with_digest(digest)
+            .filter(|(chitchat_id, _, _)| !scheduled_for_deletion.contains(chitchat_id))
+            .map(|(_, node_state, node_digest_opt)| {
+                let digest_max_version = node_digest_opt
+                    .map(|node_digest| node_digest.max_version)
+                    .unwrap_or(0);
+                node_state.max_version.saturating_sub(digest_max_version)
+            })
+            .sum()
+    }
+
+    /// Returns the number of versions our state is missing compared to the owner of `digest`,
+    /// summed over all the nodes but the self node, which peers cannot be ahead of.
+    pub(crate) fn num_versions_ahead(&self, digest: &Digest, self_chitchat_id: &ChitchatId) -> u64 {
+        digest
+            .node_digests
+            .iter()
+            .filter(|(chitchat_id, _)| *chitchat_id != self_chitchat_id)
+            .map(|(chitchat_id, node_digest)| {
+                let max_version = self
+                    .node_states
+                    .get(chitchat_id)
+                    .map(|node_state| node_state.max_version)
+                    .unwrap_or(0);
+                node_digest.max_version.saturating_sub(max_version)
+            })
+            .sum()
+    }
+
     /// Implements the Scuttlebutt reconciliation with the scuttle-depth ordering.
     ///
     /// Nodes that are scheduled for deletion (as passed by argument) are not shared.
+    #[cfg(test)]
     pub fn compute_partial_delta_respecting_mtu(
         &self,
         digest: &Digest,
         mtu: usize,
         scheduled_for_deletion: &HashSet<&ChitchatId>,
     ) -> Delta {
+        let (delta, is_truncated, reset_nodes) =
+            self.compute_partial_delta(digest, mtu, scheduled_for_deletion);
+        self.record_sent_delta(is_truncated, reset_nodes.iter().cloned());
+        delta
+    }
+
+    /// Same as [`ClusterState::compute_partial_delta_respecting_mtu`], but reuses the delta
+    /// previously computed for a digest carrying the same versions, if the cluster state has not
+    /// changed since.
+    pub(crate) fn compute_partial_delta_coalesced(
+        &self,
+        delta_cache: &mut DeltaCache,
+        digest: &Digest,
+        mtu: usize,
+        scheduled_for_deletion: &HashSet<&ChitchatId>,
+    ) -> Delta {
+        if !delta_cache.is_up_to_date(self) {
+            delta_cache.reset(self);
+        }
+        if let Some(cached_delta) = delta_cache.get(digest, mtu, scheduled_for_deletion) {
+            self.record_sent_delta(
+                cached_delta.is_truncated,
+                cached_delta.reset_nodes.iter().cloned(),
+            );
+            let delta = cached_delta.delta.clone();
+            delta_cache.num_coalesced_deltas += 1;
+            return delta;
+        }
+        let (delta, is_truncated, reset_nodes) =
+            self.compute_partial_delta(digest, mtu, scheduled_for_deletion);
+        self.record_sent_delta(is_truncated, reset_nodes.iter().cloned());
+        delta_cache.insert(CachedDelta {
+            digest: digest.clone(),
+            mtu,
+            scheduled_for_deletion: scheduled_for_deletion
+                .iter()
+                .map(|chitchat_id| (*chitchat_id).clone())
+                .collect(),
+            delta: delta.clone(),
+            is_truncated,
+            reset_nodes,
+        });
+        delta
+    }
+
+    fn record_sent_delta(&self, is_truncated: bool, reset_nodes: impl Iterator<Item = ChitchatId>) {
+        if is_truncated {
+            self.num_truncated_deltas
+                .fetch_add(1, AtomicOrdering::Relaxed);
+        }
+        for reset_node in reset_nodes {
+            self.num_sent_node_resets
+                .fetch_add(1, AtomicOrdering::Relaxed);
+            self.listeners
+                .trigger_cluster_event(|| ClusterEvent::NodeResetSent(reset_node));
+        }
+    }
+
+    /// Same as [`ClusterState::compute_partial_delta_respecting_mtu`], without recording any
+    /// statistics. Also returns whether the delta was truncated because of the MTU, and the nodes
+    /// whose state the delta resets.
+    pub(crate) fn compute_partial_delta(
+        &self,
+        digest: &Digest,
+        mtu: usize,
+        scheduled_for_deletion: &HashSet<&ChitchatId>,
+    ) -> (Delta, bool, Vec<ChitchatId>) {
         let mut stale_nodes = SortedStaleNodes::default();
+        let mut nodes_to_reset: HashSet<&ChitchatId> = HashSet::new();
 
-        for (chitchat_id, node_state) in &self.node_states {
+        for (chitchat_id, node_state, node_digest_opt) in self.node_states_with_digest(digest) {
             if scheduled_for_deletion.contains(chitchat_id) {
                 continue;
             }
 
-            let (digest_last_gc_version, digest_max_version) = digest
-                .node_digests
-                .get(chitchat_id)
+            let (digest_last_gc_version, digest_max_version) = node_digest_opt
                 .map(|node_digest| (node_digest.last_gc_version, node_digest.max_version))
                 .unwrap_or((0u64, 0u64));
 
@@ -623,6 +1768,7 @@ impl ClusterState {
                     "Node to reset {chitchat_id:?} last gc version: {} max version: {}",
                     node_state.last_gc_version, digest_max_version
                 );
+                nodes_to_reset.insert(chitchat_id);
                 0u64
             } else {
                 digest_max_version
@@ -631,34 +1777,185 @@ impl ClusterState {
             stale_nodes.offer(chitchat_id, node_state, from_version_excluded);
         }
         let mut delta_serializer = DeltaSerializer::with_mtu(mtu);
+        let mut is_delta_empty = true;
+        let mut is_truncated = false;
+        let mut reset_nodes: Vec<ChitchatId> = Vec::new();
 
-        for stale_node in stale_nodes.into_iter() {
+        'stale_nodes: for stale_node in stale_nodes.into_iter() {
             if !delta_serializer.try_add_node(
                 stale_node.chitchat_id.clone(),
                 stale_node.node_state.last_gc_version,
                 stale_node.from_version_excluded,
             ) {
+                is_truncated = true;
                 break;
             };
+            if nodes_to_reset.contains(stale_node.chitchat_id) {
+                reset_nodes.push(stale_node.chitchat_id.clone());
+            }
 
             let mut added_something = false;
-            for (key, versioned_value) in stale_node.stale_key_values() {
-                if !delta_serializer.try_add_kv(key, versioned_value.clone()) {
-                    return delta_serializer.finish();
+            let stale_key_values_per_version = stale_node
+                .stale_key_values()
+                .chunk_by(|(_, versioned_value)| versioned_value.version);
+            for (_version, stale_key_values) in &stale_key_values_per_version {
+                // Key-values sharing a version must be sent together: once the receiver has
+                // applied a version, it will never ask for the missing ones.
+                let stale_key_values: Vec<(&str, &VersionedValue)> = stale_key_values.collect();
+                let checkpoint_opt = (stale_key_values.len() > 1).then(|| delta_serializer.clone());
+                for (key, versioned_value) in stale_key_values {
+                    if !delta_serializer.try_add_kv(key, versioned_value.clone()) {
+                        if is_delta_empty && !added_something {
+                            // These key-values do not fit in a delta on their own: they can never
+                            // be gossiped with this MTU. Rather than stalling the whole delta, we
+                            // skip the node so that the other nodes can still converge. This
+                            // happens on every delta, so it is only logged once per node state.
+                            if self.record_oversized_node(
+                                stale_node.chitchat_id,
+                                stale_node.node_state.max_version,
+                            ) {
+                                warn!(
+                                    node=?stale_node.chitchat_id,
+                                    key=key,
+                                    version=versioned_value.version,
+                                    mtu=mtu,
+                                    "key-values too large to fit in a gossip message, the node \
+                                     cannot converge"
+                                );
+                            }
+                            delta_serializer = DeltaSerializer::with_mtu(mtu);
+                            reset_nodes.clear();
+                            continue 'stale_nodes;
+                        }
+                        if let Some(checkpoint) = checkpoint_opt {
+                            delta_serializer = checkpoint;
+                        }
+                        return (delta_serializer.finish(), true, reset_nodes);
+                    }
                 }
                 added_something = true;
+                is_delta_empty = false;
             }
-            // There aren't any key-values in the state_node apparently.
-            // Let's add a specific instruction to the delta to set the max version.
+            // The node has no key-values above `from_version_excluded`, e.g. because they were all
+            // tombstones that have been garbage collected since. We still need to tell the peer
+            // about the max version, so that a node state reset to the empty state catches up.
+            // See `NodeDelta::max_version`.
             if !added_something {
                 // This call returns false if the mtu has been reached.
                 //
                 // In that case, this empty node update is useless but does not hurt correctness.
                 let _ = delta_serializer.try_set_max_version(stale_node.node_state.max_version);
             }
+            is_delta_empty = false;
         }
 
-        delta_serializer.finish()
+        (delta_serializer.finish(), is_truncated, reset_nodes)
+    }
+}
+
+/// Maximum number of distinct deltas kept by a [`DeltaCache`].
+const DELTA_CACHE_CAPACITY: usize = 8;
+
+/// Deltas computed since the beginning of the current gossip round.
+///
+/// Peers at the same versions send digests that only differ by their heartbeats, which deltas do
+/// not depend on, so the delta computed for one of them can be sent to the others. Cached deltas
+/// are dropped as soon as the cluster state changes, and at every gossip round so that the nodes
+/// sharing the same staleness keep being shuffled.
+#[derive(Default)]
+pub(crate) struct DeltaCache {
+    fingerprints: Vec<(ChitchatId, DeltaFingerprint)>,
+    cached_deltas: Vec<CachedDelta>,
+    // Number of deltas reused rather than computed.
+    num_coalesced_deltas: u64,
+}
+
+/// Returns whether `key` is maintained by chitchat itself rather than by the application. Such keys
+/// can still be written while the node is draining, e.g. so that it can advertise it is no longer
+/// ready.
+fn is_internal_key(key: &str) -> bool {
+    key == READY_KEY || key == CONFIG_EPOCH_KEY
+}
+
+/// Summarizes what the deltas computed for a node depend on.
+type DeltaFingerprint = (Version, Version, usize, Option<Version>);
+
+fn delta_fingerprint(node_state: &NodeState) -> DeltaFingerprint {
+    (
+        node_state.max_version,
+        node_state.last_gc_version,
+        node_state.num_key_values(),
+        node_state.critical_version_opt,
+    )
+}
+
+struct CachedDelta {
+    digest: Digest,
+    mtu: usize,
+    scheduled_for_deletion: HashSet<ChitchatId>,
+    delta: Delta,
+    is_truncated: bool,
+    reset_nodes: Vec<ChitchatId>,
+}
+
+impl DeltaCache {
+    pub fn clear(&mut self) {
+        self.fingerprints.clear();
+        self.cached_deltas.clear();
+    }
+
+    pub fn num_coalesced_deltas(&self) -> u64 {
+        self.num_coalesced_deltas
+    }
+
+    fn is_up_to_date(&self, cluster_state: &ClusterState) -> bool {
+        self.fingerprints.len() == cluster_state.node_states.len()
+            && self
+                .fingerprints
+                .iter()
+                .zip(&cluster_state.node_states)
+                .all(
+                    |((chitchat_id, fingerprint), (node_chitchat_id, node_state))| {
+                        chitchat_id == node_chitchat_id
+                            && *fingerprint == delta_fingerprint(node_state)
+                    },
+                )
+    }
+
+    fn reset(&mut self, cluster_state: &ClusterState) {
+        self.cached_deltas.clear();
+        self.fingerprints.clear();
+        self.fingerprints.extend(
+            cluster_state
+                .node_states
+                .iter()
+                .map(|(chitchat_id, node_state)| {
+                    (chitchat_id.clone(), delta_fingerprint(node_state))
+                }),
+        );
+    }
+
+    fn get(
+        &self,
+        digest: &Digest,
+        mtu: usize,
+        scheduled_for_deletion: &HashSet<&ChitchatId>,
+    ) -> Option<&CachedDelta> {
+        self.cached_deltas.iter().find(|cached_delta| {
+            cached_delta.mtu == mtu
+                && cached_delta.digest.has_same_versions(digest)
+                && cached_delta.scheduled_for_deletion.len() == scheduled_for_deletion.len()
+                && scheduled_for_deletion
+                    .iter()
+                    .all(|chitchat_id| cached_delta.scheduled_for_deletion.contains(*chitchat_id))
+        })
+    }
+
+    fn insert(&mut self, cached_delta: CachedDelta) {
+        if self.cached_deltas.len() == DELTA_CACHE_CAPACITY {
+            self.cached_deltas.remove(0);
+        }
+        self.cached_deltas.push(cached_delta);
     }
 }
 
@@ -668,6 +1965,7 @@ impl ClusterState {
 /// local version is higher than the max version of the digest, also called "floor version".
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 struct Staleness {
+    carries_critical_key: bool,
     is_unknown: bool,
     max_version: u64,
     num_stale_key_values: usize,
@@ -685,14 +1983,19 @@ impl Ord for Staleness {
         //
         // Within known nodes, the one with the highest number of stale records gets gossiped first,
         // as described in the scuttlebutt paper.
-        self.is_unknown.cmp(&other.is_unknown).then_with(|| {
-            if self.is_unknown {
-                self.max_version.cmp(&other.max_version).reverse()
-            } else {
-                // Then nodes with the highest number of stale records get higher priority.
-                self.num_stale_key_values.cmp(&other.num_stale_key_values)
-            }
-        })
+        //
+        // All of this comes after the node carrying critical keys the peer is missing, if any.
+        self.carries_critical_key
+            .cmp(&other.carries_critical_key)
+            .then_with(|| self.is_unknown.cmp(&other.is_unknown))
+            .then_with(|| {
+                if self.is_unknown {
+                    self.max_version.cmp(&other.max_version).reverse()
+                } else {
+                    // Then nodes with the highest number of stale records get higher priority.
+                    self.num_stale_key_values.cmp(&other.num_stale_key_values)
+                }
+            })
     }
 }
 
@@ -729,7 +2032,11 @@ fn staleness_score(node_state: &NodeState, floor_version: u64) -> Option<Stalene
     } else {
         node_state.stale_key_values(floor_version).count()
     };
+    let carries_critical_key = node_state
+        .critical_version_opt
+        .is_some_and(|critical_version| critical_version > floor_version);
     Some(Staleness {
+        carries_critical_key,
         is_unknown,
         max_version: node_state.max_version,
         num_stale_key_values,
@@ -787,15 +2094,14 @@ struct StaleNode<'a> {
 impl StaleNode<'_> {
     /// Iterates over the stale key-value pairs in decreasing order of staleness.
     fn stale_key_values(&self) -> impl Iterator<Item = (&str, &VersionedValue)> {
-        self.node_state
-            .stale_key_values(self.from_version_excluded)
-            .sorted_unstable_by_key(|(_, versioned_value)| versioned_value.version)
+        self.node_state.stale_key_values(self.from_version_excluded)
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClusterStateSnapshot {
     pub node_states: Vec<NodeState>,
+    #[serde(serialize_with = "serialize_sorted_seed_addrs")]
     pub seed_addrs: HashSet<SocketAddr>,
 }
 
@@ -809,6 +2115,266 @@ impl From<&ClusterState> for ClusterStateSnapshot {
     }
 }
 
+/// Serializes the seed addresses in order, so that snapshots serialize deterministically.
+fn serialize_sorted_seed_addrs<S: Serializer>(
+    seed_addrs: &HashSet<SocketAddr>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(seed_addrs.iter().collect::<BTreeSet<_>>())
+}
+
+/// Version of the binary snapshot format, see [`ClusterStateSnapshot::to_bytes`].
+const SNAPSHOT_FORMAT_VERSION: u8 = 1;
+
+impl ClusterStateSnapshot {
+    /// Serializes the snapshot with the compact binary encoding of deltas, e.g. to persist it to
+    /// disk. The output is much smaller and faster to load than the JSON serialization of the
+    /// snapshot. See `WIRE_FORMAT.md` for a description of the format.
+    ///
+    /// Local key-values are not part of the snapshot, and neither are the instants at which
+    /// key-values were marked for deletion: as for deltas, tombstones are timestamped when the
+    /// snapshot is loaded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut stream_writer = CompressedStreamWriter::with_block_threshold(16_384);
+        let mut item_buf = Vec::new();
+        let mut append_item = |item: SnapshotItemRef| {
+            item_buf.clear();
+            item.serialize(&mut item_buf);
+            // Stream items are limited to `u16::MAX` bytes, but items may span several blocks.
+            for chunk in item_buf.chunks(u16::MAX as usize) {
+                stream_writer.append(&RawBytes(chunk));
+            }
+        };
+        for seed_addr in self.seed_addrs.iter().collect::<BTreeSet<_>>() {
+            append_item(SnapshotItemRef::SeedAddr(*seed_addr));
+        }
+        for node_state in &self.node_states {
+            append_item(SnapshotItemRef::Node(node_state));
+            for (key, versioned_value) in &node_state.key_values {
+                append_item(SnapshotItemRef::KeyValue(KeyValueMutationRef {
+                    key,
+                    value: &versioned_value.value,
+                    version: versioned_value.version,
+                    state: versioned_value.status.into(),
+                }));
+            }
+        }
+        let mut buf = vec![SNAPSHOT_FORMAT_VERSION];
+        buf.extend(stream_writer.finish());
+        buf
+    }
+
+    /// Deserializes a snapshot serialized with [`ClusterStateSnapshot::to_bytes`]. The tombstones
+    /// of the snapshot are timestamped with `now`, e.g. the current instant of the configured
+    /// [`Clock`](crate::Clock).
+    ///
+    /// Fails if a version exceeds [`MAX_VERSION`], or if a node has a last GC version or
+    /// key-values above its max version.
+    pub fn from_bytes(bytes: &[u8], now: Instant) -> anyhow::Result<ClusterStateSnapshot> {
+        let mut buf = bytes;
+        let format_version = <u8 as Deserializable>::deserialize(&mut buf)
+            .context("failed to deserialize snapshot format version")?;
+        if format_version != SNAPSHOT_FORMAT_VERSION {
+            bail!("unsupported snapshot format version {format_version}");
+        }
+        let items: Vec<SnapshotItem> =
+            deserialize_stream(&mut buf).context("failed to deserialize snapshot items")?;
+        if !buf.is_empty() {
+            bail!("{} trailing bytes after snapshot", buf.len());
+        }
+        let mut seed_addrs = HashSet::new();
+        let mut node_states: Vec<NodeState> = Vec::new();
+        for (item_idx, item) in items.into_iter().enumerate() {
+            match item {
+                SnapshotItem::SeedAddr(seed_addr) => {
+                    seed_addrs.insert(seed_addr);
+                }
+                SnapshotItem::Node {
+                    chitchat_id,
+                    heartbeat,
+                    last_gc_version,
+                    max_version,
+                } => {
+                    if max_version > MAX_VERSION || last_gc_version > max_version {
+                        bail!(
+                            "snapshot item #{item_idx} is a node with invalid versions: last GC \
+                             version {last_gc_version}, max version {max_version}"
+                        );
+                    }
+                    let mut node_state = NodeState::new(
+                        chitchat_id,
+                        Listeners::default(),
+                        KeyValidation::default(),
+                        0,
+                        SharedClock::default(),
+                    );
+                    node_state.heartbeat = heartbeat;
+                    node_state.last_gc_version = last_gc_version;
+                    node_state.max_version = max_version;
+                    node_states.push(node_state);
+                }
+                SnapshotItem::KeyValue(key_value_mutation) => {
+                    let Some(node_state) = node_states.last_mut() else {
+                        bail!("snapshot item #{item_idx} is a key-value preceding any node");
+                    };
+                    if key_value_mutation.version > node_state.max_version {
+                        bail!(
+                            "snapshot item #{item_idx} is a key-value with version {} above the \
+                             max version {} of its node",
+                            key_value_mutation.version,
+                            node_state.max_version
+                        );
+                    }
+                    let versioned_value = VersionedValue {
+                        value: key_value_mutation.value,
+                        version: key_value_mutation.version,
+                        status: key_value_mutation.status.into_status(now),
+                    };
+                    node_state
+                        .key_values
+                        .insert(key_value_mutation.key, versioned_value);
+                }
+            }
+        }
+        for node_state in &mut node_states {
+            node_state.keys_by_version = KeysByVersion::from_key_values(&node_state.key_values);
+        }
+        Ok(ClusterStateSnapshot {
+            node_states,
+            seed_addrs,
+        })
+    }
+}
+
+#[repr(u8)]
+enum SnapshotItemTag {
+    SeedAddr = 0u8,
+    Node = 1u8,
+    KeyValue = 2u8,
+}
+
+enum SnapshotItemRef<'a> {
+    SeedAddr(SocketAddr),
+    Node(&'a NodeState),
+    KeyValue(KeyValueMutationRef<'a>),
+}
+
+impl Serializable for SnapshotItemRef<'_> {
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            SnapshotItemRef::SeedAddr(seed_addr) => {
+                buf.push(SnapshotItemTag::SeedAddr as u8);
+                Serializable::serialize(seed_addr, buf);
+            }
+            SnapshotItemRef::Node(node_state) => {
+                buf.push(SnapshotItemTag::Node as u8);
+                Serializable::serialize(&node_state.chitchat_id, buf);
+                Serializable::serialize(&node_state.heartbeat, buf);
+                Serializable::serialize(&node_state.last_gc_version, buf);
+                Serializable::serialize(&node_state.max_version, buf);
+            }
+            SnapshotItemRef::KeyValue(key_value_mutation) => {
+                buf.push(SnapshotItemTag::KeyValue as u8);
+                Serializable::serialize(key_value_mutation, buf);
+            }
+        }
+    }
+
+    fn serialized_len(&self) -> usize {
+        1 + match self {
+            SnapshotItemRef::SeedAddr(seed_addr) => Serializable::serialized_len(seed_addr),
+            SnapshotItemRef::Node(node_state) => {
+                Serializable::serialized_len(&node_state.chitchat_id)
+                    + Serializable::serialized_len(&node_state.heartbeat)
+                    + Serializable::serialized_len(&node_state.last_gc_version)
+                    + Serializable::serialized_len(&node_state.max_version)
+            }
+            SnapshotItemRef::KeyValue(key_value_mutation) => {
+                Serializable::serialized_len(key_value_mutation)
+            }
+        }
+    }
+}
+
+enum SnapshotItem {
+    SeedAddr(SocketAddr),
+    Node {
+        chitchat_id: ChitchatId,
+        heartbeat: Heartbeat,
+        last_gc_version: Version,
+        max_version: Version,
+    },
+    KeyValue(KeyValueMutation),
+}
+
+impl Deserializable for SnapshotItem {
+    fn deserialize(buf: &mut &[u8]) -> anyhow::Result<Self> {
+        let tag = <u8 as Deserializable>::deserialize(buf)
+            .context("failed to deserialize snapshot item tag")?;
+        let item = match tag {
+            tag if tag == SnapshotItemTag::SeedAddr as u8 => SnapshotItem::SeedAddr(
+                <SocketAddr as Deserializable>::deserialize(buf)
+                    .context("failed to deserialize seed address")?,
+            ),
+            tag if tag == SnapshotItemTag::Node as u8 => {
+                let chitchat_id = <ChitchatId as Deserializable>::deserialize(buf)
+                    .context("failed to deserialize chitchat id")?;
+                let heartbeat = <Heartbeat as Deserializable>::deserialize(buf)
+                    .context("failed to deserialize heartbeat")?;
+                let last_gc_version = <Version as Deserializable>::deserialize(buf)
+                    .context("failed to deserialize last gc version")?;
+                let max_version = <Version as Deserializable>::deserialize(buf)
+                    .context("failed to deserialize max version")?;
+                SnapshotItem::Node {
+                    chitchat_id,
+                    heartbeat,
+                    last_gc_version,
+                    max_version,
+                }
+            }
+            tag if tag == SnapshotItemTag::KeyValue as u8 => SnapshotItem::KeyValue(
+                <KeyValueMutation as Deserializable>::deserialize(buf)
+                    .context("failed to deserialize key-value")?,
+            ),
+            _ => bail!("unknown snapshot item tag: {tag}"),
+        };
+        Ok(item)
+    }
+}
+
+/// Bytes appended verbatim to a compressed stream.
+struct RawBytes<'a>(&'a [u8]);
+
+impl Serializable for RawBytes<'_> {
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.0);
+    }
+
+    fn serialized_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Borrowed view of the cluster state.
+///
+/// It serializes exactly like [`ClusterStateSnapshot`] but does not clone the node states.
+#[derive(Debug, Serialize)]
+pub struct ClusterStateSnapshotRef<'a> {
+    pub node_states: Vec<&'a NodeState>,
+    #[serde(serialize_with = "serialize_sorted_seed_addrs")]
+    pub seed_addrs: HashSet<SocketAddr>,
+}
+
+impl<'a> From<&'a ClusterState> for ClusterStateSnapshotRef<'a> {
+    fn from(cluster_state: &'a ClusterState) -> Self {
+        let node_states = cluster_state.node_states.values().collect();
+        Self {
+            node_states,
+            seed_addrs: cluster_state.seed_addrs(),
+        }
+    }
+}
+
 #[cfg(not(test))]
 fn random_generator() -> impl Rng {
     rand::thread_rng()
@@ -824,7 +2390,10 @@ fn random_generator() -> impl Rng {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
+    use crate::clock::tests::ManualClock;
     use crate::serialize::Serializable;
     use crate::types::{DeletionStatusMutation, KeyValueMutation};
     use crate::MAX_UDP_DATAGRAM_PAYLOAD_SIZE;
@@ -895,6 +2464,7 @@ mod tests {
         node2_state.set_with_version("key_a", "value_a", 1);
         stale_nodes.offer(&node2, &node2_state, 0u64);
         let expected_staleness = Staleness {
+            carries_critical_key: false,
             is_unknown: true,
             max_version: 1,
             num_stale_key_values: 0,
@@ -907,6 +2477,7 @@ mod tests {
 
         stale_nodes.offer(&node3, &node3_state, 0u64);
         let expected_staleness = Staleness {
+            carries_critical_key: false,
             is_unknown: true,
             max_version: 3,
             num_stale_key_values: 3,
@@ -939,6 +2510,7 @@ mod tests {
         stale_nodes.offer(&node3, &node3_state, 1u64);
         assert_eq!(stale_nodes.stale_nodes.len(), 1);
         let expected_staleness = Staleness {
+            carries_critical_key: false,
             is_unknown: false,
             max_version: 1,
             num_stale_key_values: 2,
@@ -1002,6 +2574,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sorted_stale_nodes_critical_key_first() {
+        let node1 = ChitchatId::for_local_test(10_001);
+        let mut node_state1 = NodeState::for_test();
+        for version in 1..=5 {
+            node_state1.set_with_version(format!("key_{version}"), "value", version);
+        }
+        let node2 = ChitchatId::for_local_test(10_002);
+        let mut node_state2 = NodeState::for_test();
+        node_state2.set_with_version("key_a", "value_a", 1);
+        node_state2.set_with_version("critical_key", "value", 2);
+        node_state2.set_critical_version(Some(2));
+
+        // The peer lacks the critical key.
+        let mut stale_nodes = SortedStaleNodes::default();
+        stale_nodes.offer(&node1, &node_state1, 1);
+        stale_nodes.offer(&node2, &node_state2, 1);
+        assert_eq!(
+            stale_nodes
+                .into_iter()
+                .map(|stale_node| stale_node.chitchat_id.gossip_advertise_addr.port())
+                .collect::<Vec<_>>(),
+            vec![10_002, 10_001]
+        );
+
+        // The peer already has the critical key.
+        node_state2.set_with_version("key_b", "value_b", 3);
+        let mut stale_nodes = SortedStaleNodes::default();
+        stale_nodes.offer(&node1, &node_state1, 1);
+        stale_nodes.offer(&node2, &node_state2, 2);
+        assert_eq!(
+            stale_nodes
+                .into_iter()
+                .map(|stale_node| stale_node.chitchat_id.gossip_advertise_addr.port())
+                .collect::<Vec<_>>(),
+            vec![10_001, 10_002]
+        );
+    }
+
     #[test]
     fn test_cluster_state_missing_node() {
         let cluster_state = ClusterState::default();
@@ -1017,7 +2628,7 @@ mod tests {
         assert_eq!(
             node_state.get_versioned("key_a").unwrap(),
             &VersionedValue {
-                value: "".to_string(),
+                value: "".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
@@ -1032,7 +2643,7 @@ mod tests {
         assert_eq!(
             node_state.get_versioned("key_a").unwrap(),
             &VersionedValue {
-                value: "1".to_string(),
+                value: "1".into(),
                 version: 1,
                 status: DeletionStatus::Set,
             }
@@ -1041,52 +2652,507 @@ mod tests {
         assert_eq!(
             node_state.get_versioned("key_a").unwrap(),
             &VersionedValue {
-                value: "1".to_string(),
+                value: "1".into(),
+                version: 1,
+                status: DeletionStatus::Set,
+            }
+        );
+        assert_eq!(
+            node_state.get_versioned("key_b").unwrap(),
+            &VersionedValue {
+                value: "2".into(),
+                version: 2,
+                status: DeletionStatus::Set,
+            }
+        );
+        node_state.set("key_a", "3");
+        assert_eq!(
+            node_state.get_versioned("key_a").unwrap(),
+            &VersionedValue {
+                value: "3".into(),
+                version: 3,
+                status: DeletionStatus::Set
+            }
+        );
+    }
+
+    #[test]
+    fn test_cluster_state_snapshot_ref() {
+        let mut cluster_state = ClusterState::default();
+        cluster_state
+            .node_state_mut(&ChitchatId::for_local_test(10_001))
+            .set("key_a", "1");
+        let node_state = cluster_state.node_state_mut(&ChitchatId::for_local_test(10_002));
+        node_state.set("key_b", "2");
+        node_state.delete("key_b");
+
+        let snapshot = ClusterStateSnapshot::from(&cluster_state);
+        let snapshot_ref = ClusterStateSnapshotRef::from(&cluster_state);
+        assert_json_diff::assert_json_eq!(snapshot_ref, snapshot);
+    }
+
+    #[test]
+    fn test_cluster_state_snapshot_sorts_seed_addrs() {
+        let seed_addrs: Vec<SocketAddr> = (10_001..=10_010)
+            .rev()
+            .map(|port| ([127, 0, 0, 1], port).into())
+            .collect();
+        let (_seed_addrs_tx, seed_addrs_rx) =
+            watch::channel(seed_addrs.iter().copied().collect::<HashSet<_>>());
+        let cluster_state =
+            ClusterState::with_seed_addrs(seed_addrs_rx, None, None, 0, 0, SharedClock::default());
+
+        #[derive(Serialize)]
+        struct ExpectedSnapshot {
+            node_states: Vec<NodeState>,
+            seed_addrs: Vec<SocketAddr>,
+        }
+        let mut sorted_seed_addrs = seed_addrs;
+        sorted_seed_addrs.sort_unstable();
+        let expected_snapshot = ExpectedSnapshot {
+            node_states: Vec::new(),
+            seed_addrs: sorted_seed_addrs,
+        };
+        let snapshot = ClusterStateSnapshot::from(&cluster_state);
+        assert_json_diff::assert_json_eq!(snapshot, expected_snapshot);
+
+        let snapshot_ref = ClusterStateSnapshotRef::from(&cluster_state);
+        assert_json_diff::assert_json_eq!(snapshot_ref, expected_snapshot);
+    }
+
+    #[test]
+    fn test_cluster_state_snapshot_to_from_bytes() {
+        let seed_addrs: HashSet<SocketAddr> = [([127, 0, 0, 1], 10_001).into()].into();
+        let (_seed_addrs_tx, seed_addrs_rx) = watch::channel(seed_addrs);
+        let mut cluster_state =
+            ClusterState::with_seed_addrs(seed_addrs_rx, None, None, 0, 0, SharedClock::default());
+        let node_state = cluster_state.node_state_mut(&ChitchatId::for_local_test(10_001));
+        node_state.heartbeat = Heartbeat(7);
+        node_state.set("key_a", "1");
+        node_state.set("key_b", "2");
+        node_state.delete("key_b");
+        node_state.set_with_ttl("key_c", "3");
+        // Values larger than a stream item.
+        let large_value = "v".repeat(100_000);
+        let node_state = cluster_state.node_state_mut(&ChitchatId::for_local_test(10_002));
+        node_state.set("key_a", large_value.clone());
+        node_state.set_local("local_key", "local_value");
+
+        let snapshot = ClusterStateSnapshot::from(&cluster_state);
+        let snapshot_bytes = snapshot.to_bytes();
+        assert!(snapshot_bytes.len() < 1_000);
+
+        let deserialized_snapshot =
+            ClusterStateSnapshot::from_bytes(&snapshot_bytes, Instant::now()).unwrap();
+        assert_json_diff::assert_json_eq!(deserialized_snapshot, snapshot);
+        let node_state = &deserialized_snapshot.node_states[0];
+        assert_eq!(node_state.heartbeat(), Heartbeat(7));
+        assert_eq!(node_state.get("key_a"), Some("1"));
+        assert!(node_state.get_versioned("key_b").unwrap().is_deleted());
+        assert_eq!(node_state.max_version(), 4);
+        assert!(node_state
+            .keys_by_version
+            .is_in_sync_with(&node_state.key_values));
+        assert_eq!(
+            node_state
+                .stale_key_values(2)
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            ["key_b", "key_c"]
+        );
+        let node_state = &deserialized_snapshot.node_states[1];
+        assert_eq!(node_state.get("key_a"), Some(large_value.as_str()));
+        assert!(node_state.get_local("local_key").is_none());
+
+        let empty_snapshot = ClusterStateSnapshot::from(&ClusterState::default());
+        let deserialized_empty_snapshot =
+            ClusterStateSnapshot::from_bytes(&empty_snapshot.to_bytes(), Instant::now()).unwrap();
+        assert!(deserialized_empty_snapshot.node_states.is_empty());
+        assert!(deserialized_empty_snapshot.seed_addrs.is_empty());
+
+        let mut unknown_format_bytes = snapshot_bytes.clone();
+        unknown_format_bytes[0] = 2;
+        let error =
+            ClusterStateSnapshot::from_bytes(&unknown_format_bytes, Instant::now()).unwrap_err();
+        assert_eq!(error.to_string(), "unsupported snapshot format version 2");
+
+        let truncated_bytes = &snapshot_bytes[..snapshot_bytes.len() - 1];
+        assert!(ClusterStateSnapshot::from_bytes(truncated_bytes, Instant::now()).is_err());
+
+        // Versions are validated.
+        let mut invalid_snapshot = ClusterStateSnapshot::from(&cluster_state);
+        invalid_snapshot.node_states[0].max_version = MAX_VERSION + 1;
+        let error = ClusterStateSnapshot::from_bytes(&invalid_snapshot.to_bytes(), Instant::now())
+            .unwrap_err();
+        assert!(error.to_string().contains("invalid versions"));
+
+        let mut invalid_snapshot = ClusterStateSnapshot::from(&cluster_state);
+        invalid_snapshot.node_states[0].max_version = 3;
+        let error = ClusterStateSnapshot::from_bytes(&invalid_snapshot.to_bytes(), Instant::now())
+            .unwrap_err();
+        assert!(error.to_string().contains("above the max version 3"));
+    }
+
+    #[test]
+    fn test_cluster_state_snapshot_shares_values() {
+        let mut cluster_state = ClusterState::default();
+        let node_state = cluster_state.node_state_mut(&ChitchatId::for_local_test(10_001));
+        node_state.set("key_a", "1");
+
+        let snapshot = ClusterStateSnapshot::from(&cluster_state);
+        let node_state = cluster_state
+            .node_state(&ChitchatId::for_local_test(10_001))
+            .unwrap();
+        let snapshot_node_state = &snapshot.node_states[0];
+        assert!(Arc::ptr_eq(
+            &node_state.get_versioned("key_a").unwrap().value,
+            &snapshot_node_state.get_versioned("key_a").unwrap().value,
+        ));
+    }
+
+    #[test]
+    fn test_cluster_state_set_with_same_value_updates_version() {
+        let mut cluster_state = ClusterState::default();
+        let node_state = cluster_state.node_state_mut(&ChitchatId::for_local_test(10_001));
+        node_state.set("key", "1");
+        assert_eq!(
+            node_state.get_versioned("key").unwrap(),
+            &VersionedValue {
+                value: "1".into(),
                 version: 1,
+                status: DeletionStatus::Set
+            }
+        );
+        node_state.set("key", "1");
+        assert_eq!(
+            node_state.get_versioned("key").unwrap(),
+            &VersionedValue {
+                value: "1".into(),
+                version: 1,
+                status: DeletionStatus::Set,
+            }
+        );
+    }
+
+    #[test]
+    fn test_node_state_typed_values() {
+        let mut node_state = NodeState::for_test();
+        node_state.set_bool("ready", true);
+        node_state.set_bool("draining", false);
+        node_state.set_u64("num_shards", 42);
+        assert_eq!(node_state.get("ready"), Some("true"));
+        assert_eq!(node_state.get("draining"), Some("false"));
+        assert_eq!(node_state.get("num_shards"), Some("42"));
+        assert_eq!(node_state.get_bool("ready"), Some(true));
+        assert_eq!(node_state.get_bool("draining"), Some(false));
+        assert_eq!(node_state.get_u64("num_shards"), Some(42));
+        assert_eq!(node_state.get_bool("missing"), None);
+        assert_eq!(node_state.get_u64("missing"), None);
+
+        // Values written with another representation are not parsed.
+        node_state.set("ready", "1");
+        node_state.set("num_shards", "forty-two");
+        assert_eq!(node_state.get_bool("ready"), None);
+        assert_eq!(node_state.get_u64("num_shards"), None);
+        assert_eq!(node_state.get_bool("num_shards"), None);
+
+        // Setting the same value does not bump the version.
+        node_state.set_u64("num_shards", 42);
+        let max_version = node_state.max_version();
+        node_state.set_u64("num_shards", 42);
+        assert_eq!(node_state.max_version(), max_version);
+    }
+
+    #[test]
+    fn test_node_state_debug_entries() {
+        let mut node_state = NodeState::for_test();
+        node_state.set("key_a", "1");
+        node_state.set("key_b", "2");
+        node_state.set_with_ttl("key_c", "3");
+        node_state.delete("key_b");
+        assert_eq!(
+            node_state.debug_entries().collect::<Vec<_>>(),
+            [
+                KeyEntry {
+                    key: "key_a",
+                    value: "1",
+                    version: 1,
+                    status: DeletionStatusMutation::Set,
+                },
+                KeyEntry {
+                    key: "key_b",
+                    value: "",
+                    version: 4,
+                    status: DeletionStatusMutation::Delete,
+                },
+                KeyEntry {
+                    key: "key_c",
+                    value: "3",
+                    version: 3,
+                    status: DeletionStatusMutation::DeleteAfterTtl,
+                },
+            ]
+        );
+        node_state.delete_after_ttl("key_a");
+        let key_a_entry = node_state.debug_entries().next().unwrap();
+        assert_eq!(key_a_entry.value, "1");
+        assert_eq!(key_a_entry.version, 5);
+        assert_eq!(key_a_entry.status, DeletionStatusMutation::DeleteAfterTtl);
+    }
+
+    #[test]
+    fn test_node_state_tags() {
+        let mut node_state = NodeState::for_test();
+        node_state.set("region", "not-a-tag");
+        node_state.set_tag("region", "us-east-1");
+        node_state.set_tag("role", "indexer");
+        assert_eq!(node_state.get("_tag:region"), Some("us-east-1"));
+        assert_eq!(node_state.get_tag("region"), Some("us-east-1"));
+        assert_eq!(node_state.get_tag("missing"), None);
+        assert_eq!(
+            node_state.tags().collect::<Vec<_>>(),
+            [("region", "us-east-1"), ("role", "indexer")]
+        );
+        node_state.delete("_tag:role");
+        assert_eq!(node_state.get_tag("role"), None);
+        assert_eq!(
+            node_state.tags().collect::<Vec<_>>(),
+            [("region", "us-east-1")]
+        );
+    }
+
+    #[test]
+    fn test_node_state_set_batch() {
+        let mut node_state = NodeState::for_test();
+        node_state.set("key_a", "1");
+        node_state.set_batch([("key_a", "1"), ("key_b", "2"), ("key_c", "3")]);
+        assert_eq!(node_state.max_version(), 2);
+        assert_eq!(node_state.get_versioned("key_a").unwrap().version, 1);
+        assert_eq!(node_state.get_versioned("key_b").unwrap().version, 2);
+        assert_eq!(node_state.get_versioned("key_c").unwrap().version, 2);
+
+        node_state.set_batch([("key_b", "2"), ("key_c", "4")]);
+        assert_eq!(node_state.max_version(), 3);
+        assert_eq!(node_state.get_versioned("key_b").unwrap().version, 2);
+        assert_eq!(
+            node_state.get_versioned("key_c").unwrap(),
+            &VersionedValue {
+                value: "4".into(),
+                version: 3,
                 status: DeletionStatus::Set,
             }
         );
+    }
+
+    #[test]
+    fn test_node_state_commit_config() {
+        let mut node_state = NodeState::for_test();
+        assert_eq!(node_state.config_epoch(), 0);
+
+        node_state.commit_config([("key_a", "1"), ("key_b", "1")]);
+        assert_eq!(node_state.config_epoch(), 1);
+        assert_eq!(node_state.max_version(), 1);
+        assert_eq!(
+            node_state.get_versioned(CONFIG_EPOCH_KEY).unwrap().version,
+            1
+        );
+
+        // Committing an unchanged configuration does not start a new epoch.
+        node_state.commit_config([("key_a", "1"), ("key_b", "1")]);
+        assert_eq!(node_state.config_epoch(), 1);
+        assert_eq!(node_state.max_version(), 1);
+
+        // Regular writes do not start a new epoch either.
+        node_state.set("key_c", "1");
+        assert_eq!(node_state.config_epoch(), 1);
+
+        node_state.commit_config([("key_a", "1"), ("key_b", "2")]);
+        assert_eq!(node_state.config_epoch(), 2);
+        assert_eq!(node_state.max_version(), 3);
+        assert_eq!(node_state.get_versioned("key_b").unwrap().version, 3);
+        assert_eq!(
+            node_state.get_versioned(CONFIG_EPOCH_KEY).unwrap().version,
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_node_state_replace_all() {
+        let mut node_state = NodeState::for_test();
+        node_state.set_batch([("key_a", "1"), ("key_b", "1"), ("key_c", "1")]);
+        node_state.delete("key_c");
+        assert_eq!(node_state.max_version(), 2);
+        let mut cluster_event_rx = node_state.listeners.subscribe_cluster_events();
+
+        node_state.replace_all([("key_b", "1"), ("key_c", "2"), ("key_d", "2")]);
+        assert_eq!(node_state.max_version(), 3);
+        assert_eq!(
+            node_state.key_values().collect::<Vec<_>>(),
+            vec![("key_b", "1"), ("key_c", "2"), ("key_d", "2")]
+        );
+        let versioned_a = node_state.get_versioned("key_a").unwrap();
+        assert!(versioned_a.is_deleted());
+        assert_eq!(versioned_a.version, 3);
+        assert_eq!(node_state.get_versioned("key_b").unwrap().version, 1);
+        assert_eq!(node_state.get_versioned("key_c").unwrap().version, 3);
+        assert_eq!(node_state.get_versioned("key_d").unwrap().version, 3);
+
+        let mut cluster_events = Vec::new();
+        while let Ok(cluster_event) = cluster_event_rx.try_recv() {
+            cluster_events.push(cluster_event);
+        }
+        let chitchat_id = node_state.chitchat_id().clone();
+        assert_eq!(
+            cluster_events,
+            vec![
+                ClusterEvent::KeyDeleted {
+                    node: chitchat_id.clone(),
+                    key: "key_a".to_string(),
+                },
+                ClusterEvent::KeyChanged {
+                    node: chitchat_id.clone(),
+                    key: "key_c".to_string(),
+                    value: "2".into(),
+                },
+                ClusterEvent::KeyChanged {
+                    node: chitchat_id,
+                    key: "key_d".to_string(),
+                    value: "2".into(),
+                },
+            ]
+        );
+
+        // Replacing with the same key-values is a no-op.
+        node_state.replace_all([("key_b", "1"), ("key_c", "2"), ("key_d", "2")]);
+        assert_eq!(node_state.max_version(), 3);
+        assert!(cluster_event_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_node_state_compact_versions() {
+        let mut node_state = NodeState::for_test();
+        node_state.key_value_history = KeyValueHistory::with_depth(10);
+        node_state.set("key_a", "1");
+        node_state.set("key_b", "1");
+        node_state.set("key_a", "2");
+        node_state.delete("key_b");
+        node_state.set_batch([("key_c", "1"), ("key_d", "1")]);
+        node_state.set("key_a", "3");
+        assert_eq!(node_state.max_version(), 6);
+
+        // Nothing to compact.
+        node_state.compact_versions(6);
+        assert_eq!(node_state.max_version(), 6);
+
+        node_state.compact_versions(1);
+        assert_eq!(node_state.max_version(), 4);
+        assert_eq!(node_state.get_versioned("key_b").unwrap().version, 2);
+        assert!(node_state.get_versioned("key_b").unwrap().is_deleted());
+        assert_eq!(node_state.get_versioned("key_c").unwrap().version, 3);
+        assert_eq!(node_state.get_versioned("key_d").unwrap().version, 3);
+        assert_eq!(node_state.get_versioned("key_a").unwrap().version, 4);
+        assert_eq!(&*node_state.get_at_version("key_a", 3).unwrap().value, "1");
+        assert_eq!(
+            node_state
+                .stale_key_values(1)
+                .map(|(key, versioned_value)| (key, versioned_value.version))
+                .collect::<Vec<_>>(),
+            vec![("key_b", 2), ("key_c", 3), ("key_d", 3), ("key_a", 4)]
+        );
+
+        node_state.set("key_e", "1");
+        assert_eq!(node_state.get_versioned("key_e").unwrap().version, 5);
+
+        // Versions below the last GC version must stay untouched.
+        node_state.set_last_gc_version(3);
+        node_state.compact_versions(2);
+        assert_eq!(node_state.max_version(), 5);
+    }
+
+    #[test]
+    fn test_node_state_supersede_versions() {
+        let mut node_state = NodeState::for_test();
+        node_state.set("key_a", "1");
+        node_state.set("key_b", "1");
+        node_state.delete("key_b");
+        node_state.set_batch([("key_c", "1"), ("key_d", "1")]);
+        assert_eq!(node_state.max_version(), 4);
+
+        node_state.supersede_versions(10);
+        assert_eq!(node_state.last_gc_version(), 11);
+        assert_eq!(node_state.max_version(), 14);
+        assert_eq!(
+            node_state
+                .stale_key_values(0)
+                .map(|(key, versioned_value)| (key, versioned_value.version))
+                .collect::<Vec<_>>(),
+            vec![("key_a", 12), ("key_b", 13), ("key_c", 14), ("key_d", 14)]
+        );
+        assert!(node_state.get_versioned("key_b").unwrap().is_deleted());
+        assert_eq!(node_state.get("key_a"), Some("1"));
+
+        node_state.set("key_e", "1");
+        assert_eq!(node_state.get_versioned("key_e").unwrap().version, 15);
+
+        let mut node_state = NodeState::for_test();
+        node_state.supersede_versions(10);
+        assert_eq!(node_state.last_gc_version(), 11);
+        assert_eq!(node_state.max_version(), 11);
+    }
+
+    #[tokio::test]
+    async fn test_node_state_stale_key_values_uses_version_index() {
+        tokio::time::pause();
+        let mut node_state = NodeState::for_test();
+        node_state.set("key_d", "1");
+        node_state.set_batch([("key_c", "2"), ("key_b", "2"), ("key_a", "2")]);
+        node_state.set("key_d", "3");
+        node_state.delete("key_b");
+        node_state.set_with_ttl("key_e", "5");
+        node_state.delete_after_ttl("key_a");
+        node_state.remove_key_value_internal("key_e");
+
+        let stale_key_values = |node_state: &NodeState, floor_version: Version| {
+            node_state
+                .stale_key_values(floor_version)
+                .map(|(key, versioned_value)| (key.to_string(), versioned_value.version))
+                .collect::<Vec<_>>()
+        };
         assert_eq!(
-            node_state.get_versioned("key_b").unwrap(),
-            &VersionedValue {
-                value: "2".to_string(),
-                version: 2,
-                status: DeletionStatus::Set,
-            }
+            stale_key_values(&node_state, 0),
+            vec![
+                ("key_c".to_string(), 2),
+                ("key_d".to_string(), 3),
+                ("key_b".to_string(), 4),
+                ("key_a".to_string(), 6),
+            ]
         );
-        node_state.set("key_a", "3");
         assert_eq!(
-            node_state.get_versioned("key_a").unwrap(),
-            &VersionedValue {
-                value: "3".to_string(),
-                version: 3,
-                status: DeletionStatus::Set
-            }
+            stale_key_values(&node_state, 3),
+            vec![("key_b".to_string(), 4), ("key_a".to_string(), 6)]
         );
-    }
+        assert!(stale_key_values(&node_state, 6).is_empty());
 
-    #[test]
-    fn test_cluster_state_set_with_same_value_updates_version() {
-        let mut cluster_state = ClusterState::default();
-        let node_state = cluster_state.node_state_mut(&ChitchatId::for_local_test(10_001));
-        node_state.set("key", "1");
+        tokio::time::advance(Duration::from_secs(10)).await;
+        node_state.gc_keys_marked_for_deletion(Duration::from_secs(5));
         assert_eq!(
-            node_state.get_versioned("key").unwrap(),
-            &VersionedValue {
-                value: "1".to_string(),
-                version: 1,
-                status: DeletionStatus::Set
-            }
+            stale_key_values(&node_state, 0),
+            vec![("key_c".to_string(), 2), ("key_d".to_string(), 3)]
         );
-        node_state.set("key", "1");
+
+        // Without the index, e.g. after deserialization, the key-values are scanned.
+        let mut unindexed_node_state = node_state.clone();
+        unindexed_node_state.keys_by_version = KeysByVersion::default();
         assert_eq!(
-            node_state.get_versioned("key").unwrap(),
-            &VersionedValue {
-                value: "1".to_string(),
-                version: 1,
-                status: DeletionStatus::Set,
-            }
+            stale_key_values(&unindexed_node_state, 0),
+            stale_key_values(&node_state, 0)
         );
+
+        // An index with the right number of keys but wrong versions does not match.
+        assert!(node_state.keys_by_version.matches(&node_state.key_values));
+        let mut keys_by_version = node_state.keys_by_version.clone();
+        keys_by_version.update("key_c", 2, 4);
+        assert!(!keys_by_version.matches(&node_state.key_values));
     }
 
     #[test]
@@ -1099,7 +3165,7 @@ mod tests {
         assert!(node_state.get("key").is_none());
         {
             let versioned_value = node_state.get_versioned("key").unwrap();
-            assert_eq!(&versioned_value.value, "");
+            assert_eq!(&*versioned_value.value, "");
             assert_eq!(versioned_value.version, 2u64);
             assert!(versioned_value.is_deleted());
             assert!(versioned_value
@@ -1112,7 +3178,7 @@ mod tests {
         node_state.set("key", "2");
         {
             let versioned_value = node_state.get_versioned("key").unwrap();
-            assert_eq!(&versioned_value.value, "2");
+            assert_eq!(&*versioned_value.value, "2");
             assert_eq!(versioned_value.version, 3u64);
             assert!(!versioned_value.is_deleted());
             assert!(versioned_value
@@ -1133,7 +3199,7 @@ mod tests {
             let value = node_state.get("key").unwrap();
             assert_eq!(value, "1");
             let versioned_value = node_state.get_versioned("key").unwrap();
-            assert_eq!(&versioned_value.value, "1");
+            assert_eq!(&*versioned_value.value, "1");
             assert_eq!(versioned_value.version, 2u64);
             assert!(versioned_value
                 .status
@@ -1150,7 +3216,7 @@ mod tests {
         node_state.set("key", "2");
         {
             let versioned_value = node_state.get_versioned("key").unwrap();
-            assert_eq!(&versioned_value.value, "2");
+            assert_eq!(&*versioned_value.value, "2");
             assert_eq!(versioned_value.version, 3u64);
             assert!(!versioned_value.is_deleted());
             assert!(versioned_value
@@ -1226,6 +3292,37 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_cluster_state_gc_keys_marked_for_deletion_with_manual_clock() {
+        let manual_clock = Arc::new(ManualClock::new());
+        let (_seed_addrs_tx, seed_addrs_rx) = watch::channel(Default::default());
+        let mut cluster_state = ClusterState::with_seed_addrs(
+            seed_addrs_rx,
+            None,
+            None,
+            0,
+            0,
+            SharedClock::new(manual_clock.clone()),
+        );
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node1_state = cluster_state.node_state_mut(&node1);
+        node1_state.set("key_a", "1");
+        node1_state.delete("key_a");
+        node1_state.set("key_b", "2");
+        node1_state.delete_after_ttl("key_b");
+
+        manual_clock.advance(Duration::from_secs(9));
+        cluster_state.gc_keys_marked_for_deletion(Duration::from_secs(10));
+        let node1_state = cluster_state.node_state(&node1).unwrap();
+        assert!(node1_state.key_values.contains_key("key_a"));
+        assert_eq!(node1_state.get("key_b"), Some("2"));
+
+        manual_clock.advance(Duration::from_secs(1));
+        cluster_state.gc_keys_marked_for_deletion(Duration::from_secs(10));
+        let node1_state = cluster_state.node_state(&node1).unwrap();
+        assert!(node1_state.key_values.is_empty());
+    }
+
     #[test]
     fn test_cluster_state_apply_delta() {
         let mut cluster_state = ClusterState::default();
@@ -1253,7 +3350,7 @@ mod tests {
         assert_eq!(
             node1_state.get_versioned("key_a").unwrap(),
             &VersionedValue {
-                value: "4".to_string(),
+                value: "4".into(),
                 version: 4,
                 status: DeletionStatus::Set,
             }
@@ -1262,7 +3359,7 @@ mod tests {
         assert_eq!(
             node1_state.get_versioned("key_b").unwrap(),
             &VersionedValue {
-                value: "3".to_string(),
+                value: "3".into(),
                 version: 3,
                 status: DeletionStatus::Set,
             }
@@ -1273,13 +3370,75 @@ mod tests {
         assert_eq!(
             node2_state.get_versioned("key_d").unwrap(),
             &VersionedValue {
-                value: "4".to_string(),
+                value: "4".into(),
                 version: 4,
                 status: DeletionStatus::Set
             }
         );
     }
 
+    #[test]
+    fn test_cluster_state_recent_deltas() {
+        let (_seed_addrs_tx, seed_addrs_rx) = watch::channel(Default::default());
+        let mut cluster_state =
+            ClusterState::with_seed_addrs(seed_addrs_rx, None, None, 0, 2, SharedClock::default());
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node2 = ChitchatId::for_local_test(10_002);
+
+        let mut delta = Delta::default();
+        delta.add_node(node1.clone(), 0, 0);
+        delta.add_kv(&node1, "key_a", "1", 1, false);
+        delta.add_kv(&node1, "key_b", "2", 2, false);
+        cluster_state.apply_delta(delta);
+
+        // This delta is coming from the future and is ignored.
+        let mut delta = Delta::default();
+        delta.add_node(node1.clone(), 0, 5);
+        delta.add_kv(&node1, "key_c", "3", 6, false);
+        cluster_state.apply_delta(delta);
+
+        let recent_deltas: Vec<AppliedNodeDelta> = cluster_state.recent_deltas().cloned().collect();
+        assert_eq!(
+            recent_deltas,
+            [AppliedNodeDelta {
+                chitchat_id: node1.clone(),
+                from_version_excluded: 0,
+                last_gc_version: 0,
+                num_key_values: 2,
+                reset: false,
+            }]
+        );
+
+        // Node 1 tombstones were GCed: its state must be reset.
+        let mut delta = Delta::default();
+        delta.add_node(node1.clone(), 4, 0);
+        delta.add_kv(&node1, "key_c", "3", 6, false);
+        delta.add_node(node2.clone(), 0, 0);
+        delta.add_kv(&node2, "key_a", "1", 1, false);
+        cluster_state.apply_delta(delta);
+
+        let recent_deltas: Vec<AppliedNodeDelta> = cluster_state.recent_deltas().cloned().collect();
+        assert_eq!(
+            recent_deltas,
+            [
+                AppliedNodeDelta {
+                    chitchat_id: node1,
+                    from_version_excluded: 0,
+                    last_gc_version: 4,
+                    num_key_values: 1,
+                    reset: true,
+                },
+                AppliedNodeDelta {
+                    chitchat_id: node2,
+                    from_version_excluded: 0,
+                    last_gc_version: 0,
+                    num_key_values: 1,
+                    reset: false,
+                }
+            ]
+        );
+    }
+
     // This helper test function will test all possible mtu version, and check that the resulting
     // delta matches the expectation.
     fn test_with_varying_max_transmitted_kv_helper(
@@ -1363,6 +3522,271 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cluster_state_compute_delta_does_not_split_versions() {
+        let mut cluster_state = ClusterState::default();
+        let node = ChitchatId::for_local_test(10_001);
+        let node_state = cluster_state.node_state_mut(&node);
+        node_state.set("key_a", "1".repeat(100));
+        node_state.set_batch([("key_b", "2".repeat(100)), ("key_c", "3".repeat(100))]);
+
+        let mut digest = Digest::default();
+        digest.add_node(node.clone(), Heartbeat(0), 0, 0);
+
+        // `key_b` and `key_c` share the same version: they are either both in the delta, or not
+        // at all.
+        let mut num_tuples_seen = HashSet::new();
+        for mtu in 100..1_000 {
+            let delta =
+                cluster_state.compute_partial_delta_respecting_mtu(&digest, mtu, &HashSet::new());
+            num_tuples_seen.insert(delta.num_tuples());
+        }
+        assert_eq!(num_tuples_seen, HashSet::from([0, 1, 3]));
+    }
+
+    #[test]
+    fn test_cluster_state_num_truncated_deltas_and_tombstones() {
+        let mut cluster_state = ClusterState::default();
+        let node = ChitchatId::for_local_test(10_001);
+        let node_state = cluster_state.node_state_mut(&node);
+        node_state.set("key_a", "1".repeat(100));
+        node_state.set("key_b", "2".repeat(100));
+        node_state.set("key_c", "3".repeat(100));
+        node_state.delete("key_c");
+        assert_eq!(cluster_state.num_tombstones(), 1);
+
+        let digest = Digest::default();
+        let delta = cluster_state.compute_partial_delta_respecting_mtu(
+            &digest,
+            MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            &HashSet::new(),
+        );
+        assert_eq!(delta.num_tuples(), 3);
+        assert_eq!(cluster_state.num_truncated_deltas(), 0);
+
+        let delta =
+            cluster_state.compute_partial_delta_respecting_mtu(&digest, 200, &HashSet::new());
+        assert_eq!(delta.num_tuples(), 1);
+        assert_eq!(cluster_state.num_truncated_deltas(), 1);
+    }
+
+    #[test]
+    fn test_cluster_state_apply_delta_introducing_unknown_node() {
+        let node1 = ChitchatId::for_local_test(10_001);
+        let mut peer_cluster_state = ClusterState::default();
+        let peer_node1_state = peer_cluster_state.node_state_mut(&node1);
+        peer_node1_state.set_with_version("key_a", "1", 1);
+        peer_node1_state.set_with_version("key_b", "2", 2);
+        peer_node1_state.set_with_version("key_c", "3", 3);
+
+        // A delta introducing the node from version 2 lacks `key_a` and `key_b`: it is ignored.
+        let mut cluster_state = ClusterState::default();
+        let mut delta = Delta::default();
+        delta.add_node(node1.clone(), 0, 2);
+        delta.add_kv(&node1, "key_c", "3", 3, false);
+        cluster_state.apply_delta(delta);
+
+        let node1_state = cluster_state.node_state(&node1).unwrap();
+        assert_eq!(node1_state.max_version(), 0);
+        assert_eq!(node1_state.num_key_values(), 0);
+
+        // The node is now in our digest with a max version of 0, so the peer sends it whole.
+        let digest = cluster_state.compute_digest(&HashSet::new());
+        let delta = peer_cluster_state.compute_partial_delta_respecting_mtu(
+            &digest,
+            MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            &HashSet::new(),
+        );
+        assert_eq!(delta.get(&node1).unwrap().from_version_excluded, 0);
+        cluster_state.apply_delta(delta);
+
+        let node1_state = cluster_state.node_state(&node1).unwrap();
+        assert_eq!(node1_state.max_version(), 3);
+        assert_eq!(node1_state.get("key_a"), Some("1"));
+        assert_eq!(node1_state.get("key_b"), Some("2"));
+        assert_eq!(node1_state.get("key_c"), Some("3"));
+
+        // A delta introducing a node from version 0 is applied right away.
+        let node2 = ChitchatId::for_local_test(10_002);
+        let mut delta = Delta::default();
+        delta.add_node(node2.clone(), 0, 0);
+        delta.add_kv(&node2, "key_a", "1", 1, false);
+        cluster_state.apply_delta(delta);
+        let node2_state = cluster_state.node_state(&node2).unwrap();
+        assert_eq!(node2_state.max_version(), 1);
+        assert_eq!(node2_state.get("key_a"), Some("1"));
+    }
+
+    #[test]
+    fn test_cluster_state_apply_delta_ignores_versions_beyond_max_version() {
+        let node1 = ChitchatId::for_local_test(10_001);
+        let mut cluster_state = ClusterState::default();
+        let mut delta = Delta::default();
+        delta.add_node(node1.clone(), 0, 0);
+        delta.add_kv(&node1, "key_a", "1", 1, false);
+        cluster_state.apply_delta(delta);
+
+        for (last_gc_version, from_version_excluded, version) in [
+            (0, 1, MAX_VERSION + 1),
+            (0, 1, u64::MAX),
+            (MAX_VERSION + 1, 1, 2),
+            (0, u64::MAX, 2),
+        ] {
+            let mut delta = Delta::default();
+            delta.add_node(node1.clone(), last_gc_version, from_version_excluded);
+            delta.add_kv(&node1, "key_b", "2", version, false);
+            cluster_state.apply_delta(delta);
+
+            let node1_state = cluster_state.node_state(&node1).unwrap();
+            assert_eq!(node1_state.max_version(), 1);
+            assert_eq!(node1_state.last_gc_version(), 0);
+            assert_eq!(node1_state.get("key_b"), None);
+        }
+
+        let mut delta = Delta::default();
+        delta.add_node(node1.clone(), 0, 1);
+        delta.add_kv(&node1, "key_b", "2", MAX_VERSION, false);
+        cluster_state.apply_delta(delta);
+        let node1_state = cluster_state.node_state(&node1).unwrap();
+        assert_eq!(node1_state.max_version(), MAX_VERSION);
+        assert_eq!(node1_state.get("key_b"), Some("2"));
+    }
+
+    #[test]
+    fn test_cluster_state_per_node_serialized_len() {
+        let mut cluster_state = ClusterState::default();
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node2 = ChitchatId::for_local_test(10_002);
+        cluster_state.node_state_mut(&node1);
+        let node2_state = cluster_state.node_state_mut(&node2);
+        node2_state.set("key", "value");
+        node2_state.set("large_key", "x".repeat(1_000));
+        node2_state.delete("large_key");
+
+        let per_node_serialized_len = cluster_state.per_node_serialized_len();
+        assert_eq!(per_node_serialized_len.len(), 2);
+        let node_op_len = per_node_serialized_len[&node1];
+        assert_eq!(node_op_len, 1 + node1.serialized_len() + 2);
+        // tag + key + value + version + status
+        let key_value_op_len = 1 + (1 + 3) + (1 + 5) + 1 + 1;
+        // The tombstone of `large_key` no longer carries its value.
+        let tombstone_op_len = 1 + (1 + 9) + 1 + 1 + 1;
+        assert_eq!(
+            per_node_serialized_len[&node2],
+            node_op_len + key_value_op_len + tombstone_op_len
+        );
+    }
+
+    #[test]
+    fn test_cluster_state_node_states_with_digest() {
+        let mut cluster_state = ClusterState::default();
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node2 = ChitchatId::for_local_test(10_002);
+        let node3 = ChitchatId::for_local_test(10_003);
+        let node4 = ChitchatId::for_local_test(10_004);
+        cluster_state.node_state_mut(&node1).set("key", "1");
+        cluster_state.node_state_mut(&node3).set("key", "3");
+        cluster_state.node_state_mut(&node4).set("key", "4");
+
+        let mut digest = Digest::default();
+        digest.add_node(node2.clone(), Heartbeat(0), 0, 2);
+        digest.add_node(node3.clone(), Heartbeat(0), 0, 3);
+
+        let node_states_with_digest: Vec<(&ChitchatId, Option<Version>)> = cluster_state
+            .node_states_with_digest(&digest)
+            .map(|(chitchat_id, _, node_digest_opt)| {
+                (
+                    chitchat_id,
+                    node_digest_opt.map(|node_digest| node_digest.max_version),
+                )
+            })
+            .collect();
+        assert_eq!(
+            node_states_with_digest,
+            [(&node1, None), (&node3, Some(3)), (&node4, None)]
+        );
+    }
+
+    #[test]
+    fn test_cluster_state_compute_delta_orders_key_values_sharing_a_version() {
+        let mut cluster_state = ClusterState::default();
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node1_state = cluster_state.node_state_mut(&node1);
+        node1_state.set_batch([("key_c", "1"), ("key_a", "1"), ("key_b", "1")]);
+        node1_state.set("key_d", "2");
+        node1_state.replace_all([("key_d", "3"), ("key_b", "3")]);
+
+        let mut expected_delta = Delta::default();
+        expected_delta.add_node(node1.clone(), 0, 0);
+        expected_delta.add_kv(&node1, "key_a", "", 3, true);
+        expected_delta.add_kv(&node1, "key_b", "3", 3, false);
+        expected_delta.add_kv(&node1, "key_c", "", 3, true);
+        expected_delta.add_kv(&node1, "key_d", "3", 3, false);
+        expected_delta.set_serialized_len(68);
+
+        let digest = Digest::default();
+        let delta = cluster_state.compute_partial_delta_respecting_mtu(
+            &digest,
+            MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            &HashSet::new(),
+        );
+        assert_eq!(delta, expected_delta);
+
+        // Without the version index, e.g. after deserialization, the order is the same.
+        let node1_state = cluster_state.node_state_mut(&node1);
+        node1_state.keys_by_version = KeysByVersion::default();
+        let delta = cluster_state.compute_partial_delta_respecting_mtu(
+            &digest,
+            MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            &HashSet::new(),
+        );
+        assert_eq!(delta, expected_delta);
+    }
+
+    #[test]
+    fn test_cluster_state_compute_delta_skips_node_too_large_for_mtu() {
+        let mut cluster_state = ClusterState::default();
+        let node1 = ChitchatId::for_local_test(10_001);
+        let node2 = ChitchatId::for_local_test(10_002);
+        // An incompressible value, larger than the MTU.
+        let large_value: String = random_generator()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(5_000)
+            .map(char::from)
+            .collect();
+        let node1_state = cluster_state.node_state_mut(&node1);
+        node1_state.set("key_a", &large_value);
+        node1_state.set("key_b", "2");
+        // Unknown nodes with the lowest max version are gossiped first, so `node1` comes first.
+        let node2_state = cluster_state.node_state_mut(&node2);
+        node2_state.set("key_a", "1");
+        node2_state.set("key_b", "2");
+        node2_state.set("key_c", "3");
+
+        let digest = Digest::default();
+        let delta =
+            cluster_state.compute_partial_delta_respecting_mtu(&digest, 1_000, &HashSet::new());
+        let mut expected_delta = Delta::default();
+        expected_delta.add_node(node2.clone(), 0, 0);
+        expected_delta.add_kv(&node2, "key_a", "1", 1, false);
+        expected_delta.add_kv(&node2, "key_b", "2", 2, false);
+        expected_delta.add_kv(&node2, "key_c", "3", 3, false);
+        expected_delta.set_serialized_len(59);
+        assert_eq!(delta, expected_delta);
+        // The node is only reported again once its state changes.
+        assert!(!cluster_state.record_oversized_node(&node1, 2));
+        assert!(cluster_state.record_oversized_node(&node1, 3));
+
+        // Once the large key-value was received, the rest of the node state can be gossiped.
+        let mut digest = Digest::default();
+        digest.add_node(node1.clone(), Heartbeat(0), 0, 1);
+        digest.add_node(node2.clone(), Heartbeat(0), 0, 3);
+        let delta =
+            cluster_state.compute_partial_delta_respecting_mtu(&digest, 1_000, &HashSet::new());
+        assert_eq!(delta.num_tuples(), 1);
+        assert!(delta.get(&node1).is_some());
+    }
+
     #[test]
     fn test_cluster_state_compute_delta_depth_first_chitchat() {
         let cluster_state = test_cluster_state();
@@ -1458,7 +3882,7 @@ mod tests {
             expected_delta.add_kv(&node2.clone(), "key_c", "3", 2, false);
             expected_delta.add_node(node1.clone(), 0u64, 1u64);
             expected_delta.add_kv(&node1, "key_b", "2", 2, false);
-            expected_delta.set_serialized_len(76);
+            expected_delta.set_serialized_len(70);
             assert_eq!(delta, expected_delta);
         }
 
@@ -1481,7 +3905,7 @@ mod tests {
             expected_delta.add_node(node1.clone(), 0u64, 1u64);
             expected_delta.add_kv(&node1, "key_b", "2", 2, false);
             expected_delta.add_kv(&node1, "key_a", "", 3, true);
-            expected_delta.set_serialized_len(90);
+            expected_delta.set_serialized_len(80);
             assert_eq!(delta, expected_delta);
         }
 
@@ -1491,6 +3915,8 @@ mod tests {
         cluster_state
             .node_state_mut(&node1)
             .gc_keys_marked_for_deletion(DELETE_GRACE_PERIOD);
+        assert_eq!(cluster_state.num_sent_node_resets(), 0);
+        let mut cluster_event_rx = cluster_state.listeners.subscribe_cluster_events();
 
         {
             let mut digest = Digest::default();
@@ -1506,9 +3932,26 @@ mod tests {
             // Last gc set to 3 and from version to 0. That's a reset right there.
             expected_delta.add_node(node1.clone(), 3u64, 0u64);
             expected_delta.add_kv(&node1, "key_b", "2", 2, false);
-            expected_delta.set_serialized_len(75);
+            expected_delta.set_serialized_len(70);
             assert_eq!(&delta, &expected_delta);
         }
+        assert_eq!(cluster_state.num_sent_node_resets(), 1);
+        assert_eq!(
+            cluster_event_rx.try_recv().unwrap(),
+            ClusterEvent::NodeResetSent(node1.clone())
+        );
+        assert!(cluster_event_rx.try_recv().is_err());
+
+        // Computing a delta for debugging purposes does not count as a reset.
+        let mut digest = Digest::default();
+        digest.add_node(node1.clone(), Heartbeat(0), 0, 1);
+        let (_delta, _is_truncated, reset_nodes) = cluster_state.compute_partial_delta(
+            &digest,
+            MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            &HashSet::new(),
+        );
+        assert_eq!(reset_nodes, [node1]);
+        assert_eq!(cluster_state.num_sent_node_resets(), 1);
     }
 
     #[test]
@@ -1542,13 +3985,13 @@ mod tests {
             key_values: vec![
                 KeyValueMutation {
                     key: "key_c".to_string(),
-                    value: "val_c".to_string(),
+                    value: "val_c".into(),
                     version: 4,
                     status: DeletionStatusMutation::Set,
                 },
                 KeyValueMutation {
                     key: "key_b".to_string(),
-                    value: "val_b2".to_string(),
+                    value: "val_b2".into(),
                     version: 3,
                     status: DeletionStatusMutation::Set,
                 },
@@ -1563,6 +4006,24 @@ mod tests {
         assert_eq!(node_state.get("key_c").unwrap(), "val_c");
     }
 
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "above the max version")]
+    fn test_node_apply_delta_checks_invariants() {
+        let mut node_state = NodeState::for_test();
+        node_state.set_with_version("key_a", "val_a", 2);
+        // Corrupt the node state.
+        node_state.set_max_version(1);
+        let node_delta = NodeDelta {
+            chitchat_id: node_state.chitchat_id.clone(),
+            from_version_excluded: 1,
+            last_gc_version: 0u64,
+            max_version: None,
+            key_values: Vec::new(),
+        };
+        node_state.apply_delta(node_delta, Instant::now());
+    }
+
     // Here we check that the accessor that dismiss resetting a Kv to the same value is not
     // used in apply delta. Resetting to the same value is very possible in reality several updates
     // happened in a row but were shadowed by the scuttlebutt logic. We DO need to update the
@@ -1578,7 +4039,7 @@ mod tests {
             max_version: None,
             key_values: vec![KeyValueMutation {
                 key: "key_a".to_string(),
-                value: "val_a".to_string(),
+                value: "val_a".into(),
                 version: 3,
                 status: DeletionStatusMutation::Set,
             }],
@@ -1587,7 +4048,7 @@ mod tests {
         let versioned_a = node_state.get_versioned("key_a").unwrap();
         assert_eq!(versioned_a.version, 3);
         assert_eq!(versioned_a.status, DeletionStatus::Set);
-        assert_eq!(&versioned_a.value, "val_a");
+        assert_eq!(&*versioned_a.value, "val_a");
     }
 
     #[test]
@@ -1602,7 +4063,7 @@ mod tests {
             max_version: None,
             key_values: vec![KeyValueMutation {
                 key: "key_a".to_string(),
-                value: "new_val".to_string(),
+                value: "new_val".into(),
                 version: 7,
                 status: DeletionStatusMutation::Set,
             }],
@@ -1611,7 +4072,7 @@ mod tests {
         let versioned_a = node_state.get_versioned("key_a").unwrap();
         assert_eq!(versioned_a.version, 5);
         assert_eq!(versioned_a.status, DeletionStatus::Set);
-        assert_eq!(&versioned_a.value, "val_a");
+        assert_eq!(&*versioned_a.value, "val_a");
     }
 
     #[tokio::test]
@@ -1633,7 +4094,7 @@ mod tests {
             max_version: None,
             key_values: vec![KeyValueMutation {
                 key: "key_a".to_string(),
-                value: "new_val".to_string(),
+                value: "new_val".into(),
                 version: 32,
                 status: DeletionStatusMutation::Set,
             }],
@@ -1643,7 +4104,7 @@ mod tests {
         assert_eq!(versioned_a.version, 32);
         assert_eq!(node_state.max_version(), 32);
         assert_eq!(versioned_a.status, DeletionStatus::Set);
-        assert_eq!(&versioned_a.value, "new_val");
+        assert_eq!(&*versioned_a.value, "new_val");
     }
 
     #[tokio::test]
@@ -1652,6 +4113,7 @@ mod tests {
         let mut node_state = NodeState::for_test();
         node_state.set_with_version("key_a", "val_a", 17);
         assert_eq!(node_state.max_version(), 17);
+        let mut cluster_event_rx = node_state.listeners.subscribe_cluster_events();
         let node_delta = NodeDelta {
             chitchat_id: node_state.chitchat_id.clone(),
             from_version_excluded: 0, // we skipped version 6 here.
@@ -1659,7 +4121,7 @@ mod tests {
             max_version: None,
             key_values: vec![KeyValueMutation {
                 key: "key_b".to_string(),
-                value: "val_b".to_string(),
+                value: "val_b".into(),
                 version: 32,
                 status: DeletionStatusMutation::Set,
             }],
@@ -1668,6 +4130,25 @@ mod tests {
         assert!(node_state.get_versioned("key_a").is_none());
         let versioned_b = node_state.get_versioned("key_b").unwrap();
         assert_eq!(versioned_b.version, 32);
+
+        // The reset and the deletion of the dropped keys are reported before the key-values of
+        // the delta.
+        assert_eq!(
+            cluster_event_rx.try_recv().unwrap(),
+            ClusterEvent::NodeReset(node_state.chitchat_id.clone())
+        );
+        assert_eq!(
+            cluster_event_rx.try_recv().unwrap(),
+            ClusterEvent::KeyDeleted {
+                node: node_state.chitchat_id.clone(),
+                key: "key_a".to_string(),
+            }
+        );
+        assert!(matches!(
+            cluster_event_rx.try_recv().unwrap(),
+            ClusterEvent::KeyChanged { key, .. } if key == "key_b"
+        ));
+        assert!(cluster_event_rx.try_recv().is_err());
     }
 
     #[tokio::test]
@@ -1677,6 +4158,7 @@ mod tests {
         node_state.set_with_version("key_a", "val_a", 31);
         node_state.set_with_version("key_b", "val_b2", 32);
         assert_eq!(node_state.max_version(), 32);
+        let mut cluster_event_rx = node_state.listeners.subscribe_cluster_events();
         // This does look like a valid reset, but we are already at version 32.
         // Let's ignore this.
         let node_delta = NodeDelta {
@@ -1686,7 +4168,7 @@ mod tests {
             max_version: None,
             key_values: vec![KeyValueMutation {
                 key: "key_b".to_string(),
-                value: "val_b".to_string(),
+                value: "val_b".into(),
                 version: 30,
                 status: DeletionStatusMutation::Set,
             }],
@@ -1695,7 +4177,78 @@ mod tests {
         assert_eq!(node_state.max_version, 32);
         let versioned_b = node_state.get_versioned("key_b").unwrap();
         assert_eq!(versioned_b.version, 32);
-        assert_eq!(versioned_b.value, "val_b2");
+        assert_eq!(&*versioned_b.value, "val_b2");
+        assert!(cluster_event_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_node_apply_delta_max_version_only_without_reset() {
+        let mut node_state = NodeState::for_test();
+        node_state.set_with_version("key_a", "val_a", 4);
+        let node_delta = NodeDelta {
+            chitchat_id: node_state.chitchat_id.clone(),
+            from_version_excluded: 4,
+            last_gc_version: 0,
+            max_version: Some(5),
+            key_values: Vec::new(),
+        };
+        let delta_preparation = node_state.apply_delta(node_delta, Instant::now());
+        assert_eq!(delta_preparation, DeltaPreparation::Apply);
+        // The max version is only applied on reset.
+        assert_eq!(node_state.max_version(), 4);
+        assert_eq!(node_state.get("key_a"), Some("val_a"));
+    }
+
+    #[tokio::test]
+    async fn test_cluster_state_delta_with_max_version_only() {
+        tokio::time::pause();
+        const GRACE_PERIOD: Duration = Duration::from_secs(10);
+        let node1 = ChitchatId::for_local_test(10_001);
+        let mut cluster_state1 = ClusterState::default();
+        cluster_state1.node_state_mut(&node1).set("key_a", "1");
+        let mut cluster_state2 = ClusterState::default();
+        let compute_delta = |cluster_state1: &ClusterState, cluster_state2: &ClusterState| {
+            cluster_state1.compute_partial_delta_respecting_mtu(
+                &cluster_state2.compute_digest(&HashSet::new()),
+                MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+                &HashSet::new(),
+            )
+        };
+        cluster_state2.apply_delta(compute_delta(&cluster_state1, &cluster_state2));
+        assert_eq!(cluster_state2.node_state(&node1).unwrap().max_version(), 1);
+
+        // The only key-value of node 1 is deleted, and its tombstone is garbage collected before
+        // being gossiped: node 1 has no key-value left to share.
+        cluster_state1.node_state_mut(&node1).delete("key_a");
+        tokio::time::advance(GRACE_PERIOD).await;
+        cluster_state1.gc_keys_marked_for_deletion(GRACE_PERIOD);
+        let node1_state = cluster_state1.node_state(&node1).unwrap();
+        assert_eq!(node1_state.key_values_including_deleted().count(), 0);
+        assert_eq!(node1_state.max_version(), 2);
+        assert_eq!(node1_state.last_gc_version(), 2);
+
+        // The delta resets the node state to the empty state at version 2.
+        let delta = compute_delta(&cluster_state1, &cluster_state2);
+        let node_delta = delta.get(&node1).unwrap();
+        assert_eq!(node_delta.from_version_excluded, 0);
+        assert_eq!(node_delta.last_gc_version, 2);
+        assert!(node_delta.key_values.is_empty());
+        assert_eq!(node_delta.max_version, Some(2));
+
+        let mut buf = Vec::new();
+        Serializable::serialize(&delta, &mut buf);
+        let deserialized_delta = <Delta as Deserializable>::deserialize(&mut &buf[..]).unwrap();
+        assert_eq!(deserialized_delta, delta);
+
+        cluster_state2.apply_delta(deserialized_delta);
+        let node1_state = cluster_state2.node_state(&node1).unwrap();
+        assert!(node1_state.get("key_a").is_none());
+        assert_eq!(node1_state.max_version(), 2);
+        assert_eq!(node1_state.last_gc_version(), 2);
+
+        // The states have converged.
+        let delta = compute_delta(&cluster_state1, &cluster_state2);
+        assert!(delta.get(&node1).is_none());
     }
 
     #[test]
@@ -1706,6 +4259,104 @@ mod tests {
         assert!(node_state.get("key_a").is_none());
     }
 
+    #[test]
+    fn test_node_set_local() {
+        let mut cluster_state = ClusterState::default();
+        let node = ChitchatId::for_local_test(10_001);
+        let node_state = cluster_state.node_state_mut(&node);
+        node_state.set("key_a", "val_a");
+        node_state.set_local("key_b", "val_b");
+        node_state.set_local("key_a", "local_val_a");
+        assert_eq!(node_state.max_version(), 1);
+        assert_eq!(node_state.num_key_values(), 1);
+        assert_eq!(node_state.get("key_a"), Some("val_a"));
+        assert_eq!(node_state.get("key_b"), Some("val_b"));
+        assert_eq!(node_state.get_local("key_a"), Some("local_val_a"));
+        assert_eq!(
+            node_state.local_key_values().collect::<Vec<_>>(),
+            &[("key_a", "local_val_a"), ("key_b", "val_b")]
+        );
+
+        let mut digest = Digest::default();
+        digest.add_node(node.clone(), Heartbeat(0), 0, 0);
+        let delta = cluster_state.compute_partial_delta_respecting_mtu(
+            &digest,
+            MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            &HashSet::new(),
+        );
+        let mut expected_delta = Delta::default();
+        expected_delta.add_node(node.clone(), 0u64, 0u64);
+        expected_delta.add_kv(&node, "key_a", "val_a", 1, false);
+        expected_delta.set_serialized_len(41);
+        assert_eq!(delta, expected_delta);
+
+        let node_state = cluster_state.node_state_mut(&node);
+        assert_eq!(node_state.delete_local("key_b"), Some("val_b".to_string()));
+        assert!(node_state.get("key_b").is_none());
+    }
+
+    #[test]
+    fn test_node_get_at_version() {
+        let mut node_state = NodeState::new(
+            ChitchatId::for_local_test(10_001),
+            Listeners::default(),
+            KeyValidation::default(),
+            2,
+            SharedClock::default(),
+        );
+        node_state.set("key_a", "1"); // 1
+        node_state.set("key_a", "2"); // 2
+        node_state.set("key_b", "1"); // 3
+        node_state.set("key_a", "3"); // 4
+        node_state.delete("key_a"); // 5
+
+        let value_at_version = |key: &str, version: Version| {
+            let versioned_value = node_state.get_at_version(key, version)?;
+            Some((&*versioned_value.value, versioned_value.is_deleted()))
+        };
+        assert_eq!(value_at_version("key_a", 5), Some(("", true)));
+        assert_eq!(value_at_version("key_a", 4), Some(("3", false)));
+        assert_eq!(value_at_version("key_a", 3), Some(("2", false)));
+        assert_eq!(value_at_version("key_a", 2), Some(("2", false)));
+        // Only the last 2 previous values are kept.
+        assert_eq!(value_at_version("key_a", 1), None);
+        assert_eq!(value_at_version("key_b", 2), None);
+        assert_eq!(value_at_version("key_b", 3), Some(("1", false)));
+
+        // Without history, only the current value is available.
+        let mut node_state = NodeState::for_test();
+        node_state.set("key_a", "1");
+        node_state.set("key_a", "2");
+        assert!(node_state.get_at_version("key_a", 1).is_none());
+        assert_eq!(&*node_state.get_at_version("key_a", 2).unwrap().value, "2");
+    }
+
+    #[test]
+    fn test_node_get_versioned_live() {
+        let mut node_state = NodeState::for_test();
+        node_state.set("key_a", "1");
+        node_state.set("key_b", "2");
+        node_state.set("key_c", "3");
+        node_state.set_local("key_d", "4");
+        node_state.delete("key_a");
+        node_state.delete_after_ttl("key_b");
+
+        assert!(node_state.get_versioned("key_a").is_some());
+        assert!(node_state.get_versioned_live("key_a").is_none());
+        let versioned_b = node_state.get_versioned_live("key_b").unwrap();
+        assert_eq!(versioned_b.version, 5);
+        assert!(matches!(
+            versioned_b.status,
+            DeletionStatus::DeleteAfterTtl(_)
+        ));
+        assert_eq!(
+            node_state.get_versioned_live("key_c").unwrap(),
+            &VersionedValue::for_test("3", 3)
+        );
+        assert!(node_state.get_versioned_live("key_d").is_none());
+        assert!(node_state.get_versioned_live("key_e").is_none());
+    }
+
     #[test]
     fn test_node_set_delete_after_ttl_set() {
         let mut node_state = NodeState::for_test();
@@ -1727,6 +4378,6 @@ mod tests {
             versioned_value.status,
             DeletionStatus::DeleteAfterTtl(_)
         ));
-        assert_eq!(versioned_value.value, "val_b");
+        assert_eq!(&*versioned_value.value, "val_b");
     }
 }
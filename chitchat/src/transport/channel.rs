@@ -4,12 +4,13 @@ use std::sync::{Arc, Mutex};
 
 use anyhow::{bail, Context};
 use async_trait::async_trait;
+use tokio::sync::mpsc::error::TryRecvError;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::info;
 
 use crate::serialize::{Deserializable, Serializable};
 use crate::transport::{Socket, Transport};
-use crate::ChitchatMessage;
+use crate::{ChitchatError, ChitchatMessage};
 
 const MAX_MESSAGE_PER_CHANNEL: usize = 100;
 
@@ -107,7 +108,11 @@ impl ChannelTransport {
         let num_bytes = message.serialized_len();
         if let Some(mtu) = self.mtu_opt {
             if num_bytes > mtu {
-                bail!("Serialized message size exceeds MTU.");
+                return Err(ChitchatError::Mtu {
+                    message_len: num_bytes,
+                    mtu,
+                }
+                .into());
             }
         }
         let mut inner_lock = self.inner.lock().unwrap();
@@ -149,6 +154,14 @@ impl Socket for InProcessSocket {
         let (from_addr, message) = self.message_rx.recv().await.context("Channel closed")?;
         Ok((from_addr, message))
     }
+
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        match self.message_rx.try_recv() {
+            Ok((from_addr, message)) => Ok(Some((from_addr, message))),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => bail!("Channel closed"),
+        }
+    }
 }
 
 impl Drop for InProcessSocket {
@@ -6,17 +6,30 @@ use crate::message::ChitchatMessage;
 
 mod channel;
 mod udp;
+#[cfg(unix)]
+mod unix;
+#[cfg(any(test, feature = "testsuite"))]
 mod utils;
 
 pub use channel::{ChannelTransport, Statistics};
 pub use udp::{UdpSocket, UdpTransport};
-pub use utils::TransportExt;
+#[cfg(unix)]
+pub use unix::UnixDatagramTransport;
+#[cfg(any(test, feature = "testsuite"))]
+pub use utils::{TransportExt, MAX_REORDER_HOLD_DURATION};
 
 #[async_trait]
 pub trait Transport: Send + Sync + 'static {
     async fn open(&self, listen_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>>;
 }
 
+#[async_trait]
+impl Transport for Box<dyn Transport> {
+    async fn open(&self, listen_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>> {
+        (**self).open(listen_addr).await
+    }
+}
+
 #[async_trait]
 pub trait Socket: Send + Sync + 'static {
     // Only returns an error if the transport is broken and may not emit message
@@ -25,6 +38,12 @@ pub trait Socket: Send + Sync + 'static {
     // Only returns an error if the transport is broken and may not receive message
     // in the future.
     async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)>;
+    // Returns a message that has already been received, if any, without waiting. This lets the
+    // server process all the queued messages at once. By default, no message is ever returned,
+    // so messages are only received through `recv`.
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
@@ -40,7 +59,7 @@ mod tests {
     use crate::message::ChitchatMessage;
     use crate::serialize::Serializable;
     use crate::transport::{ChannelTransport, UdpTransport};
-    use crate::MAX_UDP_DATAGRAM_PAYLOAD_SIZE;
+    use crate::{ChitchatError, MAX_UDP_DATAGRAM_PAYLOAD_SIZE};
 
     fn sample_syn_msg() -> ChitchatMessage {
         ChitchatMessage::Syn {
@@ -94,6 +113,21 @@ mod tests {
         assert_eq!(addr1, exp1);
     }
 
+    async fn test_transport_try_recv_does_not_wait(transport: &dyn Transport) {
+        let addr1: SocketAddr = ([127, 0, 0, 1], 20_011u16).into();
+        let addr2: SocketAddr = ([127, 0, 0, 1], 20_012u16).into();
+        let mut socket1 = transport.open(addr1).await.unwrap();
+        let mut socket2 = transport.open(addr2).await.unwrap();
+        assert!(socket2.try_recv().unwrap().is_none());
+        socket1.send(addr2, sample_syn_msg()).await.unwrap();
+        socket1.send(addr2, sample_syn_msg()).await.unwrap();
+        let (from_addr, _received_msg) = socket2.recv().await.unwrap();
+        assert_eq!(from_addr, addr1);
+        let (from_addr, _received_msg) = socket2.try_recv().unwrap().unwrap();
+        assert_eq!(from_addr, addr1);
+        assert!(socket2.try_recv().unwrap().is_none());
+    }
+
     async fn test_transport_socket_released_on_drop(transport: &dyn Transport) {
         let addr: SocketAddr = ([127, 0, 0, 1], 10_000u16).into();
         let socket = transport.open(addr).await.unwrap();
@@ -108,11 +142,41 @@ mod tests {
         socket.send(unbound_addr, sample_syn_msg()).await.unwrap()
     }
 
+    #[tokio::test]
+    async fn test_channel_transport_mtu() {
+        let addr: SocketAddr = ([127, 0, 0, 1], 40_010u16).into();
+        let mut socket = ChannelTransport::with_mtu(10).open(addr).await.unwrap();
+        let error = socket.send(addr, sample_syn_msg()).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ChitchatError>(),
+            Some(ChitchatError::Mtu { mtu: 10, .. })
+        ));
+    }
+
+    async fn test_transport_rejects_oversized_message(transport: &dyn Transport) {
+        let addr: SocketAddr = ([127, 0, 0, 1], 40_020u16).into();
+        let mut socket = transport.open(addr).await.unwrap();
+        let oversized_syn_msg = ChitchatMessage::Syn {
+            cluster_id: "a".repeat(MAX_UDP_DATAGRAM_PAYLOAD_SIZE),
+            digest: Digest::default(),
+        };
+        let error = socket.send(addr, oversized_syn_msg).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ChitchatError>(),
+            Some(ChitchatError::Mtu {
+                mtu: MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+                ..
+            })
+        ));
+    }
+
     async fn test_transport_suite(transport: &dyn Transport) {
         test_transport_cannot_open_twice_aux(transport).await;
         test_transport_socket_released_on_drop(transport).await;
         test_transport_recv_waits_for_message(transport).await;
+        test_transport_try_recv_does_not_wait(transport).await;
         test_transport_sending_to_unbound_addr_is_ok(transport).await;
+        test_transport_rejects_oversized_message(transport).await;
     }
 
     #[tokio::test]
@@ -124,4 +188,22 @@ mod tests {
     async fn test_transport_in_mem() {
         test_transport_suite(&ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE)).await;
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transport_unix_datagram() {
+        let socket_dir = std::env::temp_dir().join(format!("chitchat-{}", ulid::Ulid::new()));
+        std::fs::create_dir(&socket_dir).unwrap();
+        let transport = super::UnixDatagramTransport::new(&socket_dir);
+        test_transport_suite(&transport).await;
+
+        // A socket file left behind by a crashed process does not prevent binding.
+        let addr: SocketAddr = ([127, 0, 0, 1], 10_000u16).into();
+        let stale_socket =
+            std::os::unix::net::UnixDatagram::bind(socket_dir.join(format!("{addr}.sock")))
+                .unwrap();
+        drop(stale_socket);
+        let _socket = transport.open(addr).await.unwrap();
+        std::fs::remove_dir_all(&socket_dir).unwrap();
+    }
 }
@@ -1,3 +1,4 @@
+use std::io;
 use std::net::SocketAddr;
 
 use anyhow::Context;
@@ -6,7 +7,7 @@ use tracing::warn;
 
 use crate::serialize::{Deserializable, Serializable};
 use crate::transport::{Socket, Transport};
-use crate::{ChitchatMessage, MAX_UDP_DATAGRAM_PAYLOAD_SIZE};
+use crate::{ChitchatError, ChitchatMessage, MAX_UDP_DATAGRAM_PAYLOAD_SIZE};
 
 pub struct UdpTransport;
 
@@ -42,6 +43,13 @@ impl Socket for UdpSocket {
     async fn send(&mut self, to_addr: SocketAddr, message: ChitchatMessage) -> anyhow::Result<()> {
         self.buf_send.clear();
         message.serialize(&mut self.buf_send);
+        if self.buf_send.len() > MAX_UDP_DATAGRAM_PAYLOAD_SIZE {
+            return Err(ChitchatError::Mtu {
+                message_len: self.buf_send.len(),
+                mtu: MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            }
+            .into());
+        }
         self.send_bytes(to_addr, &self.buf_send).await?;
         Ok(())
     }
@@ -54,6 +62,19 @@ impl Socket for UdpSocket {
             }
         }
     }
+
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        loop {
+            let (len, from_addr) = match self.socket.try_recv_from(&mut self.buf_recv[..]) {
+                Ok(received) => received,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(error) => return Err(error).context("Error while receiving UDP message"),
+            };
+            if let Some(message) = self.deserialize_message(len, from_addr) {
+                return Ok(Some(message));
+            }
+        }
+    }
 }
 
 impl UdpSocket {
@@ -63,12 +84,20 @@ impl UdpSocket {
             .recv_from(&mut self.buf_recv[..])
             .await
             .context("Error while receiving UDP message")?;
+        Ok(self.deserialize_message(len, from_addr))
+    }
+
+    fn deserialize_message(
+        &self,
+        len: usize,
+        from_addr: SocketAddr,
+    ) -> Option<(SocketAddr, ChitchatMessage)> {
         let mut buf = &self.buf_recv[..len];
         match ChitchatMessage::deserialize(&mut buf) {
-            Ok(msg) => Ok(Some((from_addr, msg))),
+            Ok(msg) => Some((from_addr, msg)),
             Err(err) => {
-                warn!(payload_len=len, from=%from_addr, err=%err, "invalid-chitchat-payload");
-                Ok(None)
+                warn!(payload_len=len, from=%from_addr, err=?err, "invalid-chitchat-payload");
+                None
             }
         }
     }
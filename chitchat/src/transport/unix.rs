@@ -0,0 +1,183 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use tokio::net::UnixDatagram;
+use tracing::{debug, warn};
+
+use crate::serialize::{Deserializable, Serializable};
+use crate::transport::{Socket, Transport};
+use crate::{ChitchatError, ChitchatMessage, MAX_UDP_DATAGRAM_PAYLOAD_SIZE};
+
+/// Transport exchanging gossip messages over Unix datagram sockets, for processes running on the
+/// same host, e.g. sidecars that cannot allocate loopback ports.
+///
+/// Nodes keep identifying each other with socket addresses: the socket bound for the gossip
+/// address `addr` is the file `{socket_dir}/{addr}.sock`, so every node of the cluster must share
+/// the same socket directory. The socket file is removed when the socket is dropped, and a stale
+/// file left behind by a crashed process is replaced.
+///
+/// Note that the maximum size of a Unix datagram is bounded by the socket buffer size of the
+/// platform, which must accommodate gossip messages of up to 65,507 bytes, the maximum payload of
+/// a UDP datagram. This may require raising the platform limit, e.g. on macOS.
+pub struct UnixDatagramTransport {
+    socket_dir: PathBuf,
+}
+
+impl UnixDatagramTransport {
+    pub fn new(socket_dir: impl Into<PathBuf>) -> Self {
+        UnixDatagramTransport {
+            socket_dir: socket_dir.into(),
+        }
+    }
+}
+
+fn socket_path(socket_dir: &Path, addr: SocketAddr) -> PathBuf {
+    socket_dir.join(format!("{addr}.sock"))
+}
+
+#[async_trait]
+impl Transport for UnixDatagramTransport {
+    async fn open(&self, bind_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>> {
+        let socket_path = socket_path(&self.socket_dir, bind_addr);
+        remove_stale_socket_file(&socket_path)?;
+        let socket = UnixDatagram::bind(&socket_path).with_context(|| {
+            format!(
+                "failed to bind to {} for gossip on {bind_addr}",
+                socket_path.display()
+            )
+        })?;
+        Ok(Box::new(UnixDatagramSocket {
+            socket_dir: self.socket_dir.clone(),
+            socket_path,
+            buf_send: Vec::with_capacity(MAX_UDP_DATAGRAM_PAYLOAD_SIZE),
+            buf_recv: Box::new([0u8; MAX_UDP_DATAGRAM_PAYLOAD_SIZE]),
+            socket,
+        }))
+    }
+}
+
+/// Removes the socket file at `socket_path` if no socket is bound to it anymore.
+fn remove_stale_socket_file(socket_path: &Path) -> anyhow::Result<()> {
+    if !socket_path.exists() {
+        return Ok(());
+    }
+    let probe_socket = UnixDatagram::unbound()?;
+    match probe_socket.connect(socket_path) {
+        // A live socket is bound to this path: binding will fail.
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(socket_path).with_context(|| {
+                format!(
+                    "failed to remove stale socket file {}",
+                    socket_path.display()
+                )
+            })
+        }
+        Err(_) => Ok(()),
+    }
+}
+
+struct UnixDatagramSocket {
+    socket_dir: PathBuf,
+    socket_path: PathBuf,
+    buf_send: Vec<u8>,
+    buf_recv: Box<[u8; MAX_UDP_DATAGRAM_PAYLOAD_SIZE]>,
+    socket: UnixDatagram,
+}
+
+impl Drop for UnixDatagramSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+#[async_trait]
+impl Socket for UnixDatagramSocket {
+    async fn send(&mut self, to_addr: SocketAddr, message: ChitchatMessage) -> anyhow::Result<()> {
+        self.buf_send.clear();
+        message.serialize(&mut self.buf_send);
+        if self.buf_send.len() > MAX_UDP_DATAGRAM_PAYLOAD_SIZE {
+            return Err(ChitchatError::Mtu {
+                message_len: self.buf_send.len(),
+                mtu: MAX_UDP_DATAGRAM_PAYLOAD_SIZE,
+            }
+            .into());
+        }
+        let to_path = socket_path(&self.socket_dir, to_addr);
+        match self.socket.send_to(&self.buf_send, &to_path).await {
+            Ok(_) => Ok(()),
+            // Like UDP, sending to a peer that is not listening is not an error.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                debug!(to=%to_addr, error=%error, "peer-socket-unavailable");
+                Ok(())
+            }
+            Err(error) => Err(error).context("failed to send chitchat message to peer"),
+        }
+    }
+
+    /// Recv needs to be cancellable.
+    async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)> {
+        loop {
+            if let Some(message) = self.receive_one().await? {
+                return Ok(message);
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        loop {
+            let (len, from_socket_addr) = match self.socket.try_recv_from(&mut self.buf_recv[..]) {
+                Ok(received) => received,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(error) => {
+                    return Err(error).context("Error while receiving Unix datagram message")
+                }
+            };
+            if let Some(message) = self.deserialize_message(len, &from_socket_addr) {
+                return Ok(Some(message));
+            }
+        }
+    }
+}
+
+impl UnixDatagramSocket {
+    async fn receive_one(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        let (len, from_socket_addr) = self
+            .socket
+            .recv_from(&mut self.buf_recv[..])
+            .await
+            .context("Error while receiving Unix datagram message")?;
+        Ok(self.deserialize_message(len, &from_socket_addr))
+    }
+
+    fn deserialize_message(
+        &self,
+        len: usize,
+        from_socket_addr: &tokio::net::unix::SocketAddr,
+    ) -> Option<(SocketAddr, ChitchatMessage)> {
+        let Some(from_addr) = from_socket_addr
+            .as_pathname()
+            .and_then(|from_path| from_path.file_name()?.to_str()?.strip_suffix(".sock"))
+            .and_then(|from_addr_str| from_addr_str.parse::<SocketAddr>().ok())
+        else {
+            warn!(payload_len=len, from=?from_socket_addr, "invalid-chitchat-sender");
+            return None;
+        };
+        let mut buf = &self.buf_recv[..len];
+        match ChitchatMessage::deserialize(&mut buf) {
+            Ok(msg) => Some((from_addr, msg)),
+            Err(err) => {
+                warn!(payload_len=len, from=%from_addr, err=?err, "invalid-chitchat-payload");
+                None
+            }
+        }
+    }
+}
@@ -7,24 +7,33 @@ use rand::distributions::Bernoulli;
 use rand::prelude::{Distribution, SmallRng};
 use rand::{thread_rng, SeedableRng};
 use tokio::sync::RwLock;
+use tokio::time::Instant;
 
 use crate::transport::{Socket, Transport};
 use crate::ChitchatMessage;
 
+/// Maximum duration a message held back by [`TransportExt::reorder`] waits for the next message.
+pub const MAX_REORDER_HOLD_DURATION: Duration = Duration::from_millis(100);
+
 struct TransportWithDelay<D: Distribution<f32> + Send + Sync + 'static> {
-    delay_secs: D,
+    delay_dist: D,
+    /// Duration of one unit of the values sampled from `delay_dist`.
+    delay_unit: Duration,
     transport: Box<dyn Transport>,
 }
 
 pub trait DelayMillisDist: Distribution<f32> + Send + Sync + Clone + 'static {}
 
+impl<D: Distribution<f32> + Send + Sync + Clone + 'static> DelayMillisDist for D {}
+
 #[async_trait]
 impl<D: DelayMillisDist> Transport for TransportWithDelay<D> {
     async fn open(&self, listen_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>> {
         let rng = SmallRng::from_rng(thread_rng()).unwrap();
         let socket = self.transport.open(listen_addr).await?;
         Ok(Box::new(SocketWithDelay {
-            delay_secs: self.delay_secs.clone(),
+            delay_dist: self.delay_dist.clone(),
+            delay_unit: self.delay_unit,
             socket: Arc::new(RwLock::new(socket)),
             rng,
         }))
@@ -32,7 +41,8 @@ impl<D: DelayMillisDist> Transport for TransportWithDelay<D> {
 }
 
 struct SocketWithDelay<D: Distribution<f32> + Send + Sync + 'static> {
-    delay_secs: D,
+    delay_dist: D,
+    delay_unit: Duration,
     socket: Arc<RwLock<Box<dyn Socket>>>,
     rng: SmallRng,
 }
@@ -41,8 +51,9 @@ struct SocketWithDelay<D: Distribution<f32> + Send + Sync + 'static> {
 impl<D: DelayMillisDist> Socket for SocketWithDelay<D> {
     async fn send(&mut self, to: SocketAddr, message: ChitchatMessage) -> anyhow::Result<()> {
         let socket_clone = self.socket.clone();
-        let delay_secs = self.delay_secs.sample(&mut self.rng);
-        let delay = Duration::from_secs_f32(delay_secs);
+        let delay = self
+            .delay_unit
+            .mul_f32(self.delay_dist.sample(&mut self.rng));
         tokio::task::spawn(async move {
             tokio::time::sleep(delay).await;
             let _ = socket_clone.write().await.send(to, message).await;
@@ -53,11 +64,30 @@ impl<D: DelayMillisDist> Socket for SocketWithDelay<D> {
     async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)> {
         self.socket.write().await.recv().await
     }
+
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        // The socket is locked while a delayed message is being sent: we just report that no
+        // message is ready and let the next call to `recv` wait for it.
+        let Ok(mut socket) = self.socket.try_write() else {
+            return Ok(None);
+        };
+        socket.try_recv()
+    }
 }
 
+/// Fault injection helpers, used to test chitchat over an unreliable network.
 pub trait TransportExt {
+    /// Drops each sent message with the given probability.
     fn drop_message(self, drop_probability: f64) -> Box<dyn Transport>;
-    fn delay<D: DelayMillisDist>(self, delay_proba: D) -> Box<dyn Transport>;
+    /// Delays each sent message by a duration, in seconds, sampled from the given distribution.
+    fn delay<D: DelayMillisDist>(self, delay_secs: D) -> Box<dyn Transport>;
+    /// Same as [`TransportExt::delay`], except that the duration is sampled in milliseconds.
+    fn delay_millis<D: DelayMillisDist>(self, delay_millis: D) -> Box<dyn Transport>;
+    /// Holds back each sent message with the given probability, and sends it right after the
+    /// next message sent by the same socket, or after [`MAX_REORDER_HOLD_DURATION`] if no message
+    /// is sent in the meantime. In the latter case, the message is sent while the socket is
+    /// waiting for a message in `recv`.
+    fn reorder(self, reorder_probability: f64) -> Box<dyn Transport>;
 }
 
 impl<T: Transport> TransportExt for T {
@@ -70,7 +100,23 @@ impl<T: Transport> TransportExt for T {
 
     fn delay<D: DelayMillisDist>(self, delay_secs: D) -> Box<dyn Transport> {
         Box::new(TransportWithDelay {
-            delay_secs,
+            delay_dist: delay_secs,
+            delay_unit: Duration::from_secs(1),
+            transport: Box::new(self),
+        })
+    }
+
+    fn delay_millis<D: DelayMillisDist>(self, delay_millis: D) -> Box<dyn Transport> {
+        Box::new(TransportWithDelay {
+            delay_dist: delay_millis,
+            delay_unit: Duration::from_millis(1),
+            transport: Box::new(self),
+        })
+    }
+
+    fn reorder(self, reorder_probability: f64) -> Box<dyn Transport> {
+        Box::new(TransportWithReorder {
+            reorder_probability: Bernoulli::new(reorder_probability).unwrap(),
             transport: Box::new(self),
         })
     }
@@ -113,4 +159,143 @@ impl Socket for SocketWithMessageDrop {
     async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)> {
         self.socket.recv().await
     }
+
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        self.socket.try_recv()
+    }
+}
+
+struct TransportWithReorder {
+    reorder_probability: Bernoulli,
+    transport: Box<dyn Transport>,
+}
+
+#[async_trait]
+impl Transport for TransportWithReorder {
+    async fn open(&self, listen_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>> {
+        let rng = SmallRng::from_rng(thread_rng()).unwrap();
+        let socket = self.transport.open(listen_addr).await?;
+        Ok(Box::new(SocketWithReorder {
+            reorder_probability: self.reorder_probability,
+            socket,
+            held_back_message_opt: None,
+            rng,
+        }))
+    }
+}
+
+struct SocketWithReorder {
+    reorder_probability: Bernoulli,
+    socket: Box<dyn Socket>,
+    /// Message held back, along with the deadline at which it is sent anyway.
+    held_back_message_opt: Option<(SocketAddr, ChitchatMessage, Instant)>,
+    rng: SmallRng,
+}
+
+#[async_trait]
+impl Socket for SocketWithReorder {
+    async fn send(&mut self, to: SocketAddr, message: ChitchatMessage) -> anyhow::Result<()> {
+        if let Some((held_back_to, held_back_message, _)) = self.held_back_message_opt.take() {
+            self.socket.send(to, message).await?;
+            return self.socket.send(held_back_to, held_back_message).await;
+        }
+        let should_reorder = self.reorder_probability.sample(&mut self.rng);
+        if should_reorder {
+            let deadline = Instant::now() + MAX_REORDER_HOLD_DURATION;
+            self.held_back_message_opt = Some((to, message, deadline));
+            return Ok(());
+        }
+        self.socket.send(to, message).await
+    }
+
+    async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)> {
+        while let Some((_, _, deadline)) = &self.held_back_message_opt {
+            tokio::select! {
+                recv_result = self.socket.recv() => return recv_result,
+                _ = tokio::time::sleep_until(*deadline) => {
+                    let (held_back_to, held_back_message, _) =
+                        self.held_back_message_opt.take().expect("a message should be held back");
+                    self.socket.send(held_back_to, held_back_message).await?;
+                }
+            }
+        }
+        self.socket.recv().await
+    }
+
+    fn try_recv(&mut self) -> anyhow::Result<Option<(SocketAddr, ChitchatMessage)>> {
+        self.socket.try_recv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Digest;
+    use crate::transport::ChannelTransport;
+    use crate::MAX_UDP_DATAGRAM_PAYLOAD_SIZE;
+
+    fn syn_message(cluster_id: &str) -> ChitchatMessage {
+        ChitchatMessage::Syn {
+            cluster_id: cluster_id.to_string(),
+            digest: Digest::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transport_reorder() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE).reorder(1.0);
+        let addr1: SocketAddr = ([127, 0, 0, 1], 20_001u16).into();
+        let addr2: SocketAddr = ([127, 0, 0, 1], 20_002u16).into();
+        let mut socket1 = transport.open(addr1).await.unwrap();
+        let mut socket2 = transport.open(addr2).await.unwrap();
+
+        socket1.send(addr2, syn_message("first")).await.unwrap();
+        socket1.send(addr2, syn_message("second")).await.unwrap();
+
+        let (_, message) = socket2.recv().await.unwrap();
+        assert_eq!(message, syn_message("second"));
+        let (_, message) = socket2.recv().await.unwrap();
+        assert_eq!(message, syn_message("first"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transport_delay_millis() {
+        let delay_millis = rand::distributions::Uniform::new_inclusive(50.0f32, 50.0f32);
+        let transport =
+            ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE).delay_millis(delay_millis);
+        let addr1: SocketAddr = ([127, 0, 0, 1], 20_005u16).into();
+        let addr2: SocketAddr = ([127, 0, 0, 1], 20_006u16).into();
+        let mut socket1 = transport.open(addr1).await.unwrap();
+        let mut socket2 = transport.open(addr2).await.unwrap();
+
+        let start = Instant::now();
+        socket1.send(addr2, syn_message("delayed")).await.unwrap();
+        let (_, message) = socket2.recv().await.unwrap();
+        assert_eq!(message, syn_message("delayed"));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transport_reorder_flushes_held_back_message() {
+        let transport = ChannelTransport::with_mtu(MAX_UDP_DATAGRAM_PAYLOAD_SIZE).reorder(1.0);
+        let addr1: SocketAddr = ([127, 0, 0, 1], 20_003u16).into();
+        let addr2: SocketAddr = ([127, 0, 0, 1], 20_004u16).into();
+        let mut socket1 = transport.open(addr1).await.unwrap();
+        let mut socket2 = transport.open(addr2).await.unwrap();
+
+        socket1.send(addr2, syn_message("first")).await.unwrap();
+        assert!(socket2.try_recv().unwrap().is_none());
+
+        // No other message is sent: the held back message is sent once the socket has waited
+        // for a message long enough.
+        let start = Instant::now();
+        let (_, message) = tokio::select! {
+            _ = socket1.recv() => panic!("no message should be sent to socket1"),
+            recv_result = socket2.recv() => recv_result.unwrap(),
+        };
+        assert_eq!(message, syn_message("first"));
+        assert!(start.elapsed() >= MAX_REORDER_HOLD_DURATION);
+    }
 }
@@ -1,8 +1,11 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
+use ulid::Ulid;
 
 use crate::serialize::Deserializable;
 use crate::Serializable;
@@ -18,17 +21,30 @@ use crate::Serializable;
 /// leaves and rejoins the cluster. Backends such as Cassandra or Quickwit typically use the node's
 /// startup time as the `generation_id`. Applications with stable state across restarts can use a
 /// constant `generation_id`, for instance, `0`.
+///
+/// The string representation of a [`ChitchatId`] is
+/// `{node_id}:{generation_id}:{gossip_advertise_addr}`, e.g. `node-1:0:127.0.0.1:10001` or
+/// `node-1:0:[::1]:10001`. It is stable, and parsing it back with [`FromStr`] yields the same
+/// [`ChitchatId`], even if the node ID contains colons.
 #[derive(Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct ChitchatId {
     /// An identifier unique across the cluster.
     pub node_id: String,
     /// A numeric identifier incremented every time the node leaves and rejoins the cluster.
     pub generation_id: u64,
-    /// The socket address peers should use to gossip with the node.
+    /// The socket address peers should use to gossip with the node. Nodes serving RPCs on a
+    /// different address advertise it in their state, see
+    /// [`NodeState::rpc_advertise_addr`](crate::NodeState::rpc_advertise_addr).
     pub gossip_advertise_addr: SocketAddr,
 }
 
 impl Debug for ChitchatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for ChitchatId {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
@@ -40,6 +56,39 @@ impl Debug for ChitchatId {
     }
 }
 
+impl FromStr for ChitchatId {
+    type Err = anyhow::Error;
+
+    fn from_str(chitchat_id_str: &str) -> anyhow::Result<Self> {
+        // The node ID may contain colons, so we look for the address from the end. No socket
+        // address has a proper suffix that is itself a valid socket address, so the shortest
+        // suffix parsing as one is the gossip advertise address.
+        let (node_id_and_generation_id, gossip_advertise_addr) = chitchat_id_str
+            .rmatch_indices(':')
+            .find_map(|(colon_idx, _)| {
+                let gossip_advertise_addr = chitchat_id_str[colon_idx + 1..].parse().ok()?;
+                Some((&chitchat_id_str[..colon_idx], gossip_advertise_addr))
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!("invalid chitchat ID `{chitchat_id_str}`: missing gossip address")
+            })?;
+        let Some((node_id, generation_id_str)) = node_id_and_generation_id.rsplit_once(':') else {
+            anyhow::bail!("invalid chitchat ID `{chitchat_id_str}`: missing generation ID");
+        };
+        let generation_id = generation_id_str.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "invalid chitchat ID `{chitchat_id_str}`: invalid generation ID \
+                 `{generation_id_str}`"
+            )
+        })?;
+        Ok(Self::new(
+            node_id.to_string(),
+            generation_id,
+            gossip_advertise_addr,
+        ))
+    }
+}
+
 impl ChitchatId {
     pub fn new(node_id: String, generation_id: u64, gossip_advertise_addr: SocketAddr) -> Self {
         Self {
@@ -48,6 +97,23 @@ impl ChitchatId {
             gossip_advertise_addr,
         }
     }
+
+    /// Creates a [`ChitchatId`] for a new node, with a ULID as `node_id` and the current Unix
+    /// timestamp in milliseconds as `generation_id`.
+    pub fn generate(gossip_advertise_addr: SocketAddr) -> Self {
+        let node_id = Ulid::new().to_string();
+        let generation_id = crate::unix_timestamp_millis();
+        Self::new(node_id, generation_id, gossip_advertise_addr)
+    }
+
+    /// Creates a [`ChitchatId`] using the gossip advertise address as `node_id` and `0` as
+    /// `generation_id`.
+    ///
+    /// Since the generation never changes, this is only suitable for nodes that do not lose their
+    /// state across restarts, or for tests.
+    pub fn from_addr(gossip_advertise_addr: SocketAddr) -> Self {
+        Self::new(gossip_advertise_addr.to_string(), 0, gossip_advertise_addr)
+    }
 }
 
 #[cfg(any(test, feature = "testsuite"))]
@@ -63,14 +129,18 @@ impl ChitchatId {
     }
 }
 
+/// Deletion status of a key-value.
 #[derive(Clone, Copy, Debug)]
 pub enum DeletionStatus {
+    /// The key-value is set.
     Set,
     // In both `Deleted` and `DeleteAfterWithTtl`, the `Instant` is NOT the scheduled time of
     // deletion, but the reference start time.
     //
     // To get the actual time of deletion, one needs to add the grace period.
+    /// The key-value is deleted: only its tombstone remains, until it is garbage collected.
     Deleted(Instant),
+    /// The key-value is still set, but will be deleted once the grace period has elapsed.
     DeleteAfterTtl(Instant),
 }
 
@@ -98,7 +168,14 @@ impl DeletionStatus {
     from = "VersionedValueForSerialization"
 )]
 pub struct VersionedValue {
-    pub value: String,
+    /// The value is reference counted so that snapshots, deltas and listeners can share it
+    /// without copying it.
+    pub value: Arc<str>,
+    /// Version of the node state at which the value was set or deleted.
+    ///
+    /// Versions are only ordered within the state of a given node: every node increments its own
+    /// version, so the versions of a key on two different nodes say nothing about which value is
+    /// the freshest.
     pub version: Version,
     // The tombstone instant is transient:
     // Only the presence of a tombstone or not is serialized, and used in partial eq eq.
@@ -106,9 +183,9 @@ pub struct VersionedValue {
 }
 
 impl VersionedValue {
-    pub fn new(value: String, version: Version, is_tombstone: bool) -> VersionedValue {
+    pub fn new(value: impl Into<Arc<str>>, version: Version, is_tombstone: bool) -> VersionedValue {
         VersionedValue {
-            value,
+            value: value.into(),
             version,
             status: if is_tombstone {
                 DeletionStatus::Deleted(Instant::now())
@@ -129,7 +206,7 @@ impl VersionedValue {
     #[cfg(test)]
     pub fn for_test(value: &str, version: Version) -> Self {
         Self {
-            value: value.to_string(),
+            value: value.into(),
             version,
             status: DeletionStatus::Set,
         }
@@ -146,14 +223,44 @@ impl PartialEq for VersionedValue {
     }
 }
 
+/// Update of a key-value carried by a delta.
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
-pub(crate) struct KeyValueMutation {
+pub struct KeyValueMutation {
     pub(crate) key: String,
-    pub(crate) value: String,
+    pub(crate) value: Arc<str>,
     pub(crate) version: Version,
     pub(crate) status: DeletionStatusMutation,
 }
 
+impl KeyValueMutation {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn status(&self) -> DeletionStatusMutation {
+        self.status
+    }
+}
+
+/// A key-value of a node state, deleted or not, as listed by
+/// [`NodeState::debug_entries`](crate::NodeState::debug_entries).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct KeyEntry<'a> {
+    pub key: &'a str,
+    /// The value of the key. It is empty for deleted keys.
+    pub value: &'a str,
+    pub version: Version,
+    pub status: DeletionStatusMutation,
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
 #[repr(u8)]
 pub enum DeletionStatusMutation {
@@ -230,7 +337,7 @@ impl<'a> From<&'a KeyValueMutation> for KeyValueMutationRef<'a> {
     fn from(mutation: &'a KeyValueMutation) -> KeyValueMutationRef<'a> {
         KeyValueMutationRef {
             key: mutation.key.as_str(),
-            value: mutation.value.as_str(),
+            value: &mutation.value,
             version: mutation.version,
             state: mutation.status,
         }
@@ -269,7 +376,7 @@ impl Deserializable for KeyValueMutation {
         let state: DeletionStatusMutation = Deserializable::deserialize(buf)?;
         Ok(KeyValueMutation {
             key,
-            value,
+            value: value.into(),
             version,
             status: state,
         })
@@ -287,7 +394,7 @@ struct VersionedValueForSerialization {
 impl From<VersionedValueForSerialization> for VersionedValue {
     fn from(versioned_value: VersionedValueForSerialization) -> Self {
         VersionedValue {
-            value: versioned_value.value,
+            value: versioned_value.value.into(),
             version: versioned_value.version,
             status: versioned_value.status.into_status(Instant::now()),
         }
@@ -297,7 +404,7 @@ impl From<VersionedValueForSerialization> for VersionedValue {
 impl From<VersionedValue> for VersionedValueForSerialization {
     fn from(versioned_value: VersionedValue) -> Self {
         VersionedValueForSerialization {
-            value: versioned_value.value,
+            value: versioned_value.value.to_string(),
             version: versioned_value.version,
             status: DeletionStatusMutation::from(versioned_value.status),
         }
@@ -307,7 +414,16 @@ impl From<VersionedValue> for VersionedValueForSerialization {
 /// The current version of a key.
 pub type Version = u64;
 
+/// The greatest version a node state can reach. Deltas carrying a greater version are ignored, so
+/// that a crafted delta cannot push the versions of a node close enough to `u64::MAX` for them to
+/// overflow.
+pub const MAX_VERSION: Version = i64::MAX as Version;
+
 /// The current heartbeat of a node.
+///
+/// Heartbeats are carried by digests only. They are not versioned like key-values, so bumping
+/// the heartbeat of an otherwise idle node does not increase its max version and does not cause
+/// any delta to be gossiped.
 #[derive(
     Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize,
 )]
@@ -325,10 +441,78 @@ impl From<Heartbeat> for u64 {
     }
 }
 
+/// The status of a node, as seen by the failure detector.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum NodeStatus {
+    Live,
+    /// The node is still live but its phi is above the suspect threshold of the failure detector.
+    Suspected,
+    Dead,
+    /// The node has been dead for long enough that it will soon be garbage collected.
+    ScheduledForDeletion,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chitchat_id_generate() {
+        let gossip_advertise_addr: SocketAddr = ([127, 0, 0, 1], 10_001).into();
+        let chitchat_id = ChitchatId::generate(gossip_advertise_addr);
+        assert!(chitchat_id.node_id.parse::<Ulid>().is_ok());
+        assert!(chitchat_id.generation_id > 0);
+        assert_eq!(chitchat_id.gossip_advertise_addr, gossip_advertise_addr);
+
+        let other_chitchat_id = ChitchatId::generate(gossip_advertise_addr);
+        assert_ne!(chitchat_id.node_id, other_chitchat_id.node_id);
+        assert!(other_chitchat_id.generation_id >= chitchat_id.generation_id);
+    }
+
+    #[test]
+    fn test_chitchat_id_from_addr() {
+        let gossip_advertise_addr: SocketAddr = ([127, 0, 0, 1], 10_001).into();
+        let chitchat_id = ChitchatId::from_addr(gossip_advertise_addr);
+        assert_eq!(chitchat_id.node_id, "127.0.0.1:10001");
+        assert_eq!(chitchat_id.generation_id, 0);
+        assert_eq!(chitchat_id.gossip_advertise_addr, gossip_advertise_addr);
+    }
+
+    #[test]
+    fn test_chitchat_id_to_from_str() {
+        let chitchat_id = ChitchatId::for_local_test(10_001);
+        assert_eq!(chitchat_id.to_string(), "node-10001:0:127.0.0.1:10001");
+        assert_eq!(format!("{chitchat_id:?}"), "node-10001:0:127.0.0.1:10001");
+
+        let gossip_advertise_addr: SocketAddr = "[::1]:10001".parse().unwrap();
+        for chitchat_id in [
+            ChitchatId::for_local_test(10_001),
+            ChitchatId::generate(gossip_advertise_addr),
+            ChitchatId::from_addr(([127, 0, 0, 1], 10_001).into()),
+            ChitchatId::from_addr(gossip_advertise_addr),
+            ChitchatId::new("".to_string(), u64::MAX, gossip_advertise_addr),
+            ChitchatId::new("node:1:0:[::1]:1".to_string(), 7, gossip_advertise_addr),
+        ] {
+            let chitchat_id_str = chitchat_id.to_string();
+            assert_eq!(chitchat_id_str.parse::<ChitchatId>().unwrap(), chitchat_id);
+        }
+
+        for (chitchat_id_str, expected_error) in [
+            ("node-1", "missing gossip address"),
+            ("127.0.0.1:10001", "missing gossip address"),
+            ("node-1:127.0.0.1:10001", "missing generation ID"),
+            ("node-1:gen:127.0.0.1:10001", "invalid generation ID `gen`"),
+            ("node-1:-1:127.0.0.1:10001", "invalid generation ID `-1`"),
+            ("node-1:0:localhost:10001", "missing gossip address"),
+        ] {
+            let error = chitchat_id_str.parse::<ChitchatId>().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("invalid chitchat ID `{chitchat_id_str}`: {expected_error}")
+            );
+        }
+    }
+
     #[test]
     fn test_deletion_status_to_u8() {
         let mut count_values = 0;
@@ -5,7 +5,8 @@ use std::time::Duration;
 use anyhow::anyhow;
 use chitchat::transport::ChannelTransport;
 use chitchat::{
-    spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId, FailureDetectorConfig, NodeState,
+    spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId, DuplicateNodeIdPolicy,
+    FailureDetectorConfig, NodeState,
 };
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
@@ -54,7 +55,7 @@ impl NodeStatePredicate {
                 let versioned_value = node_state
                     .get_versioned(key)
                     .expect("Key is expected to be present");
-                &versioned_value.value == expected_value
+                *versioned_value.value == **expected_value
             }
             NodeStatePredicate::KeyPresent(key, present) => {
                 debug!(key=%key, present=present, "assert-key-present");
@@ -229,9 +230,31 @@ impl Simulator {
                 initial_interval: self.gossip_interval * 10,
                 ..Default::default()
             },
+            failure_detector_startup_grace_period: None,
             marked_for_deletion_grace_period: self.marked_for_deletion_key_grace_period,
             catchup_callback: None,
+            self_superseded_callback: None,
+            delta_applied_callback: None,
             extra_liveness_predicate: None,
+            duplicate_node_id_policy: DuplicateNodeIdPolicy::Ignore,
+            bad_cluster_quarantine_config: None,
+            respond_to_bad_cluster: true,
+            dead_node_gossip_probability_multiplier: 1.0,
+            min_seed_nodes_per_round: 1,
+            static_peers: Vec::new(),
+            max_gossip_bytes_per_sec: None,
+            observer: false,
+            message_auth_secret: None,
+            key_validator: None,
+            value_transform: None,
+            debug_buffers_enabled: false,
+            key_value_history_depth: 0,
+            recent_deltas_capacity: 0,
+            digest_only_gossip_config: None,
+            clock: None,
+            compact_self_versions: false,
+            rpc_advertise_addr: None,
+            message_timestamp_config: None,
         };
         let handle = spawn_chitchat(config, Vec::new(), &self.transport)
             .await
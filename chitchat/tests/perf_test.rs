@@ -4,8 +4,10 @@ use std::time::Duration;
 
 use chitchat::transport::{ChannelTransport, Transport, TransportExt};
 use chitchat::{
-    spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId, FailureDetectorConfig, NodeState,
+    spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId, DuplicateNodeIdPolicy,
+    FailureDetectorConfig, NodeState,
 };
+use rand::distributions::Uniform;
 use tokio::time::Instant;
 use tokio_stream::StreamExt;
 use tracing::info;
@@ -28,9 +30,31 @@ async fn spawn_one(chitchat_id: u16, transport: &dyn Transport) -> ChitchatHandl
             initial_interval: gossip_interval,
             ..Default::default()
         },
+        failure_detector_startup_grace_period: None,
         marked_for_deletion_grace_period: Duration::from_secs(10_000),
         catchup_callback: None,
+        self_superseded_callback: None,
+        delta_applied_callback: None,
         extra_liveness_predicate: None,
+        duplicate_node_id_policy: DuplicateNodeIdPolicy::Ignore,
+        bad_cluster_quarantine_config: None,
+        respond_to_bad_cluster: true,
+        dead_node_gossip_probability_multiplier: 1.0,
+        min_seed_nodes_per_round: 1,
+        static_peers: Vec::new(),
+        max_gossip_bytes_per_sec: None,
+        observer: false,
+        message_auth_secret: None,
+        key_validator: None,
+        value_transform: None,
+        debug_buffers_enabled: false,
+        key_value_history_depth: 0,
+        recent_deltas_capacity: 0,
+        digest_only_gossip_config: None,
+        clock: None,
+        compact_self_versions: false,
+        rpc_advertise_addr: None,
+        message_timestamp_config: None,
     };
     spawn_chitchat(config, Vec::new(), transport).await.unwrap()
 }
@@ -219,3 +243,22 @@ async fn test_faulty_network_stability_100() {
     let transport: Box<dyn Transport> = ChannelTransport::with_mtu(65_507).drop_message(0.5f64);
     test_faulty_network_stability_aux(10, &*transport).await;
 }
+
+#[tokio::test]
+async fn test_convergence_on_faulty_network() {
+    let _ = tracing_subscriber::fmt::try_init();
+    // 30% messages are dropped, the others are delayed by up to 100ms and sometimes reordered.
+    let transport: Box<dyn Transport> = ChannelTransport::with_mtu(65_507)
+        .drop_message(0.3f64)
+        .delay_millis(Uniform::new(0.0f32, 100.0f32))
+        .reorder(0.2f64);
+    let num_nodes = 10;
+    let handles = spawn_nodes(num_nodes as u16, &*transport).await;
+    let converged = wait_until(&handles[1], |nodes| nodes.len() == num_nodes);
+    tokio::time::timeout(Duration::from_secs(30), converged)
+        .await
+        .expect("cluster did not converge on a faulty network");
+    for handle in handles {
+        handle.shutdown().await.unwrap();
+    }
+}